@@ -0,0 +1,176 @@
+// runs .hack machine code the way the course's CPU emulator does: 32K
+// of rom, 32K of ram with the screen at 16384 and the keyboard at 24576,
+// and the A, D and program counter registers
+pub const RAM_SIZE: usize = 32768;
+pub const ROM_SIZE: usize = 32768;
+
+pub struct Cpu {
+    pub ram: Vec<i16>,
+    pub rom: Vec<u16>,
+    pub pc: u16,
+    pub a: i16,
+    pub d: i16,
+}
+
+impl Cpu {
+    pub fn new(program: &[u16]) -> Self {
+        let mut rom = program.to_vec();
+        rom.resize(ROM_SIZE, 0);
+        Self {
+            ram: vec![0; RAM_SIZE],
+            rom,
+            pc: 0,
+            a: 0,
+            d: 0,
+        }
+    }
+
+    // a .hack file, 16 binary digits a line
+    pub fn parse(text: &str) -> Result<Vec<u16>, usize> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| u16::from_str_radix(line.trim(), 2).map_err(|_| i))
+            .collect()
+    }
+
+    pub fn step(&mut self) {
+        let instruction = self.rom[self.pc as usize];
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            self.pc = self.pc.wrapping_add(1) % ROM_SIZE as u16;
+            return;
+        }
+
+        let address = self.a as u16 as usize % RAM_SIZE;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let out = alu(self.d, y, (instruction >> 6) & 0x3f);
+
+        // M is written at the address A held before this instruction
+        if instruction & 0b001000 != 0 {
+            self.ram[address] = out;
+        }
+        if instruction & 0b100000 != 0 {
+            self.a = out;
+        }
+        if instruction & 0b010000 != 0 {
+            self.d = out;
+        }
+
+        // the jump goes to where A was before the instruction changed it
+        let jump = (instruction & 0b100 != 0 && out < 0)
+            || (instruction & 0b010 != 0 && out == 0)
+            || (instruction & 0b001 != 0 && out > 0);
+        self.pc = if jump {
+            address as u16 % ROM_SIZE as u16
+        } else {
+            self.pc.wrapping_add(1) % ROM_SIZE as u16
+        };
+    }
+
+    // programs end by jumping to themselves forever, as in
+    // (END) @END 0;JMP
+    pub fn halted(&self) -> bool {
+        let a = self.a as u16;
+        self.rom[self.pc as usize] == 0b1110_1010_1000_0111
+            && (a == self.pc
+                || (a.wrapping_add(1) == self.pc && self.rom.get(a as usize) == Some(&a)))
+    }
+}
+
+// zx nx zy ny f no
+fn alu(x: i16, y: i16, control: u16) -> i16 {
+    let x = if control & 0b100000 != 0 { 0 } else { x };
+    let x = if control & 0b010000 != 0 { !x } else { x };
+    let y = if control & 0b001000 != 0 { 0 } else { y };
+    let y = if control & 0b000100 != 0 { !y } else { y };
+    let out = if control & 0b000010 != 0 {
+        x.wrapping_add(y)
+    } else {
+        x & y
+    };
+    if control & 0b000001 != 0 {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{analyzer::analyze, emitter::emit_instructions, parser::parse_lines};
+
+    fn assemble(lines: &[&str]) -> Vec<u16> {
+        let lines = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        let instructions = parse_lines(&lines).unwrap();
+        let symbol_table = analyze(instructions.iter());
+        emit_instructions(instructions.iter(), &symbol_table)
+    }
+
+    fn run(cpu: &mut Cpu) {
+        for _ in 0..10000 {
+            if cpu.halted() {
+                return;
+            }
+            cpu.step();
+        }
+        panic!("still running at {}", cpu.pc);
+    }
+
+    #[test]
+    fn test_multiply() {
+        // R2 = R0 * R1
+        let program = assemble(&[
+            "@R2", "M=0", "(LOOP)", "@R1", "D=M", "@END", "D;JEQ", "@R0", "D=M", "@R2", "M=D+M",
+            "@R1", "M=M-1", "@LOOP", "0;JMP", "(END)", "@END", "0;JMP",
+        ]);
+        let mut cpu = Cpu::new(&program);
+        cpu.ram[0] = 6;
+        cpu.ram[1] = 7;
+        run(&mut cpu);
+        assert_eq!(cpu.ram[2], 42);
+    }
+
+    #[test]
+    fn test_alu() {
+        let program = assemble(&[
+            "@5", "D=A", "@3", "D=D-A", "@R0", "M=D", "D=!D", "@R1", "M=D", "@R1", "MD=M+1", "@R2",
+            "M=-D", "@R3", "M=-1", "@R3", "D=D|M", "@R4", "M=D", "@END", "(END)", "0;JMP",
+        ]);
+        let mut cpu = Cpu::new(&program);
+        run(&mut cpu);
+        assert_eq!(&cpu.ram[0..5], &[2, -2, 2, -1, -1]);
+    }
+
+    #[test]
+    fn test_halted() {
+        let mut cpu = Cpu::new(&assemble(&["0;JMP", "(END)", "@END", "0;JMP"]));
+        // 0;JMP to -1, which wraps round to the first instruction
+        cpu.a = -1;
+        assert!(!cpu.halted());
+        cpu.a = 0;
+        assert!(cpu.halted());
+        cpu.pc = 2;
+        cpu.a = 1;
+        assert!(cpu.halted());
+        cpu.a = -1;
+        assert!(!cpu.halted());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Cpu::parse("0000000000000101\n1110110000010000\n"),
+            Ok(vec![5, 0b1110110000010000])
+        );
+        assert_eq!(Cpu::parse("0000000000000101\n12\n"), Err(1));
+    }
+}
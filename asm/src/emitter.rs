@@ -138,6 +138,20 @@ fn emit_jump(jump: &Jump) -> u16 {
     }
 }
 
+// an instruction the way .hack files write it, as 16 ascii digits
+pub fn bits(input: u16) -> String {
+    let mut output = String::with_capacity(16);
+    for i in (0..=15u16).rev() {
+        let bit = 1u16 << i;
+        if (input & bit) != 0 {
+            output.push('1');
+        } else {
+            output.push('0');
+        }
+    }
+    output
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -254,4 +268,9 @@ mod test {
             Some(0b1110010011110101)
         );
     }
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(bits(0b1001011101011001), "1001011101011001");
+    }
 }
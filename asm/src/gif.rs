@@ -0,0 +1,227 @@
+// reads gif images, which is what the course's reference screenshots
+// are, such as projects/12/ScreenTest/ScreenTestOutput.gif. Only the
+// first frame is read, and each pixel is taken to be black or white
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum GifError {
+    NotGif,
+    Truncated,
+    NoImage,
+    BadData,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // by row, true for black
+    pub pixels: Vec<bool>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+}
+
+pub fn read(data: &[u8]) -> Result<Image, GifError> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return Err(GifError::NotGif);
+    }
+    let mut input = Input { data, at: 6 };
+    let width = input.u16()? as usize;
+    let height = input.u16()? as usize;
+    let flags = input.byte()?;
+    let background = input.byte()?;
+    input.bytes(1)?;
+    let mut palette = if flags & 0x80 != 0 {
+        read_palette(&mut input, flags)?
+    } else {
+        Vec::new()
+    };
+    let dark = |palette: &[bool], index: u8| palette.get(index as usize).copied().unwrap_or(false);
+    let mut pixels = vec![dark(&palette, background); width * height];
+
+    loop {
+        match input.byte()? {
+            // extensions, none of which matter here
+            0x21 => {
+                input.byte()?;
+                input.sub_blocks()?;
+            }
+            0x2c => break,
+            _ => return Err(GifError::NoImage),
+        }
+    }
+    let left = input.u16()? as usize;
+    let top = input.u16()? as usize;
+    let frame_width = input.u16()? as usize;
+    let frame_height = input.u16()? as usize;
+    let flags = input.byte()?;
+    if flags & 0x80 != 0 {
+        palette = read_palette(&mut input, flags)?;
+    }
+    let interlaced = flags & 0x40 != 0;
+    let minimum_code_size = input.byte()?;
+    let indices = decompress(&input.sub_blocks()?, minimum_code_size)?;
+
+    let rows = if interlaced {
+        [(0, 8), (4, 8), (2, 4), (1, 2)]
+            .iter()
+            .flat_map(|&(start, step)| (start..frame_height).step_by(step))
+            .collect::<Vec<_>>()
+    } else {
+        (0..frame_height).collect()
+    };
+    for (i, &index) in indices.iter().take(frame_width * frame_height).enumerate() {
+        let (x, y) = (left + i % frame_width, top + rows[i / frame_width]);
+        if x < width && y < height {
+            pixels[y * width + x] = dark(&palette, index);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// whether each colour is dark
+fn read_palette(input: &mut Input, flags: u8) -> Result<Vec<bool>, GifError> {
+    let size = 2 << (flags & 7);
+    let colours = input.bytes(size * 3)?;
+    Ok(colours
+        .chunks(3)
+        .map(|rgb| 299 * rgb[0] as u32 + 587 * rgb[1] as u32 + 114 * (rgb[2] as u32) < 128_000)
+        .collect())
+}
+
+// lzw with codes of a growing number of bits, packed from the lowest bit
+fn decompress(data: &[u8], minimum_code_size: u8) -> Result<Vec<u8>, GifError> {
+    if minimum_code_size > 11 {
+        return Err(GifError::BadData);
+    }
+    let clear = 1usize << minimum_code_size;
+    let end = clear + 1;
+    let reset = || -> Vec<Vec<u8>> { (0..clear + 2).map(|code| vec![code as u8]).collect() };
+    let mut table = reset();
+    let mut code_size = minimum_code_size as usize + 1;
+    let mut previous: Option<Vec<u8>> = None;
+    let mut output = Vec::new();
+
+    let (mut bits, mut bit_count, mut at) = (0usize, 0usize, 0usize);
+    loop {
+        while bit_count < code_size {
+            let Some(&byte) = data.get(at) else {
+                return Ok(output);
+            };
+            bits |= (byte as usize) << bit_count;
+            bit_count += 8;
+            at += 1;
+        }
+        let code = bits & ((1 << code_size) - 1);
+        bits >>= code_size;
+        bit_count -= code_size;
+
+        if code == clear {
+            table = reset();
+            code_size = minimum_code_size as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return Ok(output);
+        }
+        let entry = match (table.get(code), &previous) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(previous)) if code == table.len() => {
+                let mut entry = previous.clone();
+                entry.push(previous[0]);
+                entry
+            }
+            _ => return Err(GifError::BadData),
+        };
+        if let Some(mut previous) = previous.take() {
+            if table.len() < 4096 {
+                previous.push(entry[0]);
+                table.push(previous);
+            }
+        }
+        if table.len() == 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+        output.extend(&entry);
+        previous = Some(entry);
+    }
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], GifError> {
+        let bytes = self
+            .data
+            .get(self.at..self.at + n)
+            .ok_or(GifError::Truncated)?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, GifError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, GifError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    // blocks of up to 255 bytes, each after its length, ending with an
+    // empty one
+    fn sub_blocks(&mut self) -> Result<Vec<u8>, GifError> {
+        let mut data = Vec::new();
+        loop {
+            let length = self.byte()? as usize;
+            if length == 0 {
+                return Ok(data);
+            }
+            data.extend(self.bytes(length)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        // a 1 by 1 white gif with a transparency extension
+        let gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\xff\xff\xff\x00\x00\x00\
+            !\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+        let image = read(gif).unwrap();
+        assert_eq!((image.width, image.height), (1, 1));
+        assert!(!image.pixel(0, 0));
+        assert_eq!(read(b"GIF89a\x01"), Err(GifError::Truncated));
+        assert_eq!(read(b"PNG"), Err(GifError::NotGif));
+    }
+
+    #[test]
+    fn test_decompress() {
+        // clear, 1, then twice the entry being added, which makes the
+        // codes a bit longer before the end
+        let codes = [4u32, 1, 6, 7, 5];
+        let mut bits = 0u32;
+        let sizes = [3, 3, 3, 3, 4];
+        let mut shift = 0;
+        for (code, size) in codes.iter().zip(sizes) {
+            bits |= code << shift;
+            shift += size;
+        }
+        let data = bits.to_le_bytes();
+        assert_eq!(decompress(&data, 2).unwrap(), vec![1, 1, 1, 1, 1, 1]);
+    }
+}
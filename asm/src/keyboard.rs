@@ -0,0 +1,138 @@
+// what a terminal sends for each key, as the codes the hack keyboard
+// puts at 24576. Printable characters are their ascii codes and the
+// rest are the course's codes from 128 up
+pub const NEWLINE: i16 = 128;
+pub const BACKSPACE: i16 = 129;
+pub const LEFT: i16 = 130;
+pub const UP: i16 = 131;
+pub const RIGHT: i16 = 132;
+pub const DOWN: i16 = 133;
+pub const HOME: i16 = 134;
+pub const END: i16 = 135;
+pub const PAGE_UP: i16 = 136;
+pub const PAGE_DOWN: i16 = 137;
+pub const INSERT: i16 = 138;
+pub const DELETE: i16 = 139;
+pub const ESCAPE: i16 = 140;
+pub const F1: i16 = 141;
+
+// the keys in some bytes read from a terminal in raw mode. Anything
+// without a hack key, like control characters, is dropped
+pub fn key_codes(input: &[u8]) -> Vec<i16> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        let (key, length) = match input[i..] {
+            [b'\r' | b'\n', ..] => (Some(NEWLINE), 1),
+            [0x7f | 0x08, ..] => (Some(BACKSPACE), 1),
+            [0x1b, b'[' | b'O', ..] => escape_sequence(&input[i + 2..]),
+            [0x1b, ..] => (Some(ESCAPE), 1),
+            [c @ 32..=126, ..] => (Some(c as i16), 1),
+            _ => (None, 1),
+        };
+        keys.extend(key);
+        i += length;
+    }
+    keys
+}
+
+// a key by the name its constant has, as in PAGE_DOWN or F5, with a
+// single printable character standing for itself
+pub fn key_named(name: &str) -> Option<i16> {
+    let key = match name {
+        "NEWLINE" => NEWLINE,
+        "BACKSPACE" => BACKSPACE,
+        "LEFT" => LEFT,
+        "UP" => UP,
+        "RIGHT" => RIGHT,
+        "DOWN" => DOWN,
+        "HOME" => HOME,
+        "END" => END,
+        "PAGE_UP" => PAGE_UP,
+        "PAGE_DOWN" => PAGE_DOWN,
+        "INSERT" => INSERT,
+        "DELETE" => DELETE,
+        "ESCAPE" => ESCAPE,
+        "SPACE" => b' ' as i16,
+        _ => match name.as_bytes() {
+            [c @ 32..=126] => *c as i16,
+            [b'F', ..] => match name[1..].parse::<i16>() {
+                Ok(n @ 1..=12) => F1 + n - 1,
+                _ => return None,
+            },
+            _ => return None,
+        },
+    };
+    Some(key)
+}
+
+// what follows ESC [ or ESC O, and how long the whole sequence is
+fn escape_sequence(input: &[u8]) -> (Option<i16>, usize) {
+    let key = match input {
+        [b'A', ..] => UP,
+        [b'B', ..] => DOWN,
+        [b'C', ..] => RIGHT,
+        [b'D', ..] => LEFT,
+        [b'H', ..] => HOME,
+        [b'F', ..] => END,
+        [c @ b'P'..=b'S', ..] => F1 + (c - b'P') as i16,
+        _ => {
+            // a number and then ~, as in ESC [ 5 ~
+            let digits = input.iter().take_while(|c| c.is_ascii_digit()).count();
+            if input.get(digits) != Some(&b'~') {
+                return (None, 2 + digits);
+            }
+            let number = std::str::from_utf8(&input[..digits]).unwrap();
+            let key = match number.parse::<u8>().unwrap_or(0) {
+                1 | 7 => Some(HOME),
+                2 => Some(INSERT),
+                3 => Some(DELETE),
+                4 | 8 => Some(END),
+                5 => Some(PAGE_UP),
+                6 => Some(PAGE_DOWN),
+                n @ 15 => Some(F1 + 4 + (n - 15) as i16),
+                n @ 17..=21 => Some(F1 + 5 + (n - 17) as i16),
+                n @ 23..=24 => Some(F1 + 10 + (n - 23) as i16),
+                _ => None,
+            };
+            return (key, 2 + digits + 1);
+        }
+    };
+    (Some(key), 3)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_codes() {
+        assert_eq!(
+            key_codes(b"aZ 9\r\x7f"),
+            vec![97, 90, 32, 57, NEWLINE, BACKSPACE]
+        );
+        assert_eq!(
+            key_codes(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1bOH\x1b[4~"),
+            vec![UP, DOWN, RIGHT, LEFT, HOME, END]
+        );
+        assert_eq!(
+            key_codes(b"\x1b[5~\x1b[6~\x1b[2~\x1b[3~\x1b"),
+            vec![PAGE_UP, PAGE_DOWN, INSERT, DELETE, ESCAPE]
+        );
+        assert_eq!(
+            key_codes(b"\x1bOP\x1bOS\x1b[15~\x1b[21~\x1b[24~"),
+            vec![F1, F1 + 3, F1 + 4, F1 + 9, F1 + 11]
+        );
+        assert_eq!(key_codes(b"\x03\x1b[99~x"), vec![120]);
+    }
+
+    #[test]
+    fn test_key_named() {
+        assert_eq!(key_named("PAGE_DOWN"), Some(PAGE_DOWN));
+        assert_eq!(key_named("F12"), Some(F1 + 11));
+        assert_eq!(key_named("3"), Some(51));
+        assert_eq!(key_named("F"), Some(70));
+        assert_eq!(key_named("F13"), None);
+        assert_eq!(key_named("ENTER"), None);
+    }
+}
@@ -0,0 +1,11 @@
+// the assembler, shared by the asm binary and the compiler's jackc driver,
+// along with a cpu emulator, its screen and keyboard, and a reader for
+// the gif screenshots the course compares screens against
+pub mod analyzer;
+pub mod ast;
+pub mod cpu;
+pub mod emitter;
+pub mod gif;
+pub mod keyboard;
+pub mod parser;
+pub mod screen;
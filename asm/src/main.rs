@@ -4,10 +4,7 @@ use std::{
     io::{prelude::*, BufRead, BufReader, BufWriter},
 };
 
-mod analyzer;
-mod ast;
-mod emitter;
-mod parser;
+use asm::{analyzer, emitter, emitter::bits, parser};
 
 fn main() -> std::io::Result<()> {
     if args().len() < 2 {
@@ -51,19 +48,6 @@ fn create_output_filename(input_filename: &str) -> String {
     output_filename
 }
 
-fn bits(input: u16) -> String {
-    let mut output = String::with_capacity(16);
-    for i in (0..=15u16).rev() {
-        let bit = 1u16 << i;
-        if (input & bit) != 0 {
-            output.push('1');
-        } else {
-            output.push('0');
-        }
-    }
-    output
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -75,9 +59,4 @@ mod test {
             "../whatever/foo.hack".to_string()
         );
     }
-
-    #[test]
-    fn test_bits() {
-        assert_eq!(bits(0b1001011101011001), "1001011101011001");
-    }
 }
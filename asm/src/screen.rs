@@ -0,0 +1,203 @@
+// the screen memory map as pictures. The screen is 512 by 256 pixels,
+// 32 words a row starting at 16384, and the lowest bit of each word is
+// its leftmost pixel. A set bit is black. Everything here works on the
+// whole of ram, so it doesn't matter whether a cpu or a vm filled it in
+use std::fmt::Write;
+
+pub const SCREEN: usize = 16384;
+pub const KEYBOARD: usize = 24576;
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+
+pub fn pixel(ram: &[i16], x: usize, y: usize) -> bool {
+    ram[SCREEN + y * WIDTH / 16 + x / 16] & (1 << (x % 16)) != 0
+}
+
+// a row as bytes with the leftmost pixel in the top bit, which is how
+// both pbm and png pack pixels
+fn packed_row(ram: &[i16], y: usize) -> impl Iterator<Item = u8> + '_ {
+    let words = &ram[SCREEN + y * WIDTH / 16..SCREEN + (y + 1) * WIDTH / 16];
+    words.iter().flat_map(|&word| {
+        let bits = (word as u16).reverse_bits();
+        [(bits >> 8) as u8, bits as u8]
+    })
+}
+
+// a binary pbm, where 1 is black
+pub fn pbm(ram: &[i16]) -> Vec<u8> {
+    let mut image = format!("P4\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+    for y in 0..HEIGHT {
+        image.extend(packed_row(ram, y));
+    }
+    image
+}
+
+// a one bit greyscale png, where 1 is white. The image data is stored
+// without compression, which keeps this short and the file about 17K
+pub fn png(ram: &[i16]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend((WIDTH as u32).to_be_bytes());
+    header.extend((HEIGHT as u32).to_be_bytes());
+    // bit depth 1, greyscale, deflate, adaptive filtering, no interlace
+    header.extend([1, 0, 0, 0, 0]);
+
+    let mut raw = Vec::new();
+    for y in 0..HEIGHT {
+        // no filter
+        raw.push(0);
+        raw.extend(packed_row(ram, y).map(|byte| !byte));
+    }
+    // a zlib stream of stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    while let Some(block) = blocks.next() {
+        data.push(blocks.peek().is_none() as u8);
+        let length = block.len() as u16;
+        data.extend(length.to_le_bytes());
+        data.extend((!length).to_le_bytes());
+        data.extend(block);
+    }
+    data.extend(adler32(&raw).to_be_bytes());
+
+    let mut image = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    for (kind, chunk) in [(b"IHDR", header), (b"IDAT", data), (b"IEND", Vec::new())] {
+        image.extend((chunk.len() as u32).to_be_bytes());
+        let start = image.len();
+        image.extend(kind);
+        image.extend(&chunk);
+        let crc = crc32(&image[start..]);
+        image.extend(crc.to_be_bytes());
+    }
+    image
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// each character is 2 by 4 pixels in braille dots, so the screen is 256
+// characters wide and 64 lines high
+pub fn braille(ram: &[i16]) -> String {
+    // the dot for each pixel of a cell, by column and row
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    let mut text = String::new();
+    for row in 0..HEIGHT / 4 {
+        for column in 0..WIDTH / 2 {
+            let mut dots = 0;
+            for (dx, column_dots) in DOTS.iter().enumerate() {
+                for (dy, dot) in column_dots.iter().enumerate() {
+                    if pixel(ram, column * 2 + dx, row * 4 + dy) {
+                        dots |= dot;
+                    }
+                }
+            }
+            text.push(char::from_u32(0x2800 + dots).unwrap());
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// each character is 1 by 2 pixels in half blocks, so the screen is 512
+// characters wide and 128 lines high
+pub fn half_blocks(ram: &[i16]) -> String {
+    let mut text = String::new();
+    for row in 0..HEIGHT / 2 {
+        for x in 0..WIDTH {
+            let block = match (pixel(ram, x, row * 2), pixel(ram, x, row * 2 + 1)) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            };
+            text.push(block);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// the screen as text, # for black and . for white, with a rectangle of
+// it picked out. Tests compare against these
+pub fn ascii(ram: &[i16], x: usize, y: usize, width: usize, height: usize) -> String {
+    let mut text = String::new();
+    for y in y..y + height {
+        for x in x..x + width {
+            text.push(if pixel(ram, x, y) { '#' } else { '.' });
+        }
+        writeln!(text).unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ram() -> Vec<i16> {
+        let mut ram = vec![0; 32768];
+        // the top left pixel and the one right of it, and the last pixel
+        ram[SCREEN] = 0b11;
+        ram[KEYBOARD - 1] = i16::MIN;
+        ram
+    }
+
+    #[test]
+    fn test_pixel() {
+        let ram = ram();
+        assert!(pixel(&ram, 0, 0));
+        assert!(pixel(&ram, 1, 0));
+        assert!(!pixel(&ram, 2, 0));
+        assert!(pixel(&ram, 511, 255));
+        assert_eq!(ascii(&ram, 0, 0, 4, 2), "##..\n....\n");
+    }
+
+    #[test]
+    fn test_pbm() {
+        let image = pbm(&ram());
+        let header = b"P4\n512 256\n";
+        assert_eq!(&image[..header.len()], header);
+        assert_eq!(image.len(), header.len() + 64 * 256);
+        assert_eq!(image[header.len()], 0b1100_0000);
+        assert_eq!(image[image.len() - 1], 1);
+    }
+
+    #[test]
+    fn test_png() {
+        let image = png(&ram());
+        assert_eq!(&image[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&image[12..16], b"IHDR");
+        // known values, the crc of an empty IEND chunk among them
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(&image[image.len() - 8..image.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn test_text() {
+        let ram = ram();
+        let braille = braille(&ram);
+        assert_eq!(braille.lines().count(), 64);
+        assert!(braille.starts_with('\u{2809}'));
+        let blocks = half_blocks(&ram);
+        assert_eq!(blocks.lines().count(), 128);
+        assert!(blocks.starts_with("▀▀ "));
+        assert!(blocks.lines().last().unwrap().ends_with('▄'));
+    }
+}
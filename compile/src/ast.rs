@@ -1,19 +1,51 @@
 use crate::tokenizer::*;
 use thiserror::Error;
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Class {
     pub name: String,
     pub vars: Vec<ClassVarDecl>,
+    pub consts: Vec<ConstDecl>,
     pub subroutines: Vec<Subroutine>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+// where the parts of a class came from in the source. The ast itself
+// has no positions, so the parser keeps them to one side, in the order
+// the parts were parsed: every class var declaration, const or enum,
+// subroutine and statement, depth first. The init and step of a for
+// are left out
+#[derive(Debug, Default)]
+pub struct Spans {
+    pub class: Span,
+    pub vars: Vec<Span>,
+    pub consts: Vec<Span>,
+    pub subroutines: Vec<Span>,
+    pub statements: Vec<Span>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct ClassVarDecl {
     pub decorator: ClassVarDecorator,
     pub type_name: Type,
     pub declarations: Vec<String>,
 }
 
+// compile time constants from the extended dialect, they take no RAM
+// and every use is replaced by the value
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ConstDecl {
+    // const type_name name = expr;
+    Const {
+        type_name: Type,
+        name: String,
+        expr: Expr,
+    },
+    // enum { name, name = expr, ... };
+    // members without a value are one more than the one before
+    Enum {
+        members: Vec<(String, Option<Expr>)>,
+    },
+}
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum ClassVarDecorator {
     Static,
@@ -27,7 +59,7 @@ pub enum Type {
     Class(String),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Subroutine {
     pub decorator: SubroutineDecorator,
     pub type_name: Option<Type>,
@@ -37,13 +69,13 @@ pub struct Subroutine {
     pub statements: Vec<Statement>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct SubroutineVarDecl {
     pub type_name: Type,
     pub declarations: Vec<String>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct ParamDecl {
     pub type_name: Type,
     pub name: String,
@@ -56,7 +88,7 @@ pub enum SubroutineDecorator {
     Method,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Statement {
     //let name[index]=expr;
     Let {
@@ -83,15 +115,34 @@ pub enum Statement {
     Return {
         expr: Option<Expr>,
     },
+    // the rest are only in the extended dialect
+    // let name[index] op= expr;
+    Compound {
+        name: String,
+        index: Option<Expr>,
+        op: Op,
+        expr: Expr,
+    },
+    // for (init; condition; step) {statements}
+    For {
+        init: Option<Box<Statement>>,
+        condition: Expr,
+        step: Option<Box<Statement>>,
+        statements: Vec<Statement>,
+    },
+    // break;
+    Break,
+    // continue;
+    Continue,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Expr {
     pub term: Box<Term>,
     pub ops: Vec<(Op, Term)>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Term {
     IntegerLit(u16),
     StringLit(String),
@@ -110,9 +161,16 @@ pub enum Term {
         name: String,
         exprs: Vec<Expr>,
     },
+    // Class.NAME, a constant from another class
+    Constant {
+        class: String,
+        name: String,
+    },
+    // left op right, only built when parsing with operator precedence
+    Binary(Box<Term>, Op, Box<Term>),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Op {
     Add,
     Sub,
@@ -123,9 +181,42 @@ pub enum Op {
     Lt,
     Gt,
     Eq,
+    // only produced by the optimizer, for the extended vm
+    ShiftLeft,
+    ShiftRight,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl Op {
+    // higher binds tighter. Standard Jack ignores this and goes
+    // strictly left to right
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Op::Mult | Op::Div | Op::ShiftLeft | Op::ShiftRight => 4,
+            Op::Add | Op::Sub => 3,
+            Op::Lt | Op::Gt | Op::Eq => 2,
+            Op::And => 1,
+            Op::Or => 0,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mult => "*",
+            Op::Div => "/",
+            Op::And => "&",
+            Op::Or => "|",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Eq => "=",
+            Op::ShiftLeft => "<<",
+            Op::ShiftRight => ">>",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -155,6 +246,8 @@ pub enum ParseError {
     MissingVariable,
     #[error("Missing equals in let statement")]
     MissingEquals,
+    #[error("Break or continue outside of a loop")]
+    NotInLoop,
     #[error("Do statements must only be a single subroutine call")]
     DoStatementMustBeSubroutineCall,
     #[error("No class declaration found")]
@@ -177,4 +270,6 @@ pub enum ParseError {
     DuplicatedFuncitonLevelVariable,
     #[error("Could not find symbol")]
     SymbolNotFound,
+    #[error("Constant value must be known at compile time")]
+    NotConstant,
 }
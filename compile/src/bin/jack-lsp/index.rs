@@ -0,0 +1,459 @@
+// where every name in a set of jack files is declared and used. What
+// each file declares, and the kind and type of each name, come from the
+// compiler's symbol table. Where comes from the parser's spans: within
+// its declaration, a name is declared by the first token of that name
+// that ends a declarator. A file that doesn't parse keeps whatever the
+// parser got through before the error. Every other identifier is then
+// resolved against the declarations
+use compile::{
+    ast::{ClassVarDecorator, Spans, SubroutineDecorator, Type},
+    symbol_table::{SubroutineVarDecorator, SymbolTable},
+    tokenizer::{Position, Span, Token, Tokenizer},
+};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Kind {
+    Class,
+    Subroutine(SubroutineDecorator),
+    Const,
+    ClassVar(ClassVarDecorator),
+    Arg,
+    Local,
+}
+
+pub struct Definition {
+    pub name: String,
+    pub kind: Kind,
+    // the type of variables and constants
+    pub type_name: Option<Type>,
+    pub class: String,
+    pub subroutine: Option<String>,
+    pub file: usize,
+    pub position: Position,
+}
+
+pub struct Reference {
+    pub definition: usize,
+    pub file: usize,
+    pub position: Position,
+    pub length: usize,
+}
+
+#[derive(Default)]
+pub struct Index {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+pub type Tokens = Vec<(Token, Position)>;
+
+// a file as the parser left it
+pub struct File {
+    pub tokens: Tokens,
+    // the class it declared, if the parser got that far
+    pub class: Option<String>,
+    pub spans: Spans,
+}
+
+pub fn tokenize(text: &str, extended: bool) -> Tokens {
+    let mut tokenizer = Tokenizer::new(text.chars().map(Ok), extended);
+    let mut tokens = Vec::new();
+    while let Some(Ok(token)) = tokenizer.next() {
+        tokens.push((token, tokenizer.start()));
+    }
+    tokens
+}
+
+impl Index {
+    pub fn new(files: &[File], symbol_table: &SymbolTable) -> Self {
+        let mut index = Self::default();
+        for (file, source) in files.iter().enumerate() {
+            index.add_definitions(file, source, symbol_table);
+        }
+        for (file, source) in files.iter().enumerate() {
+            index.add_references(file, &source.tokens);
+        }
+        index
+    }
+
+    fn add_definitions(&mut self, file: usize, source: &File, symbol_table: &SymbolTable) {
+        let Some(class) = &source.class else {
+            return;
+        };
+        let Some(table) = symbol_table.classes.get(class) else {
+            return;
+        };
+        let mut definitions = Vec::new();
+        let mut define = |name: &str,
+                          kind: Kind,
+                          type_name: Option<&Type>,
+                          subroutine: Option<&String>,
+                          position: Option<Position>| {
+            if let Some(position) = position {
+                definitions.push(Definition {
+                    name: name.to_string(),
+                    kind,
+                    type_name: type_name.cloned(),
+                    class: class.clone(),
+                    subroutine: subroutine.cloned(),
+                    file,
+                    position,
+                });
+            }
+        };
+        let tokens = &source.tokens;
+        // up to the line after the last token
+        let file_end = Position {
+            line: tokens.last().map_or(0, |(_, position)| position.line + 1),
+            column: 0,
+        };
+        let whole = [Span {
+            start: source.spans.class.start,
+            end: file_end,
+        }];
+
+        define(
+            class,
+            Kind::Class,
+            None,
+            None,
+            find(tokens, &whole, class, "{"),
+        );
+        for (name, (decorator, type_name, _)) in &table.class_vars {
+            let position = find(tokens, &source.spans.vars, name, ",;");
+            define(
+                name,
+                Kind::ClassVar(*decorator),
+                Some(type_name),
+                None,
+                position,
+            );
+        }
+        for (name, (type_name, _)) in &table.constants {
+            let position = find(tokens, &source.spans.consts, name, ",;=}");
+            define(name, Kind::Const, Some(type_name), None, position);
+        }
+        // a subroutine goes on to the next one, as its span is only
+        // finished once it's parsed
+        let starts = source.spans.subroutines.iter().map(|span| span.start);
+        let ends = starts.clone().skip(1).map(Some).chain([None]);
+        for ((name, subroutine), (start, end)) in table.subroutines.iter().zip(starts.zip(ends)) {
+            let span = [Span {
+                start,
+                end: end.unwrap_or(file_end),
+            }];
+            let kind = Kind::Subroutine(subroutine.decorator);
+            define(name, kind, None, None, find(tokens, &span, name, "("));
+            for (var, (decorator, type_name, _)) in &subroutine.vars {
+                let kind = match decorator {
+                    SubroutineVarDecorator::Arg => Kind::Arg,
+                    SubroutineVarDecorator::Local => Kind::Local,
+                };
+                let position = find(tokens, &span, var, ",;)");
+                define(var, kind, Some(type_name), Some(name), position);
+            }
+        }
+
+        definitions.sort_by_key(|definition| definition.position);
+        self.definitions.append(&mut definitions);
+    }
+
+    fn add_references(&mut self, file: usize, tokens: &Tokens) {
+        let class = self.class_of_file(file).unwrap_or_default();
+        let mut subroutine = None;
+        for (i, (token, position)) in tokens.iter().enumerate() {
+            let Token::Identifier(name) = token else {
+                continue;
+            };
+            if let Some(definition) = self.definition_at(file, *position) {
+                if let Kind::Subroutine(_) = self.definitions[definition].kind {
+                    subroutine = Some(self.definitions[definition].name.clone());
+                }
+                continue;
+            }
+
+            let previous = i.checked_sub(1).map(|i| &tokens[i].0);
+            let next = tokens.get(i + 1).map(|(token, _)| token);
+            let definition = match (previous, next) {
+                // qualifier.name, where the qualifier is a variable or a class
+                (Some(Token::Symbol('.')), _) => match i.checked_sub(2).map(|i| &tokens[i].0) {
+                    Some(Token::Identifier(qualifier)) => self
+                        .qualifier_class(&class, subroutine.as_deref(), qualifier)
+                        .and_then(|target| self.member(&target, name)),
+                    _ => None,
+                },
+                (_, Some(Token::Symbol('('))) => self.member(&class, name),
+                _ => self
+                    .variable(&class, subroutine.as_deref(), name)
+                    .or_else(|| self.class(name)),
+            };
+            if let Some(definition) = definition {
+                self.references.push(Reference {
+                    definition,
+                    file,
+                    position: *position,
+                    length: name.len(),
+                });
+            }
+        }
+    }
+
+    pub fn class_of_file(&self, file: usize) -> Option<String> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.file == file && definition.kind == Kind::Class)
+            .map(|definition| definition.name.clone())
+    }
+
+    // the subroutine a position is in, which is the last one declared
+    // before it
+    pub fn subroutine_at(&self, file: usize, position: Position) -> Option<String> {
+        self.definitions
+            .iter()
+            .rev()
+            .find(|definition| {
+                definition.file == file
+                    && definition.position < position
+                    && matches!(definition.kind, Kind::Subroutine(_))
+            })
+            .map(|definition| definition.name.clone())
+    }
+
+    fn find(&self, f: impl Fn(&Definition) -> bool) -> Option<usize> {
+        self.definitions.iter().position(f)
+    }
+
+    pub fn class(&self, name: &str) -> Option<usize> {
+        self.find(|definition| definition.kind == Kind::Class && definition.name == name)
+    }
+
+    // subroutines and constants can be reached with Class.name
+    pub fn member(&self, class: &str, name: &str) -> Option<usize> {
+        self.find(|definition| {
+            definition.class == class
+                && definition.name == name
+                && matches!(definition.kind, Kind::Subroutine(_) | Kind::Const)
+        })
+    }
+
+    // locals and arguments hide class level names
+    pub fn variable(&self, class: &str, subroutine: Option<&str>, name: &str) -> Option<usize> {
+        self.find(|definition| {
+            definition.class == class
+                && definition.name == name
+                && matches!(definition.kind, Kind::Arg | Kind::Local)
+                && definition.subroutine.as_deref() == subroutine
+        })
+        .or_else(|| {
+            self.find(|definition| {
+                definition.class == class
+                    && definition.name == name
+                    && matches!(definition.kind, Kind::ClassVar(_) | Kind::Const)
+            })
+        })
+    }
+
+    // a qualifier is a variable of some class type, or else a class
+    pub fn qualifier_class(
+        &self,
+        class: &str,
+        subroutine: Option<&str>,
+        qualifier: &str,
+    ) -> Option<String> {
+        match self.variable(class, subroutine, qualifier) {
+            Some(variable) => match &self.definitions[variable].type_name {
+                Some(Type::Class(class)) => Some(class.clone()),
+                _ => None,
+            },
+            None => Some(qualifier.to_string()),
+        }
+    }
+
+    pub fn definition_at(&self, file: usize, position: Position) -> Option<usize> {
+        self.find(|definition| {
+            definition.file == file && covers(definition.position, definition.name.len(), position)
+        })
+    }
+
+    // the definition of the name at a position, whether that's where
+    // it's declared or where it's used
+    pub fn lookup(&self, file: usize, position: Position) -> Option<usize> {
+        self.definition_at(file, position).or_else(|| {
+            self.references
+                .iter()
+                .find(|reference| {
+                    reference.file == file && covers(reference.position, reference.length, position)
+                })
+                .map(|reference| reference.definition)
+        })
+    }
+
+    pub fn references_to(&self, definition: usize) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.definition == definition)
+    }
+}
+
+fn covers(start: Position, length: usize, position: Position) -> bool {
+    start.line == position.line
+        && start.column <= position.column
+        && position.column <= start.column + length
+}
+
+// where a name is declared: the first token of that name inside the spans
+// that's followed by one of the symbols that can end its declarator, and
+// isn't a qualified Class.name
+fn find(tokens: &Tokens, spans: &[Span], name: &str, ends: &str) -> Option<Position> {
+    let inside = |position: Position| {
+        spans
+            .iter()
+            .any(|span| span.start <= position && position < span.end)
+    };
+    tokens
+        .iter()
+        .enumerate()
+        .find_map(|(i, (token, position))| {
+            let declared = *token == Token::Identifier(name.to_string())
+                && inside(*position)
+                && matches!(tokens.get(i + 1), Some((Token::Symbol(c), _)) if ends.contains(*c))
+                && !matches!(
+                    i.checked_sub(1).map(|i| &tokens[i].0),
+                    Some(Token::Symbol('.'))
+                );
+            declared.then_some(*position)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use compile::{options::Options, parser::Parser};
+
+    fn index(sources: &[&str]) -> Index {
+        let options = Options {
+            extended: true,
+            ..Options::default()
+        };
+        let mut symbol_table = SymbolTable::new();
+        let mut files = Vec::new();
+        for source in sources {
+            let tokenizer = Tokenizer::new(source.chars().map(Ok), true);
+            let mut parser = Parser::new(tokenizer, options);
+            let class = parser
+                .parse_class(&mut symbol_table)
+                .ok()
+                .map(|class| class.name)
+                .or_else(|| symbol_table.classes.last().map(|(name, _)| name.clone()));
+            files.push(File {
+                tokens: tokenize(source, true),
+                class,
+                spans: parser.into_spans(),
+            });
+        }
+        Index::new(&files, &symbol_table)
+    }
+
+    fn at(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    // name, kind, subroutine and where, in the order of the file
+    fn definitions(index: &Index) -> Vec<(&str, Kind, Option<&str>, Position)> {
+        index
+            .definitions
+            .iter()
+            .map(|definition| {
+                (
+                    definition.name.as_str(),
+                    definition.kind,
+                    definition.subroutine.as_deref(),
+                    definition.position,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_definitions() {
+        let index = index(&["class Point {
+    field int x, y;
+    const int N = 2;
+    static Point origin;
+    enum { LEFT, RIGHT = LEFT + N };
+    method int plus(Point other, int n) {
+        var int x, sum;
+        let sum = x + other.x();
+        return sum;
+    }
+    function Point x() { var Point x; return x; }
+}"]);
+        use ClassVarDecorator::*;
+        use SubroutineDecorator::*;
+        assert_eq!(
+            definitions(&index),
+            [
+                ("Point", Kind::Class, None, at(0, 6)),
+                ("x", Kind::ClassVar(Field), None, at(1, 14)),
+                ("y", Kind::ClassVar(Field), None, at(1, 17)),
+                ("N", Kind::Const, None, at(2, 14)),
+                ("origin", Kind::ClassVar(Static), None, at(3, 17)),
+                ("LEFT", Kind::Const, None, at(4, 11)),
+                ("RIGHT", Kind::Const, None, at(4, 17)),
+                ("plus", Kind::Subroutine(Method), None, at(5, 15)),
+                ("other", Kind::Arg, Some("plus"), at(5, 26)),
+                ("n", Kind::Arg, Some("plus"), at(5, 37)),
+                ("x", Kind::Local, Some("plus"), at(6, 16)),
+                ("sum", Kind::Local, Some("plus"), at(6, 19)),
+                ("x", Kind::Subroutine(Function), None, at(10, 19)),
+                ("x", Kind::Local, Some("x"), at(10, 35)),
+            ]
+        );
+        let other = &index.definitions[8];
+        assert_eq!(other.type_name, Some(Type::Class("Point".to_string())));
+    }
+
+    #[test]
+    fn test_references() {
+        let index = index(&[
+            "class A {
+    function void f(B b) {
+        do b.g(B.N);
+        return;
+    }
+}",
+            "class B {
+    const int N = 1;
+    method void g(int n) { return; }
+}",
+        ]);
+        let reference = |line, column| {
+            let definition = &index.definitions[index.lookup(0, at(line, column)).unwrap()];
+            (definition.name.as_str(), definition.file)
+        };
+        assert_eq!(reference(1, 20), ("B", 1));
+        assert_eq!(reference(2, 11), ("b", 0));
+        assert_eq!(reference(2, 13), ("g", 1));
+        assert_eq!(reference(2, 17), ("N", 1));
+    }
+
+    // what was parsed before the error is still there
+    #[test]
+    fn test_parse_error() {
+        let index = index(&["class A {
+    field int x;
+    method void f(int n) {
+        var int i;
+        let i = ;
+    }
+    method void g() { return; }
+}"]);
+        assert_eq!(
+            definitions(&index)
+                .into_iter()
+                .map(|(name, ..)| name)
+                .collect::<Vec<_>>(),
+            ["A", "x", "f", "n", "i"]
+        );
+    }
+}
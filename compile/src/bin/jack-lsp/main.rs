@@ -0,0 +1,501 @@
+// a language server for jack, speaking LSP over stdin and stdout so any
+// editor can use it. Every request starts from a fresh look at the
+// directory the file is in, using the editor's text for open files and
+// the disk for the rest. Jack programs are a handful of small files,
+// so that's quick enough and nothing can go stale.
+// Diagnostics come from the compiler's own parser, and the index of
+// names from its symbol table and spans.
+// Files are told apart by their paths, as the same path can be written
+// as more than one uri
+mod index;
+mod protocol;
+
+use std::{
+    collections::HashMap,
+    env::args,
+    fs::read_to_string,
+    io::{stdin, stdout, BufReader},
+    path::{Path, PathBuf},
+};
+
+use compile::{
+    ast::{ClassVarDecorator, SubroutineDecorator, Type},
+    options::Options,
+    parser::Parser,
+    symbol_table::{RefType, SubroutineVarDecorator, SymbolTable},
+    tokenizer::{Position, Tokenizer},
+};
+use index::{File, Index, Kind};
+use protocol::*;
+use serde_json::{json, Value};
+
+struct Analysis {
+    uris: Vec<String>,
+    index: Index,
+    symbol_table: SymbolTable,
+    diagnostics: Vec<Vec<Value>>,
+}
+
+// analyze puts the file a request is about first
+const THIS_FILE: usize = 0;
+
+struct Server {
+    // the text of the files open in the editor, by path
+    documents: HashMap<PathBuf, String>,
+    options: Options,
+}
+
+fn main() -> std::io::Result<()> {
+    let mut options = Options::default();
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "-extended" => options.extended = true,
+            "-precedence" => options.precedence = true,
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+    let mut server = Server {
+        documents: HashMap::new(),
+        options,
+    };
+
+    let mut reader = BufReader::new(stdin().lock());
+    let mut writer = stdout().lock();
+    while let Some(incoming) = read_message(&mut reader)? {
+        let Incoming::Message(message) = incoming else {
+            write_message(
+                &mut writer,
+                &error_response(&Value::Null, PARSE_ERROR, "Parse error"),
+            )?;
+            continue;
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        if method == "exit" {
+            break;
+        }
+        for reply in server.handle(method, params, &message["id"]) {
+            write_message(&mut writer, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn handle(&mut self, method: &str, params: &Value, id: &Value) -> Vec<Value> {
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": true } },
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "jack-lsp" },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(params_path(params), text.to_string());
+                return self.publish_diagnostics(&params_uri(params));
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|changes| changes.last()?["text"].as_str()) {
+                    self.documents.insert(params_path(params), text.to_string());
+                }
+                return Vec::new();
+            }
+            "textDocument/didSave" => {
+                if let Some(text) = params["text"].as_str() {
+                    self.documents.insert(params_path(params), text.to_string());
+                }
+                return self.publish_diagnostics(&params_uri(params));
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&params_path(params));
+                return Vec::new();
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ if id.is_null() => return Vec::new(),
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Unknown method {}", method),
+                )]
+            }
+        };
+        vec![response(id, result)]
+    }
+
+    // the file at uri, which is always THIS_FILE, along with the
+    // other jack files next to it
+    fn analyze(&self, uri: &str) -> Analysis {
+        let path = uri_to_path(uri);
+        let mut paths = vec![path.clone()];
+        if let Ok(entries) = path.parent().unwrap_or(&path).read_dir() {
+            let mut others = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|other| other.extension().is_some_and(|extn| extn == "jack"))
+                .filter(|other| *other != path)
+                .collect::<Vec<_>>();
+            others.sort();
+            paths.append(&mut others);
+        }
+
+        let mut symbol_table = SymbolTable::new();
+        let mut files = Vec::new();
+        let mut diagnostics = Vec::new();
+        for path in &paths {
+            let text = self.text(path);
+            let tokenizer = Tokenizer::new(text.chars().map(Ok), self.options.extended);
+            let mut parser = Parser::new(tokenizer, self.options);
+            let classes = symbol_table.classes.len();
+            let result = parser.parse_class(&mut symbol_table);
+            // the class is entered as soon as it's named
+            let class = (symbol_table.classes.len() > classes)
+                .then(|| symbol_table.classes.last().map(|(name, _)| name.clone()))
+                .flatten();
+            diagnostics.push(match result {
+                Ok(_) => Vec::new(),
+                Err(error) => vec![json!({
+                    "range": range(parser.position(), 1),
+                    "severity": 1,
+                    "source": "jack",
+                    "message": error.to_string(),
+                })],
+            });
+            files.push(File {
+                tokens: index::tokenize(&text, self.options.extended),
+                class,
+                spans: parser.into_spans(),
+            });
+        }
+
+        // the editor's own uri for the file it asked about
+        let mut uris = vec![uri.to_string()];
+        uris.extend(paths[1..].iter().map(|path| path_to_uri(path)));
+        Analysis {
+            uris,
+            index: Index::new(&files, &symbol_table),
+            symbol_table,
+            diagnostics,
+        }
+    }
+
+    // the editor's text for open files, and the disk for the rest
+    fn text(&self, path: &Path) -> String {
+        match self.documents.get(path) {
+            Some(text) => text.clone(),
+            None => read_to_string(path).unwrap_or_default(),
+        }
+    }
+
+    // the whole directory, since a change to one class can break another
+    fn publish_diagnostics(&self, uri: &str) -> Vec<Value> {
+        let analysis = self.analyze(uri);
+        analysis
+            .uris
+            .iter()
+            .zip(analysis.diagnostics)
+            .map(|(uri, diagnostics)| {
+                notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": diagnostics }),
+                )
+            })
+            .collect()
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let analysis = self.analyze(&params_uri(params));
+        match analysis.index.lookup(THIS_FILE, params_position(params)) {
+            Some(definition) => {
+                let definition = &analysis.index.definitions[definition];
+                location(
+                    &analysis.uris[definition.file],
+                    definition.position,
+                    definition.name.len(),
+                )
+            }
+            None => Value::Null,
+        }
+    }
+
+    fn references(&self, params: &Value) -> Value {
+        let analysis = self.analyze(&params_uri(params));
+        let Some(id) = analysis.index.lookup(THIS_FILE, params_position(params)) else {
+            return Value::Null;
+        };
+        let definition = &analysis.index.definitions[id];
+        let mut locations = Vec::new();
+        if params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true)
+        {
+            locations.push(location(
+                &analysis.uris[definition.file],
+                definition.position,
+                definition.name.len(),
+            ));
+        }
+        for reference in analysis.index.references_to(id) {
+            locations.push(location(
+                &analysis.uris[reference.file],
+                reference.position,
+                reference.length,
+            ));
+        }
+        Value::Array(locations)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let analysis = self.analyze(&params_uri(params));
+        let Some(id) = analysis.index.lookup(THIS_FILE, params_position(params)) else {
+            return Value::Null;
+        };
+        let definition = &analysis.index.definitions[id];
+        let table = analysis.symbol_table.classes.get(&definition.class);
+        let text = match definition.kind {
+            Kind::Class => match table {
+                Some(table) => format!("class {} ({} fields)", definition.name, table.field_number),
+                None => format!("class {}", definition.name),
+            },
+            Kind::Subroutine(decorator) => {
+                let params = analysis
+                    .index
+                    .definitions
+                    .iter()
+                    .filter(|param| {
+                        param.kind == Kind::Arg
+                            && param.class == definition.class
+                            && param.subroutine.as_ref() == Some(&definition.name)
+                    })
+                    .map(|param| {
+                        format!("{} {}", type_name_or_unknown(&param.type_name), param.name)
+                    })
+                    .collect::<Vec<_>>();
+                let return_type = table
+                    .and_then(|table| table.subroutines.get(&definition.name))
+                    .map(|subroutine| return_type_name(&subroutine.type_name))
+                    .unwrap_or("?".to_string());
+                format!(
+                    "{} {} {}.{}({})",
+                    decorator_name(decorator),
+                    return_type,
+                    definition.class,
+                    definition.name,
+                    params.join(", ")
+                )
+            }
+            Kind::Const => match table.and_then(|table| table.constants.get(&definition.name)) {
+                Some((type_name, value)) => {
+                    format!(
+                        "const {} {} = {}",
+                        type_name_of(type_name),
+                        definition.name,
+                        value
+                    )
+                }
+                None => format!("const {}", definition.name),
+            },
+            Kind::ClassVar(_) | Kind::Arg | Kind::Local => {
+                let var = match definition.kind {
+                    Kind::ClassVar(_) => table
+                        .and_then(|table| table.class_vars.get(&definition.name))
+                        .map(|(decorator, type_name, number)| {
+                            (
+                                RefType::ClassRefType(*decorator),
+                                type_name.clone(),
+                                *number,
+                            )
+                        }),
+                    _ => analysis
+                        .symbol_table
+                        .lookup_var(
+                            &definition.class,
+                            definition.subroutine.as_deref().unwrap_or_default(),
+                            &definition.name,
+                        )
+                        .ok(),
+                };
+                match var {
+                    Some((ref_type, type_name, number)) => {
+                        let (kind, segment) = ref_type_names(&ref_type);
+                        format!(
+                            "{} {} {} ({} {})",
+                            kind,
+                            type_name_of(&type_name),
+                            definition.name,
+                            segment,
+                            number
+                        )
+                    }
+                    None => format!(
+                        "{} {}",
+                        type_name_or_unknown(&definition.type_name),
+                        definition.name
+                    ),
+                }
+            }
+        };
+        json!({ "contents": { "kind": "markdown", "value": format!("```jack\n{}\n```", text) } })
+    }
+
+    // members of the class before the dot. Through a variable that's the
+    // methods of its type, otherwise the functions, constructors and constants
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params_uri(params);
+        let position = params_position(params);
+        let analysis = self.analyze(&uri);
+        let text = self.text(&uri_to_path(&uri));
+        let line = text.lines().nth(position.line).unwrap_or_default();
+        let before = line.chars().take(position.column).collect::<String>();
+        let before = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        let Some(before) = before.strip_suffix('.') else {
+            return json!([]);
+        };
+        let qualifier_start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let qualifier = &before[qualifier_start..];
+
+        let class = analysis.index.class_of_file(THIS_FILE).unwrap_or_default();
+        let subroutine = analysis.index.subroutine_at(THIS_FILE, position);
+        let through_variable = analysis
+            .index
+            .variable(&class, subroutine.as_deref(), qualifier)
+            .is_some();
+        let Some(target) = analysis
+            .index
+            .qualifier_class(&class, subroutine.as_deref(), qualifier)
+        else {
+            return json!([]);
+        };
+        let Some(table) = analysis.symbol_table.classes.get(&target) else {
+            return json!([]);
+        };
+
+        let mut items = Vec::new();
+        for (name, subroutine) in &table.subroutines {
+            let is_method = subroutine.decorator == SubroutineDecorator::Method;
+            if is_method != through_variable {
+                continue;
+            }
+            let kind = match subroutine.decorator {
+                SubroutineDecorator::Constructor => 4,
+                SubroutineDecorator::Function => 3,
+                SubroutineDecorator::Method => 2,
+            };
+            items.push(json!({
+                "label": name,
+                "kind": kind,
+                "detail": format!(
+                    "{} {}",
+                    decorator_name(subroutine.decorator),
+                    return_type_name(&subroutine.type_name)
+                ),
+            }));
+        }
+        if !through_variable {
+            for (name, (type_name, value)) in &table.constants {
+                items.push(json!({
+                    "label": name,
+                    "kind": 21,
+                    "detail": format!("const {} = {}", type_name_of(type_name), value),
+                }));
+            }
+        }
+        items.sort_by(|a, b| a["label"].as_str().cmp(&b["label"].as_str()));
+        Value::Array(items)
+    }
+
+    // the class with its members underneath
+    fn document_symbols(&self, params: &Value) -> Value {
+        let analysis = self.analyze(&params_uri(params));
+        let symbol = |name: &str, kind: usize, position: Position, children: Vec<Value>| {
+            json!({
+                "name": name,
+                "kind": kind,
+                "range": range(position, name.len()),
+                "selectionRange": range(position, name.len()),
+                "children": children,
+            })
+        };
+
+        let definitions = analysis
+            .index
+            .definitions
+            .iter()
+            .filter(|definition| definition.file == THIS_FILE);
+        let mut class = None;
+        let mut members = Vec::new();
+        for definition in definitions {
+            let kind = match definition.kind {
+                Kind::Class => {
+                    class = Some(definition);
+                    continue;
+                }
+                Kind::Subroutine(SubroutineDecorator::Constructor) => 9,
+                Kind::Subroutine(SubroutineDecorator::Function) => 12,
+                Kind::Subroutine(SubroutineDecorator::Method) => 6,
+                Kind::Const => 14,
+                Kind::ClassVar(ClassVarDecorator::Field) => 8,
+                Kind::ClassVar(ClassVarDecorator::Static) => 13,
+                Kind::Arg | Kind::Local => continue,
+            };
+            members.push(symbol(
+                &definition.name,
+                kind,
+                definition.position,
+                Vec::new(),
+            ));
+        }
+        match class {
+            Some(class) => json!([symbol(&class.name, 5, class.position, members)]),
+            None => Value::Array(members),
+        }
+    }
+}
+
+fn decorator_name(decorator: SubroutineDecorator) -> &'static str {
+    match decorator {
+        SubroutineDecorator::Constructor => "constructor",
+        SubroutineDecorator::Function => "function",
+        SubroutineDecorator::Method => "method",
+    }
+}
+
+fn type_name_of(type_name: &Type) -> String {
+    match type_name {
+        Type::Int => "int".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Class(class) => class.clone(),
+    }
+}
+
+fn type_name_or_unknown(type_name: &Option<Type>) -> String {
+    type_name.as_ref().map_or("?".to_string(), type_name_of)
+}
+
+fn return_type_name(type_name: &Option<Type>) -> String {
+    type_name.as_ref().map_or("void".to_string(), type_name_of)
+}
+
+// the kind of variable and the vm segment it lives in
+fn ref_type_names(ref_type: &RefType) -> (&'static str, &'static str) {
+    match ref_type {
+        RefType::ClassRefType(ClassVarDecorator::Static) => ("static", "static"),
+        RefType::ClassRefType(ClassVarDecorator::Field) => ("field", "this"),
+        RefType::SubroutineRefType(SubroutineVarDecorator::Arg) => ("arg", "argument"),
+        RefType::SubroutineRefType(SubroutineVarDecorator::Local) => ("local", "local"),
+    }
+}
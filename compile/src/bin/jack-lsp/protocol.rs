@@ -0,0 +1,171 @@
+// the transport side of the language server protocol: json messages
+// with a Content-Length header, one after another on stdin and stdout
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use compile::tokenizer::Position;
+use serde_json::{json, Value};
+
+// json-rpc error codes
+pub const PARSE_ERROR: i32 = -32700;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+
+// a message, or one that couldn't be read, either for a missing
+// Content-Length or a body that isn't json
+pub enum Incoming {
+    Message(Value),
+    Malformed,
+}
+
+// None at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> std::io::Result<Option<Incoming>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Ok(Some(Incoming::Malformed));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(match serde_json::from_slice(&body) {
+        Ok(message) => Incoming::Message(message),
+        Err(_) => Incoming::Malformed,
+    }))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+pub fn response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_response(id: &Value, code: i32, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+pub fn position(position: Position) -> Value {
+    json!({ "line": position.line, "character": position.column })
+}
+
+// a name's range, which never spans lines
+pub fn range(start: Position, length: usize) -> Value {
+    let end = Position {
+        column: start.column + length,
+        ..start
+    };
+    json!({ "start": position(start), "end": position(end) })
+}
+
+pub fn location(uri: &str, start: Position, length: usize) -> Value {
+    json!({ "uri": uri, "range": range(start, length) })
+}
+
+pub fn params_position(params: &Value) -> Position {
+    Position {
+        line: params["position"]["line"].as_u64().unwrap_or(0) as usize,
+        column: params["position"]["character"].as_u64().unwrap_or(0) as usize,
+    }
+}
+
+pub fn params_uri(params: &Value) -> String {
+    params["textDocument"]["uri"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+// the same file can have more than one uri, as editors escape
+// different characters, but it has just the one path
+pub fn params_path(params: &Value) -> PathBuf {
+    uri_to_path(&params_uri(params))
+}
+
+// only file uris are supported. Percent escapes are decoded a byte at a time
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(input: &str) -> Vec<Option<Value>> {
+        let mut reader = input.as_bytes();
+        let mut messages = Vec::new();
+        while let Some(incoming) = read_message(&mut reader).unwrap() {
+            messages.push(match incoming {
+                Incoming::Message(message) => Some(message),
+                Incoming::Malformed => None,
+            });
+        }
+        messages
+    }
+
+    #[test]
+    fn test_read_message() {
+        let input = "Content-Length: 2\r\n\r\n{}\
+            Content-Length: 3\r\n\r\n{x}\
+            Content-Type: text\r\n\r\n\
+            content-length: 8\r\n\r\n[1,2,3]\n";
+        assert_eq!(
+            read_all(input),
+            vec![Some(json!({})), None, None, Some(json!([1, 2, 3]))]
+        );
+        assert!(read_all("").is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+// compiles jack all the way to a .hack file: each class to vm code, the
+// vm code together with the os classes the program doesn't define itself
+// to assembly, and the assembly to machine code. The os is the one built
+// into the vm translator unless a directory with another one is given.
+// Only the .hack file is written unless it's asked to stop early or keep
+// what's in between
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::HashSet,
+    env::args,
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use compile::{
+    emitter::Emitter, optimizer, options::Options, parser::Parser, symbol_table::SymbolTable,
+    tokenizer::Tokenizer,
+};
+use vm::{emitter::EmitOptions, files, stats};
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Stage {
+    Vm,
+    Asm,
+    Hack,
+}
+
+fn main() -> Result<()> {
+    if args().len() < 2 {
+        println!("missing file name");
+        return Ok(());
+    }
+    let args = args().collect::<Vec<_>>();
+    let mut options = Options::default();
+    let mut emit_options = EmitOptions::default();
+    let mut os = None;
+    let mut stop = Stage::Hack;
+    let mut keep = false;
+    let mut link_os = true;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-stop=vm" => stop = Stage::Vm,
+            "-stop=asm" => stop = Stage::Asm,
+            "-stop=hack" => stop = Stage::Hack,
+            "-keep" => keep = true,
+            "-no-os" => link_os = false,
+            "-extended-vm" => options.extended_vm = true,
+            "-extended" => options.extended = true,
+            "-precedence" => options.precedence = true,
+            "-optimize" => options.optimize = true,
+            "-short-circuit" => options.short_circuit = true,
+            "-checks" => options.checks = true,
+            "-short-compare" => emit_options.short_compare = true,
+            "-shared-compare" => emit_options.shared_compare = true,
+            _ if arg.starts_with("-os=") => os = Some(PathBuf::from(&arg["-os=".len()..])),
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+    let (input_files, base) = files::input_files(Path::new(&args[1]), ".jack");
+
+    // the os classes the program doesn't define itself, from jack source
+    // where there is some and otherwise as vm code
+    let mut os_sources = Vec::new();
+    let mut os_vm = Vec::new();
+    if let Some(os) = os.as_ref().filter(|_| link_os) {
+        let classes = input_files
+            .iter()
+            .map(|path| class_name(path))
+            .collect::<HashSet<_>>();
+        let (sources, _) = files::input_files(os, ".jack");
+        let (vm_files, _) = files::input_files(os, ".vm");
+        for path in sources {
+            if !classes.contains(&class_name(&path)) {
+                os_sources.push(path);
+            }
+        }
+        for path in vm_files {
+            let class = class_name(&path);
+            if !classes.contains(&class)
+                && !os_sources.iter().any(|source| class_name(source) == class)
+            {
+                os_vm.push(path);
+            }
+        }
+    }
+
+    // compile
+    // the os does raw memory accesses of its own, so it's never checked
+    let os_options = Options {
+        checks: false,
+        ..options
+    };
+    let sources = input_files
+        .iter()
+        .map(|path| (path, options))
+        .chain(os_sources.iter().map(|path| (path, os_options)))
+        .collect::<Vec<_>>();
+    let vm_files = compile_files(&sources)?;
+    if stop == Stage::Vm || keep {
+        for (path, vm) in &vm_files[..input_files.len()] {
+            println!("Creating {}", path.to_string_lossy());
+            fs::write(path, vm)?;
+        }
+    }
+    if stop == Stage::Vm {
+        return Ok(());
+    }
+
+    // link
+    let mut program = Vec::new();
+    for (path, vm) in vm_files {
+        program.push((class_name(&path), vm));
+    }
+    for path in os_vm {
+        println!("Linking {}", path.to_string_lossy());
+        program.push((class_name(&path), fs::read_to_string(&path)?));
+    }
+
+    // translate
+    let mut translated = Vec::new();
+    for (class, vm) in program {
+        let commands = match vm::parser::parse_lines(vm.lines(), options.extended_vm) {
+            Ok(commands) => commands,
+            Err(errors) => bail!("{}.vm: {:?}", class, errors),
+        };
+        let commands = if options.optimize {
+            vm::optimizer::optimize(commands)
+        } else {
+            commands
+        };
+        translated.push((class, commands));
+    }
+    // without an os directory the one built into the vm translator is used
+    if os.is_none() && link_os {
+        let classes = translated
+            .iter()
+            .map(|(class, _)| class.clone())
+            .collect::<Vec<_>>();
+        println!("Linking the built in OS");
+        for (class, commands) in vm::os::link(&classes) {
+            let commands = if options.optimize {
+                vm::optimizer::optimize(commands)
+            } else {
+                commands
+            };
+            translated.push((class, commands));
+        }
+    }
+    let translated = vm::linker::remove_unreachable(translated, "Sys.init");
+    let asm = vm::emitter::emit_program(&translated, true, emit_options);
+    let size = stats::count_instructions(&asm);
+    if size > stats::ROM_SIZE {
+        bail!(
+            "Program is {} instructions but the ROM only holds {}",
+            size,
+            stats::ROM_SIZE
+        );
+    }
+    if stop == Stage::Asm || keep {
+        write_lines(&base.with_extension("asm"), &asm)?;
+    }
+    if stop == Stage::Asm {
+        return Ok(());
+    }
+
+    // assemble
+    let instructions = match asm::parser::parse_lines(&asm) {
+        Ok(instructions) => instructions,
+        Err(errors) => bail!("assembly: {:?}", errors),
+    };
+    let symbol_table = asm::analyzer::analyze(instructions.iter());
+    let codes = asm::emitter::emit_instructions(instructions.iter(), &symbol_table);
+    let hack = codes
+        .into_iter()
+        .map(asm::emitter::bits)
+        .collect::<Vec<_>>();
+    write_lines(&base.with_extension("hack"), &hack)?;
+    Ok(())
+}
+
+// every class as vm code, along with the X.vm it would be written to
+fn compile_files(sources: &[(&PathBuf, Options)]) -> Result<Vec<(PathBuf, String)>> {
+    let mut symbol_table = SymbolTable::new();
+    let mut classes = Vec::new();
+    for &(path, options) in sources {
+        println!("Compiling {}", path.to_string_lossy());
+        let source = fs::read_to_string(path)?;
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        let mut parser = Parser::new(tokens, options);
+        let class = parser
+            .parse_class(&mut symbol_table)
+            .with_context(|| path.to_string_lossy().to_string())?;
+        for warning in parser.warnings() {
+            println!("Warning: {}", warning);
+        }
+        classes.push((path.with_extension("vm"), class, options));
+    }
+
+    let mut vm_files = Vec::new();
+    for (path, class, options) in classes {
+        let class = if options.optimize {
+            optimizer::optimize_class(class, options)
+        } else {
+            class
+        };
+        let mut vm = Vec::new();
+        let mut emitter = Emitter::new(BufWriter::new(&mut vm), &symbol_table, options);
+        emitter.emit_class(&class)?;
+        for warning in emitter.warnings() {
+            println!("Warning: {}", warning);
+        }
+        drop(emitter);
+        vm_files.push((path, String::from_utf8(vm)?));
+    }
+    Ok(vm_files)
+}
+
+fn class_name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<()> {
+    println!("Creating {}", path.to_string_lossy());
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(path, text)?;
+    Ok(())
+}
@@ -0,0 +1,633 @@
+// a source level debugger for jack programs. It compiles the program's
+// classes, links them with the os built into vm and runs the vm code in
+// vm's interpreter, keeping track of which jack line each vm command
+// came from. Commands are read from stdin, one a line, and work much
+// like gdb's
+use anyhow::{Context, Result};
+use std::{
+    env::args,
+    fs,
+    io::{stdin, stdout, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use compile::{
+    ast::{ClassVarDecorator, SubroutineDecorator, Type},
+    debug_info::DebugInfo,
+    options::Options,
+    run::{self, Program},
+    symbol_table::{RefType, SubroutineVarDecorator, SymbolTable},
+};
+use vm::{
+    files,
+    interpreter::{Frame, Machine},
+    printer::print_command,
+};
+
+// how many commands run without reaching a breakpoint before giving up,
+// as a program waiting for a key never gets one
+const STEP_LIMIT: usize = 100_000_000;
+
+// a compiled class, with the jack line each of its vm lines came from
+struct Source {
+    info: DebugInfo,
+    text: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Continue,
+    Step,
+    Next,
+    Finish,
+    Instruction,
+}
+
+struct Debugger {
+    program: Program,
+    // the program loaded but never run, to look things up in
+    code: Machine,
+    // the program's own classes, which come first in the program
+    sources: Vec<Source>,
+    symbol_table: SymbolTable,
+    machine: Option<Machine>,
+    // the command each breakpoint is on, None once it's deleted
+    breakpoints: Vec<Option<usize>>,
+    // which frame print and info look at, 0 being the innermost
+    frame: usize,
+}
+
+fn main() -> Result<()> {
+    if args().len() < 2 {
+        println!("missing file name");
+        return Ok(());
+    }
+    let args = args().collect::<Vec<_>>();
+    let mut options = Options::default();
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-extended-vm" => options.extended_vm = true,
+            "-extended" => options.extended = true,
+            "-precedence" => options.precedence = true,
+            "-short-circuit" => options.short_circuit = true,
+            "-checks" => options.checks = true,
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+    let (input_files, _) = files::input_files(Path::new(&args[1]), ".jack");
+    let mut debugger = Debugger::new(&input_files, options)?;
+
+    let mut line = String::new();
+    loop {
+        print!("(jackdbg) ");
+        stdout().flush()?;
+        line.clear();
+        if stdin().lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if words
+            .first()
+            .is_some_and(|&word| word == "quit" || word == "q")
+        {
+            return Ok(());
+        }
+        debugger.execute(&words);
+    }
+}
+
+impl Debugger {
+    fn new(input_files: &[PathBuf], options: Options) -> Result<Self> {
+        let (program, debug_info, symbol_table) = run::compile_jack(input_files, options)?;
+        let mut sources = Vec::new();
+        for (path, info) in input_files.iter().zip(debug_info) {
+            let text = fs::read_to_string(path)
+                .with_context(|| path.to_string_lossy().to_string())?
+                .lines()
+                .map(|line| line.to_string())
+                .collect();
+            sources.push(Source { info, text });
+        }
+
+        Ok(Self {
+            code: Machine::new(&program),
+            program,
+            sources,
+            symbol_table,
+            machine: None,
+            breakpoints: Vec::new(),
+            frame: 0,
+        })
+    }
+
+    fn execute(&mut self, words: &[&str]) {
+        match words {
+            [] => (),
+            ["run" | "r"] => {
+                let mut machine = Machine::new(&self.program);
+                if let Err(error) = machine.bootstrap() {
+                    println!("Error: {:?}", error);
+                    return;
+                }
+                self.machine = Some(machine);
+                self.resume(Mode::Continue);
+            }
+            ["continue" | "c"] => self.resume(Mode::Continue),
+            ["step" | "s"] => self.resume(Mode::Step),
+            ["next" | "n"] => self.resume(Mode::Next),
+            ["finish"] => self.resume(Mode::Finish),
+            ["stepi" | "si"] => self.resume(Mode::Instruction),
+            ["break" | "b", location] => self.add_breakpoint(location),
+            ["delete" | "d"] => self.breakpoints.iter_mut().for_each(|b| *b = None),
+            ["delete" | "d", number] => match number.parse::<usize>() {
+                Ok(number) if self.breakpoints.get(number.wrapping_sub(1)).is_some() => {
+                    self.breakpoints[number - 1] = None
+                }
+                _ => println!("No breakpoint number {}.", number),
+            },
+            ["backtrace" | "bt"] => self.backtrace(),
+            ["frame" | "f", number] => match (number.parse::<usize>(), self.frames()) {
+                (Ok(number), Some(frames)) if number < frames.len() => {
+                    self.frame = number;
+                    self.print_frame(number, &frames[number]);
+                }
+                _ => println!("No frame {}.", number),
+            },
+            ["print" | "p", name] => self.print(name),
+            ["info", "locals"] => self.info(Some(SubroutineVarDecorator::Local), None),
+            ["info", "args"] => self.info(Some(SubroutineVarDecorator::Arg), None),
+            ["info", "fields"] => self.info(None, Some(ClassVarDecorator::Field)),
+            ["info", "statics"] => self.info(None, Some(ClassVarDecorator::Static)),
+            ["info", "breakpoints"] => {
+                for (i, pc) in self.breakpoints.iter().enumerate() {
+                    if let Some(pc) = pc {
+                        println!("{}: {}", i + 1, self.describe(*pc));
+                    }
+                }
+            }
+            ["list" | "l"] => self.list(),
+            ["help" | "h"] => println!(
+                "run, continue, step, next, finish, stepi, break Class.subroutine, \
+                 break File.jack:line, delete [n], backtrace, frame n, print name, \
+                 print name[i], info locals|args|fields|statics|breakpoints, list, quit"
+            ),
+            _ => println!("Unknown command {}. Try help.", words.join(" ")),
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) {
+        let Some(machine) = &self.machine else {
+            println!("The program is not being run.");
+            return;
+        };
+        if machine.halted {
+            println!("The program has halted.");
+            return;
+        }
+        // stopped at a subroutine's entry, its first statement is the
+        // line being shown, so stepping goes on from that
+        let start_line = self.line(self.first_statement(machine.pc));
+        let start_depth = machine.depth;
+        let function = machine.function_name(machine.pc).unwrap_or("").to_string();
+        if let Mode::Finish = mode {
+            if start_depth <= 1 {
+                println!("\"finish\" not meaningful in the outermost frame.");
+                return;
+            }
+            println!("Run till exit from {}", function);
+        }
+        self.frame = 0;
+
+        for count in 0.. {
+            let machine = self.machine.as_mut().unwrap();
+            if count == STEP_LIMIT {
+                println!("Still running after {} commands", STEP_LIMIT);
+                break;
+            }
+            if let Err(error) = machine.step() {
+                println!("Error: {:?}", error);
+                break;
+            }
+            if machine.halted {
+                println!("Program halted");
+                return;
+            }
+            let (pc, depth) = (machine.pc, machine.depth);
+            if let Some(number) = self.breakpoints.iter().position(|&b| b == Some(pc)) {
+                // a subroutine's breakpoint is on its entry, so the prologue
+                // runs before stopping, to set up this and the locals
+                let statement = self.first_statement(pc);
+                let machine = self.machine.as_mut().unwrap();
+                while machine.pc != statement {
+                    if let Err(error) = machine.step() {
+                        println!("Error: {:?}", error);
+                        return;
+                    }
+                }
+                println!("Breakpoint {}, {}", number + 1, self.describe(pc));
+                self.print_line(pc);
+                return;
+            }
+            let line = self.line(pc);
+            let stop = match mode {
+                Mode::Continue => false,
+                Mode::Instruction => true,
+                Mode::Step => line.is_some() && (line != start_line || depth != start_depth),
+                Mode::Next => {
+                    line.is_some()
+                        && depth <= start_depth
+                        && (line != start_line || depth < start_depth)
+                }
+                Mode::Finish => depth < start_depth,
+            };
+            if stop {
+                break;
+            }
+        }
+
+        let machine = self.machine.as_ref().unwrap();
+        let pc = machine.pc;
+        match mode {
+            Mode::Instruction => {
+                if let Some(command) = machine.command(pc) {
+                    println!("{}: {}", self.describe(pc), print_command(command));
+                }
+            }
+            Mode::Finish => {
+                println!("{}", self.describe(pc));
+                self.print_line(pc);
+                if self.returns_value(&function) {
+                    let sp = machine.ram[0] as usize;
+                    println!("Value returned is {}", machine.ram[sp - 1]);
+                }
+            }
+            _ => {
+                println!("{}", self.describe(pc));
+                self.print_line(pc);
+            }
+        }
+    }
+
+    // the jack line of the statement a command is code for
+    fn line(&self, pc: usize) -> Option<(usize, usize)> {
+        let (file, index) = self.code.origin(pc)?;
+        let line = self.sources.get(file)?.info.lines[index];
+        Some((file, line.line)).filter(|_| line.statement)
+    }
+
+    // the jack line a command came from, counting the start of a
+    // subroutine as the line of its first statement, or the line it's
+    // declared on when it has none
+    fn source_line(&self, pc: usize) -> Option<(usize, usize)> {
+        let (file, index) = self.code.origin(self.first_statement(pc))?;
+        Some((file, self.sources.get(file)?.info.lines[index].line))
+    }
+
+    // the first statement of a subroutine for its entry, any other
+    // command for itself
+    fn first_statement(&self, pc: usize) -> usize {
+        let Some(function) = self.code.function_name(pc) else {
+            return pc;
+        };
+        if self.code.function(function) != Some(pc) {
+            return pc;
+        }
+        (pc..)
+            .take_while(|&pc| self.code.function_name(pc) == Some(function))
+            .find(|&pc| self.line(pc).is_some())
+            .unwrap_or(pc)
+    }
+
+    fn describe(&self, pc: usize) -> String {
+        let function = self.code.function_name(pc).unwrap_or_default();
+        match self.source_line(pc) {
+            Some((file, line)) => {
+                format!(
+                    "{} at {}:{}",
+                    function,
+                    self.sources[file].info.source,
+                    line + 1
+                )
+            }
+            None => format!("{} (no source)", function),
+        }
+    }
+
+    fn print_line(&self, pc: usize) {
+        if let Some((file, line)) = self.source_line(pc) {
+            if let Some(text) = self.sources[file].text.get(line) {
+                println!("{}\t{}", line + 1, text);
+            }
+        }
+    }
+
+    fn list(&self) {
+        let Some(frames) = self.frames() else {
+            println!("The program is not being run.");
+            return;
+        };
+        let Some((file, line)) = frames
+            .get(self.frame)
+            .and_then(|frame| self.source_line(frame.pc))
+        else {
+            println!("No source for this frame.");
+            return;
+        };
+        let text = &self.sources[file].text;
+        let first = line.saturating_sub(5);
+        for (i, text) in text.iter().enumerate().skip(first).take(line + 6 - first) {
+            let marker = if i == line { "=>" } else { "  " };
+            println!("{} {}\t{}", marker, i + 1, text);
+        }
+    }
+
+    fn add_breakpoint(&mut self, location: &str) {
+        let pc = match location.split_once(':') {
+            Some((file_name, line)) => {
+                let Ok(line) = line.parse::<usize>() else {
+                    println!("Bad line number {}", line);
+                    return;
+                };
+                let class = file_name.trim_end_matches(".jack");
+                let Some(file) = self.sources.iter().position(|s| s.info.class == class) else {
+                    println!("No source file named {}.", file_name);
+                    return;
+                };
+                // the first line at or after it with code on it
+                self.code
+                    .commands_of(file)
+                    .filter_map(|(pc, _)| Some((self.line(pc)?.1, pc)))
+                    .filter(|(statement_line, _)| *statement_line + 1 >= line)
+                    .min()
+                    .map(|(_, pc)| pc)
+            }
+            // the subroutine's entry, which unlike its first statement
+            // can't be the top of a while loop that comes back to it
+            None => self.code.function(location),
+        };
+        match pc {
+            Some(pc) => {
+                self.breakpoints.push(Some(pc));
+                println!(
+                    "Breakpoint {} at {}",
+                    self.breakpoints.len(),
+                    self.describe(pc)
+                );
+            }
+            None => println!("No code at {}.", location),
+        }
+    }
+
+    fn frames(&self) -> Option<Vec<Frame>> {
+        Some(self.machine.as_ref()?.frames())
+    }
+
+    fn backtrace(&self) {
+        let Some(frames) = self.frames() else {
+            println!("No stack.");
+            return;
+        };
+        for (i, frame) in frames.iter().enumerate() {
+            self.print_frame(i, frame);
+        }
+    }
+
+    fn print_frame(&self, i: usize, frame: &Frame) {
+        let machine = self.machine.as_ref().unwrap();
+        let args = self
+            .args(&frame.function)
+            .into_iter()
+            .enumerate()
+            .map(|(n, name)| format!("{}={}", name, machine.ram[frame.arg + n]))
+            .collect::<Vec<_>>();
+        let location = match self.source_line(frame.pc) {
+            Some((file, line)) => format!(" at {}:{}", self.sources[file].info.source, line + 1),
+            None => String::new(),
+        };
+        println!(
+            "#{}  {} ({}){}",
+            i,
+            frame.function,
+            args.join(", "),
+            location
+        );
+    }
+
+    // the names of a function's arguments, in order
+    fn args(&self, function: &str) -> Vec<String> {
+        let Some((class, subroutine)) = function.split_once('.') else {
+            return Vec::new();
+        };
+        if let Ok((_, _, _)) = self.symbol_table.lookup_subroutine(class, subroutine) {
+            let table = &self.symbol_table.classes[class].subroutines[subroutine];
+            return table
+                .vars
+                .iter()
+                .filter(|(_, (decorator, _, _))| *decorator == SubroutineVarDecorator::Arg)
+                .map(|(name, _)| name.clone())
+                .collect();
+        }
+        match vm::os::lookup(class, subroutine) {
+            Some(signature) => {
+                let this = Some("this".to_string()).filter(|_| signature.kind == "method");
+                this.into_iter()
+                    .chain(signature.params.iter().map(|(_, name)| name.to_string()))
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // whether a function leaves a value of its own, rather than the 0 a
+    // void one returns
+    fn returns_value(&self, function: &str) -> bool {
+        let Some((class, subroutine)) = function.split_once('.') else {
+            return false;
+        };
+        match self
+            .symbol_table
+            .classes
+            .get(class)
+            .and_then(|table| table.subroutines.get(subroutine))
+        {
+            Some(table) => table.type_name.is_some(),
+            None => vm::os::lookup(class, subroutine)
+                .is_some_and(|signature| signature.return_type != "void"),
+        }
+    }
+
+    // the class and subroutine of the selected frame, and its frame
+    fn selected(&self) -> Option<(String, String, Frame, usize)> {
+        let machine = self.machine.as_ref()?;
+        let mut frames = machine.frames();
+        if self.frame >= frames.len() {
+            return None;
+        }
+        // a frame's this is saved in the frame of the function it called
+        let this = match self.frame {
+            0 => machine.ram[3],
+            n => machine.ram[frames[n - 1].lcl - 2],
+        } as u16 as usize;
+        let frame = frames.swap_remove(self.frame);
+        let (class, subroutine) = frame.function.split_once('.')?;
+        Some((class.to_string(), subroutine.to_string(), frame, this))
+    }
+
+    // where a variable of the selected frame is, and its type
+    fn address(&self, name: &str) -> Result<(usize, Type), String> {
+        let no_symbol = || format!("No symbol \"{}\" in current context.", name);
+        let (class, subroutine, frame, this) = self.selected().ok_or_else(no_symbol)?;
+        let (ref_type, type_name, number) = self
+            .symbol_table
+            .lookup_var(&class, &subroutine, name)
+            .map_err(|_| no_symbol())?;
+        let address = match ref_type {
+            RefType::SubroutineRefType(SubroutineVarDecorator::Arg) => frame.arg + number,
+            RefType::SubroutineRefType(SubroutineVarDecorator::Local) => frame.lcl + number,
+            RefType::ClassRefType(ClassVarDecorator::Field) => {
+                let (decorator, _, _) = self
+                    .symbol_table
+                    .lookup_subroutine(&class, &subroutine)
+                    .map_err(|_| no_symbol())?;
+                if decorator == SubroutineDecorator::Function {
+                    return Err(format!(
+                        "{} is a field, and {} is a function",
+                        name, subroutine
+                    ));
+                }
+                this + number
+            }
+            RefType::ClassRefType(ClassVarDecorator::Static) => {
+                let file = self
+                    .sources
+                    .iter()
+                    .position(|s| s.info.class == class)
+                    .unwrap();
+                self.machine
+                    .as_ref()
+                    .unwrap()
+                    .static_address(file, number as u16)
+            }
+        };
+        Ok((address, type_name))
+    }
+
+    fn print(&self, expr: &str) {
+        let Some(machine) = &self.machine else {
+            println!("The program is not being run.");
+            return;
+        };
+        if expr == "this" {
+            match self.selected() {
+                Some((_, _, _, this)) => println!("this = {}", this),
+                None => println!("No frame selected."),
+            }
+            return;
+        }
+        let (name, index) = match expr.split_once('[') {
+            Some((name, index)) => match index.trim_end_matches(']').parse::<i32>() {
+                Ok(index) => (name, Some(index)),
+                Err(_) => {
+                    println!("Only a number can index {}", name);
+                    return;
+                }
+            },
+            None => (expr, None),
+        };
+        if let Some((class, _, _, _)) = self.selected() {
+            if let Ok((type_name, value)) = self.symbol_table.lookup_constant(&class, name) {
+                println!("{} = {}", expr, self.format(value, &type_name));
+                return;
+            }
+        }
+        match self.address(name) {
+            Ok((address, type_name)) => match index {
+                None => println!(
+                    "{} = {}",
+                    expr,
+                    self.format(machine.ram[address], &type_name)
+                ),
+                Some(index) => {
+                    let element = machine.ram[address] as i32 + index;
+                    match machine.ram.get(element as usize).filter(|_| element >= 0) {
+                        Some(value) => println!("{} = {}", expr, value),
+                        None => println!("Cannot access memory at address {}", element),
+                    }
+                }
+            },
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    fn info(
+        &self,
+        subroutine_vars: Option<SubroutineVarDecorator>,
+        class_vars: Option<ClassVarDecorator>,
+    ) {
+        let Some((class, subroutine, _, _)) = self.selected() else {
+            println!("No frame selected.");
+            return;
+        };
+        let Some(class_table) = self.symbol_table.classes.get(&class) else {
+            println!("No symbol table info available.");
+            return;
+        };
+        let names = match (subroutine_vars, class_vars) {
+            (Some(decorator), _) => class_table.subroutines[&subroutine]
+                .vars
+                .iter()
+                .filter(|(_, (d, _, _))| *d == decorator)
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>(),
+            (_, Some(decorator)) => class_table
+                .class_vars
+                .iter()
+                .filter(|(_, (d, _, _))| *d == decorator)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            (None, None) => Vec::new(),
+        };
+        if names.is_empty() {
+            println!("None.");
+        }
+        for name in names {
+            self.print(&name);
+        }
+    }
+
+    fn format(&self, value: i16, type_name: &Type) -> String {
+        match type_name {
+            Type::Boolean if value == 0 => "false".to_string(),
+            Type::Boolean if value == -1 => "true".to_string(),
+            Type::Char if (32..127).contains(&value) => {
+                format!("{} '{}'", value, value as u8 as char)
+            }
+            Type::Class(class) if class == "String" && value != 0 => match self.string(value) {
+                Some(text) => format!("{} {:?}", value, text),
+                None => value.to_string(),
+            },
+            _ => value.to_string(),
+        }
+    }
+
+    // the text of a String laid out the way the os's String is: the
+    // maximum length, the length and then the array of characters
+    fn string(&self, address: i16) -> Option<String> {
+        if self.symbol_table.classes.contains_key("String") {
+            return None;
+        }
+        let ram = &self.machine.as_ref()?.ram;
+        let address = address as u16 as usize;
+        let (max, length, chars) = (
+            *ram.get(address)?,
+            *ram.get(address + 1)?,
+            *ram.get(address + 2)? as u16 as usize,
+        );
+        if length < 0 || length > max {
+            return None;
+        }
+        let chars = ram.get(chars..chars + length as usize)?;
+        Some(chars.iter().map(|&c| c as u8 as char).collect())
+    }
+}
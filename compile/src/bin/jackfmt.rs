@@ -0,0 +1,80 @@
+// rewrites jack files in the style of compile::format. With -check
+// nothing is written, the files that would change are listed and the
+// exit code says whether there were any
+use anyhow::Result;
+use std::{
+    env::args,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use compile::{
+    format::format_class, options::Options, parser::Parser, symbol_table::SymbolTable,
+    tokenizer::Tokenizer,
+};
+
+fn main() -> Result<()> {
+    if args().len() < 2 {
+        println!("missing file name");
+        return Ok(());
+    }
+    let args = args().collect::<Vec<_>>();
+    let mut check = false;
+    let mut options = Options::default();
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-check" | "--check" => check = true,
+            "-extended" => options.extended = true,
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+
+    let mut symbol_table = SymbolTable::new();
+    let mut unformatted = Vec::new();
+    for path in input_files(Path::new(&args[1]))? {
+        let source = fs::read_to_string(&path)?;
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        let mut parser = Parser::new(tokens, options);
+        let class = match parser.parse_class(&mut symbol_table) {
+            Ok(class) => class,
+            Err(error) => {
+                println!("{}: {}", path.to_string_lossy(), error);
+                exit(1);
+            }
+        };
+
+        let formatted = format_class(&class, &source, options.extended);
+        if formatted != source {
+            if !check {
+                println!("Formatting {}", path.to_string_lossy());
+                fs::write(&path, formatted)?;
+            }
+            unformatted.push(path);
+        }
+    }
+
+    if check && !unformatted.is_empty() {
+        for path in unformatted {
+            println!("{} is not formatted", path.to_string_lossy());
+        }
+        exit(1);
+    }
+    Ok(())
+}
+
+fn input_files(input_path: &Path) -> Result<Vec<PathBuf>> {
+    if input_path.is_dir() {
+        let mut files = Vec::new();
+        for entry in input_path.read_dir()? {
+            let file = entry?.path();
+            if file.extension().is_some_and(|extn| extn == "jack") {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    } else {
+        Ok(vec![input_path.to_path_buf()])
+    }
+}
@@ -0,0 +1,187 @@
+// runs a program and reports where it spent its time: a flat profile of
+// the functions, how often each called each other one, and with -lines the
+// jack lines that ran the most. A directory of jack classes is compiled,
+// linked with the built in os and counted by hack instruction on the cpu
+// emulator, or with -vm by vm command in the vm interpreter, which is much
+// faster and takes programs too big for the rom, like Pong. Those can't be
+// counted by hack instruction at all. A .asm file as the vm translator
+// writes it runs on the cpu emulator
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::HashMap,
+    env::args,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use asm::cpu::Cpu;
+use compile::{
+    harness::Script,
+    options::Options,
+    profile::{self, CodeMap, Profile},
+    run::{self, Computer},
+};
+use vm::{emitter::EmitOptions, files, interpreter::Machine, stats};
+
+fn main() -> Result<()> {
+    if args().len() < 2 {
+        println!("missing file name");
+        return Ok(());
+    }
+    let args = args().collect::<Vec<_>>();
+    let mut options = Options::default();
+    let mut emit_options = EmitOptions::default();
+    let mut use_vm = false;
+    let mut steps = u64::MAX;
+    let mut script = Script::new();
+    let mut show_lines = false;
+    let mut folded = None;
+    let mut top = 30;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-vm" => use_vm = true,
+            "-lines" => show_lines = true,
+            "-extended-vm" => options.extended_vm = true,
+            "-extended" => options.extended = true,
+            "-precedence" => options.precedence = true,
+            "-short-circuit" => options.short_circuit = true,
+            "-checks" => options.checks = true,
+            "-short-compare" => emit_options.short_compare = true,
+            "-shared-compare" => emit_options.shared_compare = true,
+            _ if arg.starts_with("-steps=") => steps = arg["-steps=".len()..].parse()?,
+            _ if arg.starts_with("-top=") => top = arg["-top=".len()..].parse()?,
+            _ if arg.starts_with("-folded=") => {
+                folded = Some(PathBuf::from(&arg["-folded=".len()..]))
+            }
+            _ if arg.starts_with("-script=") => {
+                let path = &arg["-script=".len()..];
+                let text = fs::read_to_string(path).with_context(|| path.to_string())?;
+                script = Script::parse(&text).with_context(|| path.to_string())?
+            }
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+
+    let path = Path::new(&args[1]);
+    let mut debug_info = Vec::new();
+    let mut input_files = Vec::new();
+    let (mut computer, map): (Box<dyn Computer>, CodeMap) =
+        if path.extension().is_some_and(|e| e == "asm") {
+            if use_vm {
+                bail!("-vm needs jack classes rather than assembly");
+            }
+            let text =
+                fs::read_to_string(path).with_context(|| path.to_string_lossy().to_string())?;
+            let asm = text.lines().map(str::to_string).collect::<Vec<_>>();
+            (Box::new(assemble(&asm)?), CodeMap::hack(&asm))
+        } else {
+            (input_files, _) = files::input_files(path, ".jack");
+            let program;
+            (program, debug_info, _) = run::compile_jack(&input_files, options)?;
+            if use_vm {
+                let mut machine = Machine::new(&program);
+                machine
+                    .bootstrap()
+                    .map_err(|error| anyhow!("{:?}", error))?;
+                let map = CodeMap::vm(&machine);
+                (Box::new(machine), map)
+            } else {
+                let program = vm::linker::remove_unreachable(program, "Sys.init");
+                let asm = vm::emitter::emit_program(&program, true, emit_options);
+                let size = stats::count_instructions(&asm);
+                if size > stats::ROM_SIZE {
+                    bail!(
+                        "Program is {} instructions but the ROM only holds {}, try -vm",
+                        size,
+                        stats::ROM_SIZE
+                    );
+                }
+                (Box::new(assemble(&asm)?), CodeMap::hack(&asm))
+            }
+        };
+
+    let profile = profile::profile(computer.as_mut(), &map, &script, steps)?;
+    let unit = if use_vm {
+        "vm commands"
+    } else {
+        "instructions"
+    };
+    if profile.halted {
+        println!("Halted after {} {}", profile.steps, unit);
+    } else {
+        println!("Stopped after {} {}", profile.steps, unit);
+    }
+    print_functions(&profile, top);
+    print_calls(&profile, top);
+    if show_lines {
+        let sources = input_files
+            .iter()
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                Ok((name, fs::read_to_string(path)?))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        println!();
+        println!("{:>12}  {:>6}  line", "count", "%");
+        for (source, line, count) in profile.lines(&debug_info).into_iter().take(top) {
+            let text = sources
+                .get(source)
+                .and_then(|text| text.lines().nth(line - 1))
+                .unwrap_or("");
+            println!(
+                "{:>12}  {:>6}  {}:{}  {}",
+                count,
+                percent(count, profile.steps),
+                source,
+                line,
+                text.trim()
+            );
+        }
+    }
+    if let Some(path) = folded {
+        println!("Creating {}", path.to_string_lossy());
+        fs::write(path, profile.folded())?;
+    }
+    Ok(())
+}
+
+fn assemble(asm: &[String]) -> Result<Cpu> {
+    let instructions = match asm::parser::parse_lines(asm) {
+        Ok(instructions) => instructions,
+        Err(errors) => bail!("assembly: {:?}", errors),
+    };
+    let symbol_table = asm::analyzer::analyze(instructions.iter());
+    let codes = asm::emitter::emit_instructions(instructions.iter(), &symbol_table);
+    Ok(Cpu::new(&codes))
+}
+
+fn print_functions(profile: &Profile, top: usize) {
+    println!();
+    println!(
+        "{:>12}  {:>6}  {:>12}  {:>6}  {:>10}  function",
+        "self", "%", "total", "%", "calls"
+    );
+    for function in profile.functions().into_iter().take(top) {
+        println!(
+            "{:>12}  {:>6}  {:>12}  {:>6}  {:>10}  {}",
+            function.own,
+            percent(function.own, profile.steps),
+            function.total,
+            percent(function.total, profile.steps),
+            function.calls,
+            function.name
+        );
+    }
+}
+
+fn print_calls(profile: &Profile, top: usize) {
+    println!();
+    println!("{:>10}  caller -> callee", "calls");
+    for (caller, callee, count) in profile.calls().into_iter().take(top) {
+        println!("{:>10}  {} -> {}", count, caller, callee);
+    }
+}
+
+fn percent(count: u64, steps: u64) -> String {
+    format!("{:.1}%", 100.0 * count as f64 / steps.max(1) as f64)
+}
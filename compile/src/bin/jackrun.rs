@@ -0,0 +1,223 @@
+// runs a program with its screen drawn in the terminal and the keys
+// typed there going to its keyboard. A .hack file runs on the cpu
+// emulator, and a directory of jack classes is compiled, linked with the
+// built in os and run in the vm interpreter, which is much faster
+use anyhow::{Context, Result};
+use std::{
+    env::args,
+    fs,
+    io::{stdin, stdout, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use asm::{keyboard, screen};
+use compile::{
+    harness::{self, Script},
+    options::Options,
+    run::{self, Computer},
+};
+use vm::files;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Display {
+    Braille,
+    Blocks,
+    Off,
+}
+
+// how often the screen is drawn and the keyboard looked at
+const FRAME: Duration = Duration::from_millis(33);
+// a terminal says when a key goes down but not when it comes up, so a
+// key counts as held until this long after it last came in. Held keys
+// repeat well within it
+const HOLD: Duration = Duration::from_millis(150);
+// steps run between looking at the clock
+const BATCH: usize = 10_000;
+const CTRL_C: u8 = 3;
+
+fn main() -> Result<()> {
+    if args().len() < 2 {
+        println!("missing file name");
+        return Ok(());
+    }
+    let args = args().collect::<Vec<_>>();
+    let mut options = Options::default();
+    let mut display = if stdout().is_terminal() {
+        Display::Braille
+    } else {
+        Display::Off
+    };
+    let mut snapshot = None;
+    let mut steps = None;
+    let mut script = None;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-display=braille" => display = Display::Braille,
+            "-display=blocks" => display = Display::Blocks,
+            "-display=none" => display = Display::Off,
+            "-extended-vm" => options.extended_vm = true,
+            "-extended" => options.extended = true,
+            "-precedence" => options.precedence = true,
+            "-short-circuit" => options.short_circuit = true,
+            "-checks" => options.checks = true,
+            _ if arg.starts_with("-snapshot=") => {
+                snapshot = Some(PathBuf::from(&arg["-snapshot=".len()..]))
+            }
+            _ if arg.starts_with("-script=") => {
+                let path = &arg["-script=".len()..];
+                let text = fs::read_to_string(path).with_context(|| path.to_string())?;
+                script = Some(Script::parse(&text).with_context(|| path.to_string())?)
+            }
+            _ if arg.starts_with("-steps=") => {
+                steps = Some(arg["-steps=".len()..].parse::<usize>()?)
+            }
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+
+    let path = Path::new(&args[1]);
+    let mut computer: Box<dyn Computer> = if path.extension().is_some_and(|e| e == "hack") {
+        Box::new(run::load_hack(path)?)
+    } else {
+        let (input_files, _) = files::input_files(path, ".jack");
+        Box::new(run::load_jack(&input_files, options)?)
+    };
+
+    // a script plays out before the terminal's keys go to the program
+    let mut count = match &script {
+        Some(script) => {
+            let length = steps.map_or(script.len(), |steps| steps.min(script.len()));
+            harness::run(computer.as_mut(), script, length)?
+        }
+        None => 0,
+    };
+    let terminal = Terminal::open();
+    let mut shown = None;
+    let mut key_time = Instant::now();
+    let mut frame_time = Instant::now();
+    let stopped = loop {
+        if let Some(error) = (0..BATCH).find_map(|_| {
+            if computer.halted() {
+                return None;
+            }
+            count += 1;
+            computer.step().err()
+        }) {
+            break format!("Error: {}", error);
+        }
+        if computer.halted() {
+            break format!("Halted after {} steps", count);
+        }
+        if steps.is_some_and(|steps| count >= steps) {
+            break format!("Stopped after {} steps", count);
+        }
+        if frame_time.elapsed() < FRAME {
+            continue;
+        }
+        frame_time = Instant::now();
+
+        let ram = computer.ram();
+        if let Some(terminal) = &terminal {
+            let input = terminal.keys.try_iter().flatten().collect::<Vec<_>>();
+            if input.contains(&CTRL_C) {
+                break format!("Interrupted after {} steps", count);
+            }
+            if let Some(&key) = keyboard::key_codes(&input).last() {
+                ram[screen::KEYBOARD] = key;
+                key_time = Instant::now();
+            } else if key_time.elapsed() > HOLD {
+                ram[screen::KEYBOARD] = 0;
+            }
+        }
+        if display != Display::Off {
+            let current = &ram[screen::SCREEN..screen::KEYBOARD];
+            if shown.as_deref() != Some(current) {
+                draw(ram, display, shown.is_none());
+                shown = Some(current.to_vec());
+            }
+        }
+    };
+
+    drop(terminal);
+    if display != Display::Off {
+        draw(computer.ram(), display, shown.is_none());
+    }
+    println!("{}", stopped);
+    if let Some(path) = snapshot {
+        let ram = computer.ram();
+        let image = match path.extension().and_then(|e| e.to_str()) {
+            Some("pbm") => screen::pbm(ram),
+            _ => screen::png(ram),
+        };
+        println!("Creating {}", path.to_string_lossy());
+        fs::write(path, image)?;
+    }
+    Ok(())
+}
+
+// raw lines end with \r\n as the terminal no longer adds the \r
+fn draw(ram: &[i16], display: Display, first: bool) {
+    let text = match display {
+        Display::Braille => screen::braille(ram),
+        Display::Blocks => screen::half_blocks(ram),
+        Display::Off => return,
+    };
+    let mut out = stdout().lock();
+    if first {
+        write!(out, "\x1b[2J").unwrap();
+    }
+    write!(out, "\x1b[H{}", text.replace('\n', "\r\n")).unwrap();
+    out.flush().unwrap();
+}
+
+// stdin in raw mode, so each key comes in as soon as it's pressed, with
+// a thread passing on what's read. The mode is put back when it's dropped
+struct Terminal {
+    keys: mpsc::Receiver<Vec<u8>>,
+    saved: String,
+}
+
+impl Terminal {
+    fn open() -> Option<Self> {
+        if !stdin().is_terminal() {
+            return None;
+        }
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            while let Ok(n @ 1..) = stdin().read(&mut buffer) {
+                if sender.send(buffer[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        Some(Self {
+            keys,
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
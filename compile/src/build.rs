@@ -0,0 +1,642 @@
+// incremental builds of a set of jack files. A cache next to the files
+// remembers, for each one, a hash of its source, the symbols it declared,
+// the vm it compiled to and the signatures of the classes it used. The
+// signature of a class is what other classes can see of it: its
+// constants and the kinds, return types and argument types of its
+// subroutines. A file is compiled again when its source changed or when
+// the signature of a class it uses did, everything else comes from the
+// cache. Files are parsed and emitted in parallel, a thread per core
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    ast::*,
+    emitter::Emitter,
+    json,
+    lint::Rules,
+    optimizer,
+    options::Options,
+    parser::Parser,
+    symbol_table::{SubroutineVarDecorator, SymbolTable},
+    tokenizer::Tokenizer,
+};
+
+const VERSION: u64 = 1;
+
+// where the cache goes for files in a directory
+pub fn cache_path(directory: &Path) -> PathBuf {
+    directory.join(".jack-build.json")
+}
+
+// what the build did, for the caller to report and lint
+pub struct Build {
+    pub symbol_table: SymbolTable,
+    // the classes that were compiled, with why
+    pub rebuilt: Vec<(Class, String)>,
+    pub up_to_date: usize,
+}
+
+struct Entry {
+    hash: String,
+    class: String,
+    signature: String,
+    dependencies: BTreeMap<String, String>,
+    symbols: Value,
+    vm: String,
+}
+
+struct Source {
+    path: PathBuf,
+    text: String,
+    hash: String,
+}
+
+pub fn build(files: &[PathBuf], cache_path: &Path, options: Options) -> Result<Build> {
+    // lint doesn't change the vm, every other option can
+    let options_key = format!(
+        "{:?}",
+        Options {
+            lint: Rules::default(),
+            ..options
+        }
+    );
+    let mut cache = load_cache(cache_path, &options_key);
+
+    let mut sources = Vec::new();
+    for path in files {
+        let text = fs::read_to_string(path)?;
+        let hash = hash(&text);
+        sources.push(Source {
+            path: path.clone(),
+            text,
+            hash,
+        });
+    }
+
+    // unchanged files go straight into the symbol table from the cache,
+    // as long as the vm next to them is still what they compiled to
+    let mut symbol_table = SymbolTable::new();
+    let mut signatures = BTreeMap::new();
+    let mut reasons = Vec::new();
+    for (i, source) in sources.iter().enumerate() {
+        match cache.get(&key(&source.path)) {
+            Some(entry) if entry.hash != source.hash => {
+                reasons.push((i, "source changed".to_string()))
+            }
+            Some(entry)
+                if fs::read_to_string(source.path.with_extension("vm")).ok()
+                    != Some(entry.vm.clone()) =>
+            {
+                reasons.push((i, "vm changed".to_string()))
+            }
+            Some(entry) => {
+                json::read_class_symbols(&entry.symbols, &mut symbol_table)?;
+                signatures.insert(entry.class.clone(), entry.signature.clone());
+            }
+            None => reasons.push((i, "source changed".to_string())),
+        }
+    }
+
+    // a rebuilt class can change the signature of one that uses its
+    // constants, so this goes on until nothing more needs building
+    let mut parsed: Vec<Option<Class>> = sources.iter().map(|_| None).collect();
+    let mut why: Vec<Option<String>> = sources.iter().map(|_| None).collect();
+    while !reasons.is_empty() {
+        let batch = reasons.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        for (i, reason) in reasons.drain(..) {
+            if let Some(entry) = cache.get(&key(&sources[i].path)) {
+                symbol_table.classes.shift_remove(&entry.class);
+            }
+            why[i] = Some(reason);
+        }
+        for (i, class) in parse_all(&sources, &batch, &mut symbol_table, options)? {
+            signatures.insert(class.name.clone(), signature(&symbol_table, &class.name));
+            parsed[i] = Some(class);
+        }
+
+        for (i, source) in sources.iter().enumerate() {
+            if why[i].is_some() {
+                continue;
+            }
+            let Some(entry) = cache.get(&key(&source.path)) else {
+                continue;
+            };
+            let changed = entry.dependencies.iter().find(|(class, signature)| {
+                signatures.get(*class).map_or("", String::as_str) != signature.as_str()
+            });
+            if let Some((class, _)) = changed {
+                reasons.push((i, format!("{} changed", class)));
+            }
+        }
+    }
+
+    let to_emit = parsed
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, class)| Some((i, class?)))
+        .collect::<Vec<_>>();
+    // nothing is written until every class has emitted, so a failed
+    // build leaves the files and the cache as they were
+    let vms = parallel_map(&to_emit, |(_, class)| emit(class, &symbol_table, options))
+        .into_iter()
+        .zip(&to_emit)
+        .map(|(vm, (i, _))| vm.with_context(|| sources[*i].path.to_string_lossy().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut rebuilt = Vec::new();
+    for ((i, class), (vm, warnings)) in to_emit.into_iter().zip(vms) {
+        let source = &sources[i];
+        let vm_path = source.path.with_extension("vm");
+        println!("Creating {}", vm_path.to_string_lossy());
+        for warning in warnings {
+            println!("Warning: {}", warning);
+        }
+        fs::write(&vm_path, &vm)?;
+
+        // a class that isn't here, like the os's, has an empty
+        // signature, so adding it later rebuilds this one
+        let dependencies = dependencies(&class)
+            .into_iter()
+            .map(|name| {
+                let signature = signatures.get(&name).cloned().unwrap_or_default();
+                (name, signature)
+            })
+            .collect();
+        cache.insert(
+            key(&source.path),
+            Entry {
+                hash: source.hash.clone(),
+                class: class.name.clone(),
+                signature: signatures[&class.name].clone(),
+                dependencies,
+                symbols: json::class_symbols_json(&class.name, &symbol_table.classes[&class.name]),
+                vm,
+            },
+        );
+        rebuilt.push((class, why[i].take().unwrap()));
+    }
+
+    // files that are gone are forgotten
+    let keys = sources
+        .iter()
+        .map(|source| key(&source.path))
+        .collect::<BTreeSet<_>>();
+    cache.retain(|file, _| keys.contains(file));
+    save_cache(cache_path, &options_key, &cache)?;
+
+    Ok(Build {
+        symbol_table,
+        up_to_date: sources.len() - rebuilt.len(),
+        rebuilt,
+    })
+}
+
+// each file is parsed against its own copy of the symbol table, so a
+// class that needs constants from another one being parsed at the same
+// time fails. Those are parsed again afterwards, one at a time, in order
+fn parse_all(
+    sources: &[Source],
+    batch: &[usize],
+    symbol_table: &mut SymbolTable,
+    options: Options,
+) -> Result<Vec<(usize, Class)>> {
+    let seed = &*symbol_table;
+    let results = parallel_map(batch, |&i| {
+        let mut symbol_table = seed.clone();
+        parse(&sources[i], &mut symbol_table, options).map(|(class, warnings)| {
+            let table = symbol_table.classes.shift_remove(&class.name);
+            (class, warnings, table)
+        })
+    });
+
+    let mut classes = Vec::new();
+    let mut retry = Vec::new();
+    for (&i, result) in batch.iter().zip(results) {
+        match result {
+            Ok((class, warnings, Some(table))) => {
+                if symbol_table.classes.contains_key(&class.name) {
+                    retry.push(i);
+                    continue;
+                }
+                print_warnings(&sources[i], &warnings);
+                symbol_table.classes.insert(class.name.clone(), table);
+                classes.push((i, class));
+            }
+            _ => retry.push(i),
+        }
+    }
+    for i in retry {
+        let (class, warnings) = parse(&sources[i], symbol_table, options)
+            .with_context(|| sources[i].path.to_string_lossy().to_string())?;
+        print_warnings(&sources[i], &warnings);
+        classes.push((i, class));
+    }
+    classes.sort_by_key(|(i, _)| *i);
+    Ok(classes)
+}
+
+fn parse(
+    source: &Source,
+    symbol_table: &mut SymbolTable,
+    options: Options,
+) -> Result<(Class, Vec<String>), ParseError> {
+    let tokens = Tokenizer::new(source.text.chars().map(Ok), options.extended);
+    let mut parser = Parser::new(tokens, options);
+    let class = parser.parse_class(symbol_table)?;
+    Ok((class, parser.warnings().to_vec()))
+}
+
+fn print_warnings(source: &Source, warnings: &[String]) {
+    println!("Compiling {}", source.path.to_string_lossy());
+    for warning in warnings {
+        println!("Warning: {}", warning);
+    }
+}
+
+// the vm code, with the emitter's warnings
+fn emit(
+    class: &Class,
+    symbol_table: &SymbolTable,
+    options: Options,
+) -> Result<(String, Vec<String>)> {
+    let optimized;
+    let class = if options.optimize {
+        optimized = optimizer::optimize_class(class.clone(), options);
+        &optimized
+    } else {
+        class
+    };
+    let mut vm = Vec::new();
+    let mut emitter = Emitter::new(BufWriter::new(&mut vm), symbol_table, options);
+    emitter.emit_class(class)?;
+    let warnings = emitter.warnings().to_vec();
+    drop(emitter);
+    Ok((String::from_utf8(vm)?, warnings))
+}
+
+// what other classes can see of a class, as a hash
+fn signature(symbol_table: &SymbolTable, class: &str) -> String {
+    let table = &symbol_table.classes[class];
+    let mut text = String::new();
+    for (name, (type_name, value)) in &table.constants {
+        text += &format!("const {:?} {} {};", type_name, name, value);
+    }
+    for (name, subroutine) in &table.subroutines {
+        let args = subroutine
+            .vars
+            .values()
+            .filter(|(decorator, _, _)| *decorator == SubroutineVarDecorator::Arg)
+            .map(|(_, type_name, _)| format!("{:?}", type_name))
+            .collect::<Vec<_>>();
+        text += &format!(
+            "{:?} {:?} {}({});",
+            subroutine.decorator,
+            subroutine.type_name,
+            name,
+            args.join(",")
+        );
+    }
+    hash(&text)
+}
+
+// every class a class names: in types, as the qualifier of a call and
+// in Class.CONSTANT. Calls through variables are covered by their types
+fn dependencies(class: &Class) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let add_type = |names: &mut BTreeSet<String>, type_name: &Type| {
+        if let Type::Class(name) = type_name {
+            names.insert(name.clone());
+        }
+    };
+    for var in &class.vars {
+        add_type(&mut names, &var.type_name);
+    }
+    for const_dec in &class.consts {
+        match const_dec {
+            ConstDecl::Const { expr, .. } => expr_dependencies(expr, &mut names),
+            ConstDecl::Enum { members } => {
+                for expr in members.iter().filter_map(|(_, expr)| expr.as_ref()) {
+                    expr_dependencies(expr, &mut names);
+                }
+            }
+        }
+    }
+    for subroutine in &class.subroutines {
+        if let Some(type_name) = &subroutine.type_name {
+            add_type(&mut names, type_name);
+        }
+        for param in &subroutine.params {
+            add_type(&mut names, &param.type_name);
+        }
+        for var in &subroutine.vars {
+            add_type(&mut names, &var.type_name);
+        }
+        statement_dependencies(&subroutine.statements, &mut names);
+    }
+    names.remove(&class.name);
+    names
+}
+
+fn statement_dependencies(statements: &[Statement], names: &mut BTreeSet<String>) {
+    for statement in statements {
+        match statement {
+            Statement::Let { index, expr, .. } | Statement::Compound { index, expr, .. } => {
+                if let Some(index) = index {
+                    expr_dependencies(index, names);
+                }
+                expr_dependencies(expr, names);
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+            } => {
+                expr_dependencies(condition, names);
+                statement_dependencies(statements, names);
+                statement_dependencies(else_statements.as_deref().unwrap_or_default(), names);
+            }
+            Statement::While {
+                condition,
+                statements,
+            } => {
+                expr_dependencies(condition, names);
+                statement_dependencies(statements, names);
+            }
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => {
+                for statement in init.iter().chain(step) {
+                    statement_dependencies(std::slice::from_ref(statement), names);
+                }
+                expr_dependencies(condition, names);
+                statement_dependencies(statements, names);
+            }
+            Statement::Do { expr } => expr_dependencies(expr, names),
+            Statement::Return { expr } => {
+                if let Some(expr) = expr {
+                    expr_dependencies(expr, names);
+                }
+            }
+            Statement::Break | Statement::Continue => (),
+        }
+    }
+}
+
+fn expr_dependencies(expr: &Expr, names: &mut BTreeSet<String>) {
+    term_dependencies(&expr.term, names);
+    for (_, term) in &expr.ops {
+        term_dependencies(term, names);
+    }
+}
+
+fn term_dependencies(term: &Term, names: &mut BTreeSet<String>) {
+    match term {
+        Term::Var {
+            index: Some(index), ..
+        } => expr_dependencies(index, names),
+        Term::Bracketed(expr) => expr_dependencies(expr, names),
+        Term::Unary(_, term) => term_dependencies(term, names),
+        Term::SubroutineCall {
+            qualifier, exprs, ..
+        } => {
+            // a variable's name here does no harm, it matches no class
+            if let Some(qualifier) = qualifier {
+                names.insert(qualifier.clone());
+            }
+            for expr in exprs {
+                expr_dependencies(expr, names);
+            }
+        }
+        Term::Constant { class, .. } => {
+            names.insert(class.clone());
+        }
+        Term::Binary(left, _, right) => {
+            term_dependencies(left, names);
+            term_dependencies(right, names);
+        }
+        _ => (),
+    }
+}
+
+// f applied to every item, spread over a thread per core
+fn parallel_map<T, R>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let threads = thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(items.len());
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(i) {
+                            Some(item) => results.push((i, f(item))),
+                            None => break results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// 64 bit FNV-1a, which unlike the standard library's hasher gives the
+// same answer from one build of the compiler to the next
+fn hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn key(path: &Path) -> String {
+    path.file_name()
+        .map_or(String::new(), |name| name.to_string_lossy().to_string())
+}
+
+// a cache that can't be read, or was built with other options, is
+// as good as none
+fn load_cache(path: &Path, options_key: &str) -> BTreeMap<String, Entry> {
+    let document = fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        .filter(|document| {
+            document["version"].as_u64() == Some(VERSION)
+                && document["options"].as_str() == Some(options_key)
+        });
+    let Some(Value::Object(files)) = document.map(|mut document| document["files"].take()) else {
+        return BTreeMap::new();
+    };
+    files
+        .into_iter()
+        .filter_map(|(file, mut entry)| {
+            let dependencies = entry["dependencies"]
+                .as_object()?
+                .iter()
+                .map(|(class, signature)| Some((class.clone(), signature.as_str()?.to_string())))
+                .collect::<Option<_>>()?;
+            Some((
+                file,
+                Entry {
+                    hash: entry["hash"].as_str()?.to_string(),
+                    class: entry["class"].as_str()?.to_string(),
+                    signature: entry["signature"].as_str()?.to_string(),
+                    dependencies,
+                    vm: entry["vm"].as_str()?.to_string(),
+                    symbols: entry["symbols"].take(),
+                },
+            ))
+        })
+        .collect()
+}
+
+fn save_cache(path: &Path, options_key: &str, cache: &BTreeMap<String, Entry>) -> Result<()> {
+    let files = cache
+        .iter()
+        .map(|(file, entry)| {
+            (
+                file.clone(),
+                json!({
+                    "hash": entry.hash,
+                    "class": entry.class,
+                    "signature": entry.signature,
+                    "dependencies": entry.dependencies,
+                    "symbols": entry.symbols,
+                    "vm": entry.vm,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let document = json!({ "version": VERSION, "options": options_key, "files": files });
+    fs::write(path, serde_json::to_string(&document)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    // a copy of projects/11/Pong to build in
+    fn pong(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let directory = env::temp_dir().join(format!("build-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let pong = Path::new(env!("CARGO_MANIFEST_DIR")).join("../projects/11/Pong");
+        let mut files = Vec::new();
+        for entry in fs::read_dir(pong).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "jack") {
+                let file = directory.join(path.file_name().unwrap());
+                fs::copy(&path, &file).unwrap();
+                files.push(file);
+            }
+        }
+        files.sort();
+        (directory, files)
+    }
+
+    fn run(directory: &Path, files: &[PathBuf]) -> Result<Build> {
+        build(files, &cache_path(directory), Options::default())
+    }
+
+    fn reasons(build: &Build) -> Vec<(&str, &str)> {
+        build
+            .rebuilt
+            .iter()
+            .map(|(class, why)| (class.name.as_str(), why.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_up_to_date() {
+        let (directory, files) = pong("up-to-date");
+        let first = run(&directory, &files).unwrap();
+        assert_eq!(first.rebuilt.len(), 4);
+        assert_eq!(first.up_to_date, 0);
+
+        let second = run(&directory, &files).unwrap();
+        assert!(second.rebuilt.is_empty());
+        assert_eq!(second.up_to_date, 4);
+
+        // a class that grows a subroutine rebuilds those that use it
+        let bat = directory.join("Bat.jack");
+        let source = fs::read_to_string(&bat).unwrap();
+        fs::write(
+            &bat,
+            source.replace(
+                "    /** Shows the bat. */",
+                "    method int width() { return 0; }\n\n    /** Shows the bat. */",
+            ),
+        )
+        .unwrap();
+        let third = run(&directory, &files).unwrap();
+        assert_eq!(
+            reasons(&third),
+            [("Bat", "source changed"), ("PongGame", "Bat changed")]
+        );
+
+        // so does a vm file that isn't what the cache says it compiled to
+        fs::write(directory.join("Ball.vm"), "").unwrap();
+        let fourth = run(&directory, &files).unwrap();
+        assert_eq!(reasons(&fourth), [("Ball", "vm changed")]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_failed_build() {
+        let (directory, files) = pong("failed");
+        run(&directory, &files).unwrap();
+        let bat_vm = fs::read_to_string(directory.join("Bat.vm")).unwrap();
+
+        // Bat compiles, PongGame's call to dispose doesn't
+        let bat = directory.join("Bat.jack");
+        let source = fs::read_to_string(&bat).unwrap();
+        fs::write(
+            &bat,
+            source.replace(
+                "method void dispose() {",
+                "method void dispose(int n) {\n        do Output.printInt(n);",
+            ),
+        )
+        .unwrap();
+        assert!(run(&directory, &files).is_err());
+        assert_eq!(
+            fs::read_to_string(directory.join("Bat.vm")).unwrap(),
+            bat_vm
+        );
+
+        fs::write(&bat, source).unwrap();
+        let reverted = run(&directory, &files).unwrap();
+        assert_eq!(reverted.up_to_date, 4);
+        assert_eq!(
+            fs::read_to_string(directory.join("Bat.vm")).unwrap(),
+            bat_vm
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+// what -g writes next to each X.vm as X.dbg.json: where in the jack
+// source each vm line came from, and the names of the variables behind
+// each segment index, so a debugger or profiler working on the vm code
+// can talk about the jack program instead
+use crate::{
+    ast::*,
+    emitter::LineOrigin,
+    symbol_table::{SubroutineVarDecorator, SymbolTable},
+};
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct DebugInfo {
+    pub class: String,
+    // the file name of the source, X.jack
+    pub source: String,
+    // one for each vm line
+    pub lines: Vec<SourceLine>,
+    pub statics: Vec<Variable>,
+    pub fields: Vec<Variable>,
+    pub functions: Vec<Function>,
+}
+
+// zero based, like spans. The lines that start a subroutine point at its
+// declaration and the rest at the start of their statement
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SourceLine {
+    pub line: usize,
+    pub column: usize,
+    pub statement: bool,
+}
+
+// index is the variable's index in its segment
+#[derive(Debug, Eq, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub type_name: Type,
+    pub index: usize,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Function {
+    // Class.subroutine, as in the vm code
+    pub name: String,
+    pub decorator: SubroutineDecorator,
+    pub type_name: Option<Type>,
+    // the vm line of its function command
+    pub vm_line: usize,
+    // a method's this is argument 0
+    pub args: Vec<Variable>,
+    pub locals: Vec<Variable>,
+}
+
+impl DebugInfo {
+    // origins are what the emitter recorded while emitting the class,
+    // which has to be as it was parsed, not optimized
+    pub fn new(
+        source: &str,
+        class: &Class,
+        spans: &Spans,
+        origins: &[LineOrigin],
+        symbol_table: &SymbolTable,
+    ) -> Self {
+        let lines = origins
+            .iter()
+            .map(|origin| {
+                let (span, statement) = match *origin {
+                    LineOrigin::Subroutine(i) => (spans.subroutines[i], false),
+                    LineOrigin::Statement(i) => (spans.statements[i], true),
+                };
+                SourceLine {
+                    line: span.start.line,
+                    column: span.start.column,
+                    statement,
+                }
+            })
+            .collect();
+
+        let class_table = &symbol_table.classes[&class.name];
+        let class_vars = |decorator| {
+            class_table
+                .class_vars
+                .iter()
+                .filter(|(_, (d, _, _))| *d == decorator)
+                .map(|(name, (_, type_name, index))| variable(name, type_name, *index))
+                .collect()
+        };
+
+        let functions = class
+            .subroutines
+            .iter()
+            .enumerate()
+            .map(|(i, subroutine)| {
+                let table = &class_table.subroutines[&subroutine.name];
+                let vars = |decorator| {
+                    table
+                        .vars
+                        .iter()
+                        .filter(|(_, (d, _, _))| *d == decorator)
+                        .map(|(name, (_, type_name, index))| variable(name, type_name, *index))
+                        .collect()
+                };
+                Function {
+                    name: format!("{}.{}", class.name, subroutine.name),
+                    decorator: table.decorator,
+                    type_name: table.type_name.clone(),
+                    vm_line: origins
+                        .iter()
+                        .position(|origin| *origin == LineOrigin::Subroutine(i))
+                        .unwrap_or_default(),
+                    args: vars(SubroutineVarDecorator::Arg),
+                    locals: vars(SubroutineVarDecorator::Local),
+                }
+            })
+            .collect();
+
+        Self {
+            class: class.name.clone(),
+            source: source.to_string(),
+            lines,
+            statics: class_vars(ClassVarDecorator::Static),
+            fields: class_vars(ClassVarDecorator::Field),
+            functions,
+        }
+    }
+}
+
+fn variable(name: &str, type_name: &Type, index: usize) -> Variable {
+    Variable {
+        name: name.to_string(),
+        type_name: type_name.clone(),
+        index,
+    }
+}
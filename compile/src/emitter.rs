@@ -1,31 +1,99 @@
 use crate::{
     ast::*,
+    options::Options,
     symbol_table::{RefType, SubroutineVarDecorator, SymbolTable},
 };
 use anyhow::Result;
 use std::io::{BufWriter, Write};
 use thiserror::Error;
 
+// Sys.error codes for the runtime checks. The OS only uses 0 to 2
+// 100: an array was indexed through null
+// 101: an array index was negative or not less than the size of the array
+// 102: a method was called through a variable that was null
+pub const NULL_ARRAY_ERROR: u16 = 100;
+pub const INDEX_ERROR: u16 = 101;
+pub const NULL_RECEIVER_ERROR: u16 = 102;
+
 pub struct Emitter<'a, T>
 where
     T: Write,
 {
-    writer: BufWriter<T>,
+    writer: LineCounter<BufWriter<T>>,
     symbol_table: &'a SymbolTable,
+    options: Options,
     if_label_number: usize,
     while_label_number: usize,
+    skip_label_number: usize,
+    check_label_number: usize,
+    // continue and break labels of the loops around the current statement
+    loops: Vec<(String, String)>,
+    subroutine_number: usize,
+    statement_number: usize,
+    origin: LineOrigin,
+    // from which vm line on the code comes from where
+    origin_starts: Vec<(usize, LineOrigin)>,
+    warnings: Vec<String>,
+}
+
+// what a vm line is code for. Subroutines and statements are numbered
+// the way the parser numbers their spans
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineOrigin {
+    // the function command and the setting up of this
+    Subroutine(usize),
+    Statement(usize),
 }
 
 impl<'a, T> Emitter<'a, T>
 where
     T: Write,
 {
-    pub fn new(writer: BufWriter<T>, symbol_table: &'a SymbolTable) -> Self {
+    pub fn new(writer: BufWriter<T>, symbol_table: &'a SymbolTable, options: Options) -> Self {
         Self {
-            writer,
+            writer: LineCounter {
+                inner: writer,
+                lines: 0,
+            },
             symbol_table,
+            options,
             if_label_number: 0,
             while_label_number: 0,
+            skip_label_number: 0,
+            check_label_number: 0,
+            loops: Vec::new(),
+            subroutine_number: 0,
+            statement_number: 0,
+            origin: LineOrigin::Subroutine(0),
+            origin_starts: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // where each vm line written so far came from. Lines of a statement
+    // that holds others, like the jump back in a while, are its own
+    pub fn line_origins(&self) -> Vec<LineOrigin> {
+        let mut origins = vec![LineOrigin::Subroutine(0); self.writer.lines];
+        for (i, &(start, origin)) in self.origin_starts.iter().enumerate() {
+            let end = self
+                .origin_starts
+                .get(i + 1)
+                .map_or(self.writer.lines, |(end, _)| *end);
+            origins[start..end].fill(origin);
+        }
+        origins
+    }
+
+    fn enter(&mut self, origin: LineOrigin) {
+        self.origin = origin;
+        let line = self.writer.lines;
+        match self.origin_starts.last_mut() {
+            Some(last) if last.0 == line => last.1 = origin,
+            _ => self.origin_starts.push((line, origin)),
         }
     }
 
@@ -40,6 +108,10 @@ where
     pub fn emit_subroutine(&mut self, class: &str, subroutine: &Subroutine) -> Result<()> {
         self.if_label_number = 0;
         self.while_label_number = 0;
+        self.skip_label_number = 0;
+        self.check_label_number = 0;
+        self.enter(LineOrigin::Subroutine(self.subroutine_number));
+        self.subroutine_number += 1;
         let (_, _, locals) = self
             .symbol_table
             .lookup_subroutine(class, &subroutine.name)?;
@@ -75,7 +147,11 @@ where
         statements: &[Statement],
     ) -> Result<(), anyhow::Error> {
         for statement in statements {
+            let outer = self.origin;
+            self.enter(LineOrigin::Statement(self.statement_number));
+            self.statement_number += 1;
             self.emit_statement(class, subroutine, statement)?;
+            self.enter(outer);
         }
 
         Ok(())
@@ -102,6 +178,27 @@ where
             } => self.emit_while_statement(class, subroutine, condition, statements),
             Statement::Do { expr } => self.emit_do_statement(class, subroutine, expr),
             Statement::Return { expr } => self.emit_return_statement(class, subroutine, expr),
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => self.emit_compound_statement(class, subroutine, name, index, *op, expr),
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => self.emit_for_statement(
+                class,
+                subroutine,
+                init.as_deref(),
+                condition,
+                step.as_deref(),
+                statements,
+            ),
+            Statement::Break => self.emit_jump(|(_, break_label)| break_label),
+            Statement::Continue => self.emit_jump(|(continue_label, _)| continue_label),
         }
     }
 
@@ -118,11 +215,15 @@ where
         let end_label = format!("IF_END{}", self.if_label_number);
         self.if_label_number += 1;
 
-        self.emit_expr(class, subroutine, condition)?;
+        if self.options.short_circuit && Self::split_logical(condition).is_some() {
+            self.emit_branch(class, subroutine, condition, false, &false_label)?;
+        } else {
+            self.emit_expr(class, subroutine, condition)?;
 
-        writeln!(self.writer, "if-goto {}", true_label)?;
-        writeln!(self.writer, "goto {}", false_label)?;
-        writeln!(self.writer, "label {}", true_label)?;
+            writeln!(self.writer, "if-goto {}", true_label)?;
+            writeln!(self.writer, "goto {}", false_label)?;
+            writeln!(self.writer, "label {}", true_label)?;
+        }
         self.emit_statements(class, subroutine, statements)?;
         if else_statements.is_some() {
             writeln!(self.writer, "goto {}", end_label)?;
@@ -147,7 +248,11 @@ where
         self.emit_expr(class, subroutine, expr)?;
 
         let (reftype, _, number) = self.symbol_table.lookup_var(class, subroutine, name)?;
-        if let Some(index) = index {
+        if let Some(index) = index.as_ref().filter(|_| self.options.checks) {
+            self.emit_checked_address(class, subroutine, name, index)?;
+            writeln!(self.writer, "pop pointer 1")?;
+            writeln!(self.writer, "pop that 0")?;
+        } else if let Some(index) = index {
             self.emit_expr(class, subroutine, index)?;
             match reftype {
                 RefType::ClassRefType(ClassVarDecorator::Static) => {
@@ -167,25 +272,31 @@ where
             writeln!(self.writer, "pop pointer 1")?;
             writeln!(self.writer, "pop that 0")?;
         } else {
-            match reftype {
-                RefType::ClassRefType(ClassVarDecorator::Static) => {
-                    writeln!(self.writer, "pop static {}", number)?
-                }
-                RefType::ClassRefType(ClassVarDecorator::Field) => {
-                    writeln!(self.writer, "pop this {}", number)?
-                }
-                RefType::SubroutineRefType(SubroutineVarDecorator::Arg) => {
-                    writeln!(self.writer, "pop argument {}", number)?
-                }
-                RefType::SubroutineRefType(SubroutineVarDecorator::Local) => {
-                    writeln!(self.writer, "pop local {}", number)?
-                }
-            }
+            self.emit_var_pop(class, subroutine, name)?;
         }
 
         Ok(())
     }
 
+    fn emit_var_pop(&mut self, class: &str, subroutine: &str, name: &str) -> Result<()> {
+        let (reftype, _, number) = self.symbol_table.lookup_var(class, subroutine, name)?;
+        match reftype {
+            RefType::ClassRefType(ClassVarDecorator::Static) => {
+                writeln!(self.writer, "pop static {}", number)?
+            }
+            RefType::ClassRefType(ClassVarDecorator::Field) => {
+                writeln!(self.writer, "pop this {}", number)?
+            }
+            RefType::SubroutineRefType(SubroutineVarDecorator::Arg) => {
+                writeln!(self.writer, "pop argument {}", number)?
+            }
+            RefType::SubroutineRefType(SubroutineVarDecorator::Local) => {
+                writeln!(self.writer, "pop local {}", number)?
+            }
+        }
+        Ok(())
+    }
+
     pub fn emit_while_statement(
         &mut self,
         class: &str,
@@ -198,16 +309,254 @@ where
         self.while_label_number += 1;
 
         writeln!(self.writer, "label {}", while_label)?;
-        self.emit_expr(class, subroutine, condition)?;
-        writeln!(self.writer, "not")?;
-        writeln!(self.writer, "if-goto {}", done_label)?;
-        self.emit_statements(class, subroutine, statements)?;
+        self.emit_loop_exit(class, subroutine, condition, &done_label)?;
+        self.emit_loop_body(class, subroutine, statements, &while_label, &done_label)?;
         writeln!(self.writer, "goto {}", while_label)?;
         writeln!(self.writer, "label {}", done_label)?;
 
         Ok(())
     }
 
+    // a while loop with the step at its own label so continue still runs it
+    pub fn emit_for_statement(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        init: Option<&Statement>,
+        condition: &Expr,
+        step: Option<&Statement>,
+        statements: &[Statement],
+    ) -> Result<()> {
+        let while_label = format!("WHILE_EXP{}", self.while_label_number);
+        let step_label = format!("WHILE_STEP{}", self.while_label_number);
+        let done_label = format!("WHILE_END{}", self.while_label_number);
+        self.while_label_number += 1;
+
+        if let Some(init) = init {
+            self.emit_statement(class, subroutine, init)?;
+        }
+        writeln!(self.writer, "label {}", while_label)?;
+        self.emit_loop_exit(class, subroutine, condition, &done_label)?;
+        self.emit_loop_body(class, subroutine, statements, &step_label, &done_label)?;
+        writeln!(self.writer, "label {}", step_label)?;
+        if let Some(step) = step {
+            self.emit_statement(class, subroutine, step)?;
+        }
+        writeln!(self.writer, "goto {}", while_label)?;
+        writeln!(self.writer, "label {}", done_label)?;
+
+        Ok(())
+    }
+
+    fn emit_loop_exit(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        condition: &Expr,
+        done_label: &str,
+    ) -> Result<()> {
+        if self.options.short_circuit && Self::split_logical(condition).is_some() {
+            self.emit_branch(class, subroutine, condition, false, done_label)?;
+        } else {
+            self.emit_expr(class, subroutine, condition)?;
+            writeln!(self.writer, "not")?;
+            writeln!(self.writer, "if-goto {}", done_label)?;
+        }
+        Ok(())
+    }
+
+    fn emit_loop_body(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        statements: &[Statement],
+        continue_label: &str,
+        break_label: &str,
+    ) -> Result<()> {
+        self.loops
+            .push((continue_label.to_string(), break_label.to_string()));
+        self.emit_statements(class, subroutine, statements)?;
+        self.loops.pop();
+        Ok(())
+    }
+
+    // the parser only accepts break and continue inside a loop
+    fn emit_jump(&mut self, label: impl Fn(&(String, String)) -> &String) -> Result<()> {
+        let target = label(self.loops.last().unwrap()).clone();
+        writeln!(self.writer, "goto {}", target)?;
+        Ok(())
+    }
+
+    // the old value is read after the right hand side is evaluated, so
+    // that pointer 1 is still set for writing back an array element.
+    // That leaves the operands the wrong way round for sub
+    pub fn emit_compound_statement(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        name: &str,
+        index: &Option<Expr>,
+        op: Op,
+        expr: &Expr,
+    ) -> Result<()> {
+        if index.is_some() {
+            self.emit_expr(class, subroutine, expr)?;
+            self.emit_var_ref(class, subroutine, name, index)?;
+            self.emit_op(op)?;
+            if op == Op::Sub {
+                writeln!(self.writer, "neg")?;
+            }
+            writeln!(self.writer, "pop that 0")?;
+        } else {
+            self.emit_var_ref(class, subroutine, name, &None)?;
+            self.emit_expr(class, subroutine, expr)?;
+            self.emit_op(op)?;
+            self.emit_var_pop(class, subroutine, name)?;
+        }
+        Ok(())
+    }
+
+    // jumps to target when the condition is (when true) or isn't (when
+    // false) non zero. a & b and a | b are split up when a is a boolean,
+    // since then a alone can decide the result without changing it.
+    // Otherwise they stay bitwise, with a warning
+    fn emit_branch(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        condition: &Expr,
+        when: bool,
+        target: &str,
+    ) -> Result<()> {
+        match Self::split_logical(condition) {
+            Some((left, op, right)) if self.is_boolean(class, subroutine, &left) => {
+                // && jumps straight out when false, || when true
+                let decided_by_left = op == Op::Or;
+                if when == decided_by_left {
+                    self.emit_branch(class, subroutine, &left, when, target)?;
+                    self.emit_branch(class, subroutine, &right, when, target)?;
+                } else {
+                    let skip_label = format!("SKIP{}", self.skip_label_number);
+                    self.skip_label_number += 1;
+                    self.emit_branch(class, subroutine, &left, !when, &skip_label)?;
+                    self.emit_branch(class, subroutine, &right, when, target)?;
+                    writeln!(self.writer, "label {}", skip_label)?;
+                }
+            }
+            split => {
+                if let Some((_, op, _)) = split {
+                    self.warnings.push(format!(
+                        "in {}.{} {} in a condition is bitwise since its left side isn't a boolean",
+                        class,
+                        subroutine,
+                        op.symbol()
+                    ));
+                }
+                self.emit_expr(class, subroutine, condition)?;
+                if !when {
+                    // not only flips 0 and -1, anything else needs comparing
+                    if self.is_boolean(class, subroutine, condition) {
+                        writeln!(self.writer, "not")?;
+                    } else {
+                        writeln!(self.writer, "push constant 0")?;
+                        writeln!(self.writer, "eq")?;
+                    }
+                }
+                writeln!(self.writer, "if-goto {}", target)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the last & or | applied, with what it was applied to
+    fn split_logical(expr: &Expr) -> Option<(Expr, Op, Expr)> {
+        match (expr.ops.split_last(), expr.term.as_ref()) {
+            (Some(((op, right), ops)), _) => matches!(op, Op::And | Op::Or).then(|| {
+                let left = Expr {
+                    term: expr.term.clone(),
+                    ops: ops.to_vec(),
+                };
+                (left, *op, Self::term_expr(right))
+            }),
+            (None, Term::Bracketed(expr)) => Self::split_logical(expr),
+            (None, Term::Binary(left, op, right)) if matches!(op, Op::And | Op::Or) => {
+                Some((Self::term_expr(left), *op, Self::term_expr(right)))
+            }
+            (None, _) => None,
+        }
+    }
+
+    fn term_expr(term: &Term) -> Expr {
+        Expr {
+            term: Box::new(term.clone()),
+            ops: Vec::new(),
+        }
+    }
+
+    // true if the expression can only be 0 or -1
+    fn is_boolean(&self, class: &str, subroutine: &str, expr: &Expr) -> bool {
+        match expr.ops.last() {
+            Some((Op::Lt | Op::Gt | Op::Eq, _)) => true,
+            Some((Op::And | Op::Or, _)) => {
+                Self::split_logical(expr).is_some_and(|(left, _, right)| {
+                    self.is_boolean(class, subroutine, &left)
+                        && self.is_boolean(class, subroutine, &right)
+                })
+            }
+            Some(_) => false,
+            None => self.is_boolean_term(class, subroutine, &expr.term),
+        }
+    }
+
+    fn is_boolean_term(&self, class: &str, subroutine: &str, term: &Term) -> bool {
+        match term {
+            Term::True | Term::False => true,
+            Term::Var { name, index: None } => {
+                match self.symbol_table.lookup_var(class, subroutine, name) {
+                    Ok((_, type_name, _)) => type_name == Type::Boolean,
+                    Err(_) => matches!(
+                        self.symbol_table.lookup_constant(class, name),
+                        Ok((Type::Boolean, _))
+                    ),
+                }
+            }
+            Term::Constant { class, name } => matches!(
+                self.symbol_table.lookup_constant(class, name),
+                Ok((Type::Boolean, _))
+            ),
+            Term::Unary(UnaryOp::Not, term) => self.is_boolean_term(class, subroutine, term),
+            // only calls into classes being compiled can be looked up
+            Term::SubroutineCall {
+                qualifier, name, ..
+            } => {
+                let target = match qualifier {
+                    None => Some(class.to_string()),
+                    Some(target) if Self::starts_with_upper(target) => Some(target.to_string()),
+                    Some(target) => match self.symbol_table.lookup_var(class, subroutine, target) {
+                        Ok((_, Type::Class(target), _)) => Some(target),
+                        _ => None,
+                    },
+                };
+                target.is_some_and(|target| {
+                    matches!(
+                        self.symbol_table.lookup_return_type(&target, name),
+                        Ok(Some(Type::Boolean))
+                    )
+                })
+            }
+            Term::Bracketed(expr) => self.is_boolean(class, subroutine, expr),
+            Term::Binary(left, op, right) => match op {
+                Op::Lt | Op::Gt | Op::Eq => true,
+                Op::And | Op::Or => {
+                    self.is_boolean_term(class, subroutine, left)
+                        && self.is_boolean_term(class, subroutine, right)
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
     pub fn emit_do_statement(&mut self, class: &str, subroutine: &str, expr: &Expr) -> Result<()> {
         self.emit_expr(class, subroutine, expr)?;
 
@@ -235,23 +584,32 @@ where
 
         for (op, term) in &expr.ops {
             self.emit_term(class, subroutine, term)?;
-            let instr = match op {
-                Op::Add => "add",
-                Op::Sub => "sub",
-                Op::Mult => "call Math.multiply 2",
-                Op::Div => "call Math.divide 2",
-                Op::And => "and",
-                Op::Or => "or",
-                Op::Lt => "lt",
-                Op::Gt => "gt",
-                Op::Eq => "eq",
-            };
-            writeln!(self.writer, "{}", instr)?;
+            self.emit_op(*op)?;
         }
 
         Ok(())
     }
 
+    fn emit_op(&mut self, op: Op) -> Result<()> {
+        let instr = match op {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mult if self.options.extended_vm => "mul",
+            Op::Div if self.options.extended_vm => "div",
+            Op::Mult => "call Math.multiply 2",
+            Op::Div => "call Math.divide 2",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Lt => "lt",
+            Op::Gt => "gt",
+            Op::Eq => "eq",
+            Op::ShiftLeft => "shl",
+            Op::ShiftRight => "shr",
+        };
+        writeln!(self.writer, "{}", instr)?;
+        Ok(())
+    }
+
     pub fn emit_term(&mut self, class: &str, subroutine: &str, term: &Term) -> Result<()> {
         match term {
             Term::IntegerLit(n) => writeln!(self.writer, "push constant {}", n)?,
@@ -263,7 +621,21 @@ where
             Term::False => writeln!(self.writer, "push constant 0")?,
             Term::Null => writeln!(self.writer, "push constant 0")?,
             Term::This => writeln!(self.writer, "push pointer 0")?,
+            // constants are class level, so any variable hides them
+            Term::Var { name, index: None }
+                if self
+                    .symbol_table
+                    .lookup_var(class, subroutine, name)
+                    .is_err() =>
+            {
+                let (_, value) = self.symbol_table.lookup_constant(class, name)?;
+                self.emit_constant(value)?;
+            }
             Term::Var { name, index } => self.emit_var_ref(class, subroutine, name, index)?,
+            Term::Constant { class, name } => {
+                let (_, value) = self.symbol_table.lookup_constant(class, name)?;
+                self.emit_constant(value)?;
+            }
             Term::Bracketed(expr) => self.emit_expr(class, subroutine, expr)?,
             Term::Unary(op, term) => {
                 self.emit_term(class, subroutine, term.as_ref())?;
@@ -284,10 +656,31 @@ where
                 name,
                 exprs,
             )?,
+            Term::Binary(left, op, right) => {
+                self.emit_term(class, subroutine, left)?;
+                self.emit_term(class, subroutine, right)?;
+                self.emit_op(*op)?;
+            }
         };
         Ok(())
     }
 
+    // push constant only takes 0 to 32767
+    fn emit_constant(&mut self, value: i16) -> Result<()> {
+        match value {
+            i16::MIN => {
+                writeln!(self.writer, "push constant {}", i16::MAX)?;
+                writeln!(self.writer, "not")?;
+            }
+            n if n < 0 => {
+                writeln!(self.writer, "push constant {}", n.unsigned_abs())?;
+                writeln!(self.writer, "neg")?;
+            }
+            n => writeln!(self.writer, "push constant {}", n)?,
+        }
+        Ok(())
+    }
+
     pub fn emit_string_lit(&mut self, string: &str) -> Result<()> {
         writeln!(self.writer, "push constant {}", string.len())?;
         writeln!(self.writer, "call String.new 1")?;
@@ -305,6 +698,13 @@ where
         name: &str,
         index: &Option<Expr>,
     ) -> Result<()> {
+        if let Some(index) = index.as_ref().filter(|_| self.options.checks) {
+            self.emit_checked_address(class, subroutine, name, index)?;
+            writeln!(self.writer, "pop pointer 1")?;
+            writeln!(self.writer, "push that 0")?;
+            return Ok(());
+        }
+
         let (reftype, _, number) = self.symbol_table.lookup_var(class, subroutine, name)?;
         match reftype {
             RefType::ClassRefType(ClassVarDecorator::Static) => {
@@ -331,6 +731,66 @@ where
         Ok(())
     }
 
+    // leaves the address of name[index] on the stack after checking that
+    // the array isn't null and that index is within the size Memory.alloc
+    // keeps in the word before every block it hands out. An array that
+    // didn't come from Memory.alloc, like let screen = 16384, is checked
+    // against whatever word is before it, and 0 for the whole ram counts
+    // as null. temp 0 and 1 are only used once the index is worked out, so
+    // nested accesses are fine
+    fn emit_checked_address(
+        &mut self,
+        class: &str,
+        subroutine: &str,
+        name: &str,
+        index: &Expr,
+    ) -> Result<()> {
+        self.emit_var_ref(class, subroutine, name, &None)?;
+        self.emit_expr(class, subroutine, index)?;
+        writeln!(self.writer, "pop temp 1")?;
+        writeln!(self.writer, "pop temp 0")?;
+        self.emit_check(
+            &["push temp 0", "push constant 0", "eq", "not"],
+            NULL_ARRAY_ERROR,
+        )?;
+        self.emit_check(
+            &["push temp 1", "push constant 1", "neg", "gt"],
+            INDEX_ERROR,
+        )?;
+        self.emit_check(
+            &[
+                "push temp 0",
+                "push constant 1",
+                "sub",
+                "pop pointer 1",
+                "push that 0",
+                "push temp 1",
+                "gt",
+            ],
+            INDEX_ERROR,
+        )?;
+        writeln!(self.writer, "push temp 0")?;
+        writeln!(self.writer, "push temp 1")?;
+        writeln!(self.writer, "add")?;
+        Ok(())
+    }
+
+    // the test leaves true on the stack when all is well, otherwise
+    // Sys.error halts with the code
+    fn emit_check(&mut self, test: &[&str], code: u16) -> Result<()> {
+        let label = format!("CHECK{}", self.check_label_number);
+        self.check_label_number += 1;
+        for command in test {
+            writeln!(self.writer, "{}", command)?;
+        }
+        writeln!(self.writer, "if-goto {}", label)?;
+        writeln!(self.writer, "push constant {}", code)?;
+        writeln!(self.writer, "call Sys.error 1")?;
+        writeln!(self.writer, "pop temp 0")?;
+        writeln!(self.writer, "label {}", label)?;
+        Ok(())
+    }
+
     fn emit_subroutine_call(
         &mut self,
         class: &str,
@@ -361,6 +821,14 @@ where
                         index: None,
                     },
                 )?;
+                if self.options.checks {
+                    writeln!(self.writer, "pop temp 0")?;
+                    self.emit_check(
+                        &["push temp 0", "push constant 0", "eq", "not"],
+                        NULL_RECEIVER_ERROR,
+                    )?;
+                    writeln!(self.writer, "push temp 0")?;
+                }
                 let (_, type_name, _) = self.symbol_table.lookup_var(class, subroutine, target)?;
                 match type_name {
                     Type::Class(class) => Ok((class, expr_count + 1)),
@@ -369,6 +837,8 @@ where
             }
         };
 
+        self.check_call(&target_class, name, args > expr_count, expr_count)?;
+
         for expr in exprs {
             self.emit_expr(class, subroutine, expr)?;
         }
@@ -377,6 +847,42 @@ where
         Ok(())
     }
 
+    // calls into the classes being compiled and into the os are checked
+    // against the subroutine's declaration. Any other class is left to
+    // the vm translator to find
+    fn check_call(
+        &self,
+        target_class: &str,
+        name: &str,
+        through_object: bool,
+        arg_count: usize,
+    ) -> Result<()> {
+        let known = self.symbol_table.classes.contains_key(target_class)
+            || vm::os::is_os_class(target_class);
+        if !known {
+            return Ok(());
+        }
+        let full_name = format!("{}.{}", target_class, name);
+        let (decorator, params, _) = self
+            .symbol_table
+            .lookup_signature(target_class, name)
+            .map_err(|_| CodeGenError::UnknownSubroutine(full_name.clone()))?;
+        match decorator {
+            SubroutineDecorator::Method if !through_object => {
+                Err(CodeGenError::MethodCalledAsFunction(full_name))?
+            }
+            SubroutineDecorator::Function | SubroutineDecorator::Constructor if through_object => {
+                Err(CodeGenError::FunctionCalledAsMethod(full_name))?
+            }
+            _ if params.len() != arg_count => Err(CodeGenError::WrongArgumentCount(
+                full_name,
+                params.len(),
+                arg_count,
+            ))?,
+            _ => Ok(()),
+        }
+    }
+
     fn starts_with_upper(target: &str) -> bool {
         target.starts_with(|c: char| c.is_ascii_uppercase())
     }
@@ -386,4 +892,348 @@ where
 pub enum CodeGenError {
     #[error("Attempt to call a method on a primitive value")]
     InvalidMethodTarget,
+    #[error("No subroutine {0}")]
+    UnknownSubroutine(String),
+    #[error("{0} is a method but was called without an object")]
+    MethodCalledAsFunction(String),
+    #[error("{0} is a function or constructor but was called through an object")]
+    FunctionCalledAsMethod(String),
+    #[error("{0} takes {1} arguments but was given {2}")]
+    WrongArgumentCount(String, usize, usize),
+}
+
+// counts the lines written through it, so the emitter knows which vm
+// line each statement starts on
+struct LineCounter<W> {
+    inner: W,
+    lines: usize,
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|&&b| b == b'\n').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser::Parser, tokenizer::Tokenizer};
+
+    // the classes compiled together, with the vm code and warnings of
+    // the last one
+    fn emit(sources: &[&str], options: Options) -> Result<(String, Vec<String>)> {
+        let mut symbol_table = SymbolTable::new();
+        let mut classes = Vec::new();
+        for source in sources {
+            let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+            classes.push(Parser::new(tokens, options).parse_class(&mut symbol_table)?);
+        }
+        let mut vm = Vec::new();
+        let mut warnings = Vec::new();
+        for class in &classes {
+            vm.clear();
+            let mut emitter = Emitter::new(BufWriter::new(&mut vm), &symbol_table, options);
+            emitter.emit_class(class)?;
+            warnings = emitter.warnings().to_vec();
+        }
+        Ok((String::from_utf8(vm)?, warnings))
+    }
+
+    // the code for the statements of f, without the function command
+    // and the return
+    fn body(statements: &str, options: Options) -> (Vec<String>, Vec<String>) {
+        let source = format!(
+            "class A {{
+                function void f(boolean a, boolean b, boolean c, int x, int y) {{
+                    {}
+                    return;
+                }}
+                function void g() {{ return; }}
+            }}",
+            statements
+        );
+        let (vm, warnings) = emit(&[&source], options).unwrap();
+        let lines = vm.lines().map(str::to_string).collect::<Vec<_>>();
+        let end = lines
+            .iter()
+            .position(|line| line == "function A.g 0")
+            .unwrap();
+        (lines[1..end - 2].to_vec(), warnings)
+    }
+
+    fn short_circuit() -> Options {
+        Options {
+            short_circuit: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_short_circuit_and() {
+        let (vm, warnings) = body("if (a & b) { do A.g(); }", short_circuit());
+        assert_eq!(
+            vm,
+            [
+                "push argument 0",
+                "not",
+                "if-goto IF_FALSE0",
+                "push argument 1",
+                "not",
+                "if-goto IF_FALSE0",
+                "call A.g 0",
+                "pop temp 0",
+                "label IF_FALSE0",
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_short_circuit_or() {
+        let (vm, _) = body("while (a | b) { do A.g(); }", short_circuit());
+        assert_eq!(
+            vm,
+            [
+                "label WHILE_EXP0",
+                "push argument 0",
+                "if-goto SKIP0",
+                "push argument 1",
+                "not",
+                "if-goto WHILE_END0",
+                "label SKIP0",
+                "call A.g 0",
+                "pop temp 0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_circuit_nested() {
+        let (vm, _) = body("if ((a & b) | c) { do A.g(); }", short_circuit());
+        assert_eq!(
+            vm,
+            [
+                // a & b true skips c, a false tries c
+                "push argument 0",
+                "not",
+                "if-goto SKIP1",
+                "push argument 1",
+                "if-goto SKIP0",
+                "label SKIP1",
+                "push argument 2",
+                "not",
+                "if-goto IF_FALSE0",
+                "label SKIP0",
+                "call A.g 0",
+                "pop temp 0",
+                "label IF_FALSE0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_circuit_bitwise() {
+        // x alone can't decide x & y, so it stays bitwise
+        let (vm, warnings) = body("if (x & y) { do A.g(); }", short_circuit());
+        assert_eq!(
+            vm,
+            [
+                "push argument 3",
+                "push argument 4",
+                "and",
+                "push constant 0",
+                "eq",
+                "if-goto IF_FALSE0",
+                "call A.g 0",
+                "pop temp 0",
+                "label IF_FALSE0",
+            ]
+        );
+        assert_eq!(
+            warnings,
+            ["in A.f & in a condition is bitwise since its left side isn't a boolean"]
+        );
+
+        // and without -short-circuit & is always bitwise, with no warning
+        let (vm, warnings) = body("if (a & b) { do A.g(); }", Options::default());
+        assert_eq!(vm[..3], ["push argument 0", "push argument 1", "and"]);
+        assert!(warnings.is_empty());
+    }
+
+    fn extended() -> Options {
+        Options {
+            extended: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let (vm, _) = body(
+            "while (a) {
+                for (let x = 0; x < y; let x += 1) {
+                    if (b) { continue; }
+                    break;
+                }
+                if (c) { break; }
+                continue;
+            }",
+            extended(),
+        );
+        assert_eq!(
+            vm,
+            [
+                "label WHILE_EXP0",
+                "push argument 0",
+                "not",
+                "if-goto WHILE_END0",
+                "push constant 0",
+                "pop argument 3",
+                "label WHILE_EXP1",
+                "push argument 3",
+                "push argument 4",
+                "lt",
+                "not",
+                "if-goto WHILE_END1",
+                "push argument 1",
+                "if-goto IF_TRUE0",
+                "goto IF_FALSE0",
+                "label IF_TRUE0",
+                // continue in a for runs the step
+                "goto WHILE_STEP1",
+                "label IF_FALSE0",
+                "goto WHILE_END1",
+                "label WHILE_STEP1",
+                "push argument 3",
+                "push constant 1",
+                "add",
+                "pop argument 3",
+                "goto WHILE_EXP1",
+                "label WHILE_END1",
+                // back out in the while
+                "push argument 2",
+                "if-goto IF_TRUE1",
+                "goto IF_FALSE1",
+                "label IF_TRUE1",
+                "goto WHILE_END0",
+                "label IF_FALSE1",
+                "goto WHILE_EXP0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_else_if() {
+        let (vm, _) = body(
+            "if (a) { let x = 1; } else if (b) { let x = 2; } else { let x = 3; }",
+            extended(),
+        );
+        assert_eq!(
+            vm,
+            [
+                "push argument 0",
+                "if-goto IF_TRUE0",
+                "goto IF_FALSE0",
+                "label IF_TRUE0",
+                "push constant 1",
+                "pop argument 3",
+                "goto IF_END0",
+                "label IF_FALSE0",
+                "push argument 1",
+                "if-goto IF_TRUE1",
+                "goto IF_FALSE1",
+                "label IF_TRUE1",
+                "push constant 2",
+                "pop argument 3",
+                "goto IF_END1",
+                "label IF_FALSE1",
+                "push constant 3",
+                "pop argument 3",
+                "label IF_END1",
+                "label IF_END0",
+            ]
+        );
+    }
+
+    // the error from compiling A, which has the statement in a function,
+    // along with the other classes
+    fn call_error(others: &[&str], statement: &str) -> Option<String> {
+        let source = format!(
+            "class A {{ function void f(B b) {{ {} return; }} }}",
+            statement
+        );
+        let mut sources = others.to_vec();
+        sources.push(&source);
+        emit(&sources, Options::default())
+            .err()
+            .map(|error| error.downcast::<CodeGenError>().unwrap().to_string())
+    }
+
+    const B: &str = "class B {
+        function void g() { return; }
+        method void m(int x) { return; }
+        constructor B new() { return this; }
+    }";
+
+    #[test]
+    fn test_check_call() {
+        let error = |statement| call_error(&[B], statement);
+        assert_eq!(error("do B.g(); do b.m(1); let b = B.new();"), None);
+        assert_eq!(error("do B.h();"), Some("No subroutine B.h".to_string()));
+        assert_eq!(
+            error("do B.m(1);"),
+            Some("B.m is a method but was called without an object".to_string())
+        );
+        assert_eq!(
+            error("do b.g();"),
+            Some("B.g is a function or constructor but was called through an object".to_string())
+        );
+        assert_eq!(
+            error("let b = b.new();"),
+            Some("B.new is a function or constructor but was called through an object".to_string())
+        );
+        assert_eq!(
+            error("do b.m();"),
+            Some("B.m takes 1 arguments but was given 0".to_string())
+        );
+        assert_eq!(
+            error("do B.g(1, 2);"),
+            Some("B.g takes 0 arguments but was given 2".to_string())
+        );
+        // classes that aren't being compiled are left alone
+        assert_eq!(error("do C.anything(1, 2, 3);"), None);
+    }
+
+    #[test]
+    fn test_check_os_call() {
+        let error = |statement| call_error(&[B], statement);
+        assert_eq!(error("do Output.printInt(Math.multiply(2, 3));"), None);
+        assert_eq!(
+            error("do Math.multiply(2);"),
+            Some("Math.multiply takes 2 arguments but was given 1".to_string())
+        );
+        assert_eq!(
+            error("do Math.twice(2);"),
+            Some("No subroutine Math.twice".to_string())
+        );
+        // a class being compiled hides the os class of the same name
+        let math = "class Math { function int twice(int x) { return x + x; } }";
+        let error = |statement| call_error(&[B, math], statement);
+        assert_eq!(error("do Math.twice(2);"), None);
+        assert_eq!(
+            error("do Math.multiply(2, 3);"),
+            Some("No subroutine Math.multiply".to_string())
+        );
+    }
 }
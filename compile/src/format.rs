@@ -0,0 +1,653 @@
+// prints a parsed class back out as jack source in one consistent style:
+// four space indents, braces on the same line, a space around binary
+// operators and after commas. Blank lines are kept, at most one at a time.
+// The ast alone would lose too much, so the printer walks the source
+// tokens in step with it. Each token it prints is matched with the next
+// source token, which is where comments, the spelling of literals ('A',
+// 0x1F, escapes) and the order of declarations come from
+use crate::{
+    ast::*,
+    tokenizer::{Comment, Position, Token, Tokenizer},
+};
+
+pub fn format_class(class: &Class, source: &str, extended: bool) -> String {
+    let mut tokenizer = Tokenizer::new(source.chars().map(Ok), extended).with_comments();
+    let mut tokens = Vec::new();
+    while let Some(Ok(token)) = tokenizer.next() {
+        tokens.push((token, tokenizer.start()));
+    }
+
+    let mut formatter = Formatter {
+        lines: source.lines().map(|line| line.chars().collect()).collect(),
+        tokens,
+        comments: tokenizer.comments().to_vec(),
+        extended,
+        cursor: 0,
+        next_comment: 0,
+        last_line: None,
+        output: String::new(),
+        line: String::new(),
+        line_indent: 0,
+        indent: 0,
+        line_break: if source.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        },
+    };
+    formatter.class(class);
+    formatter.output
+}
+
+struct Formatter {
+    lines: Vec<Vec<char>>,
+    tokens: Vec<(Token, Position)>,
+    comments: Vec<Comment>,
+    extended: bool,
+    // the next source token and comment to be printed
+    cursor: usize,
+    next_comment: usize,
+    // the source line of whatever was printed last
+    last_line: Option<usize>,
+    output: String,
+    // the line being built and the indent it started at
+    line: String,
+    line_indent: usize,
+    indent: usize,
+    line_break: &'static str,
+}
+
+impl Formatter {
+    fn class(&mut self, class: &Class) {
+        self.token("class");
+        self.space();
+        self.token(&class.name);
+        self.space();
+        self.open_block();
+
+        // consts and vars are kept apart in the ast, the source says how
+        // they mix
+        let mut vars = class.vars.iter();
+        let mut consts = class.consts.iter();
+        loop {
+            match self.tokens.get(self.cursor).map(|(token, _)| token) {
+                Some(Token::Keyword("static" | "field")) => match vars.next() {
+                    Some(var) => self.class_var_dec(var),
+                    None => break,
+                },
+                Some(Token::Keyword("const" | "enum")) => match consts.next() {
+                    Some(const_dec) => self.const_dec(const_dec),
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+
+        for (i, subroutine) in class.subroutines.iter().enumerate() {
+            if i > 0 || !class.vars.is_empty() || !class.consts.is_empty() {
+                self.blank_line();
+            }
+            self.subroutine(subroutine);
+        }
+        self.close_block();
+        self.end_line();
+
+        // anything after the class
+        self.comments_before(None);
+        self.end_line();
+    }
+
+    fn class_var_dec(&mut self, var: &ClassVarDecl) {
+        self.start_line();
+        self.token(match var.decorator {
+            ClassVarDecorator::Static => "static",
+            ClassVarDecorator::Field => "field",
+        });
+        self.space();
+        self.type_name(&var.type_name);
+        self.space();
+        self.names(&var.declarations);
+        self.token(";");
+    }
+
+    fn const_dec(&mut self, const_dec: &ConstDecl) {
+        self.start_line();
+        match const_dec {
+            ConstDecl::Const {
+                type_name,
+                name,
+                expr,
+            } => {
+                self.token("const");
+                self.space();
+                self.type_name(type_name);
+                self.space();
+                self.token(name);
+                self.space();
+                self.token("=");
+                self.space();
+                self.expr(expr);
+            }
+            ConstDecl::Enum { members } => {
+                self.token("enum");
+                self.space();
+                self.token("{");
+                self.space();
+                for (i, (name, expr)) in members.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                        self.space();
+                    }
+                    self.token(name);
+                    if let Some(expr) = expr {
+                        self.space();
+                        self.token("=");
+                        self.space();
+                        self.expr(expr);
+                    }
+                }
+                self.space();
+                self.token("}");
+            }
+        }
+        self.token(";");
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.start_line();
+        self.token(match subroutine.decorator {
+            SubroutineDecorator::Constructor => "constructor",
+            SubroutineDecorator::Function => "function",
+            SubroutineDecorator::Method => "method",
+        });
+        self.space();
+        match &subroutine.type_name {
+            Some(type_name) => self.type_name(type_name),
+            None => self.token("void"),
+        }
+        self.space();
+        self.token(&subroutine.name);
+        self.token("(");
+        for (i, param) in subroutine.params.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+                self.space();
+            }
+            self.type_name(&param.type_name);
+            self.space();
+            self.token(&param.name);
+        }
+        self.token(")");
+        self.space();
+        self.open_block();
+        for var in &subroutine.vars {
+            self.start_line();
+            self.token("var");
+            self.space();
+            self.type_name(&var.type_name);
+            self.space();
+            self.names(&var.declarations);
+            self.token(";");
+        }
+        self.statements(&subroutine.statements);
+        self.close_block();
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.start_line();
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.open_block();
+        self.statements(statements);
+        self.close_block();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { name, index, expr } => {
+                self.assignment(name, index, "=", expr);
+                self.token(";");
+            }
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => {
+                self.assignment(name, index, &format!("{}=", op.symbol()), expr);
+                self.token(";");
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+            } => {
+                self.token("if");
+                self.space();
+                self.condition(condition);
+                self.space();
+                self.block(statements);
+                if let Some(else_statements) = else_statements {
+                    self.space();
+                    self.token("else");
+                    self.space();
+                    // else if is one if with no braces around it
+                    match (else_statements.as_slice(), self.next_token()) {
+                        ([statement], Some(Token::Keyword("if"))) => self.statement(statement),
+                        _ => self.block(else_statements),
+                    }
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+            } => {
+                self.token("while");
+                self.space();
+                self.condition(condition);
+                self.space();
+                self.block(statements);
+            }
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => {
+                self.token("for");
+                self.space();
+                self.token("(");
+                if let Some(init) = init {
+                    self.statement_without_semicolon(init);
+                }
+                self.token(";");
+                self.space();
+                self.expr(condition);
+                self.token(";");
+                if let Some(step) = step {
+                    self.space();
+                    self.statement_without_semicolon(step);
+                }
+                self.token(")");
+                self.space();
+                self.block(statements);
+            }
+            Statement::Do { expr } => {
+                self.token("do");
+                self.space();
+                self.expr(expr);
+                self.token(";");
+            }
+            Statement::Return { expr } => {
+                self.token("return");
+                if let Some(expr) = expr {
+                    self.space();
+                    self.expr(expr);
+                }
+                self.token(";");
+            }
+            Statement::Break => {
+                self.token("break");
+                self.token(";");
+            }
+            Statement::Continue => {
+                self.token("continue");
+                self.token(";");
+            }
+        }
+    }
+
+    // the init and step of a for are lets without the semicolon
+    fn statement_without_semicolon(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { name, index, expr } => self.assignment(name, index, "=", expr),
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => self.assignment(name, index, &format!("{}=", op.symbol()), expr),
+            _ => self.statement(statement),
+        }
+    }
+
+    fn assignment(&mut self, name: &str, index: &Option<Expr>, op: &str, expr: &Expr) {
+        self.token("let");
+        self.space();
+        self.token(name);
+        if let Some(index) = index {
+            self.token("[");
+            self.expr(index);
+            self.token("]");
+        }
+        self.space();
+        // += and -= are two tokens
+        for symbol in op.chars() {
+            self.token(&symbol.to_string());
+        }
+        self.space();
+        self.expr(expr);
+    }
+
+    fn condition(&mut self, condition: &Expr) {
+        self.token("(");
+        self.expr(condition);
+        self.token(")");
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.term(&expr.term);
+        for (op, term) in &expr.ops {
+            self.space();
+            self.token(op.symbol());
+            self.space();
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::IntegerLit(n) => self.token(&n.to_string()),
+            Term::StringLit(s) => self.token(&format!("\"{}\"", s)),
+            Term::True => self.token("true"),
+            Term::False => self.token("false"),
+            Term::Null => self.token("null"),
+            Term::This => self.token("this"),
+            Term::Var { name, index } => {
+                self.token(name);
+                if let Some(index) = index {
+                    self.token("[");
+                    self.expr(index);
+                    self.token("]");
+                }
+            }
+            Term::Bracketed(expr) => self.condition(expr),
+            Term::Unary(op, term) => {
+                self.token(match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "~",
+                });
+                self.term(term);
+            }
+            Term::SubroutineCall {
+                qualifier,
+                name,
+                exprs,
+            } => {
+                if let Some(qualifier) = qualifier {
+                    self.token(qualifier);
+                    self.token(".");
+                }
+                self.token(name);
+                self.token("(");
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                        self.space();
+                    }
+                    self.expr(expr);
+                }
+                self.token(")");
+            }
+            Term::Constant { class, name } => {
+                self.token(class);
+                self.token(".");
+                self.token(name);
+            }
+            Term::Binary(left, op, right) => {
+                self.term(left);
+                self.space();
+                self.token(op.symbol());
+                self.space();
+                self.term(right);
+            }
+        }
+    }
+
+    fn type_name(&mut self, type_name: &Type) {
+        match type_name {
+            Type::Int => self.token("int"),
+            Type::Char => self.token("char"),
+            Type::Boolean => self.token("boolean"),
+            Type::Class(class) => self.token(class),
+        }
+    }
+
+    fn names(&mut self, names: &[String]) {
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+                self.space();
+            }
+            self.token(name);
+        }
+    }
+
+    fn open_block(&mut self) {
+        self.token("{");
+        self.indent += 1;
+    }
+
+    // comments before the } belong inside the block
+    fn close_block(&mut self) {
+        let position = self.tokens.get(self.cursor).map(|(_, position)| *position);
+        self.comments_before(position);
+        self.end_line();
+        self.indent -= 1;
+        self.token("}");
+    }
+
+    fn next_token(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(token, _)| token)
+    }
+
+    // prints one source token. The text is what the ast says, except
+    // that literals are copied from the source as they were written
+    fn token(&mut self, text: &str) {
+        let position = self.tokens.get(self.cursor).map(|(_, position)| *position);
+        self.comments_before(position);
+        if let Some((token, position)) = self.tokens.get(self.cursor).cloned() {
+            let text = match token {
+                Token::IntegerLiteral(_) | Token::StringLiteral(_) => self.spelling(position),
+                _ => text.to_string(),
+            };
+            self.write(&text);
+            self.last_line = Some(position.line);
+            self.cursor += 1;
+            self.trailing_comments(position.line);
+        } else {
+            self.write(text);
+        }
+    }
+
+    // comments after the last token on its line, which stay where they are.
+    // Line comments on the lines after one, starting in the same column,
+    // carry it on and stay lined up under it
+    fn trailing_comments(&mut self, line: usize) {
+        let next = self.tokens.get(self.cursor).map(|(_, position)| *position);
+        // the source and printed columns of a trailing line comment
+        let mut carried = None;
+        while let Some(comment) = self.comments.get(self.next_comment).copied() {
+            if next.is_some_and(|next| comment.start >= next) {
+                break;
+            }
+            let text = self.comment_text(comment);
+            if comment.start.line == line {
+                let column = self.line_indent * 4 + self.line.trim_end().chars().count() + 1;
+                carried = text[0]
+                    .starts_with("//")
+                    .then_some((comment.start.column, column));
+                self.comments_before(Some(Position {
+                    column: comment.start.column + 1,
+                    ..comment.start
+                }));
+            } else if carried.is_some_and(|(source_column, _)| {
+                Some(comment.start.line) == self.last_line.map(|line| line + 1)
+                    && comment.start.column == source_column
+                    && text[0].starts_with("//")
+            }) {
+                let (_, column) = carried.unwrap();
+                self.next_comment += 1;
+                self.write(&" ".repeat(column.saturating_sub(self.indent * 4)));
+                self.line.push_str(&text[0]);
+                self.end_line();
+                self.last_line = Some(comment.end.line);
+            } else {
+                break;
+            }
+        }
+    }
+
+    // the comments that come before a position, or all that are left.
+    // One that shares a line with the token before it stays at the end
+    // of that line, the rest go on lines of their own
+    fn comments_before(&mut self, position: Option<Position>) {
+        while let Some(comment) = self.comments.get(self.next_comment).copied() {
+            if position.is_some_and(|position| comment.start >= position) {
+                break;
+            }
+            self.next_comment += 1;
+
+            let text = self.comment_text(comment);
+            if self.last_line == Some(comment.start.line)
+                && !self.line.is_empty()
+                && comment.start.line == comment.end.line
+            {
+                self.space();
+                self.line.push_str(&text[0]);
+            } else {
+                self.end_line();
+                if self
+                    .last_line
+                    .is_some_and(|line| comment.start.line > line + 1)
+                {
+                    self.blank_line();
+                }
+                for line in &text {
+                    self.write(line);
+                    self.end_line();
+                }
+            }
+            self.last_line = Some(comment.end.line);
+            // nothing can follow a line comment on the same line
+            if text[0].starts_with("//") {
+                self.end_line();
+            }
+        }
+    }
+
+    // the lines of a comment, with the lines after the first one lined
+    // up with the indent, and the * of a doc comment one space in
+    fn comment_text(&self, comment: Comment) -> Vec<String> {
+        let mut text = Vec::new();
+        for line in comment.start.line..=comment.end.line {
+            let chars = self.lines.get(line).cloned().unwrap_or_default();
+            let start = if line == comment.start.line {
+                comment.start.column
+            } else {
+                0
+            };
+            let end = if line == comment.end.line {
+                comment.end.column.min(chars.len())
+            } else {
+                chars.len()
+            };
+            let part = chars[start.min(end)..end].iter().collect::<String>();
+            if line == comment.start.line {
+                text.push(part.trim_end().to_string());
+            } else if part.trim_start().starts_with('*') {
+                text.push(format!(" {}", part.trim()));
+            } else {
+                text.push(part.trim().to_string());
+            }
+        }
+        text
+    }
+
+    // a literal as written: a number or 'c' up to the next character
+    // that can't be part of it, a string up to its closing quote
+    fn spelling(&self, position: Position) -> String {
+        let chars = &self.lines[position.line][position.column..];
+        let length = match chars.first() {
+            Some('"') => {
+                let mut i = 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += if chars[i] == '\\' && self.extended {
+                        2
+                    } else {
+                        1
+                    };
+                }
+                i + 1
+            }
+            Some('\'') => {
+                if chars.get(1) == Some(&'\\') {
+                    4
+                } else {
+                    3
+                }
+            }
+            _ => chars.iter().take_while(|c| c.is_alphanumeric()).count(),
+        };
+        chars[..length.min(chars.len())].iter().collect()
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.line.is_empty() {
+            self.line_indent = self.indent;
+        }
+        self.line.push_str(text);
+    }
+
+    fn space(&mut self) {
+        if !self.line.is_empty() && !self.line.ends_with(' ') {
+            self.line.push(' ');
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.output.push_str(&"    ".repeat(self.line_indent));
+            self.output.push_str(line.trim_end());
+            self.output.push_str(self.line_break);
+        }
+    }
+
+    // one blank line at most, and none straight after an opening brace
+    fn blank_line(&mut self) {
+        self.end_line();
+        let line_break = self.line_break;
+        let previous = self
+            .output
+            .strip_suffix(line_break)
+            .map(|output| output.rsplit(line_break).next().unwrap_or_default());
+        if previous.is_some_and(|previous| !previous.is_empty() && !previous.ends_with('{')) {
+            self.output.push_str(line_break);
+        }
+    }
+
+    // a new declaration or statement, after a blank line if the source had one
+    fn start_line(&mut self) {
+        self.end_line();
+        let next = match (
+            self.comments.get(self.next_comment),
+            self.tokens.get(self.cursor),
+        ) {
+            (Some(comment), Some((_, position))) if comment.start < *position => {
+                Some(comment.start)
+            }
+            (_, Some((_, position))) => Some(*position),
+            _ => None,
+        };
+        if let (Some(next), Some(last)) = (next, self.last_line) {
+            if next.line > last + 1 {
+                self.blank_line();
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+// testing interactive programs such as projects/12/KeyboardTest: a
+// script of key presses is played into the keyboard while the program
+// runs, and what ends up on its screen is compared with a picture of
+// what should. Times are in steps of the computer running the program,
+// so a script plays out the same way every time
+use anyhow::{anyhow, bail, Context, Result};
+use asm::{
+    gif::{self, Image},
+    keyboard, screen,
+};
+use std::{fs, iter::Peekable, ops::Range, path::Path, slice};
+
+use crate::run::Computer;
+
+// how long a tapped key is held down, and then how long the keyboard is
+// left empty for, unless a script says otherwise. Keyboard.readChar waits
+// for a key to go down and then for it to come up again
+pub const TAP: usize = 50_000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Script {
+    // the step the keyboard changes at and the key it then holds, 0 being
+    // no key
+    events: Vec<(usize, i16)>,
+    end: usize,
+    hold: usize,
+    gap: usize,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            end: 0,
+            hold: TAP,
+            gap: TAP,
+        }
+    }
+
+    // how long taps after this hold their key for
+    pub fn hold(mut self, steps: usize) -> Self {
+        self.hold = steps;
+        self
+    }
+
+    // how long taps after this leave the keyboard empty for. A program
+    // that echoes each key needs time to before the next one comes
+    pub fn gap(mut self, steps: usize) -> Self {
+        self.gap = steps;
+        self
+    }
+
+    pub fn wait(mut self, steps: usize) -> Self {
+        self.end += steps;
+        self
+    }
+
+    pub fn press(mut self, key: i16) -> Self {
+        self.events.push((self.end, key));
+        self
+    }
+
+    pub fn release(self) -> Self {
+        self.press(0)
+    }
+
+    pub fn tap(self, key: i16) -> Self {
+        let (hold, gap) = (self.hold, self.gap);
+        self.press(key).wait(hold).release().wait(gap)
+    }
+
+    // each character tapped in turn, with \n as the enter key
+    pub fn type_text(self, text: &str) -> Self {
+        text.chars().fold(self, |script, c| match c {
+            '\n' => script.tap(keyboard::NEWLINE),
+            c => script.tap(c as i16),
+        })
+    }
+
+    // the steps until the last of it
+    pub fn len(&self) -> usize {
+        self.end
+    }
+
+    // whether it has no keys in it
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // a line for each of the above, with keys by name as in
+    // keyboard::key_named or by number, and # starting a comment:
+    //   gap 1000000
+    //   wait 1000000
+    //   tap PAGE_DOWN
+    //   type JACK
+    //   tap NEWLINE
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut script = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let key = || match keyboard::key_named(argument.trim()) {
+                Some(key) => Ok(key),
+                None => argument
+                    .trim()
+                    .parse::<i16>()
+                    .with_context(|| format!("line {}: no key {:?}", i + 1, argument)),
+            };
+            let steps = || {
+                argument
+                    .trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("line {}: bad number of steps", i + 1))
+            };
+            script = match command {
+                "wait" => script.wait(steps()?),
+                "hold" => script.hold(steps()?),
+                "gap" => script.gap(steps()?),
+                "press" => script.press(key()?),
+                "release" => script.release(),
+                "tap" => script.tap(key()?),
+                "type" => script.type_text(argument),
+                _ => bail!("line {}: unknown command {:?}", i + 1, command),
+            };
+        }
+        Ok(script)
+    }
+}
+
+// plays a script into a keyboard a step at a time, for running a program
+// some other way than run does
+pub struct Player<'a> {
+    events: Peekable<slice::Iter<'a, (usize, i16)>>,
+    step: usize,
+}
+
+impl<'a> Player<'a> {
+    pub fn new(script: &'a Script) -> Self {
+        Self {
+            events: script.events.iter().peekable(),
+            step: 0,
+        }
+    }
+
+    // sets the keyboard for the next step
+    pub fn play(&mut self, ram: &mut [i16]) {
+        let step = self.step;
+        while let Some((_, key)) = self.events.next_if(|&&(at, _)| at <= step) {
+            ram[screen::KEYBOARD] = *key;
+        }
+        self.step += 1;
+    }
+}
+
+// runs the program with the script played into its keyboard, until it
+// halts or has run for steps, and returns the steps it ran
+pub fn run(computer: &mut dyn Computer, script: &Script, steps: usize) -> Result<usize> {
+    let mut player = Player::new(script);
+    for step in 0..steps {
+        player.play(computer.ram());
+        if computer.halted() {
+            return Ok(step);
+        }
+        computer.step()?;
+    }
+    Ok(steps)
+}
+
+// a picture of the screen to compare against: a .gif such as the
+// course's screenshots, or a .pbm as jackrun -snapshot writes
+pub fn read_image(path: &Path) -> Result<Image> {
+    let data = fs::read(path).with_context(|| path.to_string_lossy().to_string())?;
+    let image = if data.starts_with(b"P4") {
+        read_pbm(&data)
+    } else {
+        gif::read(&data).map_err(|error| anyhow!("{:?}", error))
+    };
+    image.with_context(|| path.to_string_lossy().to_string())
+}
+
+fn read_pbm(data: &[u8]) -> Result<Image> {
+    // the magic number, width and height, each ended by one whitespace
+    // character, and then the rows packed into bytes
+    let mut fields = Vec::new();
+    let mut at = 0;
+    while fields.len() < 3 {
+        let length = data
+            .get(at..)
+            .and_then(|rest| rest.iter().position(|c| c.is_ascii_whitespace()))
+            .context("bad pbm header")?;
+        if length > 0 {
+            fields.push(std::str::from_utf8(&data[at..at + length])?);
+        }
+        at += length + 1;
+    }
+    let width: usize = fields[1].parse()?;
+    let height: usize = fields[2].parse()?;
+    let row_bytes = width.div_ceil(8);
+    if data.len() < at + row_bytes * height {
+        bail!("the pbm ends too soon");
+    }
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .map(|(y, x)| data[at + y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0)
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Comparison {
+    // pixels of one with nothing of their colour near where they fall on
+    // the other
+    pub differences: usize,
+    // black pixels on the picture
+    pub black: usize,
+}
+
+impl Comparison {
+    // whether the differences are within a fraction of the black pixels,
+    // 0 for an exact match
+    pub fn matches(&self, tolerance: f64) -> bool {
+        self.differences as f64 <= tolerance * self.black as f64
+    }
+}
+
+// how far, in pixels of a screenshot, a pixel can be from where it falls
+// and still count. The course's screenshots are scaled down a little
+// unevenly, so they can't be lined up any closer than this
+const NEAR: usize = 2;
+
+// compares the screen with a picture of it. A 512 by 256 picture is
+// compared pixel for pixel. Anything else is taken to be a screenshot with
+// the screen scaled down inside it, as the course's are, so where the
+// screen falls on it is worked out first, and a pixel only differs if
+// there's none of its colour near where it falls
+pub fn compare(ram: &[i16], image: &Image) -> Comparison {
+    if (image.width, image.height) == (screen::WIDTH, screen::HEIGHT) {
+        let differences = (0..screen::HEIGHT)
+            .flat_map(|y| (0..screen::WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| screen::pixel(ram, x, y) != image.pixel(x, y))
+            .count();
+        let black = image.pixels.iter().filter(|&&pixel| pixel).count();
+        return Comparison { differences, black };
+    }
+
+    let image = &without_frame(image);
+    let black = image.pixels.iter().filter(|&&pixel| pixel).count();
+    let screen_pixel = |x: usize, y: usize| screen::pixel(ram, x, y);
+    let image_pixel = |x: usize, y: usize| image.pixel(x, y);
+    let across = Axis::fit(
+        &counts(screen::WIDTH, screen::HEIGHT, screen_pixel),
+        &counts(image.width, image.height, image_pixel),
+    );
+    let down = Axis::fit(
+        &counts(screen::HEIGHT, screen::WIDTH, |y, x| screen_pixel(x, y)),
+        &counts(image.height, image.width, |y, x| image_pixel(x, y)),
+    );
+
+    // a pixel of the picture differs if the screen around where it falls
+    // has none of its colour, what's past the edge of the screen being
+    // white
+    let on_screen = |x: usize, y: usize, colour: bool| {
+        let ((xs, x_edge), (ys, y_edge)) =
+            (across.near(x, screen::WIDTH), down.near(y, screen::HEIGHT));
+        (!colour && (x_edge || y_edge))
+            || ys
+                .flat_map(|sy| xs.clone().map(move |sx| (sx, sy)))
+                .any(|(sx, sy)| screen_pixel(sx, sy) == colour)
+    };
+    let mut differences = (0..image.height)
+        .flat_map(|y| (0..image.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| !on_screen(x, y, image_pixel(x, y)))
+        .count();
+
+    // and so does black on the screen where the picture has none, which
+    // a thin line can be without any one pixel of the picture showing it
+    let on_image = |x: usize, y: usize| {
+        let (ix, iy) = (across.place(x), down.place(y));
+        (iy.saturating_sub(NEAR)..(iy + NEAR + 1).min(image.height))
+            .flat_map(|y| {
+                (ix.saturating_sub(NEAR)..(ix + NEAR + 1).min(image.width)).map(move |x| (x, y))
+            })
+            .any(|(x, y)| image_pixel(x, y))
+    };
+    differences += (0..screen::HEIGHT)
+        .flat_map(|y| (0..screen::WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| screen_pixel(x, y) && !on_image(x, y))
+        .count();
+    Comparison { differences, black }
+}
+
+// the black pixels in each line across a picture, with a function giving
+// the pixel at a position along and across the lines
+fn counts(length: usize, width: usize, pixel: impl Fn(usize, usize) -> bool) -> Vec<usize> {
+    (0..length)
+        .map(|i| (0..width).filter(|&j| pixel(i, j)).count())
+        .collect()
+}
+
+// a screenshot with any line drawn right along an edge of it taken out,
+// as that's a frame around the screen rather than on it
+fn without_frame(image: &Image) -> Image {
+    let (width, height) = (image.width, image.height);
+    let mut pixels = image.pixels.clone();
+    for x in [0, width - 1] {
+        if (0..height).all(|y| image.pixel(x, y)) {
+            (0..height).for_each(|y| pixels[y * width + x] = false);
+        }
+    }
+    for y in [0, height - 1] {
+        if (0..width).all(|x| image.pixel(x, y)) {
+            (0..width).for_each(|x| pixels[y * width + x] = false);
+        }
+    }
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+// where the screen falls on a picture along one side: a screen pixel's
+// position times scale plus offset
+#[derive(Clone, Copy, Debug)]
+struct Axis {
+    scale: f64,
+    offset: f64,
+}
+
+impl Axis {
+    // the scale and offset that best line up the black pixels counted
+    // along the side of the screen with those along the side of the
+    // picture, trying scales from a half up to 1 in steps of 1/500 and
+    // offsets in quarter pixels
+    fn fit(screen: &[usize], image: &[usize]) -> Self {
+        let mut best = (
+            0.0,
+            Self {
+                scale: (image.len() as f64 / screen.len() as f64).min(1.0),
+                offset: 0.0,
+            },
+        );
+        for step in 250..=500 {
+            let scale = step as f64 / 500.0;
+            let margin = image.len() as f64 - scale * screen.len() as f64;
+            for quarter in 0..=(margin * 4.0).max(-1.0) as i64 {
+                let axis = Self {
+                    scale,
+                    offset: quarter as f64 / 4.0,
+                };
+                // the cosine of the angle between the two, leaving out the
+                // picture's length as it's the same for all of them
+                let (mut product, mut length) = (0, 0);
+                for (i, &count) in image.iter().enumerate() {
+                    if let Some(&on_screen) = axis.at(i).and_then(|i| screen.get(i)) {
+                        product += count * on_screen;
+                        length += on_screen * on_screen;
+                    }
+                }
+                if length > 0 {
+                    let score = product as f64 / (length as f64).sqrt();
+                    if score > best.0 {
+                        best = (score, axis);
+                    }
+                }
+            }
+        }
+        best.1
+    }
+
+    // the screen pixel at a picture pixel
+    fn at(&self, i: usize) -> Option<usize> {
+        let position = (i as f64 - self.offset) / self.scale;
+        (position >= 0.0).then_some(position as usize)
+    }
+
+    // the picture pixel a screen pixel falls on
+    fn place(&self, i: usize) -> usize {
+        (i as f64 * self.scale + self.offset) as usize
+    }
+
+    // the screen pixels falling near a picture pixel, and whether that
+    // goes past an edge of the screen
+    fn near(&self, i: usize, length: usize) -> (Range<usize>, bool) {
+        let start = ((i as f64 - NEAR as f64 - self.offset) / self.scale).ceil();
+        let end = ((i as f64 + NEAR as f64 + 1.0 - self.offset) / self.scale).ceil();
+        let clamp = |position: f64| (position.max(0.0) as usize).min(length);
+        (clamp(start)..clamp(end), start < 0.0 || end > length as f64)
+    }
+}
@@ -0,0 +1,851 @@
+// the tokens, ast and symbol table as json, for tools that would rather
+// not pick apart the xml. Every document has "version": 1 and the
+// layout of each is given in the README. Spans are zero based lines and
+// columns, the end being just past the last character. The ast can be
+// read back in, which is what the compile binary's -input=ast-json does
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    ast::*,
+    debug_info::{DebugInfo, Function, SourceLine, Variable},
+    parser::const_value,
+    symbol_table::{ClassSymbolTable, SubroutineVarDecorator, SymbolTable},
+    tokenizer::{Span, Token},
+};
+
+const VERSION: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("Unsupported version {0}")]
+    Version(Value),
+    #[error("\"{0}\" is missing or invalid in {1}")]
+    Invalid(String, String),
+    #[error("{0}")]
+    ParseError(ParseError),
+}
+
+impl From<ParseError> for JsonError {
+    fn from(error: ParseError) -> Self {
+        JsonError::ParseError(error)
+    }
+}
+
+pub fn span_json(span: Span) -> Value {
+    json!({
+        "start": { "line": span.start.line, "column": span.start.column },
+        "end": { "line": span.end.line, "column": span.end.column },
+    })
+}
+
+// the kinds are the element names of the xml
+pub fn token_json(token: &Token, span: Span) -> Value {
+    let (kind, value) = match token {
+        Token::IntegerLiteral(n) => ("integerConstant", json!(n)),
+        Token::StringLiteral(s) => ("stringConstant", json!(s)),
+        Token::Identifier(s) => ("identifier", json!(s)),
+        Token::Symbol(c) => ("symbol", json!(c.to_string())),
+        Token::Keyword(s) => ("keyword", json!(s)),
+    };
+    json!({ "kind": kind, "value": value, "span": span_json(span) })
+}
+
+pub fn tokens_json(tokens: Vec<Value>) -> Value {
+    json!({ "version": VERSION, "tokens": tokens })
+}
+
+// spans are only given when they line up with the class, which they
+// don't once the optimizer has been over it
+pub fn class_json(class: &Class, spans: Option<&Spans>) -> Value {
+    let mut writer = AstWriter {
+        spans,
+        vars: 0,
+        consts: 0,
+        subroutines: 0,
+        statements: 0,
+    };
+    let mut class_json = json!({
+        "kind": "class",
+        "name": class.name,
+        "vars": class.vars.iter().map(|var| writer.class_var_dec(var)).collect::<Vec<_>>(),
+        "consts": class.consts.iter().map(|const_dec| writer.const_dec(const_dec)).collect::<Vec<_>>(),
+        "subroutines": class
+            .subroutines
+            .iter()
+            .map(|subroutine| writer.subroutine(subroutine))
+            .collect::<Vec<_>>(),
+    });
+    add_span(&mut class_json, spans.map(|spans| spans.class));
+    json!({ "version": VERSION, "class": class_json })
+}
+
+fn add_span(value: &mut Value, span: Option<Span>) {
+    if let (Value::Object(object), Some(span)) = (value, span) {
+        object.insert("span".to_string(), span_json(span));
+    }
+}
+
+// goes over the class in the order the parser recorded the spans in
+struct AstWriter<'a> {
+    spans: Option<&'a Spans>,
+    vars: usize,
+    consts: usize,
+    subroutines: usize,
+    statements: usize,
+}
+
+fn next_span(spans: Option<&[Span]>, i: &mut usize) -> Option<Span> {
+    let span = spans.and_then(|spans| spans.get(*i)).copied();
+    *i += 1;
+    span
+}
+
+impl AstWriter<'_> {
+    fn class_var_dec(&mut self, var: &ClassVarDecl) -> Value {
+        let mut value = json!({
+            "kind": match var.decorator {
+                ClassVarDecorator::Static => "static",
+                ClassVarDecorator::Field => "field",
+            },
+            "type": type_text(&var.type_name),
+            "names": var.declarations,
+        });
+        let span = next_span(self.spans.map(|spans| &spans.vars[..]), &mut self.vars);
+        add_span(&mut value, span);
+        value
+    }
+
+    fn const_dec(&mut self, const_dec: &ConstDecl) -> Value {
+        let mut value = match const_dec {
+            ConstDecl::Const {
+                type_name,
+                name,
+                expr,
+            } => json!({
+                "kind": "const",
+                "type": type_text(type_name),
+                "name": name,
+                "value": expr_json(expr),
+            }),
+            ConstDecl::Enum { members } => json!({
+                "kind": "enum",
+                "members": members
+                    .iter()
+                    .map(|(name, expr)| json!({ "name": name, "value": expr.as_ref().map(expr_json) }))
+                    .collect::<Vec<_>>(),
+            }),
+        };
+        let span = next_span(self.spans.map(|spans| &spans.consts[..]), &mut self.consts);
+        add_span(&mut value, span);
+        value
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) -> Value {
+        let span = next_span(
+            self.spans.map(|spans| &spans.subroutines[..]),
+            &mut self.subroutines,
+        );
+        let mut value = json!({
+            "kind": decorator_text(subroutine.decorator),
+            "returnType": subroutine.type_name.as_ref().map_or("void".to_string(), type_text),
+            "name": subroutine.name,
+            "params": subroutine
+                .params
+                .iter()
+                .map(|param| json!({ "type": type_text(&param.type_name), "name": param.name }))
+                .collect::<Vec<_>>(),
+            "vars": subroutine
+                .vars
+                .iter()
+                .map(|var| json!({ "type": type_text(&var.type_name), "names": var.declarations }))
+                .collect::<Vec<_>>(),
+            "statements": self.statements(&subroutine.statements),
+        });
+        add_span(&mut value, span);
+        value
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Vec<Value> {
+        statements
+            .iter()
+            .map(|statement| {
+                let span = next_span(
+                    self.spans.map(|spans| &spans.statements[..]),
+                    &mut self.statements,
+                );
+                let mut value = self.statement(statement);
+                add_span(&mut value, span);
+                value
+            })
+            .collect()
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Value {
+        match statement {
+            Statement::Let { name, index, expr } => json!({
+                "kind": "let",
+                "name": name,
+                "index": index.as_ref().map(expr_json),
+                "value": expr_json(expr),
+            }),
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => json!({
+                "kind": "compound",
+                "name": name,
+                "index": index.as_ref().map(expr_json),
+                "op": op.symbol(),
+                "value": expr_json(expr),
+            }),
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+            } => json!({
+                "kind": "if",
+                "condition": expr_json(condition),
+                "statements": self.statements(statements),
+                "else": else_statements.as_ref().map(|statements| self.statements(statements)),
+            }),
+            Statement::While {
+                condition,
+                statements,
+            } => json!({
+                "kind": "while",
+                "condition": expr_json(condition),
+                "statements": self.statements(statements),
+            }),
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => json!({
+                "kind": "for",
+                "init": init.as_ref().map(|init| self.statement(init)),
+                "condition": expr_json(condition),
+                "step": step.as_ref().map(|step| self.statement(step)),
+                "statements": self.statements(statements),
+            }),
+            Statement::Do { expr } => json!({ "kind": "do", "call": expr_json(expr) }),
+            Statement::Return { expr } => {
+                json!({ "kind": "return", "value": expr.as_ref().map(expr_json) })
+            }
+            Statement::Break => json!({ "kind": "break" }),
+            Statement::Continue => json!({ "kind": "continue" }),
+        }
+    }
+}
+
+fn expr_json(expr: &Expr) -> Value {
+    json!({
+        "term": term_json(&expr.term),
+        "ops": expr
+            .ops
+            .iter()
+            .map(|(op, term)| json!({ "op": op.symbol(), "term": term_json(term) }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn term_json(term: &Term) -> Value {
+    match term {
+        Term::IntegerLit(n) => json!({ "kind": "integer", "value": n }),
+        Term::StringLit(s) => json!({ "kind": "string", "value": s }),
+        Term::True => json!({ "kind": "true" }),
+        Term::False => json!({ "kind": "false" }),
+        Term::Null => json!({ "kind": "null" }),
+        Term::This => json!({ "kind": "this" }),
+        Term::Var { name, index } => json!({
+            "kind": "var",
+            "name": name,
+            "index": index.as_ref().map(expr_json),
+        }),
+        Term::Bracketed(expr) => json!({ "kind": "bracketed", "expr": expr_json(expr) }),
+        Term::Unary(op, term) => json!({
+            "kind": "unary",
+            "op": match op {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "~",
+            },
+            "term": term_json(term),
+        }),
+        Term::SubroutineCall {
+            qualifier,
+            name,
+            exprs,
+        } => json!({
+            "kind": "call",
+            "qualifier": qualifier,
+            "name": name,
+            "args": exprs.iter().map(expr_json).collect::<Vec<_>>(),
+        }),
+        Term::Constant { class, name } => {
+            json!({ "kind": "constant", "class": class, "name": name })
+        }
+        Term::Binary(left, op, right) => json!({
+            "kind": "binary",
+            "left": term_json(left),
+            "op": op.symbol(),
+            "right": term_json(right),
+        }),
+    }
+}
+
+fn type_text(type_name: &Type) -> String {
+    match type_name {
+        Type::Int => "int".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Class(class) => class.clone(),
+    }
+}
+
+fn decorator_text(decorator: SubroutineDecorator) -> &'static str {
+    match decorator {
+        SubroutineDecorator::Constructor => "constructor",
+        SubroutineDecorator::Function => "function",
+        SubroutineDecorator::Method => "method",
+    }
+}
+
+pub fn symbol_table_json(symbol_table: &SymbolTable) -> Value {
+    let classes = symbol_table
+        .classes
+        .iter()
+        .map(|(name, table)| class_symbols_json(name, table))
+        .collect::<Vec<_>>();
+    json!({ "version": VERSION, "classes": classes })
+}
+
+pub fn class_symbols_json(name: &str, table: &ClassSymbolTable) -> Value {
+    let class_vars = table
+        .class_vars
+        .iter()
+        .map(|(name, (decorator, type_name, number))| {
+            json!({
+                "name": name,
+                "kind": match decorator {
+                    ClassVarDecorator::Static => "static",
+                    ClassVarDecorator::Field => "field",
+                },
+                "type": type_text(type_name),
+                "number": number,
+            })
+        });
+    let constants = table.constants.iter().map(|(name, (type_name, value))| {
+        json!({ "name": name, "kind": "const", "type": type_text(type_name), "value": value })
+    });
+    let subroutines = table.subroutines.iter().map(|(name, table)| {
+        let vars = table
+            .vars
+            .iter()
+            .map(|(name, (decorator, type_name, number))| {
+                json!({
+                    "name": name,
+                    "kind": match decorator {
+                        SubroutineVarDecorator::Arg => "arg",
+                        SubroutineVarDecorator::Local => "local",
+                    },
+                    "type": type_text(type_name),
+                    "number": number,
+                })
+            });
+        json!({
+            "name": name,
+            "kind": decorator_text(table.decorator),
+            "returnType": table.type_name.as_ref().map_or("void".to_string(), type_text),
+            "symbols": vars.collect::<Vec<_>>(),
+        })
+    });
+    json!({
+        "name": name,
+        "symbols": class_vars.chain(constants).collect::<Vec<_>>(),
+        "subroutines": subroutines.collect::<Vec<_>>(),
+    })
+}
+
+// enters a class written by class_symbols_json. Everything goes in in
+// the order it was written, so the numbers come out the same
+pub fn read_class_symbols(value: &Value, symbol_table: &mut SymbolTable) -> Result<(), JsonError> {
+    symbol_table.enter_class(string(value, "name")?)?;
+    for symbol in array(value, "symbols")? {
+        let name = string(symbol, "name")?;
+        let type_name = read_type(symbol, "type")?;
+        match string(symbol, "kind")?.as_str() {
+            "static" => symbol_table.enter_class_var(name, ClassVarDecorator::Static, type_name)?,
+            "field" => symbol_table.enter_class_var(name, ClassVarDecorator::Field, type_name)?,
+            "const" => {
+                let value = field(symbol, "value")?
+                    .as_i64()
+                    .and_then(|value| i16::try_from(value).ok())
+                    .ok_or_else(|| invalid(symbol, "value"))?;
+                symbol_table.enter_constant(name, type_name, value)?
+            }
+            _ => return Err(invalid(symbol, "kind")),
+        }
+    }
+    for subroutine in array(value, "subroutines")? {
+        let decorator = read_decorator(subroutine)?;
+        let type_name = read_return_type(subroutine)?;
+        symbol_table.enter_subroutine(string(subroutine, "name")?, decorator, type_name)?;
+        for symbol in array(subroutine, "symbols")? {
+            let name = string(symbol, "name")?;
+            let type_name = read_type(symbol, "type")?;
+            match string(symbol, "kind")?.as_str() {
+                "arg" => symbol_table.enter_arg(name, type_name)?,
+                "local" => symbol_table.enter_local(name, type_name)?,
+                _ => return Err(invalid(symbol, "kind")),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn debug_info_json(info: &DebugInfo) -> Value {
+    let variables = |variables: &[Variable]| {
+        variables
+            .iter()
+            .map(|variable| {
+                json!({
+                    "name": variable.name,
+                    "type": type_text(&variable.type_name),
+                    "index": variable.index,
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let functions = info.functions.iter().map(|function| {
+        json!({
+            "name": function.name,
+            "kind": decorator_text(function.decorator),
+            "returnType": function.type_name.as_ref().map_or("void".to_string(), type_text),
+            "vmLine": function.vm_line,
+            "args": variables(&function.args),
+            "locals": variables(&function.locals),
+        })
+    });
+    let lines = info.lines.iter().map(
+        |line| json!({ "line": line.line, "column": line.column, "statement": line.statement }),
+    );
+    json!({
+        "version": VERSION,
+        "class": info.class,
+        "source": info.source,
+        "statics": variables(&info.statics),
+        "fields": variables(&info.fields),
+        "functions": functions.collect::<Vec<_>>(),
+        "lines": lines.collect::<Vec<_>>(),
+    })
+}
+
+pub fn read_debug_info(document: &Value) -> Result<DebugInfo, JsonError> {
+    read_version(document)?;
+    let variables = |value: &Value, key: &str| -> Result<Vec<Variable>, JsonError> {
+        array(value, key)?
+            .iter()
+            .map(|variable| {
+                Ok(Variable {
+                    name: string(variable, "name")?,
+                    type_name: read_type(variable, "type")?,
+                    index: number(variable, "index")?,
+                })
+            })
+            .collect()
+    };
+    let functions = array(document, "functions")?
+        .iter()
+        .map(|function| {
+            Ok(Function {
+                name: string(function, "name")?,
+                decorator: read_decorator(function)?,
+                type_name: read_return_type(function)?,
+                vm_line: number(function, "vmLine")?,
+                args: variables(function, "args")?,
+                locals: variables(function, "locals")?,
+            })
+        })
+        .collect::<Result<_, JsonError>>()?;
+    let lines = array(document, "lines")?
+        .iter()
+        .map(|line| {
+            Ok(SourceLine {
+                line: number(line, "line")?,
+                column: number(line, "column")?,
+                statement: field(line, "statement")?
+                    .as_bool()
+                    .ok_or_else(|| invalid(line, "statement"))?,
+            })
+        })
+        .collect::<Result<_, JsonError>>()?;
+    Ok(DebugInfo {
+        class: string(document, "class")?,
+        source: string(document, "source")?,
+        lines,
+        statics: variables(document, "statics")?,
+        fields: variables(document, "fields")?,
+        functions,
+    })
+}
+
+// reads a document written by class_json and enters its declarations in
+// the symbol table, just as parsing the source would have
+pub fn read_class(document: &Value, symbol_table: &mut SymbolTable) -> Result<Class, JsonError> {
+    read_version(document)?;
+    let class = field(document, "class")?;
+    let name = string(class, "name")?;
+    symbol_table.enter_class(name.clone())?;
+
+    let mut vars = Vec::new();
+    for var in array(class, "vars")? {
+        let decorator = match string(var, "kind")?.as_str() {
+            "static" => ClassVarDecorator::Static,
+            "field" => ClassVarDecorator::Field,
+            _ => return Err(invalid(var, "kind")),
+        };
+        let type_name = read_type(var, "type")?;
+        let declarations = names(var)?;
+        for declaration in &declarations {
+            symbol_table.enter_class_var(declaration.clone(), decorator, type_name.clone())?;
+        }
+        vars.push(ClassVarDecl {
+            decorator,
+            type_name,
+            declarations,
+        });
+    }
+
+    let mut consts = Vec::new();
+    for const_dec in array(class, "consts")? {
+        consts.push(read_const_dec(const_dec, &name, symbol_table)?);
+    }
+
+    let mut subroutines = Vec::new();
+    for subroutine in array(class, "subroutines")? {
+        subroutines.push(read_subroutine(subroutine, &name, symbol_table)?);
+    }
+
+    Ok(Class {
+        name,
+        vars,
+        consts,
+        subroutines,
+    })
+}
+
+fn read_const_dec(
+    value: &Value,
+    class: &str,
+    symbol_table: &mut SymbolTable,
+) -> Result<ConstDecl, JsonError> {
+    match string(value, "kind")?.as_str() {
+        "const" => {
+            let type_name = read_type(value, "type")?;
+            let name = string(value, "name")?;
+            let expr = read_expr(field(value, "value")?)?;
+            let constant = const_value(&expr, class, symbol_table)?;
+            symbol_table.enter_constant(name.clone(), type_name.clone(), constant)?;
+            Ok(ConstDecl::Const {
+                type_name,
+                name,
+                expr,
+            })
+        }
+        "enum" => {
+            let mut members = Vec::new();
+            let mut next_value = 0i16;
+            for member in array(value, "members")? {
+                let name = string(member, "name")?;
+                let expr = optional(member, "value").map(read_expr).transpose()?;
+                let constant = match &expr {
+                    Some(expr) => const_value(expr, class, symbol_table)?,
+                    None => next_value,
+                };
+                symbol_table.enter_constant(name.clone(), Type::Int, constant)?;
+                next_value = constant.wrapping_add(1);
+                members.push((name, expr));
+            }
+            Ok(ConstDecl::Enum { members })
+        }
+        _ => Err(invalid(value, "kind")),
+    }
+}
+
+fn read_subroutine(
+    value: &Value,
+    class: &str,
+    symbol_table: &mut SymbolTable,
+) -> Result<Subroutine, JsonError> {
+    let decorator = read_decorator(value)?;
+    let type_name = read_return_type(value)?;
+    let name = string(value, "name")?;
+    symbol_table.enter_subroutine(name.clone(), decorator, type_name.clone())?;
+    if decorator == SubroutineDecorator::Method {
+        symbol_table.enter_arg("this".to_string(), Type::Class(class.to_string()))?;
+    }
+
+    let mut params = Vec::new();
+    for param in array(value, "params")? {
+        let param = ParamDecl {
+            type_name: read_type(param, "type")?,
+            name: string(param, "name")?,
+        };
+        symbol_table.enter_arg(param.name.clone(), param.type_name.clone())?;
+        params.push(param);
+    }
+
+    let mut vars = Vec::new();
+    for var in array(value, "vars")? {
+        let var = SubroutineVarDecl {
+            type_name: read_type(var, "type")?,
+            declarations: names(var)?,
+        };
+        for declaration in &var.declarations {
+            symbol_table.enter_local(declaration.clone(), var.type_name.clone())?;
+        }
+        vars.push(var);
+    }
+
+    Ok(Subroutine {
+        decorator,
+        type_name,
+        name,
+        params,
+        vars,
+        statements: read_statements(field(value, "statements")?, 0)?,
+    })
+}
+
+// loop_depth is how many loops the statements are in, as break and
+// continue are only valid inside one
+fn read_statements(value: &Value, loop_depth: usize) -> Result<Vec<Statement>, JsonError> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(value, "statements"))?
+        .iter()
+        .map(|statement| read_statement(statement, loop_depth))
+        .collect()
+}
+
+fn read_statement(value: &Value, loop_depth: usize) -> Result<Statement, JsonError> {
+    let index = || optional(value, "index").map(read_expr).transpose();
+    Ok(match string(value, "kind")?.as_str() {
+        "let" => Statement::Let {
+            name: string(value, "name")?,
+            index: index()?,
+            expr: read_expr(field(value, "value")?)?,
+        },
+        "compound" => Statement::Compound {
+            name: string(value, "name")?,
+            index: index()?,
+            op: read_op(value, "op")?,
+            expr: read_expr(field(value, "value")?)?,
+        },
+        "if" => Statement::If {
+            condition: read_expr(field(value, "condition")?)?,
+            statements: read_statements(field(value, "statements")?, loop_depth)?,
+            else_statements: optional(value, "else")
+                .map(|statements| read_statements(statements, loop_depth))
+                .transpose()?,
+        },
+        "while" => Statement::While {
+            condition: read_expr(field(value, "condition")?)?,
+            statements: read_statements(field(value, "statements")?, loop_depth + 1)?,
+        },
+        "for" => Statement::For {
+            init: optional(value, "init")
+                .map(|init| read_statement(init, loop_depth).map(Box::new))
+                .transpose()?,
+            condition: read_expr(field(value, "condition")?)?,
+            step: optional(value, "step")
+                .map(|step| read_statement(step, loop_depth).map(Box::new))
+                .transpose()?,
+            statements: read_statements(field(value, "statements")?, loop_depth + 1)?,
+        },
+        "do" => Statement::Do {
+            expr: read_expr(field(value, "call")?)?,
+        },
+        "return" => Statement::Return {
+            expr: optional(value, "value").map(read_expr).transpose()?,
+        },
+        "break" | "continue" if loop_depth == 0 => return Err(ParseError::NotInLoop.into()),
+        "break" => Statement::Break,
+        "continue" => Statement::Continue,
+        _ => return Err(invalid(value, "kind")),
+    })
+}
+
+fn read_expr(value: &Value) -> Result<Expr, JsonError> {
+    let term = read_term(field(value, "term")?)?;
+    let mut ops = Vec::new();
+    for op in array(value, "ops")? {
+        ops.push((read_op(op, "op")?, read_term(field(op, "term")?)?));
+    }
+    Ok(Expr {
+        term: Box::new(term),
+        ops,
+    })
+}
+
+fn read_term(value: &Value) -> Result<Term, JsonError> {
+    Ok(match string(value, "kind")?.as_str() {
+        "integer" => Term::IntegerLit(
+            field(value, "value")?
+                .as_u64()
+                .filter(|n| *n <= i16::MAX as u64)
+                .ok_or_else(|| invalid(value, "value"))? as u16,
+        ),
+        "string" => Term::StringLit(string(value, "value")?),
+        "true" => Term::True,
+        "false" => Term::False,
+        "null" => Term::Null,
+        "this" => Term::This,
+        "var" => Term::Var {
+            name: string(value, "name")?,
+            index: optional(value, "index").map(read_expr).transpose()?,
+        },
+        "bracketed" => Term::Bracketed(read_expr(field(value, "expr")?)?),
+        "unary" => {
+            let op = match string(value, "op")?.as_str() {
+                "-" => UnaryOp::Neg,
+                "~" => UnaryOp::Not,
+                _ => return Err(invalid(value, "op")),
+            };
+            Term::Unary(op, Box::new(read_term(field(value, "term")?)?))
+        }
+        "call" => Term::SubroutineCall {
+            qualifier: optional(value, "qualifier")
+                .map(|qualifier| {
+                    qualifier
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid(value, "qualifier"))
+                })
+                .transpose()?,
+            name: string(value, "name")?,
+            exprs: array(value, "args")?
+                .iter()
+                .map(read_expr)
+                .collect::<Result<_, _>>()?,
+        },
+        "constant" => Term::Constant {
+            class: string(value, "class")?,
+            name: string(value, "name")?,
+        },
+        "binary" => Term::Binary(
+            Box::new(read_term(field(value, "left")?)?),
+            read_op(value, "op")?,
+            Box::new(read_term(field(value, "right")?)?),
+        ),
+        _ => return Err(invalid(value, "kind")),
+    })
+}
+
+fn read_op(value: &Value, key: &str) -> Result<Op, JsonError> {
+    Ok(match string(value, key)?.as_str() {
+        "+" => Op::Add,
+        "-" => Op::Sub,
+        "*" => Op::Mult,
+        "/" => Op::Div,
+        "&" => Op::And,
+        "|" => Op::Or,
+        "<" => Op::Lt,
+        ">" => Op::Gt,
+        "=" => Op::Eq,
+        "<<" => Op::ShiftLeft,
+        ">>" => Op::ShiftRight,
+        _ => return Err(invalid(value, key)),
+    })
+}
+
+fn read_version(document: &Value) -> Result<(), JsonError> {
+    match document.get("version") {
+        Some(version) if version.as_u64() == Some(VERSION) => Ok(()),
+        Some(version) => Err(JsonError::Version(version.clone())),
+        None => Err(invalid(document, "version")),
+    }
+}
+
+fn read_decorator(value: &Value) -> Result<SubroutineDecorator, JsonError> {
+    Ok(match string(value, "kind")?.as_str() {
+        "constructor" => SubroutineDecorator::Constructor,
+        "function" => SubroutineDecorator::Function,
+        "method" => SubroutineDecorator::Method,
+        _ => return Err(invalid(value, "kind")),
+    })
+}
+
+fn read_return_type(value: &Value) -> Result<Option<Type>, JsonError> {
+    match string(value, "returnType")?.as_str() {
+        "void" => Ok(None),
+        _ => read_type(value, "returnType").map(Some),
+    }
+}
+
+fn read_type(value: &Value, key: &str) -> Result<Type, JsonError> {
+    Ok(match string(value, key)?.as_str() {
+        "int" => Type::Int,
+        "char" => Type::Char,
+        "boolean" => Type::Boolean,
+        "" | "void" => return Err(invalid(value, key)),
+        class => Type::Class(class.to_string()),
+    })
+}
+
+fn names(value: &Value) -> Result<Vec<String>, JsonError> {
+    array(value, "names")?
+        .iter()
+        .map(|name| {
+            name.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid(value, "names"))
+        })
+        .collect()
+}
+
+fn invalid(value: &Value, key: &str) -> JsonError {
+    let mut within = value.to_string();
+    if within.len() > 60 {
+        within = within.chars().take(60).collect::<String>() + "...";
+    }
+    JsonError::Invalid(key.to_string(), within)
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, JsonError> {
+    value
+        .as_object()
+        .and_then(|object| object.get(key))
+        .ok_or_else(|| invalid(value, key))
+}
+
+// missing and null are both nothing
+fn optional<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value
+        .as_object()
+        .and_then(|object| object.get(key))
+        .filter(|value| !value.is_null())
+}
+
+fn string(value: &Value, key: &str) -> Result<String, JsonError> {
+    field(value, key)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| invalid(value, key))
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, JsonError> {
+    field(value, key)?
+        .as_array()
+        .ok_or_else(|| invalid(value, key))
+}
+
+fn number(value: &Value, key: &str) -> Result<usize, JsonError> {
+    field(value, key)?
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| invalid(value, key))
+}
@@ -0,0 +1,19 @@
+// the compiler front and back end, shared by the compile, jackc, jackdbg,
+// jackrun, jackprof, jack-lsp and jackfmt binaries, and a harness for
+// testing programs it builds
+pub mod ast;
+pub mod build;
+pub mod debug_info;
+pub mod emitter;
+pub mod format;
+pub mod harness;
+pub mod json;
+pub mod lint;
+pub mod optimizer;
+pub mod options;
+pub mod parser;
+pub mod profile;
+pub mod run;
+pub mod symbol_table;
+pub mod tokenizer;
+pub mod xml;
@@ -0,0 +1,591 @@
+// checks for code that compiles but is probably wrong. It runs on the
+// unoptimized ast once every class is parsed, so calls into other
+// classes can be checked against the whole symbol table. Each rule can
+// be switched on by name and adds its name to the warnings it gives
+use std::collections::HashSet;
+
+use crate::{
+    ast::*,
+    symbol_table::{RefType, SymbolTable},
+};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Rule {
+    // a local or argument that is never read
+    UnusedVariable,
+    // a local read before the first let that sets it
+    ReadBeforeLet,
+    // the end of a subroutine can be reached without a return
+    MissingReturn,
+    // statements after a return, break or continue
+    UnreachableCode,
+    // do on a call that returns a value
+    DiscardedResult,
+    // a local or argument with the name of a class variable
+    ShadowedField,
+    // a constructor returning something other than this
+    ConstructorReturn,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::UnusedVariable,
+        Rule::ReadBeforeLet,
+        Rule::MissingReturn,
+        Rule::UnreachableCode,
+        Rule::DiscardedResult,
+        Rule::ShadowedField,
+        Rule::ConstructorReturn,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ReadBeforeLet => "read-before-let",
+            Rule::MissingReturn => "missing-return",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::DiscardedResult => "discarded-result",
+            Rule::ShadowedField => "shadowed-field",
+            Rule::ConstructorReturn => "constructor-return",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+// the rules that are switched on, one bit each
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct Rules(u8);
+
+impl Rules {
+    pub fn all() -> Self {
+        Rule::ALL
+            .into_iter()
+            .fold(Self::default(), |rules, rule| rules.with(rule))
+    }
+
+    pub fn with(self, rule: Rule) -> Self {
+        Self(self.0 | 1 << rule as u8)
+    }
+
+    pub fn contains(&self, rule: Rule) -> bool {
+        self.0 & 1 << rule as u8 != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    // rule names separated by commas, as -lint= takes them, or the first
+    // name that isn't a rule
+    pub fn parse(names: &str) -> Result<Self, &str> {
+        names.split(',').try_fold(Self::default(), |rules, name| {
+            Rule::from_name(name)
+                .map(|rule| rules.with(rule))
+                .ok_or(name)
+        })
+    }
+}
+
+pub fn lint_class(class: &Class, symbol_table: &SymbolTable, rules: Rules) -> Vec<String> {
+    let mut lint = Lint {
+        symbol_table,
+        rules,
+        class: &class.name,
+        subroutine: "",
+        locals: HashSet::new(),
+        assigned: HashSet::new(),
+        read: HashSet::new(),
+        warnings: Vec::new(),
+    };
+    for subroutine in &class.subroutines {
+        lint.subroutine(subroutine);
+    }
+    lint.warnings
+}
+
+struct Lint<'a> {
+    symbol_table: &'a SymbolTable,
+    rules: Rules,
+    class: &'a str,
+    subroutine: &'a str,
+    // locals not yet set by a let, in source order, and every name read
+    locals: HashSet<&'a str>,
+    assigned: HashSet<&'a str>,
+    read: HashSet<&'a str>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Lint<'a> {
+    fn warn(&mut self, rule: Rule, message: String) {
+        if self.rules.contains(rule) {
+            self.warnings.push(format!(
+                "in {}.{} {} [{}]",
+                self.class,
+                self.subroutine,
+                message,
+                rule.name()
+            ));
+        }
+    }
+
+    fn subroutine(&mut self, subroutine: &'a Subroutine) {
+        self.subroutine = &subroutine.name;
+        self.locals = subroutine
+            .vars
+            .iter()
+            .flat_map(|var| var.declarations.iter().map(String::as_str))
+            .collect();
+        self.assigned.clear();
+        self.read.clear();
+
+        let args = subroutine
+            .params
+            .iter()
+            .map(|param| ("argument", &param.name));
+        let locals = subroutine
+            .vars
+            .iter()
+            .flat_map(|var| var.declarations.iter().map(|name| ("local", name)));
+        let variables = args.chain(locals).collect::<Vec<_>>();
+
+        for (kind, name) in &variables {
+            if let Some((decorator, _, _)) = self.class_var(name) {
+                let decorator = match decorator {
+                    ClassVarDecorator::Static => "static",
+                    ClassVarDecorator::Field => "field",
+                };
+                self.warn(
+                    Rule::ShadowedField,
+                    format!("{} {} hides the {} of the same name", kind, name, decorator),
+                );
+            }
+        }
+
+        self.statements(&subroutine.statements);
+
+        for (kind, name) in &variables {
+            if !self.read.contains(name.as_str()) {
+                self.warn(
+                    Rule::UnusedVariable,
+                    format!("{} {} is never used", kind, name),
+                );
+            }
+        }
+        if completes(&subroutine.statements) {
+            self.warn(
+                Rule::MissingReturn,
+                "the end can be reached without a return".to_string(),
+            );
+        }
+    }
+
+    fn class_var(&self, name: &str) -> Option<&(ClassVarDecorator, Type, usize)> {
+        self.symbol_table
+            .classes
+            .get(self.class)?
+            .class_vars
+            .get(name)
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) {
+        let mut reported = false;
+        for (i, statement) in statements.iter().enumerate() {
+            if !reported && i + 1 < statements.len() && !completes(std::slice::from_ref(statement))
+            {
+                self.warn(
+                    Rule::UnreachableCode,
+                    "has statements that can never run".to_string(),
+                );
+                reported = true;
+            }
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match statement {
+            Statement::Let { name, index, expr } => {
+                if let Some(index) = index {
+                    self.var(name);
+                    self.expr(index);
+                }
+                self.expr(expr);
+                self.assigned.insert(name);
+            }
+            Statement::Compound {
+                name, index, expr, ..
+            } => {
+                self.var(name);
+                if let Some(index) = index {
+                    self.expr(index);
+                }
+                self.expr(expr);
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+            } => {
+                self.expr(condition);
+                self.statements(statements);
+                if let Some(else_statements) = else_statements {
+                    self.statements(else_statements);
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+            } => {
+                self.expr(condition);
+                self.statements(statements);
+            }
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                self.expr(condition);
+                self.statements(statements);
+                if let Some(step) = step {
+                    self.statement(step);
+                }
+            }
+            Statement::Do { expr } => {
+                self.expr(expr);
+                if let Term::SubroutineCall {
+                    qualifier, name, ..
+                } = expr.term.as_ref()
+                {
+                    if self.returns_value(qualifier.as_deref(), name) {
+                        self.warn(
+                            Rule::DiscardedResult,
+                            format!("the value returned by {} is thrown away", name),
+                        );
+                    }
+                }
+            }
+            Statement::Return { expr } => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+                let this = matches!(expr, Some(Expr { term, ops }) if **term == Term::This && ops.is_empty());
+                if self.is_constructor() && !this {
+                    self.warn(
+                        Rule::ConstructorReturn,
+                        "returns something other than this".to_string(),
+                    );
+                }
+            }
+            Statement::Break | Statement::Continue => (),
+        }
+    }
+
+    fn is_constructor(&self) -> bool {
+        matches!(
+            self.symbol_table
+                .lookup_subroutine(self.class, self.subroutine),
+            Ok((SubroutineDecorator::Constructor, _, _))
+        )
+    }
+
+    // calls into classes that weren't compiled along with this one are
+    // not checked
+    fn returns_value(&self, qualifier: Option<&str>, name: &str) -> bool {
+        let class = match qualifier {
+            None => self.class.to_string(),
+            Some(qualifier) => {
+                match self
+                    .symbol_table
+                    .lookup_var(self.class, self.subroutine, qualifier)
+                {
+                    Ok((_, Type::Class(class), _)) => class,
+                    Ok(_) => return false,
+                    Err(_) => qualifier.to_string(),
+                }
+            }
+        };
+        matches!(
+            self.symbol_table.lookup_return_type(&class, name),
+            Ok(Some(_))
+        )
+    }
+
+    fn var(&mut self, name: &'a str) {
+        self.read.insert(name);
+        if self.locals.contains(name) && !self.assigned.contains(name) {
+            // only the first read is reported
+            self.assigned.insert(name);
+            self.warn(
+                Rule::ReadBeforeLet,
+                format!("local {} is read before it is set", name),
+            );
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        self.term(&expr.term);
+        for (_, term) in &expr.ops {
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &'a Term) {
+        match term {
+            Term::Var { name, index } => {
+                self.var(name);
+                if let Some(index) = index {
+                    self.expr(index);
+                }
+            }
+            Term::Bracketed(expr) => self.expr(expr),
+            Term::Unary(_, term) => self.term(term),
+            Term::SubroutineCall {
+                qualifier, exprs, ..
+            } => {
+                if let Some(qualifier) = qualifier {
+                    if let Ok((RefType::SubroutineRefType(_), _, _)) =
+                        self.symbol_table
+                            .lookup_var(self.class, self.subroutine, qualifier)
+                    {
+                        self.var(qualifier);
+                    }
+                }
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            Term::Binary(left, _, right) => {
+                self.term(left);
+                self.term(right);
+            }
+            _ => (),
+        }
+    }
+}
+
+// whether running the statements can get past the last one. A return,
+// break or continue stops it, so does an if whose branches all stop and
+// a while (true) with no break
+fn completes(statements: &[Statement]) -> bool {
+    statements.iter().all(|statement| match statement {
+        Statement::Return { .. } | Statement::Break | Statement::Continue => false,
+        Statement::If {
+            statements,
+            else_statements: Some(else_statements),
+            ..
+        } => completes(statements) || completes(else_statements),
+        Statement::While {
+            condition,
+            statements,
+        }
+        | Statement::For {
+            condition,
+            statements,
+            ..
+        } => !is_true(condition) || breaks(statements),
+        _ => true,
+    })
+}
+
+fn is_true(expr: &Expr) -> bool {
+    *expr.term == Term::True && expr.ops.is_empty()
+}
+
+// a break out of this loop, and not one nested inside it
+fn breaks(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Break => true,
+        Statement::If {
+            statements,
+            else_statements,
+            ..
+        } => breaks(statements) || else_statements.as_deref().is_some_and(breaks),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{options::Options, parser::Parser, tokenizer::Tokenizer};
+
+    fn lint(source: &str, rule: Rule) -> Vec<String> {
+        let options = Options {
+            extended: true,
+            ..Options::default()
+        };
+        let mut symbol_table = SymbolTable::new();
+        let tokens = Tokenizer::new(source.chars().map(Ok), true);
+        let class = Parser::new(tokens, options)
+            .parse_class(&mut symbol_table)
+            .unwrap();
+        lint_class(&class, &symbol_table, Rules::default().with(rule))
+    }
+
+    #[test]
+    fn test_unused_variable() {
+        assert_eq!(
+            lint(
+                "class A { function int f(int x, int y) { var int z; return x; } }",
+                Rule::UnusedVariable
+            ),
+            vec![
+                "in A.f argument y is never used [unused-variable]",
+                "in A.f local z is never used [unused-variable]"
+            ]
+        );
+        assert!(lint(
+            "class A { function int f(int x) { var int z; let z = x; return z; } }",
+            Rule::UnusedVariable
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_read_before_let() {
+        assert_eq!(
+            lint(
+                "class A { function int f() { var int z; let z = z + 1; return z + z; } }",
+                Rule::ReadBeforeLet
+            ),
+            vec!["in A.f local z is read before it is set [read-before-let]"]
+        );
+        assert!(lint(
+            "class A { function int f(int x) { var int z; let z = x; return z + x; } }",
+            Rule::ReadBeforeLet
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_missing_return() {
+        assert_eq!(
+            lint(
+                "class A { function int f(int x) { if (x) { return 1; } } }",
+                Rule::MissingReturn
+            ),
+            vec!["in A.f the end can be reached without a return [missing-return]"]
+        );
+        assert!(lint(
+            "class A {
+                function int f(int x) { if (x) { return 1; } else { return 2; } }
+                function void g() { while (true) { } }
+            }",
+            Rule::MissingReturn
+        )
+        .is_empty());
+        assert_eq!(
+            lint(
+                "class A { function void g() { while (true) { break; } } }",
+                Rule::MissingReturn
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        assert_eq!(
+            lint(
+                "class A { function void f() { while (true) { break; do A.f(); } return; do A.f(); } }",
+                Rule::UnreachableCode
+            ),
+            vec![
+                "in A.f has statements that can never run [unreachable-code]",
+                "in A.f has statements that can never run [unreachable-code]"
+            ]
+        );
+        assert!(lint(
+            "class A { function void f(int x) { if (x) { return; } do A.f(x); return; } }",
+            Rule::UnreachableCode
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_discarded_result() {
+        let source = "class A {
+            function int g() { return 1; }
+            function void h() { return; }
+            function void f() { do A.g(); do A.h(); do h(); do g(); return; }
+        }";
+        assert_eq!(
+            lint(source, Rule::DiscardedResult),
+            vec!["in A.f the value returned by g is thrown away [discarded-result]"; 2]
+        );
+        // calls to classes that weren't compiled along with this one
+        assert!(lint(
+            "class A { function void f() { do Output.printInt(1); return; } }",
+            Rule::DiscardedResult
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_field() {
+        assert_eq!(
+            lint(
+                "class A { field int x; static int y; method void f(int x) { var int y; return; } }",
+                Rule::ShadowedField
+            ),
+            vec![
+                "in A.f argument x hides the field of the same name [shadowed-field]",
+                "in A.f local y hides the static of the same name [shadowed-field]"
+            ]
+        );
+        assert!(lint(
+            "class A { field int x; method void f(int z) { var int w; return; } }",
+            Rule::ShadowedField
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_constructor_return() {
+        assert_eq!(
+            lint(
+                "class A { constructor A new() { return 0; } }",
+                Rule::ConstructorReturn
+            ),
+            vec!["in A.new returns something other than this [constructor-return]"]
+        );
+        assert!(lint(
+            "class A {
+                constructor A new() { return this; }
+                function int f() { return 0; }
+            }",
+            Rule::ConstructorReturn
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules::parse("missing-return,shadowed-field").unwrap();
+        assert!(rules.contains(Rule::MissingReturn));
+        assert!(rules.contains(Rule::ShadowedField));
+        assert!(!rules.contains(Rule::UnusedVariable));
+        assert_eq!(Rules::parse("missing-return,typo"), Err("typo"));
+        assert!(Rule::ALL.iter().all(|&rule| Rules::all().contains(rule)));
+        assert!(Rules::default().is_empty());
+
+        // only the rules switched on give warnings
+        let source = "class A { field int x; function int f(int x) { } }";
+        let mut symbol_table = SymbolTable::new();
+        let tokens = Tokenizer::new(source.chars().map(Ok), false);
+        let class = Parser::new(tokens, Options::default())
+            .parse_class(&mut symbol_table)
+            .unwrap();
+        assert_eq!(lint_class(&class, &symbol_table, Rules::default()).len(), 0);
+        assert_eq!(lint_class(&class, &symbol_table, rules).len(), 2);
+        assert_eq!(lint_class(&class, &symbol_table, Rules::all()).len(), 3);
+    }
+}
@@ -3,62 +3,164 @@ use ast::Class;
 use std::{
     env::args,
     fs::File,
-    io::{BufReader, BufWriter},
+    io::{BufReader, BufWriter, Write},
     path::{Path, PathBuf},
 };
 use utf8_chars::BufReadCharsExt;
 
-use crate::{emitter::Emitter, parser::Parser, symbol_table::SymbolTable, tokenizer::Tokenizer};
-
-mod ast;
-mod emitter;
-mod parser;
-mod symbol_table;
-mod tokenizer;
-mod xml;
-
-use crate::xml::*;
+use compile::{
+    ast::{self, Spans},
+    build,
+    debug_info::DebugInfo,
+    emitter::Emitter,
+    json,
+    lint::{self, Rules},
+    optimizer,
+    options::Options,
+    parser::Parser,
+    symbol_table::SymbolTable,
+    tokenizer,
+    tokenizer::Tokenizer,
+    xml::*,
+};
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 enum Output {
     Tokens,
     Ast,
     SymbolTable,
+    TokensJson,
+    AstJson,
+    SymbolsJson,
     Vm,
 }
 
+// what the files to compile are, jack source or X.ast.json written by
+// -output=ast-json
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Input {
+    Jack,
+    AstJson,
+}
+
 fn main() -> Result<()> {
     if args().len() < 2 {
         println!("missing file name")
     } else {
         let args = args().collect::<Vec<_>>();
-        let output = if args.len() >= 3 {
-            match args[2].as_str() {
-                "-output=tokens" => Output::Tokens,
-                "-output=ast" => Output::Ast,
-                "-output=symbol-table" => Output::SymbolTable,
-                "-output=vm" => Output::Vm,
-                _ => panic!("Unrecognized option {0}", args[2]),
+        let mut output = Output::Vm;
+        let mut input = Input::Jack;
+        let mut options = Options::default();
+        let mut compare = false;
+        let mut incremental = false;
+        let mut debug_info = false;
+        for arg in &args[2..] {
+            match arg.as_str() {
+                "-output=tokens" => output = Output::Tokens,
+                "-output=ast" => output = Output::Ast,
+                "-output=symbol-table" => output = Output::SymbolTable,
+                "-output=tokens-json" => output = Output::TokensJson,
+                "-output=ast-json" => output = Output::AstJson,
+                "-output=symbols-json" => output = Output::SymbolsJson,
+                "-output=vm" => output = Output::Vm,
+                "-input=ast-json" => input = Input::AstJson,
+                "-extended-vm" => options.extended_vm = true,
+                "-extended" => options.extended = true,
+                "-precedence" => options.precedence = true,
+                "-optimize" => options.optimize = true,
+                "-short-circuit" => options.short_circuit = true,
+                "-checks" => options.checks = true,
+                "-compare" => compare = true,
+                "-incremental" => incremental = true,
+                "-g" => debug_info = true,
+                "-lint" => options.lint = Rules::all(),
+                _ if arg.starts_with("-lint=") => {
+                    options.lint = Rules::parse(&arg["-lint=".len()..])
+                        .unwrap_or_else(|name| panic!("Unrecognized lint rule {0}", name));
+                }
+                _ => panic!("Unrecognized option {0}", arg),
             }
-        } else {
-            Output::Vm
-        };
+        }
         let input_name = args[1].as_str();
         let input_path = Path::new(input_name);
-        let (input_files, symbol_table_output_path) = create_output_path(input_path);
+        let (input_files, symbol_table_output_path) = match input {
+            Input::Jack => create_output_path(input_path, ".jack"),
+            Input::AstJson => {
+                if matches!(
+                    output,
+                    Output::Tokens | Output::TokensJson | Output::AstJson
+                ) {
+                    panic!("-input=ast-json can't be used with -output=tokens, tokens-json or ast-json");
+                }
+                create_output_path(input_path, ".ast.json")
+            }
+        };
+        // what output file names are made from, X.jack either way
+        let bases = input_files
+            .iter()
+            .map(|input_file| match input {
+                Input::Jack => input_file.clone(),
+                Input::AstJson => input_file.with_extension("").with_extension("jack"),
+            })
+            .collect::<Vec<_>>();
+
+        // the debug info is worked out from the spans, which only line up
+        // with the class as it was parsed
+        if debug_info
+            && (output != Output::Vm || input != Input::Jack || options.optimize || incremental)
+        {
+            panic!("-g only compiles jack files to vm, without -optimize or -incremental");
+        }
+
+        if incremental {
+            if output != Output::Vm || input != Input::Jack {
+                panic!("-incremental only compiles jack files to vm");
+            }
+            let directory = symbol_table_output_path.parent().unwrap();
+            let build = build::build(&input_files, &build::cache_path(directory), options)?;
+            for (class, why) in &build.rebuilt {
+                for warning in lint::lint_class(class, &build.symbol_table, options.lint) {
+                    println!("Warning: {}", warning);
+                }
+                println!("Rebuilt {} ({})", class.name, why);
+            }
+            println!("{} classes up to date", build.up_to_date);
+            return Ok(());
+        }
+
         let mut symbol_table = SymbolTable::new();
         let mut classes = Vec::new();
-        for input_file in &input_files {
-            if let Some(class) = compile_file(input_file.as_path(), output, &mut symbol_table)? {
-                let mut output_path = input_file.to_path_buf();
-                output_path.set_extension("vm");
-                classes.push((output_path, class));
+        for (input_file, base) in input_files.iter().zip(&bases) {
+            let compiled = match input {
+                Input::Jack => compile_file(input_file, output, options, &mut symbol_table)?,
+                Input::AstJson => Some(read_ast_json(input_file, &mut symbol_table)?),
+            };
+            if let Some((class, spans)) = compiled {
+                classes.push((base, class, spans));
+            }
+        }
+
+        if !options.lint.is_empty() {
+            for (_, class, _) in &classes {
+                for warning in lint::lint_class(class, &symbol_table, options.lint) {
+                    println!("Warning: {}", warning);
+                }
             }
         }
 
         match output {
             Output::Tokens => (),
-            Output::Ast => (),
+            Output::Ast => {
+                for (input_file, class, _) in classes {
+                    let mut output_path = input_file.to_path_buf();
+                    output_path.set_extension("gen.xml");
+                    println!("Creating {}", output_path.to_string_lossy());
+                    let file = File::create(&output_path)?;
+                    let writer = BufWriter::new(file);
+                    let mut xml = Xml::new(writer);
+                    xml.write_ast(optimize(class, options))?;
+                }
+            }
             Output::SymbolTable => {
                 println!("Creating {}", symbol_table_output_path.to_string_lossy());
                 let file = File::create(&symbol_table_output_path)?;
@@ -66,23 +168,58 @@ fn main() -> Result<()> {
                 let mut xml = Xml::new(writer);
                 xml.write_symbol_table(&symbol_table)?;
             }
+            Output::TokensJson => (),
+            Output::AstJson => {
+                for (input_file, class, spans) in classes {
+                    let output_path = input_file.with_extension("ast.json");
+                    let spans = spans.filter(|_| !options.optimize);
+                    let class = optimize(class, options);
+                    write_json(&output_path, &json::class_json(&class, spans.as_ref()))?;
+                }
+            }
+            Output::SymbolsJson => {
+                let output_path = symbol_table_output_path
+                    .with_extension("")
+                    .with_extension("symbols.json");
+                write_json(&output_path, &json::symbol_table_json(&symbol_table))?;
+            }
             Output::Vm => {
-                for (output_path, class) in classes {
+                for (input_file, class, spans) in classes {
+                    let mut output_path = input_file.to_path_buf();
+                    output_path.set_extension("vm");
                     println!("Creating {}", output_path.to_string_lossy());
                     let file = File::create(&output_path)?;
                     let writer = BufWriter::new(file);
-                    let mut emitter = Emitter::new(writer, &symbol_table);
+                    let mut emitter = Emitter::new(writer, &symbol_table, options);
+                    let class = optimize(class, options);
                     emitter.emit_class(&class)?;
+                    for warning in emitter.warnings() {
+                        println!("Warning: {}", warning);
+                    }
+                    if let Some(spans) = spans.filter(|_| debug_info) {
+                        let source = input_file.file_name().unwrap().to_string_lossy();
+                        let origins = emitter.line_origins();
+                        let info = DebugInfo::new(&source, &class, &spans, &origins, &symbol_table);
+                        let output_path = input_file.with_extension("dbg.json");
+                        write_json(&output_path, &json::debug_info_json(&info))?;
+                    }
                 }
             }
         }
 
-        if output == Output::SymbolTable {
-            println!("Creating {}", symbol_table_output_path.to_string_lossy());
-            let file = File::create(&symbol_table_output_path)?;
-            let writer = BufWriter::new(file);
-            let mut xml = Xml::new(writer);
-            xml.write_symbol_table(&symbol_table)?;
+        if compare {
+            let mut matched = true;
+            for input_file in &bases {
+                let generated_path = match output {
+                    Output::Tokens => tokens_output_path(input_file),
+                    Output::Ast => input_file.with_extension("gen.xml"),
+                    _ => panic!("-compare needs -output=tokens or -output=ast"),
+                };
+                matched &= compare_with_golden(&generated_path)?;
+            }
+            if !matched {
+                std::process::exit(1);
+            }
         }
     }
 
@@ -92,23 +229,18 @@ fn main() -> Result<()> {
 fn compile_file(
     input_path: &Path,
     output: Output,
+    options: Options,
     symbol_table: &mut SymbolTable,
-) -> Result<Option<Class>> {
+) -> Result<Option<(Class, Option<Spans>)>> {
     println!("Compiling {}", input_path.to_string_lossy());
 
     let input_file = File::open(input_path)?;
     let mut reader = BufReader::new(input_file);
 
-    let tokens = Tokenizer::new(reader.chars());
+    let tokens = Tokenizer::new(reader.chars(), options.extended);
     match output {
         Output::Tokens => {
-            let mut path = input_path.to_path_buf();
-            path.set_extension("");
-            let mut name = path.file_name().unwrap().to_string_lossy().to_string();
-            name.push('T');
-            path.set_file_name(name);
-            path.set_extension("gen.xml");
-
+            let path = tokens_output_path(input_path);
             println!("Creating {}", path.to_string_lossy());
             let file = File::create(path)?;
             let writer = BufWriter::new(file);
@@ -129,62 +261,117 @@ fn compile_file(
             xml.end(0, "tokens")?;
             Ok(None)
         }
-        Output::Ast => {
-            let mut parser = Parser::new(tokens);
-            let ast = parser.parse_class(symbol_table)?;
-
-            let mut path = input_path.to_path_buf();
-            path.set_extension("gen.xml");
-
-            println!("Creating {}", path.to_string_lossy());
-            let file = File::create(path)?;
-            let writer = BufWriter::new(file);
-            let mut xml = Xml::new(writer);
-            xml.write_ast(ast)?;
-            Ok(None)
-        }
-        Output::SymbolTable => {
-            let mut parser = Parser::new(tokens);
-            parser.parse_class(symbol_table)?;
+        Output::TokensJson => {
+            let mut tokens = tokens;
+            let mut values = Vec::new();
+            while let Some(token) = tokens.next() {
+                values.push(json::token_json(&token?, tokens.span()));
+            }
+            let path = input_path.with_extension("tokens.json");
+            write_json(&path, &json::tokens_json(values))?;
             Ok(None)
         }
-        Output::Vm => {
-            let mut parser = Parser::new(tokens);
+        _ => {
+            let mut parser = Parser::new(tokens, options);
             let class = parser.parse_class(symbol_table)?;
-            Ok(Some(class))
+            print_warnings(&parser);
+            Ok(Some((class, Some(parser.into_spans()))))
         }
     }
 }
 
-fn create_output_path(input_path: &Path) -> (Vec<PathBuf>, PathBuf) {
-    let base_name = input_path
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .into_owned();
-    let (files, mut symbol_table_output_path) = if input_path.is_file() {
-        (vec![PathBuf::from(input_path)], PathBuf::from(input_path))
-    } else if input_path.is_dir() {
-        let files = input_path
-            .read_dir()
-            .unwrap()
-            .filter_map(|p| {
-                let file = p.unwrap().path();
-                file.extension().and_then(|extn| {
-                    if extn.to_string_lossy().as_ref() == "jack" {
-                        Some(file.clone())
-                    } else {
-                        None
-                    }
+fn read_ast_json(
+    input_path: &Path,
+    symbol_table: &mut SymbolTable,
+) -> Result<(Class, Option<Spans>)> {
+    println!("Reading {}", input_path.to_string_lossy());
+    let document = serde_json::from_reader(BufReader::new(File::open(input_path)?))?;
+    Ok((json::read_class(&document, symbol_table)?, None))
+}
+
+fn write_json(path: &Path, value: &serde_json::Value) -> Result<()> {
+    println!("Creating {}", path.to_string_lossy());
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn tokens_output_path(input_path: &Path) -> PathBuf {
+    let mut path = input_path.to_path_buf();
+    path.set_extension("");
+    let mut name = path.file_name().unwrap().to_string_lossy().to_string();
+    name.push('T');
+    path.set_file_name(name);
+    path.set_extension("gen.xml");
+    path
+}
+
+// checks X.gen.xml against the X.xml next to it the way the course's
+// TextComparer does, ignoring all whitespace, and reports the first
+// line that differs
+fn compare_with_golden(generated_path: &Path) -> Result<bool> {
+    let golden_path = generated_path.with_extension("").with_extension("xml");
+    let lines = |path: &Path| -> Result<Vec<(usize, String)>> {
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split_whitespace().collect::<String>()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect())
+    };
+    let generated = lines(generated_path)?;
+    let golden = lines(&golden_path)?;
+
+    let difference = (0..generated.len().max(golden.len()))
+        .find(|&i| generated.get(i).map(|(_, line)| line) != golden.get(i).map(|(_, line)| line));
+    match difference {
+        None => {
+            println!(
+                "{} matches {}",
+                generated_path.to_string_lossy(),
+                golden_path.to_string_lossy()
+            );
+            Ok(true)
+        }
+        Some(i) => {
+            let line = |lines: &[(usize, String)]| {
+                lines.get(i).map_or("the end".to_string(), |(number, _)| {
+                    format!("line {}", number)
                 })
-            })
-            .collect();
+            };
+            println!(
+                "{} at {} differs from {} at {}",
+                generated_path.to_string_lossy(),
+                line(&generated),
+                golden_path.to_string_lossy(),
+                line(&golden)
+            );
+            Ok(false)
+        }
+    }
+}
 
-        let symbol_table_output_path = PathBuf::from(input_path).join(base_name);
-        (files, symbol_table_output_path)
+fn optimize(class: Class, options: Options) -> Class {
+    if options.optimize {
+        optimizer::optimize_class(class, options)
     } else {
-        panic!("Unable to find {}.", input_path.to_string_lossy());
-    };
-    symbol_table_output_path.set_extension("sym.xml");
-    (files, symbol_table_output_path)
+        class
+    }
+}
+
+fn print_warnings<T>(parser: &Parser<T>)
+where
+    T: Iterator<Item = Result<char, std::io::Error>>,
+{
+    for warning in parser.warnings() {
+        println!("Warning: {}", warning);
+    }
+}
+
+// the files to compile, which end with suffix, and where the symbol
+// table for all of them goes
+fn create_output_path(input_path: &Path, suffix: &str) -> (Vec<PathBuf>, PathBuf) {
+    let (files, base) = vm::files::input_files(input_path, suffix);
+    (files, base.with_extension("sym.xml"))
 }
@@ -0,0 +1,455 @@
+// optimization pass over the ast, run between parsing and emitting.
+// Constant subexpressions are folded with the same 16 bit wraparound
+// the hardware has, operations that do nothing are dropped, multiplying
+// and dividing by powers of two get cheaper forms and if/while
+// statements with constant conditions lose the code that can't run.
+// Expressions keep their shape, so a flat expression is still
+// evaluated left to right and precedence trees stay trees
+use crate::ast::*;
+use crate::options::Options;
+
+pub fn optimize_class(class: Class, options: Options) -> Class {
+    Class {
+        subroutines: class
+            .subroutines
+            .into_iter()
+            .map(|subroutine| Subroutine {
+                statements: optimize_statements(subroutine.statements, options),
+                ..subroutine
+            })
+            .collect(),
+        ..class
+    }
+}
+
+fn optimize_statements(statements: Vec<Statement>, options: Options) -> Vec<Statement> {
+    let mut results = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Let { name, index, expr } => results.push(Statement::Let {
+                name,
+                index: index.map(|index| optimize_expr(index, options)),
+                expr: optimize_expr(expr, options),
+            }),
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+            } => {
+                let condition = optimize_expr(condition, options);
+                let statements = optimize_statements(statements, options);
+                let else_statements =
+                    else_statements.map(|statements| optimize_statements(statements, options));
+                // if-goto jumps on anything other than 0
+                match expr_value(&condition) {
+                    Some(0) => results.extend(else_statements.unwrap_or_default()),
+                    Some(_) => results.extend(statements),
+                    None => results.push(Statement::If {
+                        condition,
+                        statements,
+                        else_statements,
+                    }),
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+            } => {
+                let condition = optimize_expr(condition, options);
+                if expr_value(&condition) != Some(0) {
+                    results.push(Statement::While {
+                        condition,
+                        statements: optimize_statements(statements, options),
+                    })
+                }
+            }
+            Statement::Do { expr } => results.push(Statement::Do {
+                expr: optimize_expr(expr, options),
+            }),
+            Statement::Return { expr } => results.push(Statement::Return {
+                expr: expr.map(|expr| optimize_expr(expr, options)),
+            }),
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => results.push(Statement::Compound {
+                name,
+                index: index.map(|index| optimize_expr(index, options)),
+                op,
+                expr: optimize_expr(expr, options),
+            }),
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => {
+                let init = init.map(|init| optimize_let(*init, options));
+                let condition = optimize_expr(condition, options);
+                if expr_value(&condition) == Some(0) {
+                    results.extend(init.map(|init| *init));
+                } else {
+                    results.push(Statement::For {
+                        init,
+                        condition,
+                        step: step.map(|step| optimize_let(*step, options)),
+                        statements: optimize_statements(statements, options),
+                    })
+                }
+            }
+            Statement::Break | Statement::Continue => results.push(statement),
+        }
+    }
+    results
+}
+
+// for init and step, which are always a single let
+fn optimize_let(statement: Statement, options: Options) -> Box<Statement> {
+    Box::new(optimize_statements(vec![statement], options).pop().unwrap())
+}
+
+fn optimize_expr(expr: Expr, options: Options) -> Expr {
+    let mut term = optimize_term(*expr.term, options);
+    let mut ops: Vec<(Op, Term)> = Vec::new();
+
+    for (op, right) in expr.ops {
+        let mut right = optimize_term(right, options);
+
+        // while nothing has been applied, term is the value so far and
+        // can be folded with or replaced by the right hand side
+        if ops.is_empty() {
+            if let Some(result) = fold(op, value(&term), value(&right)) {
+                term = constant(result);
+                continue;
+            }
+            if is_left_identity(op, value(&term)) {
+                term = right;
+                continue;
+            }
+            if op == Op::Mult && power_of_two(value(&term)).is_some() && is_simple(&right) {
+                std::mem::swap(&mut term, &mut right);
+            }
+        }
+        if is_right_identity(op, value(&right)) {
+            continue;
+        }
+
+        match (op, power_of_two(value(&right))) {
+            (Op::Mult, Some(shift)) if options.extended_vm => {
+                ops.push((Op::ShiftLeft, Term::IntegerLit(shift)));
+            }
+            // x+x+x+x is still cheaper than a call to Math.multiply
+            (Op::Mult, Some(shift)) if shift <= 3 && ops.is_empty() && is_simple(&term) => {
+                for _ in 1..(1 << shift) {
+                    ops.push((Op::Add, term.clone()));
+                }
+            }
+            // a plain arithmetic shift rounds negative numbers down, so
+            // they're first biased by divisor-1 to round toward zero
+            // like Math.divide
+            (Op::Div, Some(shift)) if options.extended_vm && ops.is_empty() && is_simple(&term) => {
+                let bias = Expr {
+                    term: Box::new(term.clone()),
+                    ops: vec![
+                        (Op::Lt, Term::IntegerLit(0)),
+                        (Op::And, Term::IntegerLit((1 << shift) - 1)),
+                    ],
+                };
+                ops.push((Op::Add, Term::Bracketed(bias)));
+                ops.push((Op::ShiftRight, Term::IntegerLit(shift)));
+            }
+            _ => ops.push((op, right)),
+        }
+    }
+
+    Expr {
+        term: Box::new(term),
+        ops,
+    }
+}
+
+fn optimize_term(term: Term, options: Options) -> Term {
+    match term {
+        Term::Var { name, index } => Term::Var {
+            name,
+            index: index.map(|index| optimize_expr(index, options)),
+        },
+        Term::Bracketed(expr) => {
+            let expr = optimize_expr(expr, options);
+            if expr.ops.is_empty() {
+                *expr.term
+            } else {
+                Term::Bracketed(expr)
+            }
+        }
+        Term::Unary(op, term) => {
+            let term = optimize_term(*term, options);
+            match (op, value(&term)) {
+                (UnaryOp::Neg, Some(n)) => constant(n.wrapping_neg()),
+                (UnaryOp::Not, Some(n)) => constant(!n),
+                _ => Term::Unary(op, Box::new(term)),
+            }
+        }
+        Term::SubroutineCall {
+            qualifier,
+            name,
+            exprs,
+        } => Term::SubroutineCall {
+            qualifier,
+            name,
+            exprs: exprs
+                .into_iter()
+                .map(|expr| optimize_expr(expr, options))
+                .collect(),
+        },
+        // optimized as a one op expression then put back together as a
+        // left leaning tree, which evaluates the same way
+        Term::Binary(left, op, right) => {
+            let expr = optimize_expr(
+                Expr {
+                    term: left,
+                    ops: vec![(op, *right)],
+                },
+                options,
+            );
+            expr.ops.into_iter().fold(*expr.term, |left, (op, right)| {
+                Term::Binary(Box::new(left), op, Box::new(right))
+            })
+        }
+        term => term,
+    }
+}
+
+fn value(term: &Term) -> Option<i16> {
+    match term {
+        Term::IntegerLit(n) => Some(*n as i16),
+        Term::True => Some(-1),
+        Term::False | Term::Null => Some(0),
+        Term::Unary(UnaryOp::Neg, term) => value(term).map(i16::wrapping_neg),
+        Term::Unary(UnaryOp::Not, term) => value(term).map(|n| !n),
+        _ => None,
+    }
+}
+
+fn expr_value(expr: &Expr) -> Option<i16> {
+    if expr.ops.is_empty() {
+        value(&expr.term)
+    } else {
+        None
+    }
+}
+
+// the shortest term for a value. Negative values still need a neg or
+// not at runtime since push constant only takes 0 to 32767
+fn constant(n: i16) -> Term {
+    match n {
+        -1 => Term::True,
+        i16::MIN => Term::Unary(UnaryOp::Not, Box::new(Term::IntegerLit(i16::MAX as u16))),
+        n if n < 0 => Term::Unary(UnaryOp::Neg, Box::new(Term::IntegerLit(n.unsigned_abs()))),
+        n => Term::IntegerLit(n as u16),
+    }
+}
+
+// division by zero is left for Math.divide to report at runtime
+pub fn fold(op: Op, left: Option<i16>, right: Option<i16>) -> Option<i16> {
+    let (left, right) = (left?, right?);
+    let boolean = |b: bool| if b { -1 } else { 0 };
+    match op {
+        Op::Add => Some(left.wrapping_add(right)),
+        Op::Sub => Some(left.wrapping_sub(right)),
+        Op::Mult => Some(left.wrapping_mul(right)),
+        Op::Div if right != 0 => Some(left.wrapping_div(right)),
+        Op::Div => None,
+        Op::And => Some(left & right),
+        Op::Or => Some(left | right),
+        Op::Lt => Some(boolean(left < right)),
+        Op::Gt => Some(boolean(left > right)),
+        Op::Eq => Some(boolean(left == right)),
+        Op::ShiftLeft | Op::ShiftRight => None,
+    }
+}
+
+// 0+x, 1*x, true&x and 0|x are all just x
+fn is_left_identity(op: Op, left: Option<i16>) -> bool {
+    matches!(
+        (op, left),
+        (Op::Add, Some(0)) | (Op::Mult, Some(1)) | (Op::And, Some(-1)) | (Op::Or, Some(0))
+    )
+}
+
+// as are x+0, x-0, x*1, x/1, x&true and x|0
+fn is_right_identity(op: Op, right: Option<i16>) -> bool {
+    matches!(
+        (op, right),
+        (Op::Add | Op::Sub | Op::Or, Some(0)) | (Op::Mult | Op::Div, Some(1)) | (Op::And, Some(-1))
+    )
+}
+
+// 2 or more, since multiplying or dividing by 1 is dropped altogether
+fn power_of_two(n: Option<i16>) -> Option<u16> {
+    match n {
+        Some(n) if n > 1 && n & (n - 1) == 0 => Some(n.trailing_zeros() as u16),
+        _ => None,
+    }
+}
+
+// cheap to evaluate more than once and without side effects
+fn is_simple(term: &Term) -> bool {
+    matches!(term, Term::Var { index: None, .. } | Term::This)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser::Parser, symbol_table::SymbolTable, tokenizer::Tokenizer};
+
+    fn parse(body: &str, options: Options) -> Class {
+        let source = format!(
+            "class A {{ function int f(int x) {{ var Array a; {} }} }}",
+            body
+        );
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        Parser::new(tokens, options)
+            .parse_class(&mut SymbolTable::new())
+            .unwrap()
+    }
+
+    fn statements(body: &str, options: Options) -> Vec<Statement> {
+        parse(body, options).subroutines.remove(0).statements
+    }
+
+    fn optimize(body: &str, options: Options) -> Vec<Statement> {
+        optimize_class(parse(body, options), options)
+            .subroutines
+            .remove(0)
+            .statements
+    }
+
+    fn check(body: &str, expected: &str) {
+        let options = Options::default();
+        assert_eq!(optimize(body, options), statements(expected, options));
+    }
+
+    fn var(name: &str) -> Term {
+        Term::Var {
+            name: name.to_string(),
+            index: None,
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        check("return 1 + 2 * 3;", "return 9;");
+        check("return (2 * 3) + x;", "return 6 + x;");
+        check("return -(2 - 7);", "return 5;");
+        check("return ~0;", "return true;");
+        check("return 3 < 4;", "return true;");
+        check("return (3 = 4) | false;", "return 0;");
+        // 16 bit wraparound, with negative results built from constants
+        // push constant can take
+        check("return 32767 + 1;", "return ~32767;");
+        check("return 200 * 200;", "return -25536;");
+        check("return 0 - 5;", "return -5;");
+        // left for Math.divide to report
+        check("return 7 / 0;", "return 7 / 0;");
+        // nothing is folded once a variable has been applied
+        check("return x + 1 + 2;", "return x + 1 + 2;");
+    }
+
+    #[test]
+    fn test_identities() {
+        check("return 0 + x * 1 - 0;", "return x;");
+        check("return true & x | 0;", "return x;");
+        check("return x / 1;", "return x;");
+        check("return 1 - x;", "return 1 - x;");
+        check("return x * 0;", "return x * 0;");
+    }
+
+    #[test]
+    fn test_power_of_two() {
+        check("return x * 4;", "return x + x + x + x;");
+        check("return 2 * x;", "return x + x;");
+        check("return x * 16;", "return x * 16;");
+        check("return x / 4;", "return x / 4;");
+        // a call or array access mustn't be evaluated more than once
+        check("return a[0] * 2;", "return a[0] * 2;");
+        // which is (x + 1) * 2, evaluated left to right
+        check("return x + 1 * 2;", "return x + 1 * 2;");
+
+        let options = Options {
+            extended_vm: true,
+            ..Options::default()
+        };
+        let shifted = |ops| {
+            vec![Statement::Return {
+                expr: Some(Expr {
+                    term: Box::new(var("x")),
+                    ops,
+                }),
+            }]
+        };
+        assert_eq!(
+            optimize("return x * 16;", options),
+            shifted(vec![(Op::ShiftLeft, Term::IntegerLit(4))])
+        );
+        let bias = Expr {
+            term: Box::new(var("x")),
+            ops: vec![
+                (Op::Lt, Term::IntegerLit(0)),
+                (Op::And, Term::IntegerLit(3)),
+            ],
+        };
+        assert_eq!(
+            optimize("return x / 4;", options),
+            shifted(vec![
+                (Op::Add, Term::Bracketed(bias)),
+                (Op::ShiftRight, Term::IntegerLit(2))
+            ])
+        );
+    }
+
+    #[test]
+    fn test_dead_code() {
+        check("if (false) { return 1; } else { return 2; }", "return 2;");
+        check(
+            "if (1 = 1) { let x = 1; } return x;",
+            "let x = 1; return x;",
+        );
+        check("if (0) { let x = 1; } return x;", "return x;");
+        check("while (2 < 1) { let x = 1; } return x;", "return x;");
+        check(
+            "while (x) { let x = x - 0; } return x;",
+            "while (x) { let x = x; } return x;",
+        );
+
+        let options = Options {
+            extended: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            optimize(
+                "for (let x = 1; false; let x = x + 1) { do A.f(x); } return x;",
+                options
+            ),
+            statements("let x = 1; return x;", options)
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        let options = Options {
+            precedence: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            optimize("return x * 1 + 2 * 3;", options),
+            statements("return x + 6;", options)
+        );
+        assert_eq!(
+            optimize("return 1 + 2 * 3;", options),
+            statements("return 7;", options)
+        );
+    }
+}
@@ -0,0 +1,28 @@
+// parsing and code generation choices that can be switched on from the
+// command line. The defaults produce standard Jack/VM behaviour
+use crate::lint::Rules;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Options {
+    // emit the extended vm commands (mul, div) instead of calling Math
+    pub extended_vm: bool,
+    // parse expressions with the usual operator precedence
+    // instead of evaluating them left to right
+    pub precedence: bool,
+    // accept the extended dialect: for, break, continue, else if, += and -=,
+    // character literals, hex and binary integers, string escapes, const
+    // and enum
+    pub extended: bool,
+    // run the ast optimizer before emitting
+    pub optimize: bool,
+    // in if and while conditions, skip the right operand of & and |
+    // when the left one is a boolean that already decides the result
+    pub short_circuit: bool,
+    // check array accesses and method calls through variables at runtime,
+    // halting with Sys.error instead of silently corrupting memory. The
+    // codes are listed in the emitter. Memory itself does raw accesses,
+    // so the OS should be compiled without this
+    pub checks: bool,
+    // the lint rules to check, none unless asked for
+    pub lint: Rules,
+}
@@ -1,5 +1,9 @@
+use std::iter::Peekable;
+
 use crate::ast::*;
 
+use crate::optimizer;
+use crate::options::Options;
 use crate::symbol_table::SymbolTable;
 use crate::tokenizer::*;
 
@@ -11,34 +15,67 @@ where
     T: Iterator<Item = Result<char, std::io::Error>>,
 {
     tokenizer: Tokenizer<T>,
-    push_back: Vec<Token>,
+    // tokens are pushed back with their spans, and the spans of the
+    // tokens read are kept until they're pushed back
+    push_back: Vec<(Token, Span)>,
+    read: Vec<Span>,
+    spans: Spans,
+    options: Options,
+    // the subroutine being parsed, for warnings
+    location: String,
+    warnings: Vec<String>,
+    // how many loops deep the statement being parsed is, for break and continue
+    loop_depth: usize,
 }
 impl<T> Parser<T>
 where
     T: Iterator<Item = Result<char, std::io::Error>>,
 {
-    pub fn new(tokenizer: Tokenizer<T>) -> Self {
+    pub fn new(tokenizer: Tokenizer<T>, options: Options) -> Self {
         Self {
             tokenizer,
             push_back: Vec::new(),
+            read: Vec::new(),
+            spans: Spans::default(),
+            options,
+            location: String::new(),
+            warnings: Vec::new(),
+            loop_depth: 0,
         }
     }
 
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // roughly where parsing got to, the start of the token last read
+    pub fn position(&self) -> Position {
+        self.tokenizer.start()
+    }
+
+    // where each part of the class came from, once it's parsed
+    pub fn into_spans(self) -> Spans {
+        self.spans
+    }
+
     pub fn parse_class(&mut self, symbol_table: &mut SymbolTable) -> Result<Class, ParseError> {
         if !self.check_token(Token::Keyword("class"))? {
             Err(ParseError::MissingClassDeclaration)
         } else {
+            self.spans.class = self.last_span();
             let name = self.parse_identifier(ParseError::MissingClassName)?;
             symbol_table.enter_class(name.clone())?;
             self.require_opening_curly(())?;
-            let vars = self.parse_class_vars(symbol_table)?;
+            let (vars, consts) = self.parse_class_vars(&name, symbol_table)?;
             let subroutines = self.parse_subroutines(&name, symbol_table)?;
             let class = Class {
                 name,
                 vars,
+                consts,
                 subroutines,
             };
             self.require_closing_curly(())?;
+            self.spans.class = self.span_from(self.spans.class);
             let token = self.next_token()?;
             match token {
                 Some(token) => Err(ParseError::UnexpectedToken(token)),
@@ -47,17 +84,79 @@ where
         }
     }
 
+    // constants can be mixed in with the class vars. The const and enum
+    // keywords only exist in the extended dialect
     fn parse_class_vars(
         &mut self,
+        class: &str,
         symbol_table: &mut SymbolTable,
-    ) -> Result<Vec<ClassVarDecl>, ParseError> {
+    ) -> Result<(Vec<ClassVarDecl>, Vec<ConstDecl>), ParseError> {
         let mut class_vars = Vec::new();
+        let mut consts = Vec::new();
         loop {
+            if let Some(const_decl) = self.parse_const_decl_opt(class, symbol_table)? {
+                consts.push(const_decl);
+                continue;
+            }
             let class_var_decl = self.parse_class_var_decl_opt(symbol_table)?;
             match class_var_decl {
                 Some(c) => class_vars.push(c),
-                None => break Ok(class_vars),
+                None => break Ok((class_vars, consts)),
+            }
+        }
+    }
+
+    fn parse_const_decl_opt(
+        &mut self,
+        class: &str,
+        symbol_table: &mut SymbolTable,
+    ) -> Result<Option<ConstDecl>, ParseError> {
+        if self.check_token(Token::Keyword("const"))? {
+            let start = self.last_span();
+            let type_name = self.parse_type()?;
+            let name = self.parse_identifier(ParseError::MissingVariable)?;
+            self.require(Token::Symbol('='), (), ParseError::MissingEquals)?;
+            let expr = self.parse_expr()?;
+            let value = const_value(&expr, class, symbol_table)?;
+            symbol_table.enter_constant(name.clone(), type_name.clone(), value)?;
+            let const_decl = self.require_semicolon(Some(ConstDecl::Const {
+                type_name,
+                name,
+                expr,
+            }))?;
+            let span = self.span_from(start);
+            self.spans.consts.push(span);
+            Ok(const_decl)
+        } else if self.check_token(Token::Keyword("enum"))? {
+            let start = self.last_span();
+            self.require_opening_curly(())?;
+            let mut members = Vec::new();
+            let mut next_value = 0i16;
+            loop {
+                let name = self.parse_identifier(ParseError::MissingVariable)?;
+                let expr = if self.check_token(Token::Symbol('='))? {
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                let value = match &expr {
+                    Some(expr) => const_value(expr, class, symbol_table)?,
+                    None => next_value,
+                };
+                symbol_table.enter_constant(name.clone(), Type::Int, value)?;
+                next_value = value.wrapping_add(1);
+                members.push((name, expr));
+                if !self.check_token(Token::Symbol(','))? {
+                    break;
+                }
             }
+            self.require_closing_curly(())?;
+            let const_decl = self.require_semicolon(Some(ConstDecl::Enum { members }))?;
+            let span = self.span_from(start);
+            self.spans.consts.push(span);
+            Ok(const_decl)
+        } else {
+            Ok(None)
         }
     }
 
@@ -67,10 +166,13 @@ where
     ) -> Result<Option<ClassVarDecl>, ParseError> {
         let decorator = self.parse_class_var_decorator_opt()?;
         if let Some(decorator) = decorator {
+            let start = self.last_span();
             let (type_name, declarations) = self.parse_var_declarations()?;
             for name in &declarations {
                 symbol_table.enter_class_var(name.clone(), decorator, type_name.clone())?;
             }
+            let span = self.span_from(start);
+            self.spans.vars.push(span);
             Ok(Some(ClassVarDecl {
                 decorator,
                 type_name,
@@ -115,12 +217,16 @@ where
     ) -> Result<Option<Subroutine>, ParseError> {
         let decorator = self.parse_subroutine_decorator_opt()?;
         if let Some(decorator) = decorator {
+            // reserved now so it comes before the statements inside
+            let at = self.spans.subroutines.len();
+            self.spans.subroutines.push(self.last_span());
             let type_name = if self.check_token(Token::Keyword("void"))? {
                 None
             } else {
                 Some(self.parse_type()?)
             };
             let name = self.parse_identifier(ParseError::MissingSubroutineName)?;
+            self.location = format!("{}.{}", class, name);
             symbol_table.enter_subroutine(name.clone(), decorator, type_name.clone())?;
             if decorator == SubroutineDecorator::Method {
                 symbol_table.enter_arg("this".to_string(), Type::Class(class.to_string()))?;
@@ -130,6 +236,7 @@ where
             let vars = self.parse_subroutine_vars(symbol_table)?;
             let statements = self.parse_statement_list()?;
             self.require_closing_curly(())?;
+            self.spans.subroutines[at] = self.span_from(self.spans.subroutines[at]);
             Ok(Some(Subroutine {
                 decorator,
                 type_name,
@@ -261,20 +368,34 @@ where
 
     fn parse_statement_opt(&mut self) -> Result<Option<Statement>, ParseError> {
         let token = self.next_token()?;
-        match token {
-            Some(Token::Keyword("let")) => Self::optionalize(self.parse_let_statement()),
-            Some(Token::Keyword("if")) => Self::optionalize(self.parse_if_statement()),
-            Some(Token::Keyword("while")) => Self::optionalize(self.parse_while_statement()),
-            Some(Token::Keyword("do")) => Self::optionalize(self.parse_do_statement()),
-            Some(Token::Keyword("return")) => Self::optionalize(self.parse_return_statement()),
+        let at = self.spans.statements.len();
+        self.spans.statements.push(self.last_span());
+        let statement = match token {
+            Some(Token::Keyword("let")) => self.parse_let_statement()?,
+            Some(Token::Keyword("if")) => self.parse_if_statement()?,
+            Some(Token::Keyword("while")) => self.parse_while_statement()?,
+            Some(Token::Keyword("do")) => self.parse_do_statement()?,
+            Some(Token::Keyword("return")) => self.parse_return_statement()?,
+            Some(Token::Keyword("for")) => self.parse_for_statement()?,
+            Some(Token::Keyword("break")) => self.parse_jump(Statement::Break)?,
+            Some(Token::Keyword("continue")) => self.parse_jump(Statement::Continue)?,
             _ => {
+                self.spans.statements.pop();
                 self.push_back(token);
-                Ok(None)
+                return Ok(None);
             }
-        }
+        };
+        self.spans.statements[at] = self.span_from(self.spans.statements[at]);
+        Ok(Some(statement))
     }
 
     fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
+        let st = self.parse_let()?;
+        self.require_semicolon(st)
+    }
+
+    // everything but the semicolon, which a for step doesn't have
+    fn parse_let(&mut self) -> Result<Statement, ParseError> {
         let name = self.parse_identifier(ParseError::MissingVariable)?;
         let index = {
             if self.check_token(Token::Symbol('['))? {
@@ -284,17 +405,47 @@ where
                 None
             }
         };
-        self.require(Token::Symbol('='), (), ParseError::MissingEquals)?;
+        let token = self.next_token()?;
+        let op = match token {
+            Some(Token::Symbol('=')) => None,
+            Some(Token::Symbol('+')) if self.options.extended => Some(Op::Add),
+            Some(Token::Symbol('-')) if self.options.extended => Some(Op::Sub),
+            _ => return Err(ParseError::MissingEquals),
+        };
+        if op.is_some() {
+            // += and -= are two tokens, which mustn't be apart
+            let end = self.last_span().end;
+            self.require(Token::Symbol('='), (), ParseError::MissingEquals)?;
+            if self.last_span().start != end {
+                return Err(ParseError::MissingEquals);
+            }
+        }
         let expr = self.parse_expr()?;
-        let st = Statement::Let { name, index, expr };
-        self.require_semicolon(st)
+        Ok(match op {
+            None => Statement::Let { name, index, expr },
+            Some(op) => Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            },
+        })
     }
 
     fn parse_if_statement(&mut self) -> Result<Statement, ParseError> {
-        let condition = self.parse_bracketed_expr()?;
+        let condition = self.parse_condition()?;
         let statements = self.parse_statement_block()?;
         let else_statements = if self.check_token(Token::Keyword("else"))? {
-            Some(self.parse_statement_block()?)
+            // else if is an else block holding just the if
+            if self.options.extended && self.check_token(Token::Keyword("if"))? {
+                let at = self.spans.statements.len();
+                self.spans.statements.push(self.last_span());
+                let statement = self.parse_if_statement()?;
+                self.spans.statements[at] = self.span_from(self.spans.statements[at]);
+                Some(vec![statement])
+            } else {
+                Some(self.parse_statement_block()?)
+            }
         } else {
             None
         };
@@ -307,14 +458,110 @@ where
     }
 
     fn parse_while_statement(&mut self) -> Result<Statement, ParseError> {
-        let condition = self.parse_bracketed_expr()?;
-        let statements = self.parse_statement_block()?;
+        let condition = self.parse_condition()?;
+        let statements = self.parse_loop_block()?;
         Ok(Statement::While {
             condition,
             statements,
         })
     }
 
+    // init and step are optional let statements
+    fn parse_for_statement(&mut self) -> Result<Statement, ParseError> {
+        self.require_opening_paren(())?;
+        let init = if self.check_token(Token::Keyword("let"))? {
+            Some(Box::new(self.parse_let_statement()?))
+        } else {
+            self.require_semicolon(None)?
+        };
+        let condition = self.parse_expr()?;
+        self.check_condition(&condition);
+        self.require_semicolon(())?;
+        let step = if self.check_token(Token::Keyword("let"))? {
+            Some(Box::new(self.parse_let()?))
+        } else {
+            None
+        };
+        self.require_closing_paren(())?;
+        let statements = self.parse_loop_block()?;
+        Ok(Statement::For {
+            init,
+            condition,
+            step,
+            statements,
+        })
+    }
+
+    fn parse_loop_block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.loop_depth += 1;
+        let statements = self.parse_statement_block();
+        self.loop_depth -= 1;
+        statements
+    }
+
+    fn parse_jump(&mut self, statement: Statement) -> Result<Statement, ParseError> {
+        if self.loop_depth == 0 {
+            Err(ParseError::NotInLoop)
+        } else {
+            self.require_semicolon(statement)
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.parse_bracketed_expr()?;
+        self.check_condition(&condition);
+        Ok(condition)
+    }
+
+    // without short circuiting both sides of & and | are always
+    // evaluated, which is a trap for guards like (i < n) & (a[i] = 0)
+    fn check_condition(&mut self, condition: &Expr) {
+        if !self.options.short_circuit && Self::has_guarded_access(condition) {
+            self.warnings.push(format!(
+                "in {} the right side of & or | in a condition is always evaluated",
+                self.location
+            ));
+        }
+    }
+
+    fn has_guarded_access(expr: &Expr) -> bool {
+        expr.ops
+            .iter()
+            .any(|(op, term)| matches!(op, Op::And | Op::Or) && Self::has_call_or_index(term))
+            || Self::has_guarded_access_term(&expr.term)
+    }
+
+    fn has_guarded_access_term(term: &Term) -> bool {
+        match term {
+            Term::Bracketed(expr) => Self::has_guarded_access(expr),
+            Term::Binary(left, op, right) => {
+                (matches!(op, Op::And | Op::Or) && Self::has_call_or_index(right))
+                    || Self::has_guarded_access_term(left)
+                    || Self::has_guarded_access_term(right)
+            }
+            _ => false,
+        }
+    }
+
+    fn has_call_or_index(term: &Term) -> bool {
+        match term {
+            Term::Var { index, .. } => index.is_some(),
+            Term::SubroutineCall { .. } => true,
+            Term::Bracketed(expr) => {
+                Self::has_call_or_index(&expr.term)
+                    || expr
+                        .ops
+                        .iter()
+                        .any(|(_, term)| Self::has_call_or_index(term))
+            }
+            Term::Unary(_, term) => Self::has_call_or_index(term),
+            Term::Binary(left, _, right) => {
+                Self::has_call_or_index(left) || Self::has_call_or_index(right)
+            }
+            _ => false,
+        }
+    }
+
     fn parse_do_statement(&mut self) -> Result<Statement, ParseError> {
         let expr = self.parse_expr()?;
         let term = expr.term.as_ref();
@@ -370,10 +617,7 @@ where
                         Some(Token::Symbol('=')) => Op::Eq,
                         _ => {
                             self.push_back(token);
-                            break Ok(Some(Expr {
-                                term: Box::new(term),
-                                ops,
-                            }));
+                            break Ok(Some(self.build_expr(term, ops)));
                         }
                     };
                     let term = self.parse_term()?;
@@ -383,6 +627,55 @@ where
         }
     }
 
+    // the flat list of ops is either left as is to be evaluated left to
+    // right, or climbed into a tree of binary terms by precedence
+    fn build_expr(&mut self, term: Term, ops: Vec<(Op, Term)>) -> Expr {
+        if self.options.precedence {
+            let mut ops = ops.into_iter().peekable();
+            let term = Self::climb_precedence(term, 0, &mut ops);
+            Expr {
+                term: Box::new(term),
+                ops: Vec::new(),
+            }
+        } else {
+            if ops
+                .windows(2)
+                .any(|pair| pair[1].0.precedence() > pair[0].0.precedence())
+            {
+                let symbols = ops
+                    .iter()
+                    .map(|(op, _)| op.symbol())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.warnings.push(format!(
+                    "in {} an expression mixing {} is evaluated left to right",
+                    self.location, symbols
+                ));
+            }
+            Expr {
+                term: Box::new(term),
+                ops,
+            }
+        }
+    }
+
+    fn climb_precedence(
+        mut left: Term,
+        min_precedence: u8,
+        ops: &mut Peekable<impl Iterator<Item = (Op, Term)>>,
+    ) -> Term {
+        while let Some((op, mut right)) = ops.next_if(|(op, _)| op.precedence() >= min_precedence) {
+            while let Some((next, _)) = ops.peek() {
+                if next.precedence() <= op.precedence() {
+                    break;
+                }
+                right = Self::climb_precedence(right, op.precedence() + 1, ops);
+            }
+            left = Term::Binary(Box::new(left), op, Box::new(right));
+        }
+        left
+    }
+
     fn parse_term(&mut self) -> Result<Term, ParseError> {
         let term = self.parse_term_opt()?;
         match term {
@@ -444,9 +737,17 @@ where
         }
     }
 
+    // Class.NAME without a call is a constant in the extended dialect
     fn parse_qualified_subroutine_call(&mut self, qualifier: String) -> Result<Term, ParseError> {
         let name = self.parse_identifier(ParseError::MissingSubroutineName)?;
-        self.require_opening_paren(())?;
+        if !self.options.extended {
+            self.require_opening_paren(())?;
+        } else if !self.check_token(Token::Symbol('('))? {
+            return Ok(Term::Constant {
+                class: qualifier,
+                name,
+            });
+        }
         self.parse_subroutine_call(Some(qualifier), name)
     }
 
@@ -507,13 +808,17 @@ where
     }
 
     fn next_token(&mut self) -> Result<Option<Token>, ParseError> {
-        if !self.push_back.is_empty() {
-            Ok(self.push_back.pop())
+        if let Some((token, span)) = self.push_back.pop() {
+            self.read.push(span);
+            Ok(Some(token))
         } else {
             let token = self.tokenizer.next();
             match token {
                 None => Ok(None),
-                Some(Ok(token)) => Ok(Some(token)),
+                Some(Ok(token)) => {
+                    self.read.push(self.tokenizer.span());
+                    Ok(Some(token))
+                }
                 Some(Err(token_error)) => Err(ParseError::TokenError(token_error)),
             }
         }
@@ -532,7 +837,21 @@ where
 
     fn push_back(&mut self, token: Option<Token>) {
         if let Some(token) = token {
-            self.push_back.push(token)
+            let span = self.read.pop().unwrap_or_default();
+            self.push_back.push((token, span))
+        }
+    }
+
+    // the span of the token last read
+    fn last_span(&self) -> Span {
+        self.read.last().copied().unwrap_or_default()
+    }
+
+    // from the start of one span to the end of the token last read
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            start: start.start,
+            end: self.last_span().end,
         }
     }
 
@@ -573,3 +892,425 @@ where
         }
     }
 }
+
+// constants are worked out as they're declared, so they can only
+// use literals and constants declared before them. Other.NAME needs
+// Other to be parsed first, so its file has to sort before this one
+pub fn const_value(
+    expr: &Expr,
+    class: &str,
+    symbol_table: &SymbolTable,
+) -> Result<i16, ParseError> {
+    let mut value = const_term_value(&expr.term, class, symbol_table)?;
+    for (op, term) in &expr.ops {
+        let right = const_term_value(term, class, symbol_table)?;
+        value = optimizer::fold(*op, Some(value), Some(right)).ok_or(ParseError::NotConstant)?;
+    }
+    Ok(value)
+}
+
+fn const_term_value(
+    term: &Term,
+    class: &str,
+    symbol_table: &SymbolTable,
+) -> Result<i16, ParseError> {
+    match term {
+        Term::IntegerLit(n) => Ok(*n as i16),
+        Term::True => Ok(-1),
+        Term::False | Term::Null => Ok(0),
+        Term::Var { name, index: None } => symbol_table
+            .lookup_constant(class, name)
+            .map(|(_, value)| value)
+            .map_err(|_| ParseError::NotConstant),
+        Term::Constant { class, name } => symbol_table
+            .lookup_constant(class, name)
+            .map(|(_, value)| value)
+            .map_err(|_| ParseError::NotConstant),
+        Term::Bracketed(expr) => const_value(expr, class, symbol_table),
+        Term::Unary(UnaryOp::Neg, term) => {
+            Ok(const_term_value(term, class, symbol_table)?.wrapping_neg())
+        }
+        Term::Unary(UnaryOp::Not, term) => Ok(!const_term_value(term, class, symbol_table)?),
+        Term::Binary(left, op, right) => optimizer::fold(
+            *op,
+            Some(const_term_value(left, class, symbol_table)?),
+            Some(const_term_value(right, class, symbol_table)?),
+        )
+        .ok_or(ParseError::NotConstant),
+        _ => Err(ParseError::NotConstant),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(source: &str, options: Options) -> (Result<Class, ParseError>, Vec<String>) {
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        let mut parser = Parser::new(tokens, options);
+        let class = parser.parse_class(&mut SymbolTable::new());
+        (class, parser.warnings().to_vec())
+    }
+
+    fn statements(body: &str, options: Options) -> Vec<Statement> {
+        let source = format!(
+            "class A {{ function int f(int a, int b, int c, int d) {{ var int i; {} }} }}",
+            body
+        );
+        parse(&source, options)
+            .0
+            .unwrap()
+            .subroutines
+            .remove(0)
+            .statements
+    }
+
+    fn expr(text: &str, options: Options) -> Expr {
+        match statements(&format!("return {};", text), options).remove(0) {
+            Statement::Return { expr: Some(expr) } => expr,
+            statement => panic!("{:?}", statement),
+        }
+    }
+
+    fn precedence() -> Options {
+        Options {
+            precedence: true,
+            ..Options::default()
+        }
+    }
+
+    fn var(name: &str) -> Term {
+        Term::Var {
+            name: name.to_string(),
+            index: None,
+        }
+    }
+
+    fn int(n: u16) -> Term {
+        Term::IntegerLit(n)
+    }
+
+    fn binary(left: Term, op: Op, right: Term) -> Term {
+        Term::Binary(Box::new(left), op, Box::new(right))
+    }
+
+    fn tree(term: Term) -> Expr {
+        Expr {
+            term: Box::new(term),
+            ops: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            expr("1 + 2 * 3", precedence()),
+            tree(binary(int(1), Op::Add, binary(int(2), Op::Mult, int(3))))
+        );
+        assert_eq!(
+            expr("2 * 3 + 1", precedence()),
+            tree(binary(binary(int(2), Op::Mult, int(3)), Op::Add, int(1)))
+        );
+        assert_eq!(
+            expr("a | b & c = d", precedence()),
+            tree(binary(
+                var("a"),
+                Op::Or,
+                binary(var("b"), Op::And, binary(var("c"), Op::Eq, var("d")))
+            ))
+        );
+        // brackets are a term of their own and climbed inside
+        assert_eq!(
+            expr("(a + b) * c", precedence()),
+            tree(binary(
+                Term::Bracketed(tree(binary(var("a"), Op::Add, var("b")))),
+                Op::Mult,
+                var("c")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_left_associative() {
+        assert_eq!(
+            expr("a - b - c", precedence()),
+            tree(binary(
+                binary(var("a"), Op::Sub, var("b")),
+                Op::Sub,
+                var("c")
+            ))
+        );
+        assert_eq!(
+            expr("a / b / c", precedence()),
+            tree(binary(
+                binary(var("a"), Op::Div, var("b")),
+                Op::Div,
+                var("c")
+            ))
+        );
+        assert_eq!(
+            expr("a - b + c * d - 1", precedence()),
+            tree(binary(
+                binary(
+                    binary(var("a"), Op::Sub, var("b")),
+                    Op::Add,
+                    binary(var("c"), Op::Mult, var("d"))
+                ),
+                Op::Sub,
+                int(1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_mixed_precedence_warning() {
+        let source = "class A { function int f(int a) { return 1 + a * 3; } }";
+        let (class, warnings) = parse(source, Options::default());
+        assert_eq!(
+            warnings,
+            ["in A.f an expression mixing + * is evaluated left to right"]
+        );
+        // still left to right, as the standard compiler does it
+        let Statement::Return { expr: Some(expr) } = &class.unwrap().subroutines[0].statements[0]
+        else {
+            panic!()
+        };
+        assert_eq!(
+            expr.ops,
+            [(Op::Add, var("a")), (Op::Mult, int(3))]
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+
+        // falling precedence means the same either way
+        let source = "class A { function int f(int a) { return a * 3 + 1 - a; } }";
+        assert!(parse(source, Options::default()).1.is_empty());
+        let source = "class A { function int f(int a) { return 1 + a * 3; } }";
+        assert!(parse(source, precedence()).1.is_empty());
+    }
+
+    #[test]
+    fn test_guarded_access_warning() {
+        let warnings = |condition: &str, options: Options| {
+            let source = format!(
+                "class A {{ function void f(Array a, int i, int n) {{ if ({}) {{ }} return; }} }}",
+                condition
+            );
+            parse(&source, options).1
+        };
+        let warning = ["in A.f the right side of & or | in a condition is always evaluated"];
+        assert_eq!(
+            warnings("(i < n) & (a[i] = 0)", Options::default()),
+            warning
+        );
+        assert_eq!(warnings("(i = n) | A.g(i)", Options::default()), warning);
+        assert_eq!(
+            warnings("((i < n) & ~(a[i] = 0))", Options::default()),
+            warning
+        );
+        assert_eq!(warnings("(i < n) & (a[i] = 0)", precedence()), warning);
+        // a guard on the left, or no & or |, is fine
+        assert!(warnings("(a[i] = 0) & (i < n)", Options::default()).is_empty());
+        assert!(warnings("a[i] + A.g(i)", Options::default()).is_empty());
+        let options = Options {
+            short_circuit: true,
+            ..Options::default()
+        };
+        assert!(warnings("(i < n) & (a[i] = 0)", options).is_empty());
+    }
+
+    fn extended() -> Options {
+        Options {
+            extended: true,
+            ..Options::default()
+        }
+    }
+
+    fn error(body: &str) -> ParseError {
+        let source = format!("class A {{ function void f() {{ var int i; {} }} }}", body);
+        parse(&source, extended()).0.unwrap_err()
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let body = "while (a) { for (let i = 0; i < b; let i += 1) { \
+                    if (c) { continue; } break; } \
+                    if (d) { break; } continue; }";
+        let Statement::While { statements, .. } = statements(body, extended()).remove(0) else {
+            panic!()
+        };
+        let [Statement::For {
+            init: Some(init),
+            step: Some(step),
+            statements: inner,
+            ..
+        }, Statement::If {
+            statements: then, ..
+        }, Statement::Continue] = &statements[..]
+        else {
+            panic!("{:?}", statements)
+        };
+        assert!(matches!(**init, Statement::Let { .. }));
+        assert!(matches!(**step, Statement::Compound { op: Op::Add, .. }));
+        assert!(matches!(
+            &inner[..],
+            [Statement::If { statements, .. }, Statement::Break]
+                if statements == &[Statement::Continue]
+        ));
+        assert_eq!(then, &[Statement::Break]);
+    }
+
+    #[test]
+    fn test_not_in_loop() {
+        assert!(matches!(error("break;"), ParseError::NotInLoop));
+        assert!(matches!(
+            error("if (true) { continue; }"),
+            ParseError::NotInLoop
+        ));
+        // a loop's body ends its depth
+        assert!(matches!(
+            error("while (true) { } break;"),
+            ParseError::NotInLoop
+        ));
+        assert!(matches!(
+            error("for (;true;) { } continue;"),
+            ParseError::NotInLoop
+        ));
+    }
+
+    #[test]
+    fn test_else_if() {
+        let body = "if (a) { return 1; } else if (b) { return 2; } else if (c) { return 3; } \
+                    else { return 4; }";
+        let ret = |n| Statement::Return {
+            expr: Some(tree(int(n))),
+        };
+        let condition = |name| tree(var(name));
+        assert_eq!(
+            statements(body, extended()),
+            [Statement::If {
+                condition: condition("a"),
+                statements: vec![ret(1)],
+                else_statements: Some(vec![Statement::If {
+                    condition: condition("b"),
+                    statements: vec![ret(2)],
+                    else_statements: Some(vec![Statement::If {
+                        condition: condition("c"),
+                        statements: vec![ret(3)],
+                        else_statements: Some(vec![ret(4)]),
+                    }]),
+                }]),
+            }]
+        );
+        // standard jack needs the braces
+        let source = "class A { function void f() { if (true) { } else if (true) { } } }";
+        assert!(matches!(
+            parse(source, Options::default()).0,
+            Err(ParseError::MissingOpeningCurly)
+        ));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(
+            statements("let i += 1; let i -= a;", extended()),
+            [
+                Statement::Compound {
+                    name: "i".to_string(),
+                    index: None,
+                    op: Op::Add,
+                    expr: tree(int(1)),
+                },
+                Statement::Compound {
+                    name: "i".to_string(),
+                    index: None,
+                    op: Op::Sub,
+                    expr: tree(var("a")),
+                },
+            ]
+        );
+        assert!(matches!(error("let i + = 1;"), ParseError::MissingEquals));
+        assert!(matches!(error("let i -\n= 1;"), ParseError::MissingEquals));
+        assert!(matches!(
+            error("let i +/* */= 1;"),
+            ParseError::MissingEquals
+        ));
+    }
+
+    // the classes parsed in order into one symbol table, with the
+    // constants of the last one
+    fn constants(sources: &[&str]) -> Result<Vec<(String, i16)>, ParseError> {
+        let mut symbol_table = SymbolTable::new();
+        let mut name = String::new();
+        for source in sources {
+            let tokens = Tokenizer::new(source.chars().map(Ok), true);
+            name = Parser::new(tokens, extended())
+                .parse_class(&mut symbol_table)?
+                .name;
+        }
+        Ok(symbol_table.classes[&name]
+            .constants
+            .iter()
+            .map(|(name, (_, value))| (name.clone(), *value))
+            .collect())
+    }
+
+    fn named(constants: &[(&str, i16)]) -> Vec<(String, i16)> {
+        constants
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn test_const_folding() {
+        let source = "class A {
+            const int N = 3 * 4 + 1;
+            const int M = -(N - 1) / 2;
+            const boolean B = ~false & true;
+            const char C = 'a' + 1;
+            enum { X, Y, Z = M * -1, W };
+        }";
+        assert_eq!(
+            constants(&[source]).unwrap(),
+            named(&[
+                ("N", 13),
+                ("M", -6),
+                ("B", -1),
+                ("C", 98),
+                ("X", 0),
+                ("Y", 1),
+                ("Z", 6),
+                ("W", 7),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_class_constant() {
+        let other = "class Other { enum { RED, GREEN }; const int SIZE = 16; }";
+        let source = "class A { const int N = Other.SIZE * 2 + Other.GREEN; }";
+        assert_eq!(constants(&[other, source]).unwrap(), named(&[("N", 33)]));
+        // the class has to be parsed before the one using it
+        assert!(matches!(
+            constants(&[source, other]),
+            Err(ParseError::NotConstant)
+        ));
+    }
+
+    #[test]
+    fn test_not_constant() {
+        let not_constant = |decl: &str| {
+            let source = format!("class A {{ static int s; {} }}", decl);
+            matches!(constants(&[&source]), Err(ParseError::NotConstant))
+        };
+        assert!(not_constant("const int N = s;"));
+        assert!(not_constant("const int N = M;"));
+        assert!(not_constant("const int N = 1 / 0;"));
+        assert!(not_constant("const int N = A.f();"));
+        assert!(not_constant("const int N = Other.M;"));
+        assert!(not_constant("enum { X = \"x\" };"));
+        // a constant only sees those before it
+        assert!(not_constant("const int N = M; const int M = 1;"));
+    }
+}
@@ -0,0 +1,377 @@
+// counts where a program spends its time. Every instruction or vm command
+// run is put down to the function it's in and the calls that led there,
+// and for jack classes to the line of source it came from. On the cpu
+// the functions are found from the labels in the assembly: a call jumps
+// to a function's own label, and the return comes back to the $ret label
+// right after the jump
+use anyhow::Result;
+use std::collections::HashMap;
+use vm::{ast::Command, interpreter::Machine};
+
+use crate::{
+    debug_info::DebugInfo,
+    harness::{Player, Script},
+    run::Computer,
+};
+
+// what the code at each position is, a position being a rom address or
+// the index of a vm command
+pub struct CodeMap {
+    functions: Vec<String>,
+    // the function each position is in, as an index into functions
+    function_at: Vec<usize>,
+    // which vm command of its function each position comes from, the
+    // function command being 0. None for code that isn't from one, like
+    // the bootstrap and the shared routines
+    offset_at: Vec<Option<usize>>,
+    // where functions start and where calls come back to, which can be
+    // the same place, as the bootstrap's call is followed by a function
+    entry_at: Vec<bool>,
+    return_at: Vec<bool>,
+}
+
+impl CodeMap {
+    // assembly as the vm translator writes it, each command starting with
+    // a comment of itself. (Class.function) labels start functions, and
+    // ($mul) and the like the routines. A call's return label comes right
+    // after its jump, and is named after the caller with a number, as in
+    // Main.main$ret.2 or, for a routine, Main.main$mul.3
+    pub fn hack<S: AsRef<str>>(asm: &[S]) -> Self {
+        let routines = asm
+            .iter()
+            .filter_map(|line| label(line.as_ref()))
+            .filter_map(|label| label.strip_prefix('$'))
+            .filter(|routine| !routine.contains('$'))
+            .collect::<Vec<_>>();
+        let mut map = Self::empty();
+        let mut function = None;
+        let mut offset = None;
+        let (mut entry, mut return_point) = (false, false);
+        for line in asm {
+            let line = line.as_ref().trim();
+            if line.starts_with("//") {
+                offset = offset.map(|offset| offset + 1);
+            } else if let Some(label) = label(line) {
+                if !label.trim_start_matches('$').contains('$') {
+                    function = Some(map.function(label));
+                    offset = (!label.starts_with('$')).then_some(0);
+                    entry = true;
+                } else if label.rsplit_once('$').is_some_and(|(_, name)| {
+                    let name = name.split('.').next().unwrap();
+                    name == "ret" || routines.contains(&name)
+                }) {
+                    return_point = true;
+                }
+            } else if !line.is_empty() {
+                // code ahead of any function is the bootstrap's
+                let function = *function.get_or_insert_with(|| map.function("(bootstrap)"));
+                map.function_at.push(function);
+                map.offset_at.push(offset);
+                map.entry_at.push(entry);
+                map.return_at.push(return_point);
+                (entry, return_point) = (false, false);
+            }
+        }
+        map
+    }
+
+    // the vm code loaded into the interpreter
+    pub fn vm(machine: &Machine) -> Self {
+        let mut map = Self::empty();
+        let mut function = map.function("(bootstrap)");
+        let mut offset = None;
+        let mut after_call = false;
+        let mut pc = 0;
+        while let Some(command) = machine.command(pc) {
+            offset = offset.map(|offset| offset + 1);
+            let entry = matches!(command, Command::Function(_, _));
+            if let Command::Function(name, _) = command {
+                function = map.function(name);
+                offset = Some(0);
+            }
+            map.function_at.push(function);
+            map.offset_at.push(offset);
+            map.entry_at.push(entry);
+            map.return_at.push(after_call);
+            after_call = matches!(command, Command::Call(_, _));
+            pc += 1;
+        }
+        map
+    }
+
+    fn empty() -> Self {
+        Self {
+            functions: Vec::new(),
+            function_at: Vec::new(),
+            offset_at: Vec::new(),
+            entry_at: Vec::new(),
+            return_at: Vec::new(),
+        }
+    }
+
+    fn function(&mut self, name: &str) -> usize {
+        match self.functions.iter().position(|function| function == name) {
+            Some(index) => index,
+            None => {
+                self.functions.push(name.to_string());
+                self.functions.len() - 1
+            }
+        }
+    }
+
+    fn entry(&self, pc: usize) -> bool {
+        self.entry_at.get(pc).copied().unwrap_or(false)
+    }
+
+    fn return_point(&self, pc: usize) -> bool {
+        self.return_at.get(pc).copied().unwrap_or(false)
+    }
+}
+
+fn label(line: &str) -> Option<&str> {
+    line.trim().strip_prefix('(')?.strip_suffix(')')
+}
+
+// the calls that were open while something ran, as a tree with a node for
+// each different chain of them
+struct Node {
+    function: usize,
+    parent: usize,
+    count: u64,
+}
+
+// the root of the tree has no function
+const ROOT: usize = 0;
+
+pub struct Profile<'a> {
+    map: &'a CodeMap,
+    pub steps: u64,
+    // whether it ran to the end rather than running out of steps
+    pub halted: bool,
+    counts: Vec<u64>,
+    nodes: Vec<Node>,
+    calls: HashMap<(usize, usize), u64>,
+}
+
+pub struct FunctionCount<'a> {
+    pub name: &'a str,
+    // what ran in the function itself
+    pub own: u64,
+    // and in it along with everything it called
+    pub total: u64,
+    pub calls: u64,
+}
+
+// runs a program for up to steps with the script played into its keyboard,
+// counting as it goes. It stops at the end of the program or when
+// Sys.halt is called, as the os's Sys.halt only loops forever
+pub fn profile<'a>(
+    computer: &mut dyn Computer,
+    map: &'a CodeMap,
+    script: &Script,
+    steps: u64,
+) -> Result<Profile<'a>> {
+    let mut profile = Profile {
+        map,
+        steps: 0,
+        halted: false,
+        counts: vec![0; map.function_at.len()],
+        nodes: vec![Node {
+            function: usize::MAX,
+            parent: ROOT,
+            count: 0,
+        }],
+        calls: HashMap::new(),
+    };
+    let mut children = HashMap::new();
+    let halt = map.functions.iter().position(|name| name == "Sys.halt");
+    let mut node = match map.function_at.get(computer.pc()) {
+        Some(&function) => profile.child(&mut children, ROOT, function),
+        None => ROOT,
+    };
+
+    let mut player = Player::new(script);
+    while profile.steps < steps {
+        player.play(computer.ram());
+        if computer.halted() {
+            profile.halted = true;
+            break;
+        }
+        let pc = computer.pc();
+        computer.step()?;
+        profile.steps += 1;
+        profile.counts[pc] += 1;
+        profile.nodes[node].count += 1;
+
+        let next = computer.pc();
+        if next == pc + 1 {
+            continue;
+        }
+        // a jump to a function from just ahead of a return label is a
+        // call, where a loop back to the start of one isn't
+        if map.entry(next) && map.return_point(pc + 1) {
+            let function = map.function_at[next];
+            *profile
+                .calls
+                .entry((profile.nodes[node].function, function))
+                .or_insert(0) += 1;
+            node = profile.child(&mut children, node, function);
+            if Some(function) == halt {
+                profile.halted = true;
+                break;
+            }
+        } else if map.return_point(next) {
+            // back up the calls to the caller, which is normally the next
+            // one up but needn't be if the stack was unwound some other way
+            let function = map.function_at[next];
+            let mut caller = node;
+            while caller != ROOT && profile.nodes[caller].function != function {
+                caller = profile.nodes[caller].parent;
+            }
+            if caller != ROOT {
+                node = caller;
+            }
+        }
+    }
+    Ok(profile)
+}
+
+impl<'a> Profile<'a> {
+    fn child(
+        &mut self,
+        children: &mut HashMap<(usize, usize), usize>,
+        parent: usize,
+        function: usize,
+    ) -> usize {
+        *children.entry((parent, function)).or_insert_with(|| {
+            self.nodes.push(Node {
+                function,
+                parent,
+                count: 0,
+            });
+            self.nodes.len() - 1
+        })
+    }
+
+    fn names(&self, mut node: usize) -> Vec<&'a str> {
+        let mut names = Vec::new();
+        while node != ROOT {
+            names.push(self.map.functions[self.nodes[node].function].as_str());
+            node = self.nodes[node].parent;
+        }
+        names.reverse();
+        names
+    }
+
+    // the flat profile, the functions that ran the most first
+    pub fn functions(&self) -> Vec<FunctionCount<'a>> {
+        let map = self.map;
+        let mut own = vec![0; map.functions.len()];
+        for (pc, count) in self.counts.iter().enumerate() {
+            own[map.function_at[pc]] += count;
+        }
+
+        // everything under each node of the tree. Children come after
+        // their parents, so going backwards adds each up before its parent
+        let mut under = self.nodes.iter().map(|node| node.count).collect::<Vec<_>>();
+        for node in (1..self.nodes.len()).rev() {
+            under[self.nodes[node].parent] += under[node];
+        }
+        // a function's total is what's under its outermost calls, so
+        // recursion isn't counted twice
+        let mut total = vec![0; map.functions.len()];
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let mut parent = node.parent;
+            while parent != ROOT && self.nodes[parent].function != node.function {
+                parent = self.nodes[parent].parent;
+            }
+            if parent == ROOT {
+                total[node.function] += under[index];
+            }
+        }
+
+        let mut calls = vec![0; map.functions.len()];
+        for (&(_, callee), &count) in &self.calls {
+            calls[callee] += count;
+        }
+        let mut functions = map
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| own[i] > 0 || total[i] > 0 || calls[i] > 0)
+            .map(|(i, name)| FunctionCount {
+                name,
+                own: own[i],
+                total: total[i],
+                calls: calls[i],
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.own.cmp(&a.own).then(b.total.cmp(&a.total)));
+        functions
+    }
+
+    // how many times each function called each other one, as caller,
+    // callee and count, the most first
+    pub fn calls(&self) -> Vec<(&'a str, &'a str, u64)> {
+        let name = |function: usize| {
+            self.map
+                .functions
+                .get(function)
+                .map_or("(start)", |name| name.as_str())
+        };
+        let mut calls = self
+            .calls
+            .iter()
+            .map(|(&(caller, callee), &count)| (name(caller), name(callee), count))
+            .collect::<Vec<_>>();
+        calls.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+        calls
+    }
+
+    // a line for each chain of calls that ran anything itself, the
+    // functions separated by ; and then the count, which is what
+    // flamegraph.pl and most other flame graph tools read
+    pub fn folded(&self) -> String {
+        let mut lines = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.count > 0)
+            .map(|(index, node)| format!("{} {}\n", self.names(index).join(";"), node.count))
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines.concat()
+    }
+
+    // counts for each line of the jack classes, by source file name and
+    // one based line number, the most first. Only classes with debug info
+    // are counted, so the os's functions aren't
+    pub fn lines<'b>(&self, debug_info: &'b [DebugInfo]) -> Vec<(&'b str, usize, u64)> {
+        let mut starts = HashMap::new();
+        for info in debug_info {
+            for function in &info.functions {
+                starts.insert(function.name.as_str(), (info, function.vm_line));
+            }
+        }
+        let mut lines = HashMap::new();
+        for (pc, &count) in self.counts.iter().enumerate() {
+            let name = self.map.functions[self.map.function_at[pc]].as_str();
+            let (Some(offset), Some(&(info, start))) = (self.map.offset_at[pc], starts.get(name))
+            else {
+                continue;
+            };
+            if let Some(line) = info.lines.get(start + offset) {
+                *lines
+                    .entry((info.source.as_str(), line.line + 1))
+                    .or_insert(0) += count;
+            }
+        }
+        let mut lines = lines
+            .into_iter()
+            .filter(|&(_, count)| count > 0)
+            .map(|((source, line), count)| (source, line, count))
+            .collect::<Vec<_>>();
+        lines.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(&b.1)));
+        lines
+    }
+}
@@ -0,0 +1,139 @@
+// running a program, either as machine code on the cpu emulator or as
+// vm code in the vm interpreter. The screen and keyboard only need the
+// ram, so anything that runs a program can be plugged into them
+use anyhow::{anyhow, bail, Context, Result};
+use asm::cpu::Cpu;
+use std::{
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use vm::{ast::Command, interpreter::Machine};
+
+use crate::{
+    debug_info::DebugInfo, emitter::Emitter, options::Options, parser::Parser,
+    symbol_table::SymbolTable, tokenizer::Tokenizer,
+};
+
+pub trait Computer {
+    // runs one instruction or vm command
+    fn step(&mut self) -> Result<()>;
+    fn halted(&self) -> bool;
+    fn ram(&mut self) -> &mut [i16];
+    // the instruction or vm command it's at
+    fn pc(&self) -> usize;
+}
+
+impl Computer for Cpu {
+    fn step(&mut self) -> Result<()> {
+        Cpu::step(self);
+        Ok(())
+    }
+
+    fn halted(&self) -> bool {
+        Cpu::halted(self)
+    }
+
+    fn ram(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    fn pc(&self) -> usize {
+        self.pc as usize
+    }
+}
+
+impl Computer for Machine {
+    fn step(&mut self) -> Result<()> {
+        Machine::step(self)
+            .map_err(|error| anyhow!("{:?} in {:?}", error, self.function_name(self.pc)))
+    }
+
+    fn halted(&self) -> bool {
+        self.halted
+    }
+
+    fn ram(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    fn pc(&self) -> usize {
+        self.pc
+    }
+}
+
+// a .hack file on the cpu emulator
+pub fn load_hack(path: &Path) -> Result<Cpu> {
+    let text = fs::read_to_string(path).with_context(|| path.to_string_lossy().to_string())?;
+    match Cpu::parse(&text) {
+        Ok(program) => Ok(Cpu::new(&program)),
+        Err(line) => bail!(
+            "{}: line {} isn't an instruction",
+            path.to_string_lossy(),
+            line + 1
+        ),
+    }
+}
+
+// jack classes compiled, linked with the built in os and ready to run
+// from Sys.init in the vm interpreter
+pub fn load_jack(files: &[PathBuf], options: Options) -> Result<Machine> {
+    let (program, _, _) = compile_jack(files, options)?;
+    let mut machine = Machine::new(&program);
+    machine
+        .bootstrap()
+        .map_err(|error| anyhow!("{:?}", error))?;
+    Ok(machine)
+}
+
+// the vm code of each class by its name
+pub type Program = Vec<(String, Vec<Command>)>;
+
+// jack classes compiled to vm code and linked with the built in os, along
+// with the debug info of each class and the symbols of them all
+pub fn compile_jack(
+    files: &[PathBuf],
+    options: Options,
+) -> Result<(Program, Vec<DebugInfo>, SymbolTable)> {
+    let mut symbol_table = SymbolTable::new();
+    let mut classes = Vec::new();
+    for path in files {
+        let source = fs::read_to_string(path)?;
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        let mut parser = Parser::new(tokens, options);
+        let class = parser
+            .parse_class(&mut symbol_table)
+            .with_context(|| path.to_string_lossy().to_string())?;
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        classes.push((file_name, class, parser.into_spans()));
+    }
+
+    let mut program: Program = Vec::new();
+    let mut debug_info = Vec::new();
+    for (file_name, class, spans) in classes {
+        let mut vm = Vec::new();
+        let mut emitter = Emitter::new(BufWriter::new(&mut vm), &symbol_table, options);
+        emitter.emit_class(&class)?;
+        let origins = emitter.line_origins();
+        drop(emitter);
+        let vm = String::from_utf8(vm)?;
+        let commands = match vm::parser::parse_lines(vm.lines(), options.extended_vm) {
+            Ok(commands) => commands,
+            Err(errors) => bail!("{}.vm: {:?}", class.name, errors),
+        };
+        debug_info.push(DebugInfo::new(
+            &file_name,
+            &class,
+            &spans,
+            &origins,
+            &symbol_table,
+        ));
+        program.push((class.name, commands));
+    }
+    let classes = program
+        .iter()
+        .map(|(class, _)| class.clone())
+        .collect::<Vec<_>>();
+    program.extend(vm::os::link(&classes));
+    Ok((program, debug_info, symbol_table))
+}
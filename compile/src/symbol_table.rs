@@ -1,8 +1,9 @@
 use crate::ast::*;
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
+#[derive(Clone)]
 pub struct SymbolTable {
-    pub classes: HashMap<String, ClassSymbolTable>,
+    pub classes: IndexMap<String, ClassSymbolTable>,
     last_class: String,
     last_subroutine: String,
 }
@@ -21,7 +22,7 @@ pub enum RefType {
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
-            classes: HashMap::new(),
+            classes: IndexMap::new(),
             last_class: "".to_string(),
             last_subroutine: "".to_string(),
         }
@@ -45,6 +46,25 @@ impl SymbolTable {
             .ok_or(ParseError::ClassNotFound)
     }
 
+    pub fn enter_constant(
+        &mut self,
+        name: String,
+        type_name: Type,
+        value: i16,
+    ) -> Result<(), ParseError> {
+        let class_table = self.get_class_table_mut()?;
+        if class_table.class_vars.contains_key(&name) {
+            return Err(ParseError::DuplicatedClassLevelVariable);
+        }
+        swap_result(
+            class_table
+                .constants
+                .insert(name, (type_name, value))
+                .map(|_| ParseError::DuplicatedClassLevelVariable)
+                .ok_or(()),
+        )
+    }
+
     pub fn enter_class_var(
         &mut self,
         name: String,
@@ -52,6 +72,9 @@ impl SymbolTable {
         type_name: Type,
     ) -> Result<(), ParseError> {
         let class_table = self.get_class_table_mut()?;
+        if class_table.constants.contains_key(&name) {
+            return Err(ParseError::DuplicatedClassLevelVariable);
+        }
 
         let var_number = match decorator {
             ClassVarDecorator::Static => {
@@ -186,6 +209,15 @@ impl SymbolTable {
         }
     }
 
+    pub fn lookup_constant(&self, class: &str, name: &str) -> Result<(Type, i16), ParseError> {
+        let class_table = self.get_class_table(class)?;
+        class_table
+            .constants
+            .get(name)
+            .cloned()
+            .ok_or(ParseError::SymbolNotFound)
+    }
+
     pub fn lookup_subroutine(
         &self,
         class: &str,
@@ -199,15 +231,74 @@ impl SymbolTable {
         ))
     }
 
+    pub fn lookup_return_type(
+        &self,
+        class: &str,
+        subroutine: &str,
+    ) -> Result<Option<Type>, ParseError> {
+        let (_, _, type_name) = self.lookup_signature(class, subroutine)?;
+        Ok(type_name)
+    }
+
+    // the kind, parameter types and return type of a subroutine in one of
+    // the classes here or, for a class that isn't, in the os the vm
+    // translator links in
+    pub fn lookup_signature(
+        &self,
+        class: &str,
+        subroutine: &str,
+    ) -> Result<(SubroutineDecorator, Vec<Type>, Option<Type>), ParseError> {
+        if !self.classes.contains_key(class) && vm::os::is_os_class(class) {
+            let signature = vm::os::lookup(class, subroutine).ok_or(ParseError::SymbolNotFound)?;
+            let decorator = match signature.kind {
+                "constructor" => SubroutineDecorator::Constructor,
+                "method" => SubroutineDecorator::Method,
+                _ => SubroutineDecorator::Function,
+            };
+            let params = signature
+                .params
+                .iter()
+                .map(|(type_name, _)| os_type(type_name))
+                .collect();
+            let type_name = Some(signature.return_type)
+                .filter(|type_name| *type_name != "void")
+                .map(os_type);
+            return Ok((decorator, params, type_name));
+        }
+
+        let sub_routine_table = self.get_subroutine_table(class, subroutine)?;
+        let params = sub_routine_table
+            .vars
+            .iter()
+            .filter(|(name, (decorator, _, _))| {
+                *decorator == SubroutineVarDecorator::Arg && *name != "this"
+            })
+            .map(|(_, (_, type_name, _))| type_name.clone())
+            .collect();
+        Ok((
+            sub_routine_table.decorator,
+            params,
+            sub_routine_table.type_name.clone(),
+        ))
+    }
+
     pub fn lookup_class(&self, class: &str) -> Result<usize, ParseError> {
         let class_table = self.get_class_table(class)?;
         Ok(class_table.field_number)
     }
 }
 
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
 pub struct ClassSymbolTable {
-    pub class_vars: HashMap<String, (ClassVarDecorator, Type, usize)>,
-    pub subroutines: HashMap<String, SubroutineSymbolTable>,
+    pub class_vars: IndexMap<String, (ClassVarDecorator, Type, usize)>,
+    pub constants: IndexMap<String, (Type, i16)>,
+    pub subroutines: IndexMap<String, SubroutineSymbolTable>,
     pub static_number: usize,
     pub field_number: usize,
 }
@@ -215,16 +306,18 @@ pub struct ClassSymbolTable {
 impl ClassSymbolTable {
     fn new() -> Self {
         Self {
-            class_vars: HashMap::new(),
-            subroutines: HashMap::new(),
+            class_vars: IndexMap::new(),
+            constants: IndexMap::new(),
+            subroutines: IndexMap::new(),
             static_number: 0,
             field_number: 0,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct SubroutineSymbolTable {
-    pub vars: HashMap<String, (SubroutineVarDecorator, Type, usize)>,
+    pub vars: IndexMap<String, (SubroutineVarDecorator, Type, usize)>,
     pub decorator: SubroutineDecorator,
     pub type_name: Option<Type>,
     pub arg_number: usize,
@@ -234,7 +327,7 @@ pub struct SubroutineSymbolTable {
 impl SubroutineSymbolTable {
     fn new(decorator: SubroutineDecorator, type_name: Option<Type>) -> Self {
         Self {
-            vars: HashMap::new(),
+            vars: IndexMap::new(),
             decorator,
             type_name,
             arg_number: 0,
@@ -243,6 +336,15 @@ impl SubroutineSymbolTable {
     }
 }
 
+fn os_type(type_name: &str) -> Type {
+    match type_name {
+        "int" => Type::Int,
+        "char" => Type::Char,
+        "boolean" => Type::Boolean,
+        class => Type::Class(class.to_string()),
+    }
+}
+
 fn swap_result<E, T>(x: Result<E, T>) -> Result<T, E> {
     match x {
         Ok(e) => Err(e),
@@ -36,6 +36,29 @@ impl Display for Token {
     }
 }
 
+// zero based, like editors count them
+#[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+// from the first character of something up to but not including the
+// character after it
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+// where a comment is in the source, from its first / up to but not
+// including the character after it
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Comment {
+    pub start: Position,
+    pub end: Position,
+}
+
 #[derive(Debug, Error)]
 pub enum TokenError {
     #[error("A comment was started but not closed before the end of the file \n--\"{0}\"")]
@@ -50,6 +73,12 @@ pub enum TokenError {
     IOError(std::io::Error),
     #[error("There was a character out of the range 0-127")]
     CharacterOutOfBounds(char),
+    #[error("Unknown escape sequence \"\\{0}\"")]
+    InvalidEscape(char),
+    #[error("Character literal was invalid \"{0}\"")]
+    InvalidCharacterLiteral(String),
+    #[error("Integer was invalid \"{0}\"")]
+    InvalidInteger(String),
 }
 
 pub struct Tokenizer<T>
@@ -60,18 +89,63 @@ where
     accum: String,
     state: State,
     push_back: Option<Category>,
+    extended: bool,
+    // where the character last read is, where the one after it will be,
+    // where the token being built started and where the last one ended
+    current: Position,
+    next: Position,
+    start: Position,
+    end: Position,
+    // comments are normally thrown away, but a formatter needs them back
+    keep_comments: bool,
+    comments: Vec<Comment>,
 }
 
 impl<T> Tokenizer<T>
 where
     T: Iterator<Item = Result<char, std::io::Error>>,
 {
-    pub fn new(iter: T) -> Self {
+    // extended adds the keywords of the extended dialect along with
+    // character literals, 0x and 0b integers and escapes in strings
+    pub fn new(iter: T, extended: bool) -> Self {
         Self {
             iter,
             state: State::Fresh,
             accum: String::new(),
             push_back: None,
+            extended,
+            current: Position::default(),
+            next: Position::default(),
+            start: Position::default(),
+            end: Position::default(),
+            keep_comments: false,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn with_comments(self) -> Self {
+        Self {
+            keep_comments: true,
+            ..self
+        }
+    }
+
+    // every comment passed over so far, when they're being kept
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    // where the token last returned started. Only tokens begin
+    // in the fresh state, so the start is moved on from there
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    // the source the token last returned came from
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.end,
         }
     }
 
@@ -86,6 +160,10 @@ where
                     break Some(Err(TokenError::IOError(error)));
                 }
                 Ok(next) => {
+                    if self.state == State::Fresh {
+                        self.start = self.current;
+                    }
+                    let comment_state = self.state;
                     let opt_token = match self.state {
                         State::Fresh => self.handle_fresh(next),
                         State::PossibleCommentStart(depth) => {
@@ -101,9 +179,39 @@ where
                         State::Integer => self.handle_integer(next),
                         State::Eof => self.handle_eof(next),
                         State::String => self.handle_string(next),
+                        State::StringEscape => self.handle_string_escape(next),
+                        State::Character => self.handle_character(next),
+                        State::CharacterEscape => self.handle_character_escape(next),
+                        State::CharacterEnd => self.handle_character_end(next),
+                        State::PossibleRadix => self.handle_possible_radix(next),
+                        State::RadixInteger(radix) => self.handle_radix_integer(next, radix),
                     };
 
+                    // a comment ends on the newline after it, or just after
+                    // the */
+                    if self.keep_comments && self.state == State::Fresh {
+                        let end = match comment_state {
+                            State::LineComment => Some(self.current),
+                            State::PossibleCommentEnd(_) => Some(Position {
+                                column: self.current.column + 1,
+                                ..self.current
+                            }),
+                            _ => None,
+                        };
+                        if let Some(end) = end {
+                            self.comments.push(Comment {
+                                start: self.start,
+                                end,
+                            });
+                        }
+                    }
+
                     if let Some(token) = opt_token {
+                        self.end = if self.push_back.is_some() {
+                            self.current
+                        } else {
+                            self.next
+                        };
                         break Some(token);
                     }
                 }
@@ -120,6 +228,9 @@ where
             Category::Letter(c) => {
                 self.transition(State::IdentifierOrKeyword, Action::Accum(c), Self::no_token)
             }
+            Category::OtherCharacter('\'') if self.extended => {
+                self.transition(State::Character, Action::Ignore, Self::no_token)
+            }
             Category::OtherCharacter(c) => {
                 self.transition(State::InvalidIdentifier, Action::Accum(c), Self::no_token)
             }
@@ -128,6 +239,9 @@ where
                 Action::Accum('_'),
                 Self::no_token,
             ),
+            Category::Number('0') if self.extended => {
+                self.transition(State::PossibleRadix, Action::Accum('0'), Self::no_token)
+            }
             Category::Number(c) => {
                 self.transition(State::Integer, Action::Accum(c), Self::no_token)
             }
@@ -248,11 +362,12 @@ where
             Category::OtherCharacter(c) => {
                 self.transition(State::InvalidIdentifier, Action::Accum(c), Self::no_token)
             }
-            _ => self.transition(
-                State::Fresh,
-                Action::PushBack(next),
-                Self::identifier_or_keyword_token,
-            ),
+            _ => {
+                let extended = self.extended;
+                self.transition(State::Fresh, Action::PushBack(next), |s| {
+                    Self::identifier_or_keyword_token(s, extended)
+                })
+            }
         }
     }
 
@@ -287,11 +402,45 @@ where
         }
     }
 
+    // 0x and 0b prefixes, or just a 0 that may go on as a decimal
+    fn handle_possible_radix(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
+        match next {
+            Category::Letter('x') => {
+                self.transition(State::RadixInteger(16), Action::Accum('x'), Self::no_token)
+            }
+            Category::Letter('b') => {
+                self.transition(State::RadixInteger(2), Action::Accum('b'), Self::no_token)
+            }
+            Category::Number(c) => {
+                self.transition(State::Integer, Action::Accum(c), Self::no_token)
+            }
+            _ => self.transition(State::Fresh, Action::PushBack(next), Self::integer_token),
+        }
+    }
+
+    fn handle_radix_integer(
+        &mut self,
+        next: Category,
+        radix: u32,
+    ) -> Option<Result<Token, TokenError>> {
+        match next {
+            Category::Number(c) | Category::Letter(c) => {
+                self.transition(State::RadixInteger(radix), Action::Accum(c), Self::no_token)
+            }
+            _ => self.transition(State::Fresh, Action::PushBack(next), |s| {
+                Self::radix_integer_token(s, radix)
+            }),
+        }
+    }
+
     fn handle_string(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
         match next {
             Category::Quote => self.transition(State::Fresh, Action::Ignore, |s| {
                 Some(Ok(Token::StringLiteral(s)))
             }),
+            Category::OtherCharacter('\\') if self.extended => {
+                self.transition(State::StringEscape, Action::Keep, Self::no_token)
+            }
             Category::Eof => self.transition(
                 State::Fresh,
                 Action::Ignore,
@@ -308,6 +457,90 @@ where
         }
     }
 
+    // an unknown escape is reported and then kept as the character after the \\
+    fn handle_string_escape(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
+        match (next, Self::escape(next)) {
+            (_, Some(c)) => self.transition(State::String, Action::Accum(c), Self::no_token),
+            (Category::Eof, _) => {
+                self.transition(State::String, Action::PushBack(next), Self::no_token)
+            }
+            (_, None) => self.transition(State::String, Action::Accum(next.character()), |_| {
+                Some(Err(TokenError::InvalidEscape(next.character())))
+            }),
+        }
+    }
+
+    // 'c' is the jack character code of c, so it's just another integer
+    fn handle_character(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
+        match next {
+            Category::OtherCharacter('\\') => {
+                self.transition(State::CharacterEscape, Action::Ignore, Self::no_token)
+            }
+            Category::OtherCharacter('\'') => self.transition(
+                State::Fresh,
+                Action::Ignore,
+                Self::token_error(TokenError::InvalidCharacterLiteral),
+            ),
+            Category::Eof => self.transition(
+                State::Fresh,
+                Action::PushBack(next),
+                Self::token_error(TokenError::InvalidCharacterLiteral),
+            ),
+            _ => {
+                let character = next.character();
+                if character > 127 as char {
+                    Some(Err(TokenError::CharacterOutOfBounds(character)))
+                } else {
+                    self.transition(
+                        State::CharacterEnd,
+                        Action::Accum(character),
+                        Self::no_token,
+                    )
+                }
+            }
+        }
+    }
+
+    fn handle_character_escape(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
+        match (next, Self::escape(next)) {
+            (_, Some(c)) => self.transition(State::CharacterEnd, Action::Accum(c), Self::no_token),
+            (Category::Eof, _) => self.transition(
+                State::Fresh,
+                Action::PushBack(next),
+                Self::token_error(TokenError::InvalidCharacterLiteral),
+            ),
+            (_, None) => {
+                self.transition(State::CharacterEnd, Action::Accum(next.character()), |_| {
+                    Some(Err(TokenError::InvalidEscape(next.character())))
+                })
+            }
+        }
+    }
+
+    fn handle_character_end(&mut self, next: Category) -> Option<Result<Token, TokenError>> {
+        match next {
+            Category::OtherCharacter('\'') => self.transition(State::Fresh, Action::Ignore, |s| {
+                Some(Ok(Token::IntegerLiteral(s.chars().next().unwrap() as u16)))
+            }),
+            _ => self.transition(
+                State::Fresh,
+                Action::PushBack(next),
+                Self::token_error(TokenError::InvalidCharacterLiteral),
+            ),
+        }
+    }
+
+    // newline is 128 in the jack character set
+    fn escape(next: Category) -> Option<char> {
+        match next {
+            Category::Letter('n') => Some(128 as char),
+            Category::OtherCharacter('\\') => Some('\\'),
+            Category::OtherCharacter('\'') => Some('\''),
+            Category::Quote => Some('"'),
+            _ => None,
+        }
+    }
+
     fn no_token(_: String) -> Option<Result<Token, TokenError>> {
         None
     }
@@ -318,7 +551,7 @@ where
         move |s| Some(Err(f(s)))
     }
 
-    fn identifier_or_keyword_token(s: String) -> Option<Result<Token, TokenError>> {
+    fn identifier_or_keyword_token(s: String, extended: bool) -> Option<Result<Token, TokenError>> {
         static KEYWORDS: [&str; 21] = [
             "boolean",
             "char",
@@ -343,10 +576,33 @@ where
             "while",
         ];
 
-        KEYWORDS
-            .binary_search(&s.as_str())
-            .map(|index| Some(Ok(Token::Keyword(KEYWORDS[index]))))
-            .unwrap_or_else(|_| Some(Ok(Token::Identifier(s))))
+        static EXTENDED_KEYWORDS: [&str; 5] = ["break", "const", "continue", "enum", "for"];
+
+        let find = |keywords: &[&'static str]| {
+            keywords
+                .binary_search(&s.as_str())
+                .ok()
+                .map(|index| keywords[index])
+        };
+        let keyword = find(&KEYWORDS).or_else(|| {
+            if extended {
+                find(&EXTENDED_KEYWORDS)
+            } else {
+                None
+            }
+        });
+        match keyword {
+            Some(keyword) => Some(Ok(Token::Keyword(keyword))),
+            None => Some(Ok(Token::Identifier(s))),
+        }
+    }
+
+    fn radix_integer_token(s: String, radix: u32) -> Option<Result<Token, TokenError>> {
+        match u16::from_str_radix(&s[2..], radix) {
+            Ok(i) if i <= 32767 => Some(Ok(Token::IntegerLiteral(i))),
+            Ok(_) => Some(Err(TokenError::IntegerOutOfRange(s))),
+            Err(_) => Some(Err(TokenError::InvalidInteger(s))),
+        }
     }
 
     fn integer_token(s: String) -> Option<Result<Token, TokenError>> {
@@ -371,6 +627,13 @@ where
         } else {
             match self.iter.next() {
                 Some(Ok(c)) => Ok({
+                    self.current = self.next;
+                    if c == '\n' {
+                        self.next.line += 1;
+                        self.next.column = 0;
+                    } else {
+                        self.next.column += 1;
+                    }
                     if c.is_whitespace() {
                         Category::WhiteSpace(c)
                     } else if c == '"' {
@@ -389,7 +652,10 @@ where
                 }),
                 Some(Err(io_error)) => Err(io_error),
 
-                None => Ok(Category::Eof),
+                None => {
+                    self.current = self.next;
+                    Ok(Category::Eof)
+                }
             }
         }
     }
@@ -410,6 +676,7 @@ where
             }
             Action::Accum(c) => self.accum.push(c),
             Action::Ignore => self.accum.clear(),
+            Action::Keep => {}
         }
         result
     }
@@ -420,6 +687,7 @@ enum Action {
     PushBack(Category),
     Accum(char),
     Ignore,
+    Keep,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
@@ -471,4 +739,111 @@ enum State {
     String,
     Eof,
     InvalidIdentifier,
+    // the rest are only reached in the extended dialect
+    StringEscape,
+    Character,
+    CharacterEscape,
+    CharacterEnd,
+    PossibleRadix,
+    RadixInteger(u32),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Result<Token, TokenError>> {
+        Tokenizer::new(source.chars().map(Ok), true).collect()
+    }
+
+    fn token(source: &str) -> Token {
+        match &tokens(source)[..] {
+            [Ok(token)] => token.clone(),
+            tokens => panic!("{:?}", tokens),
+        }
+    }
+
+    fn error(source: &str) -> TokenError {
+        tokens(source)
+            .into_iter()
+            .find_map(Result::err)
+            .unwrap_or_else(|| panic!("{} tokenized", source))
+    }
+
+    #[test]
+    fn test_character_literals() {
+        assert_eq!(token("'a'"), Token::IntegerLiteral(97));
+        assert_eq!(token("'\\n'"), Token::IntegerLiteral(128));
+        assert_eq!(token("'\\''"), Token::IntegerLiteral(39));
+        assert_eq!(token("'\\\\'"), Token::IntegerLiteral(92));
+        assert_eq!(token("'\"'"), Token::IntegerLiteral(34));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(
+            token("\"a\\\"b\""),
+            Token::StringLiteral("a\"b".to_string())
+        );
+        assert_eq!(
+            token("\"a\\nb\""),
+            Token::StringLiteral("a\u{80}b".to_string())
+        );
+        // standard jack has no escapes
+        let tokens = Tokenizer::new("\"a\\\"".chars().map(Ok), false)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(tokens, [Token::StringLiteral("a\\".to_string())]);
+    }
+
+    #[test]
+    fn test_radix_integers() {
+        assert_eq!(token("0x7FFF"), Token::IntegerLiteral(32767));
+        assert_eq!(token("0x7fff"), Token::IntegerLiteral(32767));
+        assert_eq!(token("0b101"), Token::IntegerLiteral(5));
+        assert_eq!(token("0"), Token::IntegerLiteral(0));
+        assert_eq!(token("012"), Token::IntegerLiteral(12));
+        assert!(matches!(error("0x8000"), TokenError::IntegerOutOfRange(s) if s == "0x8000"));
+        assert!(matches!(error("0x10000"), TokenError::InvalidInteger(_)));
+        assert!(matches!(error("0b102"), TokenError::InvalidInteger(_)));
+        assert!(matches!(error("0x"), TokenError::InvalidInteger(_)));
+        assert!(matches!(error("32768"), TokenError::IntegerOutOfRange(_)));
+    }
+
+    #[test]
+    fn test_bad_character_literals() {
+        assert!(matches!(
+            error("''"),
+            TokenError::InvalidCharacterLiteral(_)
+        ));
+        assert!(matches!(
+            error("'a"),
+            TokenError::InvalidCharacterLiteral(_)
+        ));
+        assert!(matches!(
+            error("'ab'"),
+            TokenError::InvalidCharacterLiteral(_)
+        ));
+        assert!(matches!(
+            error("'\\"),
+            TokenError::InvalidCharacterLiteral(_)
+        ));
+        assert!(matches!(
+            error("'é'"),
+            TokenError::CharacterOutOfBounds('é')
+        ));
+    }
+
+    #[test]
+    fn test_bad_escapes() {
+        assert!(matches!(error("'\\q'"), TokenError::InvalidEscape('q')));
+        assert!(matches!(error("\"a\\tb\""), TokenError::InvalidEscape('t')));
+        // the string goes on after a bad escape
+        let tokens = tokens("\"a\\tb\" x");
+        assert!(matches!(
+            &tokens[..],
+            [Err(TokenError::InvalidEscape('t')), Ok(Token::StringLiteral(s)), Ok(Token::Identifier(x))]
+                if s == "atb" && x == "x"
+        ));
+    }
 }
@@ -50,7 +50,7 @@ where
     pub fn leaf(&mut self, indent: usize, element: &str, string: &str) -> Result<()> {
         self.indent(indent)?;
         let string = string.replace('&', "&amp;");
-        let string = string.replace('\'', "&quot;");
+        let string = string.replace('"', "&quot;");
         let string = string.replace('<', "&lt;");
         let string = string.replace('>', "&gt;");
 
@@ -77,6 +77,9 @@ where
         self.identifier(indent + 1, &class.name)?;
         self.symbol(indent + 1, "{")?;
 
+        for const_dec in class.consts {
+            self.const_dec(indent + 1, const_dec)?;
+        }
         for class_var_dec in class.vars {
             self.class_var_dec(indent + 1, class_var_dec)?;
         }
@@ -99,13 +102,27 @@ where
         Ok(())
     }
 
+    // every symbol in declaration order, with its kind and type
     fn class_symbol(&mut self, indent: usize, name: &str, table: &ClassSymbolTable) -> Result<()> {
         self.start(indent, "class")?;
 
         self.leaf(indent + 1, "name", name)?;
 
-        for (name, (decorator, _, number)) in &table.class_vars {
-            self.class_var_symbol(indent + 1, name, decorator, *number)?;
+        for (name, (decorator, type_name, number)) in &table.class_vars {
+            let kind = match decorator {
+                ClassVarDecorator::Static => "static",
+                ClassVarDecorator::Field => "field",
+            };
+            self.var_symbol(indent + 1, kind, name, type_name, number)?;
+        }
+
+        for (name, (type_name, value)) in &table.constants {
+            self.start(indent + 1, "const")?;
+            self.leaf(indent + 2, "name", name)?;
+            self.leaf(indent + 2, "kind", "const")?;
+            self.leaf(indent + 2, "type", &type_text(type_name))?;
+            self.leaf(indent + 2, "value", &value.to_string())?;
+            self.end(indent + 1, "const")?;
         }
 
         for (subroutine, table) in &table.subroutines {
@@ -116,70 +133,52 @@ where
         Ok(())
     }
 
-    fn class_var_symbol(
-        &mut self,
-        indent: usize,
-        name: &str,
-        decorator: &ClassVarDecorator,
-        number: usize,
-    ) -> Result<()> {
-        let var_type = match decorator {
-            ClassVarDecorator::Static => "static",
-            ClassVarDecorator::Field => "field",
-        };
-        self.var_symbol(indent, var_type, name, number)
-    }
-
     fn subroutine_symbol(
         &mut self,
         indent: usize,
         name: &str,
         table: &SubroutineSymbolTable,
     ) -> Result<()> {
-        let decorator = table.decorator;
-        let sub_type = match decorator {
+        let kind = match table.decorator {
             SubroutineDecorator::Constructor => "constructor",
             SubroutineDecorator::Function => "function",
             SubroutineDecorator::Method => "method",
         };
-        self.start(indent, sub_type)?;
+        let return_type = table
+            .type_name
+            .as_ref()
+            .map_or("void".to_string(), type_text);
+        self.start(indent, kind)?;
         self.leaf(indent + 1, "name", name)?;
-
-        for (var, (decorator, _, number)) in &table.vars {
-            self.subroutine_var_symbol(indent + 1, var, decorator, *number)?;
+        self.leaf(indent + 1, "kind", kind)?;
+        self.leaf(indent + 1, "type", &return_type)?;
+
+        for (var, (decorator, type_name, number)) in &table.vars {
+            let kind = match decorator {
+                SubroutineVarDecorator::Arg => "arg",
+                SubroutineVarDecorator::Local => "local",
+            };
+            self.var_symbol(indent + 1, kind, var, type_name, number)?;
         }
 
-        self.end(indent, sub_type)?;
+        self.end(indent, kind)?;
         Ok(())
     }
 
-    fn subroutine_var_symbol(
-        &mut self,
-        indent: usize,
-        name: &str,
-        decorator: &SubroutineVarDecorator,
-        number: usize,
-    ) -> Result<()> {
-        let var_type = match decorator {
-            SubroutineVarDecorator::Arg => "arg",
-            SubroutineVarDecorator::Local => "local",
-        };
-
-        self.var_symbol(indent, var_type, name, number)
-    }
-
     fn var_symbol(
         &mut self,
         indent: usize,
-        var_type: &str,
+        kind: &str,
         name: &str,
-        number: usize,
-    ) -> std::result::Result<(), std::io::Error> {
-        self.start(indent, var_type)?;
+        type_name: &Type,
+        number: &usize,
+    ) -> Result<()> {
+        self.start(indent, kind)?;
         self.leaf(indent + 1, "name", name)?;
+        self.leaf(indent + 1, "kind", kind)?;
+        self.leaf(indent + 1, "type", &type_text(type_name))?;
         self.leaf(indent + 1, "number", &number.to_string())?;
-
-        self.end(indent, var_type)?;
+        self.end(indent, kind)?;
         Ok(())
     }
 
@@ -197,6 +196,44 @@ where
         Ok(())
     }
 
+    fn const_dec(&mut self, indent: usize, const_dec: ConstDecl) -> Result<()> {
+        match const_dec {
+            ConstDecl::Const {
+                type_name,
+                name,
+                expr,
+            } => {
+                self.start(indent, "constDec")?;
+                self.keyword(indent + 1, "const")?;
+                self.type_name(indent + 1, &type_name)?;
+                self.identifier(indent + 1, &name)?;
+                self.symbol(indent + 1, "=")?;
+                self.expr(indent + 1, &expr)?;
+                self.symbol(indent + 1, ";")?;
+                self.end(indent, "constDec")?;
+            }
+            ConstDecl::Enum { members } => {
+                self.start(indent, "enumDec")?;
+                self.keyword(indent + 1, "enum")?;
+                self.symbol(indent + 1, "{")?;
+                for (i, (name, expr)) in members.iter().enumerate() {
+                    if i > 0 {
+                        self.symbol(indent + 1, ",")?;
+                    }
+                    self.identifier(indent + 1, name)?;
+                    if let Some(expr) = expr {
+                        self.symbol(indent + 1, "=")?;
+                        self.expr(indent + 1, expr)?;
+                    }
+                }
+                self.symbol(indent + 1, "}")?;
+                self.symbol(indent + 1, ";")?;
+                self.end(indent, "enumDec")?;
+            }
+        }
+        Ok(())
+    }
+
     fn subroutine(&mut self, indent: usize, subroutine: Subroutine) -> Result<()> {
         self.start(indent, "subroutineDec")?;
         let value = match subroutine.decorator {
@@ -316,6 +353,7 @@ where
                 } => self.while_statement(indent + 1, condition, statements)?,
                 Statement::Do { expr } => self.do_statement(indent + 1, expr)?,
                 Statement::Return { expr } => self.return_statement(indent + 1, expr)?,
+                statement => self.extended_statement(indent + 1, statement)?,
             }
         }
 
@@ -329,22 +367,104 @@ where
         name: String,
         index: Option<Expr>,
         expr: Expr,
+    ) -> Result<()> {
+        self.assignment(indent, &name, &index, "=", &expr, true)
+    }
+
+    fn assignment(
+        &mut self,
+        indent: usize,
+        name: &str,
+        index: &Option<Expr>,
+        assign: &str,
+        expr: &Expr,
+        semicolon: bool,
     ) -> Result<()> {
         self.start(indent, "letStatement")?;
 
         self.keyword(indent + 1, "let")?;
-        self.identifier(indent + 1, &name)?;
-        self.indexed_expr(indent + 1, &index)?;
+        self.identifier(indent + 1, name)?;
+        self.indexed_expr(indent + 1, index)?;
 
-        self.symbol(indent + 1, "=")?;
-        self.expr(indent + 1, &expr)?;
+        self.symbol(indent + 1, assign)?;
+        self.expr(indent + 1, expr)?;
 
-        self.symbol(indent + 1, ";")?;
+        if semicolon {
+            self.symbol(indent + 1, ";")?;
+        }
 
         self.end(indent, "letStatement")?;
         Ok(())
     }
 
+    // statements from the extended dialect
+    fn extended_statement(&mut self, indent: usize, statement: Statement) -> Result<()> {
+        match statement {
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => {
+                let assign = format!("{}=", op.symbol());
+                self.assignment(indent, &name, &index, &assign, &expr, true)?;
+            }
+            Statement::For {
+                init,
+                condition,
+                step,
+                statements,
+            } => {
+                self.start(indent, "forStatement")?;
+                self.keyword(indent + 1, "for")?;
+                self.symbol(indent + 1, "(")?;
+                match init {
+                    Some(init) => self.for_clause(indent + 1, *init, true)?,
+                    None => self.symbol(indent + 1, ";")?,
+                }
+                self.expr(indent + 1, &condition)?;
+                self.symbol(indent + 1, ";")?;
+                if let Some(step) = step {
+                    self.for_clause(indent + 1, *step, false)?;
+                }
+                self.symbol(indent + 1, ")")?;
+                self.statement_block(indent + 1, statements)?;
+                self.end(indent, "forStatement")?;
+            }
+            Statement::Break => self.jump_statement(indent, "break")?,
+            Statement::Continue => self.jump_statement(indent, "continue")?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn for_clause(&mut self, indent: usize, statement: Statement, semicolon: bool) -> Result<()> {
+        match statement {
+            Statement::Let { name, index, expr } => {
+                self.assignment(indent, &name, &index, "=", &expr, semicolon)
+            }
+            Statement::Compound {
+                name,
+                index,
+                op,
+                expr,
+            } => {
+                let assign = format!("{}=", op.symbol());
+                self.assignment(indent, &name, &index, &assign, &expr, semicolon)
+            }
+            _ => panic!("got a non let statement in a for"),
+        }
+    }
+
+    fn jump_statement(&mut self, indent: usize, keyword: &str) -> Result<()> {
+        let element = format!("{}Statement", keyword);
+        self.start(indent, &element)?;
+        self.keyword(indent + 1, keyword)?;
+        self.symbol(indent + 1, ";")?;
+        self.end(indent, &element)?;
+        Ok(())
+    }
+
     fn if_statement(
         &mut self,
         indent: usize,
@@ -444,6 +564,17 @@ where
     }
 
     fn term(&mut self, indent: usize, term: &Term) -> Result<()> {
+        // not part of the standard grammar, so it gets its own element
+        // rather than pretending to be a term
+        if let Term::Binary(left, op, right) = term {
+            self.start(indent, "binaryExpression")?;
+            self.term(indent + 1, left)?;
+            self.op(indent + 1, op)?;
+            self.term(indent + 1, right)?;
+            self.end(indent, "binaryExpression")?;
+            return Ok(());
+        }
+
         self.start(indent, "term")?;
         match term {
             Term::IntegerLit(i) => self.leaf(indent + 1, "integerConstant", &i.to_string())?,
@@ -456,6 +587,11 @@ where
                 self.identifier(indent + 1, name)?;
                 self.indexed_expr(indent + 1, index)?;
             }
+            Term::Constant { class, name } => {
+                self.identifier(indent + 1, class)?;
+                self.symbol(indent + 1, ".")?;
+                self.identifier(indent + 1, name)?;
+            }
             Term::Bracketed(expr) => {
                 self.symbol(indent + 1, "(")?;
                 self.expr(indent + 1, expr)?;
@@ -474,6 +610,7 @@ where
                 name,
                 exprs,
             } => self.subroutine_call(indent + 1, qualifier, name, exprs)?,
+            Term::Binary(..) => unreachable!(),
         }
 
         self.end(indent, "term")?;
@@ -512,18 +649,7 @@ where
     }
 
     fn op(&mut self, indent: usize, op: &Op) -> Result<()> {
-        let symbol = match op {
-            Op::Add => "+",
-            Op::Sub => "-",
-            Op::Mult => "*",
-            Op::Div => "/",
-            Op::And => "&",
-            Op::Or => "|",
-            Op::Lt => "<",
-            Op::Gt => ">",
-            Op::Eq => "=",
-        };
-        self.symbol(indent, symbol)?;
+        self.symbol(indent, op.symbol())?;
         Ok(())
     }
 
@@ -542,3 +668,68 @@ where
         Ok(())
     }
 }
+
+fn type_text(type_name: &Type) -> String {
+    match type_name {
+        Type::Int => "int".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Class(class) => class.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{options::Options, parser::Parser, tokenizer::Tokenizer};
+
+    fn ast(source: &str, options: Options) -> String {
+        let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+        let class = Parser::new(tokens, options)
+            .parse_class(&mut SymbolTable::new())
+            .unwrap();
+        let mut xml = Vec::new();
+        Xml::new(BufWriter::new(&mut xml)).write_ast(class).unwrap();
+        String::from_utf8(xml).unwrap()
+    }
+
+    #[test]
+    fn test_binary_expression() {
+        let source = "class A { function int f() { return 1 + 2 * 3; } }";
+        let options = Options {
+            precedence: true,
+            ..Options::default()
+        };
+        let expression = "
+          <expression>
+            <binaryExpression>
+              <term>
+                <integerConstant> 1 </integerConstant>
+              </term>
+              <symbol> + </symbol>
+              <binaryExpression>
+                <term>
+                  <integerConstant> 2 </integerConstant>
+                </term>
+                <symbol> * </symbol>
+                <term>
+                  <integerConstant> 3 </integerConstant>
+                </term>
+              </binaryExpression>
+            </binaryExpression>
+          </expression>
+";
+        assert!(ast(source, options).contains(&expression[1..]));
+
+        // without -precedence it's the standard flat list
+        let flat = ast(source, Options::default());
+        assert!(!flat.contains("binaryExpression"));
+        assert!(flat.contains(
+            "<symbol> + </symbol>
+            <term>
+              <integerConstant> 2 </integerConstant>
+            </term>
+            <symbol> * </symbol>"
+        ));
+    }
+}
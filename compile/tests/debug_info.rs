@@ -0,0 +1,139 @@
+// compile -g on a small class: the X.dbg.json it writes next to X.vm
+use std::{env, fs, path::PathBuf, process::Command};
+
+use serde_json::{json, Value};
+
+const COUNTER: &str = "class Counter {
+    static int total;
+    field int count, step;
+
+    constructor Counter new(int start) {
+        let count = start;
+        let step = 1;
+        return this;
+    }
+
+    method void add(int n) {
+        var int i, unused;
+        let i = 0;
+        while (i < n) {
+            let count = count + step;
+            let i = i + 1;
+        }
+        let total = total + n;
+        return;
+    }
+}
+";
+
+// one for each test, as they run at the same time
+fn project(test: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("debug-info-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("Counter.jack"), COUNTER).unwrap();
+    directory
+}
+
+// the vm lines, and the debug info for them
+fn compile(test: &str) -> (Vec<String>, Value) {
+    let directory = project(test);
+    let output = Command::new(env!("CARGO_BIN_EXE_compile"))
+        .arg(&directory)
+        .arg("-g")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let vm = fs::read_to_string(directory.join("Counter.vm")).unwrap();
+    let info = fs::read_to_string(directory.join("Counter.dbg.json")).unwrap();
+    fs::remove_dir_all(directory).unwrap();
+    (
+        vm.lines().map(str::to_string).collect(),
+        serde_json::from_str(&info).unwrap(),
+    )
+}
+
+fn variables(value: &Value) -> Vec<(&str, u64)> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|variable| {
+            (
+                variable["name"].as_str().unwrap(),
+                variable["index"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_lines() {
+    let (vm, info) = compile("lines");
+    let lines = info["lines"].as_array().unwrap();
+    assert_eq!(lines.len(), vm.len());
+    let line = |vm_line: usize| &lines[vm_line];
+
+    // let count = start; is the constructor's first statement
+    let first = vm.iter().position(|line| line == "pop this 0").unwrap();
+    assert_eq!(vm[first - 1], "push argument 0");
+    assert_eq!(
+        *line(first - 1),
+        json!({ "line": 5, "column": 8, "statement": true })
+    );
+    assert_eq!(*line(first), *line(first - 1));
+
+    // the function commands and the setup of this give the declaration
+    let new = vm
+        .iter()
+        .position(|line| line == "function Counter.new 0")
+        .unwrap();
+    for (command, line) in vm[new..new + 4].iter().zip(&lines[new..new + 4]) {
+        assert_eq!(
+            *line,
+            json!({ "line": 4, "column": 4, "statement": false }),
+            "{}",
+            command
+        );
+    }
+    let add = vm
+        .iter()
+        .position(|line| line == "function Counter.add 2")
+        .unwrap();
+    assert_eq!(vm[add + 1..add + 3], ["push argument 0", "pop pointer 0"]);
+    for line in &lines[add..add + 3] {
+        assert_eq!(
+            *line,
+            json!({ "line": 10, "column": 4, "statement": false })
+        );
+    }
+    assert_eq!(
+        *line(add + 3),
+        json!({ "line": 12, "column": 8, "statement": true })
+    );
+    assert_eq!(info["functions"][0]["vmLine"], new);
+    assert_eq!(info["functions"][1]["vmLine"], add);
+}
+
+#[test]
+fn test_variables() {
+    let (_, info) = compile("variables");
+    assert_eq!(info["class"], "Counter");
+    assert_eq!(info["source"], "Counter.jack");
+    assert_eq!(variables(&info["statics"]), [("total", 0)]);
+    assert_eq!(variables(&info["fields"]), [("count", 0), ("step", 1)]);
+
+    let new = &info["functions"][0];
+    assert_eq!(new["name"], "Counter.new");
+    assert_eq!(new["kind"], "constructor");
+    assert_eq!(new["returnType"], "Counter");
+    assert_eq!(variables(&new["args"]), [("start", 0)]);
+    assert!(variables(&new["locals"]).is_empty());
+
+    // a method's this is argument 0
+    let add = &info["functions"][1];
+    assert_eq!(add["kind"], "method");
+    assert_eq!(add["returnType"], "void");
+    assert_eq!(variables(&add["args"]), [("this", 0), ("n", 1)]);
+    assert_eq!(variables(&add["locals"]), [("i", 0), ("unused", 1)]);
+    assert_eq!(add["args"][0]["type"], "Counter");
+}
@@ -0,0 +1,66 @@
+// the formatter on comments it has to keep in place, and on every jack
+// file in the course projects, which formatting twice mustn't change
+use std::{fs, path::Path};
+
+use compile::{
+    format::format_class, options::Options, parser::Parser, symbol_table::SymbolTable,
+    tokenizer::Tokenizer,
+};
+
+fn format(source: &str) -> String {
+    let tokens = Tokenizer::new(source.chars().map(Ok), false);
+    let class = Parser::new(tokens, Options::default())
+        .parse_class(&mut SymbolTable::new())
+        .unwrap();
+    format_class(&class, source, false)
+}
+
+#[test]
+fn test_carried_comment() {
+    let source = "class A {
+   field int direction; // the direction:
+                        // 0=none, 1=up
+   field int size;  // the size
+   // of the square, which isn't lined up,
+   // so it goes with what follows
+   method void f() { return; }
+}
+";
+    assert_eq!(
+        format(source),
+        "class A {
+    field int direction; // the direction:
+                         // 0=none, 1=up
+    field int size; // the size
+
+    // of the square, which isn't lined up,
+    // so it goes with what follows
+    method void f() {
+        return;
+    }
+}
+"
+    );
+}
+
+#[test]
+fn test_projects() {
+    let projects = Path::new(env!("CARGO_MANIFEST_DIR")).join("../projects");
+    let mut paths = Vec::new();
+    let mut directories = vec![projects];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|e| e == "jack") {
+                paths.push(path);
+            }
+        }
+    }
+    assert!(!paths.is_empty());
+    for path in paths {
+        let formatted = format(&fs::read_to_string(&path).unwrap());
+        assert_eq!(format(&formatted), formatted, "{}", path.display());
+    }
+}
@@ -0,0 +1,135 @@
+// jack-lsp over stdin and stdout, with the editor escaping uris in its own
+// way: every file still has to be seen once, with the editor's text
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+const A: &str = "class A {
+    function int f() {
+        return B.size() + B.extra();
+    }
+}
+";
+
+const B: &str = "class B {
+    function int size() {
+        return 1;
+    }
+}
+";
+
+// the editor has added a function that isn't saved yet
+const B_OPEN: &str = "class B {
+    function int size() {
+        return 1;
+    }
+
+    function int extra() {
+        return 2;
+    }
+}
+";
+
+// the directory's name needs escaping in a uri
+fn project() -> PathBuf {
+    let directory = env::temp_dir().join(format!("jack lsp-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("A.jack"), A).unwrap();
+    fs::write(directory.join("B.jack"), B).unwrap();
+    directory
+}
+
+// escaped more than jack-lsp would, as some editors do
+fn uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'/' {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02x}", byte));
+        }
+    }
+    uri
+}
+
+// the replies to the messages, which are sent one after another
+fn session(messages: &[Value]) -> Vec<Value> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jack-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages
+        .iter()
+        .chain([&json!({ "jsonrpc": "2.0", "method": "exit" })])
+    {
+        let body = message.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    let mut replies = Vec::new();
+    let mut rest = String::from_utf8(output.stdout).unwrap();
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let length = header["Content-Length: ".len()..].parse::<usize>().unwrap();
+        replies.push(serde_json::from_str(&body[..length]).unwrap());
+        rest = body[length..].to_string();
+    }
+    replies
+}
+
+fn open(uri: &str, text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": uri, "languageId": "jack", "version": 1, "text": text } },
+    })
+}
+
+#[test]
+fn test_uris() {
+    let directory = project();
+    let a = uri(&directory.join("A.jack"));
+    let b = uri(&directory.join("B.jack"));
+    let replies = session(&[
+        open(&a, A),
+        open(&b, B_OPEN),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/definition",
+            "params": { "textDocument": { "uri": a }, "position": { "line": 2, "character": 30 } },
+        }),
+    ]);
+
+    // each open publishes the directory: A and B once each, and clean
+    let published = replies
+        .iter()
+        .filter(|reply| reply["method"] == "textDocument/publishDiagnostics")
+        .collect::<Vec<_>>();
+    assert_eq!(published.len(), 4, "{:#?}", replies);
+    for notification in &published {
+        assert_eq!(notification["params"]["diagnostics"], json!([]));
+    }
+    assert_eq!(published[0]["params"]["uri"], a);
+    assert_eq!(published[2]["params"]["uri"], b);
+
+    // B.extra is only in the editor's text for B
+    let definition = replies.iter().find(|reply| reply["id"] == 1).unwrap();
+    let result = &definition["result"];
+    assert_eq!(
+        result["range"]["start"],
+        json!({ "line": 5, "character": 17 })
+    );
+    let path = result["uri"].as_str().unwrap();
+    assert!(path.ends_with("/B.jack"), "{}", path);
+
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,95 @@
+// jackc on the course's multi-class programs: Square fits in the rom
+// once it's optimized and runs from the .hack file, Pong doesn't fit
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use asm::cpu::Cpu;
+use vm::stats;
+
+const STEPS: usize = 20_000_000;
+
+// a copy of a projects/11 program to build in
+fn project(name: &str) -> PathBuf {
+    // named as the program is, since the .hack file is named after it
+    let directory = env::temp_dir()
+        .join(format!("jackc-{}", std::process::id()))
+        .join(name);
+    fs::create_dir_all(&directory).unwrap();
+    let project = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../projects/11")
+        .join(name);
+    for entry in fs::read_dir(project).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "jack") {
+            fs::copy(&path, directory.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    directory
+}
+
+fn jackc(directory: &Path, options: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jackc"))
+        .arg(directory)
+        .args(options)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_square() {
+    let directory = project("Square");
+    let output = jackc(
+        &directory,
+        &["-optimize", "-shared-compare", "-extended-vm"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let hack = fs::read_to_string(directory.join("Square.hack")).unwrap();
+    let program = Cpu::parse(&hack).unwrap();
+    assert!(program.len() <= stats::ROM_SIZE);
+
+    // the game starts by drawing its square in the top left corner
+    let mut cpu = Cpu::new(&program);
+    let mut steps = 0;
+    while cpu.ram[screen(0, 0)] == 0 && steps < STEPS {
+        cpu.step();
+        steps += 1;
+    }
+    while cpu.ram[screen(29, 0)] == 0 && steps < STEPS {
+        cpu.step();
+        steps += 1;
+    }
+    assert_eq!(cpu.ram[screen(0, 0)], -1);
+    assert_eq!(cpu.ram[screen(29, 0)], -1);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_pong() {
+    let directory = project("Pong");
+    for options in [
+        &[][..],
+        &[
+            "-optimize",
+            "-shared-compare",
+            "-short-compare",
+            "-extended-vm",
+        ],
+    ] {
+        let output = jackc(&directory, options);
+        assert!(!output.status.success());
+        let error = String::from_utf8_lossy(&output.stderr);
+        assert!(error.contains("the ROM only holds 32768"), "{}", error);
+        assert!(!directory.join("Pong.hack").exists());
+    }
+    fs::remove_dir_all(directory).unwrap();
+}
+
+// the address of the word holding the first pixel of row y of the
+// screen's first 16 columns, from x
+fn screen(y: usize, x: usize) -> usize {
+    16384 + y * 32 + x / 16
+}
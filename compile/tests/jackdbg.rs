@@ -0,0 +1,85 @@
+// jackdbg driven through stdin on projects/11/ConvertToBin and Pong
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+fn debug(commands: &str) -> String {
+    debug_project("ConvertToBin", commands)
+}
+
+fn debug_project(project: &str, commands: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jackdbg"))
+        .arg(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../projects/11")
+                .join(project),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_function_breakpoint() {
+    // fillMemory starts with a while loop, which mustn't stop again
+    // every time round
+    let output = debug("break Main.fillMemory\nrun\nnext\ncontinue\nquit\n");
+    assert!(output.contains("Breakpoint 1 at Main.fillMemory at Main.jack:75\n"));
+    assert!(output.contains("Breakpoint 1, Main.fillMemory at Main.jack:75\n75\t"));
+    assert!(output.contains("Main.fillMemory at Main.jack:76\n76\t"));
+    assert!(output.contains("Program halted"));
+    assert_eq!(output.matches("Breakpoint 1,").count(), 1);
+
+    let output = debug("break Main.fillMemory\nrun\nfinish\nquit\n");
+    assert!(output.contains("Run till exit from Main.fillMemory"));
+    assert!(output.contains("Main.main at Main.jack:"));
+    assert_eq!(output.matches("Breakpoint 1,").count(), 1);
+    // fillMemory is void
+    assert!(!output.contains("Value returned"));
+
+    let output = debug("break Main.nextMask\nrun\nfinish\nquit\n");
+    assert!(output.contains("Value returned is 1\n"));
+}
+
+// a method's breakpoint stops once its prologue has set this
+#[test]
+fn test_method_breakpoint() {
+    let output = debug_project(
+        "Pong",
+        "break Ball.move\nrun\nprint this\ninfo fields\nframe 1\nprint ball\nquit\n",
+    );
+    assert!(output.contains("Breakpoint 1, Ball.move at Ball.jack:109\n109\t"));
+    let value = |name: &str| {
+        let rest = output.split(&format!("{} = ", name)).nth(1).unwrap();
+        rest.split_once('\n').unwrap().0.to_string()
+    };
+    // this is the ball the game calling it holds
+    assert_eq!(value("this"), value("ball"));
+    // and the fields are the ball's, not the game's
+    assert!(output.contains("(jackdbg) x = "));
+    assert!(output.contains("\nbottomWall = "));
+    assert!(!output.contains("\nbatWidth = "));
+}
+
+#[test]
+fn test_line_breakpoint() {
+    let output = debug("break Main.jack:76\nrun\ncontinue\ndelete\ncontinue\nquit\n");
+    assert_eq!(
+        output
+            .matches("Breakpoint 1, Main.fillMemory at Main.jack:76")
+            .count(),
+        2
+    );
+    assert!(output.contains("Program halted"));
+}
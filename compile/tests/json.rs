@@ -0,0 +1,164 @@
+// classes written out as ast json and read back in, which should compile
+// to the same vm code as the classes they came from
+use std::{fs, io::BufWriter, path::Path};
+
+use compile::{
+    ast::{Class, ParseError},
+    emitter::Emitter,
+    json::{self, JsonError},
+    options::Options,
+    parser::Parser,
+    symbol_table::SymbolTable,
+    tokenizer::Tokenizer,
+};
+use vm::files;
+
+const EXTENDED: &str = r#"class Shapes {
+    const int SIDES = 4;
+    const int LIMIT = 0x7F;
+    enum { NONE, UP = 3, DOWN };
+    static char mark;
+
+    function int count(Array a, int n) {
+        var int i, total;
+        let mark = 'x';
+        for (let i = 0; i < n; let i = i + 1) {
+            if (a[i] = NONE) {
+                continue;
+            } else if (a[i] > LIMIT) {
+                break;
+            } else {
+                let total += a[i] * SIDES;
+            }
+        }
+        let total -= DOWN | 0b101;
+        do Output.printString("say \"hi\"\\n");
+        return total;
+    }
+}
+"#;
+
+fn emit(classes: &[Class], symbol_table: &SymbolTable, options: Options) -> Vec<String> {
+    classes
+        .iter()
+        .map(|class| {
+            let mut vm = Vec::new();
+            Emitter::new(BufWriter::new(&mut vm), symbol_table, options)
+                .emit_class(class)
+                .unwrap();
+            String::from_utf8(vm).unwrap()
+        })
+        .collect()
+}
+
+fn check(sources: &[String], options: Options) {
+    let mut symbol_table = SymbolTable::new();
+    let classes = sources
+        .iter()
+        .map(|source| {
+            let tokens = Tokenizer::new(source.chars().map(Ok), options.extended);
+            Parser::new(tokens, options)
+                .parse_class(&mut symbol_table)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let vm = emit(&classes, &symbol_table, options);
+
+    // through text, as -output=ast-json and -input=ast-json would
+    let mut read_symbol_table = SymbolTable::new();
+    let read = classes
+        .iter()
+        .map(|class| {
+            let text = serde_json::to_string_pretty(&json::class_json(class, None)).unwrap();
+            let document = serde_json::from_str(&text).unwrap();
+            json::read_class(&document, &mut read_symbol_table).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(read, classes);
+    assert_eq!(emit(&read, &read_symbol_table, options), vm);
+}
+
+fn project(name: &str) -> Vec<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../projects/11")
+        .join(name);
+    let (input_files, _) = files::input_files(&path, ".jack");
+    input_files
+        .iter()
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_pong() {
+    check(&project("Pong"), Options::default());
+    let options = Options {
+        precedence: true,
+        ..Options::default()
+    };
+    check(&project("Pong"), options);
+}
+
+#[test]
+fn test_extended() {
+    let options = Options {
+        extended: true,
+        ..Options::default()
+    };
+    check(&[EXTENDED.to_string()], options);
+}
+
+// the reader checks what the parser would have, so the emitter never
+// sees a break or continue outside a loop
+#[test]
+fn test_jump_outside_loop() {
+    let options = Options {
+        extended: true,
+        ..Options::default()
+    };
+    let source = "class A {
+        function void f() {
+            while (true) { if (true) { break; } }
+            for (;true;) { }
+            return;
+        }
+    }";
+    let tokens = Tokenizer::new(source.chars().map(Ok), true);
+    let class = Parser::new(tokens, options)
+        .parse_class(&mut SymbolTable::new())
+        .unwrap();
+    let document = json::class_json(&class, None);
+    let read = |pointer: &str, kind: &str| {
+        let mut document = document.clone();
+        let statements = document.pointer_mut(pointer).unwrap();
+        statements
+            .as_array_mut()
+            .unwrap()
+            .insert(0, serde_json::json!({ "kind": kind }));
+        json::read_class(&document, &mut SymbolTable::new())
+    };
+
+    let statements = "/class/subroutines/0/statements";
+    let while_body = "/class/subroutines/0/statements/0/statements";
+    let if_body = "/class/subroutines/0/statements/0/statements/0/statements";
+    let for_body = "/class/subroutines/0/statements/1/statements";
+    for kind in ["break", "continue"] {
+        assert!(matches!(
+            read(statements, kind),
+            Err(JsonError::ParseError(ParseError::NotInLoop))
+        ));
+        assert!(read(while_body, kind).is_ok());
+        assert!(read(if_body, kind).is_ok());
+        assert!(read(for_body, kind).is_ok());
+    }
+
+    // a for's step runs outside its body
+    let mut document = document;
+    *document
+        .pointer_mut("/class/subroutines/0/statements/1/step")
+        .unwrap() = serde_json::json!({ "kind": "break" });
+    assert!(matches!(
+        json::read_class(&document, &mut SymbolTable::new()),
+        Err(JsonError::ParseError(ParseError::NotInLoop))
+    ));
+}
@@ -0,0 +1,81 @@
+// the signatures of the os built into vm are written out by hand, so
+// they're checked against the projects/12 jack source they describe
+use std::{fs, path::Path};
+
+use compile::{
+    json, options::Options, parser::Parser, symbol_table::SymbolTable, tokenizer::Tokenizer,
+};
+use serde_json::Value;
+use vm::os::{CLASSES, SIGNATURES};
+
+// class, kind, return type, name and parameters
+type Signature = (String, String, String, String, Vec<(String, String)>);
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap().to_string()
+}
+
+fn parsed(class: &str) -> Vec<Signature> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../projects/12")
+        .join(format!("{}.jack", class));
+    let source = fs::read_to_string(path).unwrap();
+    let tokens = Tokenizer::new(source.chars().map(Ok), false);
+    let parsed = Parser::new(tokens, Options::default())
+        .parse_class(&mut SymbolTable::new())
+        .unwrap();
+    let document = json::class_json(&parsed, None);
+    document["class"]["subroutines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subroutine| {
+            let params = subroutine["params"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|param| (text(&param["type"]), text(&param["name"])))
+                .collect();
+            (
+                class.to_string(),
+                text(&subroutine["kind"]),
+                text(&subroutine["returnType"]),
+                text(&subroutine["name"]),
+                params,
+            )
+        })
+        .collect()
+}
+
+fn built_in(class: &str) -> Vec<Signature> {
+    SIGNATURES
+        .iter()
+        .filter(|signature| signature.class == class)
+        .map(|signature| {
+            let params = signature
+                .params
+                .iter()
+                .map(|(type_name, name)| (type_name.to_string(), name.to_string()))
+                .collect();
+            (
+                signature.class.to_string(),
+                signature.kind.to_string(),
+                signature.return_type.to_string(),
+                signature.name.to_string(),
+                params,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_signatures() {
+    let mut count = 0;
+    for (class, _) in CLASSES {
+        let signatures = built_in(class);
+        assert_eq!(signatures, parsed(class));
+        count += signatures.len();
+    }
+    // and there are none for classes that aren't in the os
+    assert_eq!(count, SIGNATURES.len());
+}
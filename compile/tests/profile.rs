@@ -0,0 +1,120 @@
+// projects/11/Seven profiled by vm command in the vm interpreter and by
+// instruction on the cpu emulator, which should agree on who called what,
+// and projects/11/Pong, which only the vm interpreter can run
+use std::path::Path;
+
+use asm::cpu::Cpu;
+use compile::{
+    harness::Script,
+    options::Options,
+    profile::{self, CodeMap, Profile},
+    run,
+};
+use vm::{emitter::EmitOptions, files, interpreter::Machine};
+
+const STEPS: u64 = 10_000_000;
+
+fn check(profile: &Profile) {
+    assert!(profile.halted);
+    let folded = profile.folded();
+    let total = folded
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap())
+        .sum::<u64>();
+    assert_eq!(total, profile.steps);
+    assert!(folded.contains("Sys.init;Main.main;Output.printInt;"));
+
+    let functions = profile.functions();
+    assert_eq!(
+        functions.iter().map(|function| function.own).sum::<u64>(),
+        profile.steps
+    );
+    let main = functions
+        .iter()
+        .find(|function| function.name == "Main.main")
+        .unwrap();
+    assert_eq!(main.calls, 1);
+    assert!(main.total > main.own);
+    let multiply = functions
+        .iter()
+        .find(|function| function.name == "Math.multiply")
+        .unwrap();
+    assert_eq!(multiply.calls, 354);
+    assert_eq!(multiply.own, multiply.total);
+
+    let calls = profile.calls();
+    assert!(calls.contains(&("Sys.init", "Main.main", 1)));
+    assert!(calls.contains(&("Main.main", "Math.multiply", 1)));
+}
+
+#[test]
+fn test_profile() {
+    let (input_files, _) = files::input_files(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("../projects/11/Seven"),
+        ".jack",
+    );
+    let (program, debug_info, _) = run::compile_jack(&input_files, Options::default()).unwrap();
+
+    let mut machine = Machine::new(&program);
+    machine.bootstrap().unwrap();
+    let map = CodeMap::vm(&machine);
+    let by_command = profile::profile(&mut machine, &map, &Script::new(), STEPS).unwrap();
+    check(&by_command);
+    let lines = by_command.lines(&debug_info);
+    assert_eq!(lines[0].0, "Main.jack");
+    assert_eq!(lines[0].1, 13);
+
+    let program = vm::linker::remove_unreachable(program, "Sys.init");
+    let asm = vm::emitter::emit_program(&program, true, EmitOptions::default());
+    let instructions = asm::parser::parse_lines(&asm).unwrap();
+    let symbol_table = asm::analyzer::analyze(instructions.iter());
+    let mut cpu = Cpu::new(&asm::emitter::emit_instructions(
+        instructions.iter(),
+        &symbol_table,
+    ));
+    let map = CodeMap::hack(&asm);
+    let by_instruction = profile::profile(&mut cpu, &map, &Script::new(), STEPS).unwrap();
+    check(&by_instruction);
+    assert!(by_instruction.steps > by_command.steps);
+    assert!(by_instruction.folded().starts_with("(bootstrap) "));
+    assert_eq!(by_instruction.lines(&debug_info)[0].1, 13);
+}
+
+// Pong is too big for the rom, so it's profiled by vm command only
+#[test]
+fn test_pong() {
+    let (input_files, _) = files::input_files(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("../projects/11/Pong"),
+        ".jack",
+    );
+    let (program, debug_info, _) = run::compile_jack(&input_files, Options::default()).unwrap();
+
+    let mut machine = Machine::new(&program);
+    machine.bootstrap().unwrap();
+    let map = CodeMap::vm(&machine);
+    let profile = profile::profile(&mut machine, &map, &Script::new(), u64::MAX).unwrap();
+    // without key presses the ball gets past the bat and the game ends
+    assert!(profile.halted);
+    assert!(profile
+        .folded()
+        .contains("Sys.init;Main.main;PongGame.run;PongGame.moveBall;Ball.move;"));
+
+    let functions = profile.functions();
+    assert_eq!(
+        functions.iter().map(|function| function.own).sum::<u64>(),
+        profile.steps
+    );
+    assert_eq!(functions[0].name, "Math.multiply");
+    let run = functions
+        .iter()
+        .find(|function| function.name == "PongGame.run")
+        .unwrap();
+    assert_eq!(run.calls, 1);
+    assert!(profile.calls().contains(&("Main.main", "PongGame.run", 1)));
+    let (source, _, _) = profile.lines(&debug_info)[0];
+    assert!(["Ball.jack", "Bat.jack", "PongGame.jack"].contains(&source));
+
+    let program = vm::linker::remove_unreachable(program, "Sys.init");
+    let asm = vm::emitter::emit_program(&program, true, EmitOptions::default());
+    assert!(vm::stats::count_instructions(&asm) > vm::stats::ROM_SIZE);
+}
@@ -0,0 +1,108 @@
+// the course's os tests in projects/12 that show their results on the
+// screen, run with their key presses scripted and their screens compared
+// with the screenshots that come with them
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use asm::{gif::Image, keyboard, screen};
+use compile::{
+    harness::{self, Script, TAP},
+    options::Options,
+    run,
+};
+use vm::{files, interpreter::Machine};
+
+const STEPS: usize = 200_000_000;
+// the screenshots are scaled down, so a little of each screen is lost
+const TOLERANCE: f64 = 0.03;
+
+fn project(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../projects/12")
+        .join(name)
+}
+
+// runs a test to its end, and reads the screenshot of how it should end
+fn run_test(name: &str, script: &Script) -> (Machine, Image) {
+    let (input_files, _) = files::input_files(&project(name), ".jack");
+    let mut machine = run::load_jack(&input_files, Options::default()).unwrap();
+    let steps = harness::run(&mut machine, script, STEPS).unwrap();
+    assert!(
+        machine.halted,
+        "{} still running after {} steps",
+        name, steps
+    );
+    let image = harness::read_image(&project(name).join(format!("{}Output.gif", name))).unwrap();
+    (machine, image)
+}
+
+fn check(name: &str, script: &Script) {
+    let (machine, image) = run_test(name, script);
+    let comparison = harness::compare(&machine.ram, &image);
+    assert!(comparison.matches(TOLERANCE), "{}: {:?}", name, comparison);
+}
+
+#[test]
+fn test_screen() {
+    check("ScreenTest", &Script::new());
+}
+
+#[test]
+fn test_output() {
+    check("OutputTest", &Script::new());
+}
+
+#[test]
+fn test_string() {
+    check("StringTest", &Script::new());
+}
+
+#[test]
+fn test_keyboard() {
+    let script = Script::parse(include_str!("keyboard_test.txt")).unwrap();
+    check("KeyboardTest", &script);
+}
+
+#[test]
+fn test_wrong_screen() {
+    let (machine, _) = run_test("OutputTest", &Script::new());
+    let image = harness::read_image(&project("StringTest").join("StringTestOutput.gif")).unwrap();
+    let comparison = harness::compare(&machine.ram, &image);
+    assert!(!comparison.matches(TOLERANCE), "{:?}", comparison);
+    let blank = vec![0; machine.ram.len()];
+    assert!(!harness::compare(&blank, &image).matches(TOLERANCE));
+}
+
+#[test]
+fn test_exact() {
+    let (mut machine, _) = run_test("ScreenTest", &Script::new());
+    let path = env::temp_dir().join(format!("screen-test-{}.pbm", std::process::id()));
+    fs::write(&path, screen::pbm(&machine.ram)).unwrap();
+    let image = harness::read_image(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let comparison = harness::compare(&machine.ram, &image);
+    assert_eq!(comparison.differences, 0);
+    assert!(comparison.matches(0.0));
+    machine.ram[screen::SCREEN] ^= 1;
+    assert_eq!(harness::compare(&machine.ram, &image).differences, 1);
+}
+
+#[test]
+fn test_script() {
+    let script = Script::parse("# a comment\nwait 10\ntap PAGE_DOWN\n\ngap 100\ntype J\n").unwrap();
+    assert_eq!(
+        script,
+        Script::new()
+            .wait(10)
+            .tap(keyboard::PAGE_DOWN)
+            .gap(100)
+            .type_text("J")
+    );
+    assert_eq!(script.len(), 10 + 2 * TAP + TAP + 100);
+    assert!(Script::parse("tap ENTER").is_err());
+    assert!(Script::parse("jump 3").is_err());
+    assert!(Script::parse("wait soon").is_err());
+}
@@ -158,7 +158,7 @@ M=D
 D=A
 @THAT
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -176,7 +176,7 @@ M=D+M
 D=A
 @ARG
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -194,7 +194,7 @@ M=M-D
 D=A
 @THIS
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -205,7 +205,7 @@ M=D
 D=A
 @THIS
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -229,7 +229,7 @@ M=M-D
 @6
 D=A
 @R5
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
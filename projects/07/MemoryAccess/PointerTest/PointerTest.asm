@@ -93,7 +93,7 @@ M=D
 @1
 D=A
 @THIS
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -111,7 +111,7 @@ M=D+M
 D=A
 @THIS
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -129,7 +129,7 @@ M=M-D
 D=A
 @THAT
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
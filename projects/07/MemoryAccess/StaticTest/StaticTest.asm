@@ -30,31 +30,31 @@ M=D
 M=M-1
 A=M
 D=M
-@StaticTest8
+@StaticTest.8
 M=D
 // pop static 3
 @SP
 M=M-1
 A=M
 D=M
-@StaticTest3
+@StaticTest.3
 M=D
 // pop static 1
 @SP
 M=M-1
 A=M
 D=M
-@StaticTest1
+@StaticTest.1
 M=D
 // push static 3
-@StaticTest3
+@StaticTest.3
 D=M
 @SP
 M=M+1
 A=M-1
 M=D
 // push static 1
-@StaticTest1
+@StaticTest.1
 D=M
 @SP
 M=M+1
@@ -68,7 +68,7 @@ D=M
 A=A-1
 M=M-D
 // push static 8
-@StaticTest8
+@StaticTest.8
 D=M
 @SP
 M=M+1
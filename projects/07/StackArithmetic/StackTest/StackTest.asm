@@ -120,8 +120,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.3
+D;JLT
+@R13
+D=M
+@StackTest$same.3
+D;JGE
+D=1
+@StackTest$test.3
+0;JMP
+(StackTest$negative.3)
+@R13
+D=M
+@StackTest$same.3
+D;JLT
+D=-1
+@StackTest$test.3
+0;JMP
+(StackTest$same.3)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.3)
 @StackTest$true.3
 D;JLT
 D=0
@@ -152,8 +179,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.4
+D;JLT
+@R13
+D=M
+@StackTest$same.4
+D;JGE
+D=1
+@StackTest$test.4
+0;JMP
+(StackTest$negative.4)
+@R13
+D=M
+@StackTest$same.4
+D;JLT
+D=-1
+@StackTest$test.4
+0;JMP
+(StackTest$same.4)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.4)
 @StackTest$true.4
 D;JLT
 D=0
@@ -184,8 +238,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.5
+D;JLT
+@R13
+D=M
+@StackTest$same.5
+D;JGE
+D=1
+@StackTest$test.5
+0;JMP
+(StackTest$negative.5)
+@R13
+D=M
+@StackTest$same.5
+D;JLT
+D=-1
+@StackTest$test.5
+0;JMP
+(StackTest$same.5)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.5)
 @StackTest$true.5
 D;JLT
 D=0
@@ -216,8 +297,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.6
+D;JLT
+@R13
+D=M
+@StackTest$same.6
+D;JGE
+D=1
+@StackTest$test.6
+0;JMP
+(StackTest$negative.6)
+@R13
+D=M
+@StackTest$same.6
+D;JLT
+D=-1
+@StackTest$test.6
+0;JMP
+(StackTest$same.6)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.6)
 @StackTest$true.6
 D;JGT
 D=0
@@ -248,8 +356,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.7
+D;JLT
+@R13
+D=M
+@StackTest$same.7
+D;JGE
+D=1
+@StackTest$test.7
+0;JMP
+(StackTest$negative.7)
+@R13
+D=M
+@StackTest$same.7
+D;JLT
+D=-1
+@StackTest$test.7
+0;JMP
+(StackTest$same.7)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.7)
 @StackTest$true.7
 D;JGT
 D=0
@@ -280,8 +415,35 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@StackTest$negative.8
+D;JLT
+@R13
+D=M
+@StackTest$same.8
+D;JGE
+D=1
+@StackTest$test.8
+0;JMP
+(StackTest$negative.8)
+@R13
+D=M
+@StackTest$same.8
+D;JLT
+D=-1
+@StackTest$test.8
+0;JMP
+(StackTest$same.8)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
+(StackTest$test.8)
 @StackTest$true.8
 D;JGT
 D=0
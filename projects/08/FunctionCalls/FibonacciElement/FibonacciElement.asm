@@ -10,7 +10,7 @@ D=M
 D=D-A
 @13
 M=D
-@Sys.init$ret0
+@Main$ret.0
 D=A
 @SP
 M=M+1
@@ -50,79 +50,7 @@ D=M
 M=D
 @Sys.init
 0;JMP
-(Sys.init$ret0)
-// // This file is part of www.nand2tetris.org
-// // and the book "The Elements of Computing Systems"
-// // by Nisan and Schocken, MIT Press.
-// // File name: projects/08/FunctionCalls/FibonacciElement/Sys.vm
-// 
-// // Pushes a constant, say n, onto the stack, and calls the Main.fibonacii
-// // function, which computes the n'th element of the Fibonacci series.
-// // Note that by convention, the Sys.init function is called "automatically" 
-// // by the bootstrap code.
-// 
-// function Sys.init 0
-(Sys.init)
-// push constant 4
-@4
-D=A
-@SP
-M=M+1
-A=M-1
-M=D
-// call Main.fibonacci 1
-@SP
-D=M
-@1
-D=D-A
-@13
-M=D
-@Main.fibonacci$ret0
-D=A
-@SP
-M=M+1
-A=M-1
-M=D
-@LCL
-D=M
-@SP
-M=M+1
-A=M-1
-M=D
-@ARG
-D=M
-@SP
-M=M+1
-A=M-1
-M=D
-@THIS
-D=M
-@SP
-M=M+1
-A=M-1
-M=D
-@THAT
-D=M
-@SP
-M=M+1
-A=M-1
-M=D
-@R13
-D=M
-@ARG
-M=D
-@SP
-D=M
-@LCL
-M=D
-@Main.fibonacci
-0;JMP
-(Main.fibonacci$ret0)
-// label WHILE
-(Sys.init$WHILE)
-// goto WHILE
-@Sys.init$WHILE
-0;JMP
+(Main$ret.0)
 // // This file is part of www.nand2tetris.org
 // // and the book "The Elements of Computing Systems"
 // // by Nisan and Schocken, MIT Press.
@@ -155,16 +83,43 @@ M=D
 M=M-1
 A=M
 D=M
-A=A-1
+@R13
+M=D
+@SP
+A=M-1
+D=M
+@Main.fibonacci$negative.0
+D;JLT
+@R13
+D=M
+@Main.fibonacci$same.0
+D;JGE
+D=1
+@Main.fibonacci$test.0
+0;JMP
+(Main.fibonacci$negative.0)
+@R13
+D=M
+@Main.fibonacci$same.0
+D;JLT
+D=-1
+@Main.fibonacci$test.0
+0;JMP
+(Main.fibonacci$same.0)
+@R13
+D=M
+@SP
+A=M-1
 D=M-D
-@Main.fibonacci$true0
+(Main.fibonacci$test.0)
+@Main.fibonacci$true.0
 D;JLT
 D=0
-@Main.fibonacci$join0
+@Main.fibonacci$join.0
 0;JMP
-(Main.fibonacci$true0)
+(Main.fibonacci$true.0)
 D=-1
-(Main.fibonacci$join0)
+(Main.fibonacci$join.0)
 @SP
 A=M-1
 M=D
@@ -268,7 +223,7 @@ D=M
 D=D-A
 @13
 M=D
-@Main.fibonacci$ret1
+@Main.fibonacci$ret.1
 D=A
 @SP
 M=M+1
@@ -308,7 +263,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(Main.fibonacci$ret1)
+(Main.fibonacci$ret.1)
 // push argument 0
 @ARG
 A=M
@@ -338,7 +293,7 @@ D=M
 D=D-A
 @13
 M=D
-@Main.fibonacci$ret2
+@Main.fibonacci$ret.2
 D=A
 @SP
 M=M+1
@@ -378,7 +333,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(Main.fibonacci$ret2)
+(Main.fibonacci$ret.2)
 // add
 @SP
 M=M-1
@@ -435,3 +390,75 @@ M=D
 @R14
 A=M
 0;JMP
+// // This file is part of www.nand2tetris.org
+// // and the book "The Elements of Computing Systems"
+// // by Nisan and Schocken, MIT Press.
+// // File name: projects/08/FunctionCalls/FibonacciElement/Sys.vm
+// 
+// // Pushes a constant, say n, onto the stack, and calls the Main.fibonacii
+// // function, which computes the n'th element of the Fibonacci series.
+// // Note that by convention, the Sys.init function is called "automatically" 
+// // by the bootstrap code.
+// 
+// function Sys.init 0
+(Sys.init)
+// push constant 4
+@4
+D=A
+@SP
+M=M+1
+A=M-1
+M=D
+// call Main.fibonacci 1
+@SP
+D=M
+@1
+D=D-A
+@13
+M=D
+@Sys.init$ret.0
+D=A
+@SP
+M=M+1
+A=M-1
+M=D
+@LCL
+D=M
+@SP
+M=M+1
+A=M-1
+M=D
+@ARG
+D=M
+@SP
+M=M+1
+A=M-1
+M=D
+@THIS
+D=M
+@SP
+M=M+1
+A=M-1
+M=D
+@THAT
+D=M
+@SP
+M=M+1
+A=M-1
+M=D
+@R13
+D=M
+@ARG
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Main.fibonacci
+0;JMP
+(Sys.init$ret.0)
+// label WHILE
+(Sys.init$WHILE)
+// goto WHILE
+@Sys.init$WHILE
+0;JMP
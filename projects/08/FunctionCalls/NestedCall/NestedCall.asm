@@ -49,7 +49,7 @@ D=M
 D=D-A
 @13
 M=D
-@Sys.main$ret0
+@Sys.init$ret.0
 D=A
 @SP
 M=M+1
@@ -89,7 +89,7 @@ D=M
 M=D
 @Sys.main
 0;JMP
-(Sys.main$ret0)
+(Sys.init$ret.0)
 // pop temp 1
 @R5
 D=A
@@ -262,7 +262,7 @@ D=M
 D=D-A
 @13
 M=D
-@Sys.add12$ret0
+@Sys.main$ret.0
 D=A
 @SP
 M=M+1
@@ -302,7 +302,7 @@ D=M
 M=D
 @Sys.add12
 0;JMP
-(Sys.add12$ret0)
+(Sys.main$ret.0)
 // pop temp 0
 @SP
 M=M-1
@@ -323,7 +323,7 @@ M=D
 D=A
 @LCL
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -334,7 +334,7 @@ M=D
 D=A
 @LCL
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -345,7 +345,7 @@ M=D
 D=A
 @LCL
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -356,7 +356,7 @@ M=D
 D=A
 @LCL
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -31,7 +31,7 @@ M=D
 D=A
 @LCL
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -68,7 +68,7 @@ M=D+M
 D=A
 @ARG
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -10,7 +10,7 @@ D=M
 D=D-A
 @13
 M=D
-@Sys.init$ret0
+@Class1$ret.0
 D=A
 @SP
 M=M+1
@@ -50,15 +50,15 @@ D=M
 M=D
 @Sys.init
 0;JMP
-(Sys.init$ret0)
+(Class1$ret.0)
 // // This file is part of www.nand2tetris.org
 // // and the book "The Elements of Computing Systems"
 // // by Nisan and Schocken, MIT Press.
-// // File name: projects/08/FunctionCalls/StaticsTest/Class2.vm
+// // File name: projects/08/FunctionCalls/StaticsTest/Class1.vm
 // 
 // // Stores two supplied arguments in static[0] and static[1].
-// function Class2.set 0
-(Class2.set)
+// function Class1.set 0
+(Class1.set)
 // push argument 0
 @ARG
 A=M
@@ -72,14 +72,14 @@ M=D
 M=M-1
 A=M
 D=M
-@Class2.0
+@Class1.0
 M=D
 // push argument 1
 @1
 D=A
 @ARG
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -90,7 +90,7 @@ M=D
 M=M-1
 A=M
 D=M
-@Class2.1
+@Class1.1
 M=D
 // push constant 0
 @0
@@ -150,17 +150,17 @@ A=M
 0;JMP
 // 
 // // Returns static[0] - static[1].
-// function Class2.get 0
-(Class2.get)
+// function Class1.get 0
+(Class1.get)
 // push static 0
-@Class2.0
+@Class1.0
 D=M
 @SP
 M=M+1
 A=M-1
 M=D
 // push static 1
-@Class2.1
+@Class1.1
 D=M
 @SP
 M=M+1
@@ -225,11 +225,11 @@ A=M
 // // This file is part of www.nand2tetris.org
 // // and the book "The Elements of Computing Systems"
 // // by Nisan and Schocken, MIT Press.
-// // File name: projects/08/FunctionCalls/StaticsTest/Class1.vm
+// // File name: projects/08/FunctionCalls/StaticsTest/Class2.vm
 // 
 // // Stores two supplied arguments in static[0] and static[1].
-// function Class1.set 0
-(Class1.set)
+// function Class2.set 0
+(Class2.set)
 // push argument 0
 @ARG
 A=M
@@ -243,14 +243,14 @@ M=D
 M=M-1
 A=M
 D=M
-@Class1.0
+@Class2.0
 M=D
 // push argument 1
 @1
 D=A
 @ARG
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -261,7 +261,7 @@ M=D
 M=M-1
 A=M
 D=M
-@Class1.1
+@Class2.1
 M=D
 // push constant 0
 @0
@@ -321,17 +321,17 @@ A=M
 0;JMP
 // 
 // // Returns static[0] - static[1].
-// function Class1.get 0
-(Class1.get)
+// function Class2.get 0
+(Class2.get)
 // push static 0
-@Class1.0
+@Class2.0
 D=M
 @SP
 M=M+1
 A=M-1
 M=D
 // push static 1
-@Class1.1
+@Class2.1
 D=M
 @SP
 M=M+1
@@ -423,7 +423,7 @@ D=M
 D=D-A
 @13
 M=D
-@Class1.set$ret0
+@Sys.init$ret.0
 D=A
 @SP
 M=M+1
@@ -463,7 +463,7 @@ D=M
 M=D
 @Class1.set
 0;JMP
-(Class1.set$ret0)
+(Sys.init$ret.0)
 // pop temp 0
 @SP
 M=M-1
@@ -492,7 +492,7 @@ D=M
 D=D-A
 @13
 M=D
-@Class2.set$ret1
+@Sys.init$ret.1
 D=A
 @SP
 M=M+1
@@ -532,7 +532,7 @@ D=M
 M=D
 @Class2.set
 0;JMP
-(Class2.set$ret1)
+(Sys.init$ret.1)
 // pop temp 0
 @SP
 M=M-1
@@ -547,7 +547,7 @@ D=M
 D=D-A
 @13
 M=D
-@Class1.get$ret2
+@Sys.init$ret.2
 D=A
 @SP
 M=M+1
@@ -587,7 +587,7 @@ D=M
 M=D
 @Class1.get
 0;JMP
-(Class1.get$ret2)
+(Sys.init$ret.2)
 // call Class2.get 0
 @SP
 D=M
@@ -595,7 +595,7 @@ D=M
 D=D-A
 @13
 M=D
-@Class2.get$ret3
+@Sys.init$ret.3
 D=A
 @SP
 M=M+1
@@ -635,7 +635,7 @@ D=M
 M=D
 @Class2.get
 0;JMP
-(Class2.get$ret3)
+(Sys.init$ret.3)
 // label WHILE
 (Sys.init$WHILE)
 // goto WHILE
@@ -13,7 +13,7 @@
 D=A
 @ARG
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -140,7 +140,7 @@ M=D
 D=A
 @THAT
 A=M
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
@@ -172,7 +172,7 @@ M=D
 @1
 D=A
 @THIS
-A=A+D
+A=D+A
 D=M
 @SP
 M=M+1
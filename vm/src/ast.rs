@@ -11,6 +11,15 @@ pub enum Command {
     And,
     Or,
     Not,
+    // extended commands, only accepted when enabled
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    Le,
+    Ge,
+    Ne,
     Goto(String),
     IfGoto(String),
     Label(String),
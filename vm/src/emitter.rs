@@ -6,20 +6,36 @@ mod function;
 mod math_logic;
 mod names;
 mod push_pop;
+mod runtime;
 
 use branching::*;
 use compare::*;
 use function::*;
 use math_logic::*;
 use push_pop::*;
+use runtime::*;
+
+pub use runtime::{emit_routines, Routine};
+
+use std::collections::BTreeSet;
 
 use crate::ast::*;
 use crate::printer;
 
+#[derive(Clone, Copy, Default)]
+pub struct EmitOptions {
+    // skip the overflow checks in gt/lt/le/ge for smaller code
+    pub short_compare: bool,
+    // call shared routines for comparisons rather than expanding them inline
+    pub shared_compare: bool,
+}
+
 pub fn emit_commands(
     commands: Vec<Command>,
     statics_base: &str,
     bootstrap: bool,
+    options: EmitOptions,
+    routines: &mut BTreeSet<Routine>,
 ) -> impl IntoIterator<Item = String> {
     let mut current_function = statics_base.to_string();
     let mut label_number = 0;
@@ -36,11 +52,35 @@ pub fn emit_commands(
             statics_base,
             &mut current_function,
             &mut label_number,
+            options,
+            routines,
         )
     }));
     results
 }
 
+// a whole program, one (statics base, commands) per file, followed by the
+// shared routines they use. The bootstrap goes in front of the first file
+pub fn emit_program(
+    files: &[(String, Vec<Command>)],
+    bootstrap: bool,
+    options: EmitOptions,
+) -> Vec<String> {
+    let mut routines = BTreeSet::new();
+    let mut asm = Vec::new();
+    for (index, (statics_base, commands)) in files.iter().enumerate() {
+        asm.extend(emit_commands(
+            commands.clone(),
+            statics_base,
+            bootstrap && index == 0,
+            options,
+            &mut routines,
+        ));
+    }
+    asm.append(&mut emit_routines(&routines, options));
+    asm
+}
+
 fn emit_bootstrap(
     statics_base: &str,
     current_function: &mut String,
@@ -57,6 +97,8 @@ fn emit_bootstrap(
         statics_base,
         current_function,
         label_number,
+        EmitOptions::default(),
+        &mut BTreeSet::new(),
     ));
     results
 }
@@ -66,6 +108,8 @@ fn emit_command(
     statics_base: &str,
     current_function: &mut String,
     label_number: &mut usize,
+    options: EmitOptions,
+    routines: &mut BTreeSet<Routine>,
 ) -> Vec<String> {
     let mut results = Vec::new();
     results.push(emit_comment(&command));
@@ -75,25 +119,121 @@ fn emit_command(
         Command::Add => emit_binary(BinaryOp::Add),
         Command::Sub => emit_binary(BinaryOp::Sub),
         Command::Neg => emit_unary(UnaryOp::Neg),
-        Command::Eq => emit_compare(CompareOp::Eq, current_function, label_number),
-        Command::Gt => emit_compare(CompareOp::Gt, current_function, label_number),
-        Command::Lt => emit_compare(CompareOp::Lt, current_function, label_number),
+        Command::Eq => emit_comparison(
+            CompareOp::Eq,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
+        Command::Gt => emit_comparison(
+            CompareOp::Gt,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
+        Command::Lt => emit_comparison(
+            CompareOp::Lt,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
         Command::And => emit_binary(BinaryOp::And),
         Command::Or => emit_binary(BinaryOp::Or),
         Command::Not => emit_unary(UnaryOp::Not),
+        Command::Mul => emit_routine_call(
+            "$mul",
+            Routine::Mul,
+            current_function,
+            label_number,
+            routines,
+        ),
+        Command::Div => emit_routine_call(
+            "$div",
+            Routine::DivMod,
+            current_function,
+            label_number,
+            routines,
+        ),
+        Command::Mod => emit_routine_call(
+            "$mod",
+            Routine::DivMod,
+            current_function,
+            label_number,
+            routines,
+        ),
+        Command::Shl => emit_routine_call(
+            "$shl",
+            Routine::Shl,
+            current_function,
+            label_number,
+            routines,
+        ),
+        Command::Shr => emit_routine_call(
+            "$shr",
+            Routine::Shr,
+            current_function,
+            label_number,
+            routines,
+        ),
+        Command::Le => emit_comparison(
+            CompareOp::Le,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
+        Command::Ge => emit_comparison(
+            CompareOp::Ge,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
+        Command::Ne => emit_comparison(
+            CompareOp::Ne,
+            current_function,
+            label_number,
+            options,
+            routines,
+        ),
         Command::Goto(label) => emit_goto(current_function, &label),
         Command::IfGoto(label) => emit_if_goto(current_function, &label),
         Command::Label(label) => emit_label(current_function, &label),
         Command::Function(function, n_locals) => {
             emit_function(&function, n_locals, current_function, label_number)
         }
-        Command::Call(function, n_args) => emit_call(&function, n_args, label_number),
+        Command::Call(function, n_args) => {
+            emit_call(&function, n_args, current_function, label_number)
+        }
         Command::Return => emit_return(),
         Command::Comment(_) => Vec::new(),
     });
     results
 }
 
+fn emit_comparison(
+    op: CompareOp,
+    current_function: &str,
+    label_number: &mut usize,
+    options: EmitOptions,
+    routines: &mut BTreeSet<Routine>,
+) -> Vec<String> {
+    if options.shared_compare {
+        emit_routine_call(
+            compare_entry(op),
+            Routine::Compare(op),
+            current_function,
+            label_number,
+            routines,
+        )
+    } else {
+        emit_compare(op, current_function, label_number, options.short_compare)
+    }
+}
+
 fn emit_comment(command: &Command) -> String {
     let mut string = String::new();
     string.push_str("// ");
@@ -101,6 +241,33 @@ fn emit_comment(command: &Command) -> String {
     string
 }
 
+// assembles emitted code and runs it on the cpu, with the ram starting
+// out as given and copied back at the end. It runs until it reaches a
+// "(x) @x 0;JMP" halt loop, and one is added for code that falls off
+// its end
+#[cfg(test)]
+fn run(asm: &[String], ram: &mut [i16]) {
+    use asm::cpu::Cpu;
+
+    let mut lines = asm.to_vec();
+    lines.extend(["($end)", "@$end", "0;JMP"].map(String::from));
+    let instructions = asm::parser::parse_lines(&lines).unwrap();
+    let symbol_table = asm::analyzer::analyze(instructions.iter());
+    let mut cpu = Cpu::new(&asm::emitter::emit_instructions(
+        instructions.iter(),
+        &symbol_table,
+    ));
+    cpu.ram[..ram.len()].copy_from_slice(ram);
+    for _ in 0..1_000_000 {
+        if cpu.halted() {
+            ram.copy_from_slice(&cpu.ram[..ram.len()]);
+            return;
+        }
+        cpu.step();
+    }
+    panic!("still running after 1000000 instructions");
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -113,7 +280,14 @@ mod test {
     #[test]
     fn test_emit_command() {
         assert_eq!(
-            emit_command(Command::Add, "foo", &mut "bar".to_string(), &mut 123),
+            emit_command(
+                Command::Add,
+                "foo",
+                &mut "bar".to_string(),
+                &mut 123,
+                EmitOptions::default(),
+                &mut BTreeSet::new()
+            ),
             vec!["// add", "@SP", "M=M-1", "A=M", "D=M", "A=A-1", "M=D+M"]
         );
     }
@@ -1,14 +1,25 @@
 use super::names::*;
 use super::push_pop::*;
 
-pub fn emit_compare(op: CompareOp, function: &str, label_number: &mut usize) -> Vec<String> {
-    let mut results = pop_d();
+// the obvious arg1 - arg2 overflows when the signs differ by enough,
+// e.g. 32767 - -1, so unless asked for the short sequence gt/lt/le/ge
+// check the signs first and only subtract when they agree.
+// eq and ne are fine either way since the difference is only 0 when
+// the values are equal
+pub fn emit_compare(
+    op: CompareOp,
+    function: &str,
+    label_number: &mut usize,
+    short_compare: bool,
+) -> Vec<String> {
+    let mut results = if short_compare || matches!(op, CompareOp::Eq | CompareOp::Ne) {
+        emit_difference()
+    } else {
+        emit_safe_difference(function, *label_number)
+    };
     let true_name = make_numbered_label_name(function, "true", *label_number);
     let join_name = make_numbered_label_name(function, "join", *label_number);
 
-    results.push("A=A-1".to_string()); //  sp = sp-1       (sp = &arg1)
-    results.push("D=M-D".to_string()); //  D = *sp - D   (D = arg1 - arg2)
-
     // branch to set D=result of comparison
     results.push(make_ref(&true_name));
 
@@ -31,35 +42,133 @@ pub fn emit_compare(op: CompareOp, function: &str, label_number: &mut usize) ->
     results
 }
 
+// the same comparison as a shared routine, entered with the
+// return address in D
+pub fn emit_compare_routine(op: CompareOp, short_compare: bool) -> Vec<String> {
+    let entry = compare_entry(op);
+    let mut results = Vec::new();
+    results.push(make_label(entry));
+    results.push("@R15".to_string());
+    results.push("M=D".to_string());
+    results.append(&mut emit_compare(op, entry, &mut 0, short_compare));
+    results.push("@R15".to_string());
+    results.push("A=M".to_string());
+    results.push("0;JMP".to_string());
+    results
+}
+
+pub fn compare_entry(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::Eq => "$eq",
+        CompareOp::Gt => "$gt",
+        CompareOp::Lt => "$lt",
+        CompareOp::Le => "$le",
+        CompareOp::Ge => "$ge",
+        CompareOp::Ne => "$ne",
+    }
+}
+
+fn emit_difference() -> Vec<String> {
+    let mut results = pop_d();
+    results.push("A=A-1".to_string()); //  sp = sp-1       (sp = &arg1)
+    results.push("D=M-D".to_string()); //  D = *sp - D   (D = arg1 - arg2)
+    results
+}
+
+// leaves D with the same sign as arg1 - arg2 would have without overflow
+fn emit_safe_difference(function: &str, label_number: usize) -> Vec<String> {
+    let negative_name = make_numbered_label_name(function, "negative", label_number);
+    let same_name = make_numbered_label_name(function, "same", label_number);
+    let test_name = make_numbered_label_name(function, "test", label_number);
+
+    let mut results = pop_d();
+    results.push("@R13".to_string()); //     R13 = arg2
+    results.push("M=D".to_string());
+    results.append(&mut peek_m());
+    results.push("D=M".to_string()); //      D = arg1
+    results.push(make_ref(&negative_name));
+    results.push("D;JLT".to_string());
+
+    // arg1 >= 0
+    results.push("@R13".to_string());
+    results.push("D=M".to_string());
+    results.push(make_ref(&same_name));
+    results.push("D;JGE".to_string());
+    results.push("D=1".to_string()); //      arg2 < 0 so arg1 > arg2
+    results.push(make_ref(&test_name));
+    results.push("0;JMP".to_string());
+
+    // arg1 < 0
+    results.push(make_label(&negative_name));
+    results.push("@R13".to_string());
+    results.push("D=M".to_string());
+    results.push(make_ref(&same_name));
+    results.push("D;JLT".to_string());
+    results.push("D=-1".to_string()); //     arg2 >= 0 so arg1 < arg2
+    results.push(make_ref(&test_name));
+    results.push("0;JMP".to_string());
+
+    // same signs so the subtraction can't overflow
+    results.push(make_label(&same_name));
+    results.push("@R13".to_string());
+    results.push("D=M".to_string());
+    results.append(&mut peek_m());
+    results.push("D=M-D".to_string()); //    D = arg1 - arg2
+    results.push(make_label(&test_name));
+    results
+}
+
 fn compare_op(op: CompareOp) -> String {
     match op {
         CompareOp::Eq => "D;JEQ".to_string(),
         CompareOp::Gt => "D;JGT".to_string(),
         CompareOp::Lt => "D;JLT".to_string(),
+        CompareOp::Le => "D;JLE".to_string(),
+        CompareOp::Ge => "D;JGE".to_string(),
+        CompareOp::Ne => "D;JNE".to_string(),
     }
 }
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum CompareOp {
     Eq,
     Gt,
     Lt,
+    Le,
+    Ge,
+    Ne,
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::run;
     use super::*;
 
+    // runs the comparison with arg1 and arg2 on a stack at 256
+    fn run_compare(op: CompareOp, arg1: i16, arg2: i16, short_compare: bool) -> i16 {
+        let mut ram = vec![0; 512];
+        ram[0] = 258;
+        ram[256] = arg1;
+        ram[257] = arg2;
+        run(&emit_compare(op, "bar", &mut 0, short_compare), &mut ram);
+        assert_eq!(ram[0], 257);
+        ram[256]
+    }
+
     #[test]
     fn test_compare_op() {
         assert_eq!(compare_op(CompareOp::Eq), "D;JEQ");
         assert_eq!(compare_op(CompareOp::Gt), "D;JGT");
         assert_eq!(compare_op(CompareOp::Lt), "D;JLT");
+        assert_eq!(compare_op(CompareOp::Le), "D;JLE");
+        assert_eq!(compare_op(CompareOp::Ge), "D;JGE");
+        assert_eq!(compare_op(CompareOp::Ne), "D;JNE");
     }
 
     #[test]
     fn test_emit_compare() {
         let mut label_number = 42;
         assert_eq!(
-            emit_compare(CompareOp::Gt, "bar", &mut label_number),
+            emit_compare(CompareOp::Gt, "bar", &mut label_number, true),
             vec![
                 "@SP",
                 "M=M-1",
@@ -82,4 +191,116 @@ mod test {
         );
         assert_eq!(label_number, 43);
     }
+
+    #[test]
+    fn test_emit_compare_eq_is_always_short() {
+        let mut label_number = 0;
+        assert_eq!(
+            emit_compare(CompareOp::Eq, "bar", &mut 0, false),
+            emit_compare(CompareOp::Eq, "bar", &mut label_number, true),
+        );
+    }
+
+    #[test]
+    fn test_emit_safe_difference() {
+        assert_eq!(
+            emit_safe_difference("bar", 7),
+            vec![
+                "@SP",
+                "M=M-1",
+                "A=M",
+                "D=M",
+                "@R13",
+                "M=D",
+                "@SP",
+                "A=M-1",
+                "D=M",
+                "@bar$negative.7",
+                "D;JLT",
+                "@R13",
+                "D=M",
+                "@bar$same.7",
+                "D;JGE",
+                "D=1",
+                "@bar$test.7",
+                "0;JMP",
+                "(bar$negative.7)",
+                "@R13",
+                "D=M",
+                "@bar$same.7",
+                "D;JLT",
+                "D=-1",
+                "@bar$test.7",
+                "0;JMP",
+                "(bar$same.7)",
+                "@R13",
+                "D=M",
+                "@SP",
+                "A=M-1",
+                "D=M-D",
+                "(bar$test.7)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_compare_routine() {
+        let routine = emit_compare_routine(CompareOp::Lt, true);
+        assert_eq!(routine[..3], ["($lt)", "@R15", "M=D"]);
+        assert!(routine.contains(&"@$lt$true.0".to_string()));
+        assert_eq!(routine[routine.len() - 3..], ["@R15", "A=M", "0;JMP"]);
+    }
+
+    #[test]
+    fn test_compare_overflow() {
+        let cases = [
+            (32767, -1),
+            (-1, 32767),
+            (-32768, 1),
+            (1, -32768),
+            (-32768, 32767),
+            (32767, -32768),
+            (-32768, -32768),
+            (32767, 32767),
+            (0, -32768),
+            (-32768, 0),
+            (-2, -3),
+            (5, 5),
+            (0, 0),
+        ];
+        let bool_value = |b| if b { -1 } else { 0 };
+        for (arg1, arg2) in cases {
+            assert_eq!(
+                run_compare(CompareOp::Gt, arg1, arg2, false),
+                bool_value(arg1 > arg2)
+            );
+            assert_eq!(
+                run_compare(CompareOp::Lt, arg1, arg2, false),
+                bool_value(arg1 < arg2)
+            );
+            assert_eq!(
+                run_compare(CompareOp::Le, arg1, arg2, false),
+                bool_value(arg1 <= arg2)
+            );
+            assert_eq!(
+                run_compare(CompareOp::Ge, arg1, arg2, false),
+                bool_value(arg1 >= arg2)
+            );
+            assert_eq!(
+                run_compare(CompareOp::Eq, arg1, arg2, false),
+                bool_value(arg1 == arg2)
+            );
+            assert_eq!(
+                run_compare(CompareOp::Ne, arg1, arg2, false),
+                bool_value(arg1 != arg2)
+            );
+        }
+    }
+
+    #[test]
+    fn test_short_compare_overflows() {
+        assert_eq!(run_compare(CompareOp::Gt, 32767, -1, true), 0);
+        assert_eq!(run_compare(CompareOp::Lt, -32768, 1, true), 0);
+        assert_eq!(run_compare(CompareOp::Gt, 3, -1, true), -1);
+    }
 }
@@ -19,8 +19,15 @@ pub fn emit_function(
     results
 }
 
-pub fn emit_call(function: &str, n_args: u16, label_number: &mut usize) -> Vec<String> {
-    let return_name = make_numbered_label_name(function, "ret", *label_number);
+// the return label is numbered within the calling function, the only
+// place the numbering is unique
+pub fn emit_call(
+    function: &str,
+    n_args: u16,
+    current_function: &str,
+    label_number: &mut usize,
+) -> Vec<String> {
+    let return_name = make_numbered_label_name(current_function, "ret", *label_number);
     let mut results = Vec::new();
 
     // args start at SP-n_args
@@ -27,6 +27,7 @@ pub fn make_qualified_label_name(function_name: &str, label: &str) -> String {
 
 pub fn make_numbered_label_name(function_name: &str, label: &str, number: usize) -> String {
     let mut string = make_qualified_label_name(function_name, label);
+    string.push('.');
     string.push_str(&number.to_string());
     string
 }
@@ -24,7 +24,7 @@ pub fn emit_indirect_push(segment: &str, index: u16) -> Vec<String> {
     results.push(segment.to_string());
     results.push("A=M".to_string());
     if index != 0 {
-        results.push("A=A+D".to_string());
+        results.push("A=D+A".to_string());
     }
     results.push("D=M".to_string());
     results.append(&mut push_d());
@@ -41,7 +41,7 @@ pub fn emit_direct_push(reference: &str, index: u16) -> Vec<String> {
     }
     results.push(reference.to_string());
     if index != 0 {
-        results.push("A=A+D".to_string());
+        results.push("A=D+A".to_string());
     }
     results.push("D=M".to_string());
     results.append(&mut push_d());
@@ -157,7 +157,7 @@ mod test {
     fn test_emit_push() {
         assert_eq!(
             emit_push(Segment::Argument, "foo", 2),
-            vec!["@2", "D=A", "@ARG", "A=M", "A=A+D", "D=M", "@SP", "M=M+1", "A=M-1", "M=D"]
+            vec!["@2", "D=A", "@ARG", "A=M", "A=D+A", "D=M", "@SP", "M=M+1", "A=M-1", "M=D"]
         );
         assert_eq!(
             emit_push(Segment::Constant, "foo", 2),
@@ -0,0 +1,496 @@
+// shared subroutines for the extended commands that are too big
+// to expand inline, and for comparisons when code size matters more
+// than speed. A call site puts its return address in D and jumps,
+// the routine saves it in R15, works on the stack in place and
+// jumps back. Only routines that were actually used get emitted,
+// after all the translated code.
+use std::collections::BTreeSet;
+
+use super::compare::*;
+use super::names::*;
+use super::EmitOptions;
+
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Routine {
+    Mul,
+    DivMod,
+    Shl,
+    Shr,
+    Compare(CompareOp),
+}
+
+impl Routine {
+    // roughly the most instructions one call can execute, including
+    // the call site. The loops were measured at their longest, e.g. mul
+    // by -1 or div of 32767 by 1. Comparisons don't loop so every
+    // instruction of the routine is counted once
+    pub fn worst_case_cycles(self, options: EmitOptions) -> usize {
+        match self {
+            Routine::Mul => 375,
+            Routine::DivMod => 675,
+            Routine::Shl => 205,
+            Routine::Shr => 290,
+            Routine::Compare(op) => {
+                4 + emit_compare_routine(op, options.short_compare)
+                    .iter()
+                    .filter(|line| !line.starts_with('('))
+                    .count()
+            }
+        }
+    }
+}
+
+pub fn emit_routine_call(
+    entry: &str,
+    routine: Routine,
+    function: &str,
+    label_number: &mut usize,
+    routines: &mut BTreeSet<Routine>,
+) -> Vec<String> {
+    let return_name = make_numbered_label_name(function, &entry[1..], *label_number);
+    *label_number += 1;
+    routines.insert(routine);
+
+    let mut results = Vec::new();
+    results.push(make_ref(&return_name));
+    results.push("D=A".to_string());
+    results.push(make_ref(entry));
+    results.push("0;JMP".to_string());
+    results.push(make_label(&return_name));
+    results
+}
+
+pub fn emit_routines(routines: &BTreeSet<Routine>, options: EmitOptions) -> Vec<String> {
+    let mut results = Vec::new();
+    if routines.is_empty() {
+        return results;
+    }
+
+    // don't let a program without a bootstrap run off the end into the routines
+    results.push("// runtime".to_string());
+    results.append(&mut lines(&["($halt)", "@$halt", "0;JMP"]));
+    for routine in routines {
+        results.append(&mut match routine {
+            Routine::Mul => emit_mul(),
+            Routine::DivMod => emit_div_mod(),
+            Routine::Shl => emit_shl(),
+            Routine::Shr => emit_shr(),
+            Routine::Compare(op) => {
+                let mut results = vec![format!("// {}", &compare_entry(*op)[1..])];
+                results.append(&mut emit_compare_routine(*op, options.short_compare));
+                results
+            }
+        });
+    }
+    results
+}
+
+fn lines(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+// shift and add, one bit of y per iteration. Wraps on overflow.
+// R13 = x (doubled each time), R14 = y, the free slot just above
+// the stack holds the current bit, and the result accumulates in place
+fn emit_mul() -> Vec<String> {
+    lines(&[
+        "// mul",
+        "($mul)",
+        "@R15",
+        "M=D",
+        "@SP",
+        "AM=M-1",
+        "D=M",
+        "@R14",
+        "M=D",
+        "@SP",
+        "A=M-1",
+        "D=M",
+        "@R13",
+        "M=D",
+        "@SP",
+        "A=M-1",
+        "M=0",
+        "@SP",
+        "A=M",
+        "M=1",
+        "($mul.loop)",
+        "@SP",
+        "A=M",
+        "D=M",
+        "@R14",
+        "D=D&M",
+        "@$mul.skip",
+        "D;JEQ",
+        "@R13",
+        "D=M",
+        "@SP",
+        "A=M-1",
+        "M=D+M",
+        "($mul.skip)",
+        "@R13",
+        "D=M",
+        "M=D+M",
+        "@SP",
+        "A=M",
+        "D=M",
+        "M=D+M",
+        "D=M",
+        "@$mul.loop",
+        "D;JNE",
+        "@R15",
+        "A=M",
+        "0;JMP",
+    ])
+}
+
+// restoring division on the magnitudes, one bit of x per iteration.
+// Everything only ever shifts left. The remainder can get past 32767
+// when |y| is large so its comparison with |y| is unsigned.
+// The quotient truncates toward zero and the remainder takes the sign
+// of x, like Math.divide. Division by zero gives -1 or x rather than an error
+fn emit_div_mod() -> Vec<String> {
+    lines(&[
+        "// div and mod",
+        "($mod)",
+        "@R15",
+        "M=D",
+        "@$div.mod",
+        "M=-1",
+        "@$div.start",
+        "0;JMP",
+        "($div)",
+        "@R15",
+        "M=D",
+        "@$div.mod",
+        "M=0",
+        "($div.start)",
+        // R14 = |y|, R13 = |x|, $div.sign has the sign of x^y
+        "@SP",
+        "AM=M-1",
+        "D=M",
+        "@R14",
+        "M=D",
+        "@SP",
+        "A=M-1",
+        "D=M",
+        "@R13",
+        "M=D",
+        "@$div.x",
+        "M=D",
+        "@$div.sign",
+        "M=D",
+        "@R14",
+        "D=M",
+        "@$div.abs_y",
+        "D;JGE",
+        "@R14",
+        "M=-M",
+        "@$div.sign",
+        "M=!M",
+        "($div.abs_y)",
+        "@R13",
+        "D=M",
+        "@$div.abs_x",
+        "D;JGE",
+        "@R13",
+        "M=-M",
+        "($div.abs_x)",
+        "@$div.q",
+        "M=0",
+        "@$div.r",
+        "M=0",
+        "@16",
+        "D=A",
+        "@$div.n",
+        "M=D",
+        "($div.loop)",
+        // r = 2r + top bit of x, x = 2x, q = 2q
+        "@$div.r",
+        "D=M",
+        "M=D+M",
+        "@R13",
+        "D=M",
+        "M=D+M",
+        "@$div.shifted",
+        "D;JGE",
+        "@$div.r",
+        "M=M+1",
+        "($div.shifted)",
+        "@$div.q",
+        "D=M",
+        "M=D+M",
+        // if r >= |y| (unsigned) then r = r - |y| and q = q + 1
+        "@$div.r",
+        "D=M",
+        "@$div.r_high",
+        "D;JLT",
+        "@R14",
+        "D=M",
+        "@$div.next",
+        "D;JLT",
+        "@$div.compare",
+        "0;JMP",
+        "($div.r_high)",
+        "@R14",
+        "D=M",
+        "@$div.subtract",
+        "D;JGE",
+        "($div.compare)",
+        "@$div.r",
+        "D=M",
+        "@R14",
+        "D=D-M",
+        "@$div.next",
+        "D;JLT",
+        "($div.subtract)",
+        "@R14",
+        "D=M",
+        "@$div.r",
+        "M=M-D",
+        "@$div.q",
+        "M=M+1",
+        "($div.next)",
+        "@$div.n",
+        "MD=M-1",
+        "@$div.loop",
+        "D;JGT",
+        // pick the result and the value whose sign it takes
+        "@$div.mod",
+        "D=M",
+        "@$div.remainder",
+        "D;JNE",
+        "@$div.q",
+        "D=M",
+        "@R13",
+        "M=D",
+        "@$div.sign",
+        "D=M",
+        "@$div.apply_sign",
+        "0;JMP",
+        "($div.remainder)",
+        "@$div.r",
+        "D=M",
+        "@R13",
+        "M=D",
+        "@$div.x",
+        "D=M",
+        "($div.apply_sign)",
+        "@$div.positive",
+        "D;JGE",
+        "@R13",
+        "M=-M",
+        "($div.positive)",
+        "@R13",
+        "D=M",
+        "@SP",
+        "A=M-1",
+        "M=D",
+        "@R15",
+        "A=M",
+        "0;JMP",
+    ])
+}
+
+// x << n by repeated doubling, stopping early once x is 0.
+// Counts of 0 or less leave x alone
+fn emit_shl() -> Vec<String> {
+    lines(&[
+        "// shl",
+        "($shl)",
+        "@R15",
+        "M=D",
+        "@SP",
+        "AM=M-1",
+        "D=M",
+        "@R14",
+        "M=D",
+        "($shl.loop)",
+        "@R14",
+        "D=M",
+        "@$shl.done",
+        "D;JLE",
+        "@R14",
+        "M=D-1",
+        "@SP",
+        "A=M-1",
+        "D=M",
+        "MD=D+M",
+        "@$shl.loop",
+        "D;JNE",
+        "($shl.done)",
+        "@R15",
+        "A=M",
+        "0;JMP",
+    ])
+}
+
+// arithmetic x >> n. There's no right shift in the hardware so bits are
+// copied one at a time from bit n (R14) of x to bit 0 ($shr.dst) of the
+// result, then the sign is filled in above them.
+// Counts of 0 or less leave x alone
+fn emit_shr() -> Vec<String> {
+    lines(&[
+        "// shr",
+        "($shr)",
+        "@R15",
+        "M=D",
+        "@SP",
+        "AM=M-1",
+        "D=M",
+        "@$shr.n",
+        "M=D",
+        "@SP",
+        "A=M-1",
+        "D=M",
+        "@R13",
+        "M=D",
+        "@SP",
+        "A=M-1",
+        "M=0",
+        "@R14",
+        "M=1",
+        "@$shr.dst",
+        "M=1",
+        "($shr.scale)",
+        "@$shr.n",
+        "D=M",
+        "@$shr.loop",
+        "D;JLE",
+        "@$shr.n",
+        "M=D-1",
+        "@R14",
+        "D=M",
+        "MD=D+M",
+        "@$shr.scale",
+        "D;JNE",
+        "($shr.loop)",
+        "@R14",
+        "D=M",
+        "@$shr.fill",
+        "D;JEQ",
+        "@R13",
+        "D=D&M",
+        "@$shr.next",
+        "D;JEQ",
+        "@$shr.dst",
+        "D=M",
+        "@SP",
+        "A=M-1",
+        "M=D|M",
+        "($shr.next)",
+        "@R14",
+        "D=M",
+        "M=D+M",
+        "@$shr.dst",
+        "D=M",
+        "M=D+M",
+        "@$shr.loop",
+        "0;JMP",
+        "($shr.fill)",
+        "@R13",
+        "D=M",
+        "@$shr.done",
+        "D;JGE",
+        "@$shr.dst",
+        "D=-M",
+        "@SP",
+        "A=M-1",
+        "M=D|M",
+        "($shr.done)",
+        "@R15",
+        "A=M",
+        "0;JMP",
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::run;
+    use super::*;
+
+    #[test]
+    fn test_emit_routine_call() {
+        let mut label_number = 3;
+        let mut routines = BTreeSet::new();
+        assert_eq!(
+            emit_routine_call(
+                "$mul",
+                Routine::Mul,
+                "Foo.bar",
+                &mut label_number,
+                &mut routines
+            ),
+            vec!["@Foo.bar$mul.3", "D=A", "@$mul", "0;JMP", "(Foo.bar$mul.3)"]
+        );
+        assert_eq!(label_number, 4);
+        assert_eq!(routines, BTreeSet::from([Routine::Mul]));
+    }
+
+    #[test]
+    fn test_emit_routines() {
+        assert!(emit_routines(&BTreeSet::new(), EmitOptions::default()).is_empty());
+        let asm = emit_routines(
+            &BTreeSet::from([
+                Routine::Shl,
+                Routine::DivMod,
+                Routine::Compare(CompareOp::Gt),
+            ]),
+            EmitOptions::default(),
+        );
+        assert_eq!(asm[1..4], ["($halt)", "@$halt", "0;JMP"]);
+        assert!(asm.contains(&"($div)".to_string()));
+        assert!(asm.contains(&"($mod)".to_string()));
+        assert!(asm.contains(&"($shl)".to_string()));
+        assert!(asm.contains(&"($gt)".to_string()));
+        assert!(!asm.contains(&"($mul)".to_string()));
+    }
+
+    // calls a routine on args pushed on a stack at 256, returning what
+    // it leaves there
+    fn run_routine(entry: &str, routine: Routine, args: &[i16]) -> i16 {
+        let mut routines = BTreeSet::new();
+        let mut asm = emit_routine_call(entry, routine, "Foo.bar", &mut 0, &mut routines);
+        // the call returns into the halt loop in front of the routines
+        asm.append(&mut emit_routines(&routines, EmitOptions::default()));
+        let mut ram = vec![0; 512];
+        ram[0] = 256 + args.len() as i16;
+        ram[256..256 + args.len()].copy_from_slice(args);
+        run(&asm, &mut ram);
+        assert_eq!(ram[0], 257);
+        ram[256]
+    }
+
+    #[test]
+    fn test_run_routines() {
+        assert_eq!(run_routine("$mul", Routine::Mul, &[7, -6]), -42);
+        assert_eq!(run_routine("$mul", Routine::Mul, &[300, 300]), 24464);
+        assert_eq!(run_routine("$div", Routine::DivMod, &[-7, 2]), -3);
+        assert_eq!(run_routine("$mod", Routine::DivMod, &[-7, 2]), -1);
+        assert_eq!(run_routine("$div", Routine::DivMod, &[-32768, 1]), -32768);
+        assert_eq!(run_routine("$shl", Routine::Shl, &[1, 15]), -32768);
+        assert_eq!(run_routine("$shr", Routine::Shr, &[-16, 2]), -4);
+        assert_eq!(run_routine("$shr", Routine::Shr, &[5, 0]), 5);
+        let gt = Routine::Compare(CompareOp::Gt);
+        assert_eq!(
+            run_routine(compare_entry(CompareOp::Gt), gt, &[32767, -1]),
+            -1
+        );
+        assert_eq!(
+            run_routine(compare_entry(CompareOp::Gt), gt, &[-32768, 1]),
+            0
+        );
+    }
+
+    #[test]
+    fn test_worst_case_cycles() {
+        let options = EmitOptions::default();
+        let gt = Routine::Compare(CompareOp::Gt).worst_case_cycles(options);
+        let short_gt = Routine::Compare(CompareOp::Gt).worst_case_cycles(EmitOptions {
+            short_compare: true,
+            ..options
+        });
+        assert!(short_gt < gt);
+        assert!(gt < Routine::Shl.worst_case_cycles(options));
+    }
+}
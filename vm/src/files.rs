@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+// the files a tool works on, given one file or a directory of them, and
+// the name without an extension that output for all of them goes to:
+// X for X.suffix, and Dir/Dir for a directory. Files in a directory are
+// sorted so every run sees them in the same order
+pub fn input_files(input_path: &Path, suffix: &str) -> (Vec<PathBuf>, PathBuf) {
+    if input_path.is_file() {
+        let name = input_path.to_string_lossy();
+        let base = PathBuf::from(name.strip_suffix(suffix).unwrap_or(&name));
+        (vec![PathBuf::from(input_path)], base)
+    } else if input_path.is_dir() {
+        let mut files: Vec<_> = input_path
+            .read_dir()
+            .unwrap()
+            .filter_map(|p| {
+                let file = p.unwrap().path();
+                if file.to_string_lossy().ends_with(suffix) {
+                    Some(file)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        files.sort();
+
+        // "." has no name of its own, the directory it stands for does
+        let base_name = input_path
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.file_name().map(|name| name.to_owned()))
+            .unwrap_or_else(|| input_path.as_os_str().to_owned());
+        (files, input_path.join(base_name))
+    } else {
+        panic!("Unable to find {}.", input_path.to_string_lossy());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_input_files() {
+        let directory = std::env::temp_dir().join("vm_test_input_files");
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in ["B.vm", "A.vm", "A.asm"] {
+            std::fs::write(directory.join(name), "").unwrap();
+        }
+
+        let (files, base) = input_files(&directory, ".vm");
+        assert_eq!(files, vec![directory.join("A.vm"), directory.join("B.vm")]);
+        assert_eq!(base, directory.join("vm_test_input_files"));
+
+        let (files, base) = input_files(&directory.join("B.vm"), ".vm");
+        assert_eq!(files, vec![directory.join("B.vm")]);
+        assert_eq!(base, directory.join("B"));
+    }
+}
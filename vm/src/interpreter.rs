@@ -0,0 +1,482 @@
+// runs vm commands directly, for debugging programs without translating
+// and assembling them. Memory is laid out the way the translator lays it
+// out: SP, LCL, ARG, THIS and THAT in 0 to 4, temp from 5, statics from
+// 16, the stack from 256 and the heap, screen and keyboard above that.
+// Calls build the same frames too, except that a return address is the
+// index of the command to go back to
+use std::collections::HashMap;
+
+use crate::ast::*;
+
+pub const RAM_SIZE: usize = 32768;
+const STACK_BASE: i16 = 256;
+const STATICS_BASE: usize = 16;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RunError {
+    UnknownFunction(String),
+    UnknownLabel(String),
+    // a command outside any function, or a function without a return
+    NoFunction,
+    PopConstant,
+    BadAddress(i32),
+    DivideByZero,
+}
+
+// a function that is being run, found by following the saved LCL and ARG
+pub struct Frame {
+    pub function: String,
+    pub pc: usize,
+    pub lcl: usize,
+    pub arg: usize,
+}
+
+pub struct Machine {
+    pub ram: Vec<i16>,
+    pub pc: usize,
+    // how many calls deep the command at pc is
+    pub depth: usize,
+    pub halted: bool,
+    commands: Vec<Command>,
+    // the file each command came from and its index there
+    origins: Vec<(usize, usize)>,
+    statics_bases: Vec<usize>,
+    functions: HashMap<String, usize>,
+    // the function each command is in, if it's in one
+    function_of: Vec<Option<usize>>,
+    labels: HashMap<(usize, String), usize>,
+}
+
+impl Machine {
+    pub fn new(files: &[(String, Vec<Command>)]) -> Self {
+        let mut commands = Vec::new();
+        let mut origins = Vec::new();
+        let mut statics_bases = Vec::new();
+        let mut functions = HashMap::new();
+        let mut function_of = Vec::new();
+        let mut labels = HashMap::new();
+
+        let mut statics = STATICS_BASE;
+        let mut function = None;
+        for (file, (_, file_commands)) in files.iter().enumerate() {
+            statics_bases.push(statics);
+            for (index, command) in file_commands.iter().enumerate() {
+                let pc = commands.len();
+                match command {
+                    Command::Function(name, _) => {
+                        functions.insert(name.clone(), pc);
+                        function = Some(pc);
+                    }
+                    Command::Label(label) => {
+                        if let Some(function) = function {
+                            labels.insert((function, label.clone()), pc);
+                        }
+                    }
+                    Command::Push(Segment::Static, i) | Command::Pop(Segment::Static, i) => {
+                        statics = statics.max(statics_bases[file] + *i as usize + 1);
+                    }
+                    _ => (),
+                }
+                commands.push(command.clone());
+                origins.push((file, index));
+                function_of.push(function);
+            }
+            function = None;
+        }
+
+        Self {
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            depth: 0,
+            halted: false,
+            commands,
+            origins,
+            statics_bases,
+            functions,
+            function_of,
+            labels,
+        }
+    }
+
+    // sets up the stack and calls Sys.init. Returning from it halts
+    pub fn bootstrap(&mut self) -> Result<(), RunError> {
+        self.ram[0] = STACK_BASE;
+        self.pc = self.commands.len();
+        self.call("Sys.init", 0)
+    }
+
+    pub fn command(&self, pc: usize) -> Option<&Command> {
+        self.commands.get(pc)
+    }
+
+    // the file a command came from and its index in that file
+    pub fn origin(&self, pc: usize) -> Option<(usize, usize)> {
+        self.origins.get(pc).copied()
+    }
+
+    // where a function's code starts
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).copied()
+    }
+
+    // the name of the function a command is in
+    pub fn function_name(&self, pc: usize) -> Option<&str> {
+        let start = (*self.function_of.get(pc)?)?;
+        match &self.commands[start] {
+            Command::Function(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    // the command index of every command that came from a file
+    pub fn commands_of(&self, file: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.origins
+            .iter()
+            .enumerate()
+            .filter(move |(_, (from, _))| *from == file)
+            .map(|(pc, (_, index))| (pc, *index))
+    }
+
+    // where static i of a file is
+    pub fn static_address(&self, file: usize, index: u16) -> usize {
+        self.statics_bases[file] + index as usize
+    }
+
+    // the innermost call first
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let (mut pc, mut lcl, mut arg) = (self.pc, self.ram[1] as usize, self.ram[2] as usize);
+        for _ in 0..self.depth {
+            let Some(function) = self.function_name(pc) else {
+                break;
+            };
+            frames.push(Frame {
+                function: function.to_string(),
+                pc,
+                lcl,
+                arg,
+            });
+            if !(5..RAM_SIZE).contains(&lcl) {
+                break;
+            }
+            pc = self.ram[lcl - 5] as u16 as usize;
+            if pc >= self.commands.len() {
+                break;
+            }
+            arg = self.ram[lcl - 3] as u16 as usize;
+            lcl = self.ram[lcl - 4] as u16 as usize;
+            // the return goes just past the call, which is in the caller
+            pc = pc.saturating_sub(1);
+        }
+        frames
+    }
+
+    pub fn step(&mut self) -> Result<(), RunError> {
+        if self.halted {
+            return Ok(());
+        }
+        let command = self.commands.get(self.pc).cloned();
+        let Some(command) = command else {
+            self.halted = true;
+            return Ok(());
+        };
+        self.pc += 1;
+        match command {
+            Command::Push(segment, index) => {
+                let value = match segment {
+                    Segment::Constant => index as i16,
+                    _ => {
+                        let address = self.address(&segment, index)?;
+                        self.ram[address]
+                    }
+                };
+                self.push(value)?;
+            }
+            Command::Pop(segment, index) => {
+                if segment == Segment::Constant {
+                    return Err(RunError::PopConstant);
+                }
+                let address = self.address(&segment, index)?;
+                self.ram[address] = self.pop()?;
+            }
+            Command::Add => self.binary(|a, b| Ok(a.wrapping_add(b)))?,
+            Command::Sub => self.binary(|a, b| Ok(a.wrapping_sub(b)))?,
+            Command::And => self.binary(|a, b| Ok(a & b))?,
+            Command::Or => self.binary(|a, b| Ok(a | b))?,
+            Command::Eq => self.binary(|a, b| Ok(truth(a == b)))?,
+            Command::Gt => self.binary(|a, b| Ok(truth(a > b)))?,
+            Command::Lt => self.binary(|a, b| Ok(truth(a < b)))?,
+            Command::Le => self.binary(|a, b| Ok(truth(a <= b)))?,
+            Command::Ge => self.binary(|a, b| Ok(truth(a >= b)))?,
+            Command::Ne => self.binary(|a, b| Ok(truth(a != b)))?,
+            Command::Mul => self.binary(|a, b| Ok(a.wrapping_mul(b)))?,
+            Command::Div => self.binary(|a, b| a.checked_div(b).ok_or(RunError::DivideByZero))?,
+            Command::Mod => self.binary(|a, b| a.checked_rem(b).ok_or(RunError::DivideByZero))?,
+            Command::Shl => self.binary(|a, b| Ok(a.wrapping_shl(b as u32)))?,
+            Command::Shr => self.binary(|a, b| Ok(a.wrapping_shr(b as u32)))?,
+            Command::Neg => {
+                let value = self.pop()?;
+                self.push(value.wrapping_neg())?;
+            }
+            Command::Not => {
+                let value = self.pop()?;
+                self.push(!value)?;
+            }
+            Command::Label(_) | Command::Comment(_) => (),
+            Command::Goto(label) => self.pc = self.label(&label)?,
+            Command::IfGoto(label) => {
+                if self.pop()? != 0 {
+                    self.pc = self.label(&label)?;
+                }
+            }
+            Command::Function(_, locals) => {
+                for _ in 0..locals {
+                    self.push(0)?;
+                }
+            }
+            Command::Call(name, args) => self.call(&name, args)?,
+            Command::Return => self.ret()?,
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, args: u16) -> Result<(), RunError> {
+        // the os ends a program by spinning in Sys.halt
+        if name == "Sys.halt" {
+            self.pc -= 1;
+            self.halted = true;
+            return Ok(());
+        }
+        let target = self
+            .function(name)
+            .ok_or_else(|| RunError::UnknownFunction(name.to_string()))?;
+        let sp = self.ram[0];
+        self.push(self.pc as i16)?;
+        for register in 1..=4 {
+            self.push(self.ram[register])?;
+        }
+        self.ram[2] = sp - args as i16;
+        self.ram[1] = self.ram[0];
+        self.pc = target;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<(), RunError> {
+        let frame = self.ram[1] as usize;
+        if frame < 5 {
+            return Err(RunError::BadAddress(frame as i32 - 5));
+        }
+        let return_address = self.ram[frame - 5] as u16 as usize;
+        let value = self.pop()?;
+        let arg = self.checked(self.ram[2] as i32)?;
+        self.ram[arg] = value;
+        self.ram[0] = self.ram[2] + 1;
+        for register in 1..=4 {
+            self.ram[register] = self.ram[frame - 5 + register];
+        }
+        self.pc = return_address;
+        self.depth = self.depth.saturating_sub(1);
+        if self.pc >= self.commands.len() {
+            self.halted = true;
+        }
+        Ok(())
+    }
+
+    fn label(&self, label: &str) -> Result<usize, RunError> {
+        let function = self.function_of[self.pc - 1].ok_or(RunError::NoFunction)?;
+        self.labels
+            .get(&(function, label.to_string()))
+            .copied()
+            .ok_or_else(|| RunError::UnknownLabel(label.to_string()))
+    }
+
+    fn address(&self, segment: &Segment, index: u16) -> Result<usize, RunError> {
+        let index = index as i32;
+        let address = match segment {
+            Segment::Local => self.ram[1] as i32 + index,
+            Segment::Argument => self.ram[2] as i32 + index,
+            Segment::This => self.ram[3] as i32 + index,
+            Segment::That => self.ram[4] as i32 + index,
+            Segment::Pointer => 3 + index,
+            Segment::Temp => 5 + index,
+            Segment::Static => {
+                let (file, _) = self.origins[self.pc - 1];
+                (self.statics_bases[file] as i32) + index
+            }
+            Segment::Constant => unreachable!(),
+        };
+        self.checked(address)
+    }
+
+    fn checked(&self, address: i32) -> Result<usize, RunError> {
+        if (0..RAM_SIZE as i32).contains(&address) {
+            Ok(address as usize)
+        } else {
+            Err(RunError::BadAddress(address))
+        }
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), RunError> {
+        let sp = self.checked(self.ram[0] as i32)?;
+        self.ram[sp] = value;
+        self.ram[0] += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16, RunError> {
+        let sp = self.checked(self.ram[0] as i32 - 1)?;
+        self.ram[0] -= 1;
+        Ok(self.ram[sp])
+    }
+
+    fn binary(&mut self, op: impl Fn(i16, i16) -> Result<i16, RunError>) -> Result<(), RunError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(op(a, b)?)
+    }
+}
+
+fn truth(b: bool) -> i16 {
+    if b {
+        -1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_lines;
+
+    fn machine(files: &[(&str, &str)]) -> Machine {
+        let files = files
+            .iter()
+            .map(|(name, vm)| (name.to_string(), parse_lines(vm.lines(), true).unwrap()))
+            .collect::<Vec<_>>();
+        let mut machine = Machine::new(&files);
+        machine.bootstrap().unwrap();
+        machine
+    }
+
+    fn run(machine: &mut Machine) {
+        while !machine.halted {
+            machine.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let mut machine = machine(&[(
+            "Sys",
+            "function Sys.init 0
+             push constant 3
+             push constant 4
+             call Sys.add 2
+             pop static 0
+             push constant 0
+             return
+             function Sys.add 1
+             push argument 0
+             push argument 1
+             add
+             pop local 0
+             push local 0
+             return",
+        )]);
+        run(&mut machine);
+        assert_eq!(machine.ram[STATICS_BASE], 7);
+        assert_eq!(machine.depth, 0);
+    }
+
+    #[test]
+    fn test_statics_are_per_file() {
+        let mut machine = machine(&[
+            (
+                "Sys",
+                "function Sys.init 0
+                 push constant 1
+                 pop static 0
+                 call Main.main 0
+                 return",
+            ),
+            (
+                "Main",
+                "function Main.main 0
+                 push constant 2
+                 pop static 0
+                 push constant 0
+                 return",
+            ),
+        ]);
+        run(&mut machine);
+        assert_eq!(machine.ram[STATICS_BASE], 1);
+        assert_eq!(machine.ram[STATICS_BASE + 1], 2);
+    }
+
+    #[test]
+    fn test_loop_and_halt() {
+        let mut machine = machine(&[(
+            "Sys",
+            "function Sys.init 1
+             label LOOP
+             push local 0
+             push constant 1
+             add
+             pop local 0
+             push local 0
+             push constant 5
+             lt
+             if-goto LOOP
+             push local 0
+             pop static 0
+             call Sys.halt 0
+             function Sys.halt 0
+             label SPIN
+             goto SPIN",
+        )]);
+        run(&mut machine);
+        assert_eq!(machine.ram[STATICS_BASE], 5);
+        assert_eq!(machine.function_name(machine.pc), Some("Sys.init"));
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut machine = machine(&[(
+            "Sys",
+            "function Sys.init 0
+             push constant 9
+             call Sys.inner 1
+             return
+             function Sys.inner 2
+             push constant 0
+             return",
+        )]);
+        for _ in 0..4 {
+            machine.step().unwrap();
+        }
+        let frames = machine.frames();
+        let names = frames
+            .iter()
+            .map(|frame| frame.function.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Sys.inner", "Sys.init"]);
+        assert_eq!(machine.ram[frames[0].arg], 9);
+        assert_eq!(frames[1].pc, 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut machine = machine(&[(
+            "Sys",
+            "function Sys.init 0
+             call Sys.missing 0",
+        )]);
+        machine.step().unwrap();
+        assert_eq!(
+            machine.step(),
+            Err(RunError::UnknownFunction("Sys.missing".to_string()))
+        );
+    }
+}
@@ -0,0 +1,11 @@
+// the vm translator, shared by the vm binary and the compiler's jackc driver
+pub mod ast;
+pub mod emitter;
+pub mod files;
+pub mod interpreter;
+pub mod linker;
+pub mod optimizer;
+pub mod os;
+pub mod parser;
+pub mod printer;
+pub mod stats;
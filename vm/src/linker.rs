@@ -0,0 +1,109 @@
+// drops the functions a program can never call. A program is linked
+// with a whole os of which it uses only part, and all of it together
+// doesn't always fit in the rom. What can run is whatever is reachable
+// through calls from the entry point, with each file in its original
+// order. A program without the entry point is left as it is
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+
+pub fn remove_unreachable(
+    files: Vec<(String, Vec<Command>)>,
+    entry: &str,
+) -> Vec<(String, Vec<Command>)> {
+    let mut calls = HashMap::new();
+    for (_, commands) in &files {
+        let mut current = None;
+        for command in commands {
+            match command {
+                Command::Function(name, _) => {
+                    current = Some(name.as_str());
+                    calls.entry(name.as_str()).or_insert_with(Vec::new);
+                }
+                Command::Call(name, _) => {
+                    if let Some(function) = current {
+                        calls.get_mut(function).unwrap().push(name.as_str());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // without the entry point there's nothing to go on
+    if !calls.contains_key(entry) {
+        return files;
+    }
+
+    let mut reachable = HashSet::from([entry.to_string()]);
+    let mut pending = vec![entry];
+    while let Some(function) = pending.pop() {
+        for &called in calls.get(function).into_iter().flatten() {
+            if reachable.insert(called.to_string()) {
+                pending.push(called);
+            }
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(statics_base, commands)| {
+            let mut keep = true;
+            let commands = commands
+                .into_iter()
+                .filter(|command| {
+                    if let Command::Function(name, _) = command {
+                        keep = reachable.contains(name);
+                    }
+                    keep
+                })
+                .collect();
+            (statics_base, commands)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remove_unreachable() {
+        let files = vec![
+            (
+                "Sys".to_string(),
+                vec![
+                    Command::Function("Sys.init".to_string(), 0),
+                    Command::Call("Main.main".to_string(), 0),
+                    Command::Return,
+                    Command::Function("Sys.halt".to_string(), 0),
+                    Command::Return,
+                ],
+            ),
+            (
+                "Main".to_string(),
+                vec![
+                    Command::Function("Main.main".to_string(), 0),
+                    Command::Call("Main.helper".to_string(), 0),
+                    Command::Return,
+                    Command::Function("Main.helper".to_string(), 0),
+                    Command::Call("Main.main".to_string(), 0),
+                    Command::Return,
+                ],
+            ),
+        ];
+        let linked = remove_unreachable(files, "Sys.init");
+        assert_eq!(
+            linked[0].1,
+            vec![
+                Command::Function("Sys.init".to_string(), 0),
+                Command::Call("Main.main".to_string(), 0),
+                Command::Return,
+            ]
+        );
+        assert_eq!(linked[1].1.len(), 6);
+
+        let linked = remove_unreachable(linked, "Main.init");
+        assert_eq!(linked[0].1.len(), 3);
+    }
+}
@@ -1,93 +1,269 @@
-mod ast;
-mod emitter;
-mod parser;
-mod printer;
-
 use std::{
+    collections::HashSet,
     env::args,
-    fs::File,
+    fs::{create_dir_all, File},
     io::{prelude::*, BufRead, BufReader, BufWriter},
     path::{Path, PathBuf},
 };
 
+use vm::{
+    ast::Command, emitter, emitter::EmitOptions, files, linker, optimizer, os, parser, printer,
+    stats,
+};
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Output {
+    Asm,
+    Vm,
+}
+
+// where the os classes a program calls come from
+#[derive(Clone)]
+enum Os {
+    BuiltIn,
+    Directory(PathBuf),
+    Off,
+}
+
+#[derive(Clone)]
+struct Options {
+    output: Output,
+    os: Os,
+    optimize: bool,
+    extended: bool,
+    short_compare: bool,
+    shared_compare: bool,
+    stats: bool,
+}
+
 fn main() -> std::io::Result<()> {
     if args().len() < 2 {
         println!("missing file name")
     } else {
         let input_name = args().nth(1).unwrap();
+        let options = parse_options(args().skip(2));
         let input_path = Path::new(&input_name);
-        let (input_files, output_path) = create_output_path(input_path);
-        println!("Creating {}", output_path.to_string_lossy());
-        let output_file = File::create(&output_path)?;
+        let (input_files, output_path) = files::input_files(input_path, ".vm");
+        let output_path = output_path.with_extension("asm");
 
-        let mut boostrap = input_files.len() > 1;
+        match options.output {
+            Output::Asm => {
+                let files = link_os(read_files(&input_files, &options)?, &options)?;
+                let bootstrap = files.len() > 1;
+                let emit_options = emit_options(&options);
+                let asm = emitter::emit_program(&files, bootstrap, emit_options);
 
-        for input_file in input_files {
-            translate_file(input_file.as_path(), &output_file, boostrap)?;
-            boostrap = false;
+                if options.stats {
+                    stats::print_report(&collect_stats(&files, bootstrap, emit_options));
+                }
+                if options.shared_compare {
+                    let inline_options = EmitOptions {
+                        shared_compare: false,
+                        ..emit_options
+                    };
+                    let inline = collect_stats(&files, bootstrap, inline_options);
+                    let shared = collect_stats(&files, bootstrap, emit_options);
+                    stats::print_comparison("inline", &inline, "shared", &shared);
+                }
+
+                // a program that doesn't fit can't be loaded, so don't write
+                // one
+                let size = stats::count_instructions(&asm);
+                if size > stats::ROM_SIZE {
+                    eprintln!(
+                        "Program is {} instructions but the ROM only holds {}",
+                        size,
+                        stats::ROM_SIZE
+                    );
+                    std::process::exit(1);
+                }
+
+                println!("Creating {}", output_path.to_string_lossy());
+                let output_file = File::create(&output_path)?;
+                let mut writer = BufWriter::new(&output_file);
+                for s in asm {
+                    writeln!(writer, "{}", s)?;
+                }
+            }
+            Output::Vm => {
+                for input_file in input_files {
+                    optimize_file(input_file.as_path(), &options)?;
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-fn translate_file(
-    input_path: &Path,
-    output_file: &File,
-    bootstrap: bool,
-) -> Result<(), std::io::Error> {
-    println!("Translating {}", input_path.to_string_lossy());
-    let statics_base = input_path.file_stem().unwrap().to_string_lossy();
+fn parse_options(args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options {
+        output: Output::Asm,
+        os: Os::BuiltIn,
+        optimize: false,
+        extended: false,
+        short_compare: false,
+        shared_compare: false,
+        stats: false,
+    };
+    for arg in args {
+        match arg.as_str() {
+            "-output=asm" => options.output = Output::Asm,
+            "-output=vm" => {
+                options.output = Output::Vm;
+                options.optimize = true;
+            }
+            "-optimize" => options.optimize = true,
+            "-extended" => options.extended = true,
+            "-short-compare" => options.short_compare = true,
+            "-shared-compare" => options.shared_compare = true,
+            "-stats" | "--stats" => options.stats = true,
+            "-no-os" => options.os = Os::Off,
+            _ if arg.starts_with("-os=") => {
+                options.os = Os::Directory(PathBuf::from(&arg["-os=".len()..]))
+            }
+            _ => panic!("Unrecognized option {0}", arg),
+        }
+    }
+    options
+}
 
+fn read_file(input_path: &Path, options: &Options) -> Result<Option<Vec<Command>>, std::io::Error> {
     let input_file = File::open(input_path)?;
     let reader = BufReader::new(input_file);
     let lines = reader.lines().map(|line| line.unwrap());
-    let results = parser::parse_lines(lines);
+    let results = parser::parse_lines(lines, options.extended);
     match results {
         Err(errors) => {
             errors.iter().for_each(|error| println!("{:?}", error));
+            Ok(None)
         }
-        Ok(commands) => {
-            let asm = emitter::emit_commands(commands, &statics_base, bootstrap);
+        Ok(commands) => Ok(Some(commands)),
+    }
+}
 
-            let mut writer = BufWriter::new(output_file);
-            for s in asm {
-                writeln!(writer, "{}", s)?;
-            }
+// reads, and if asked optimizes, every file before any translation
+// so that the same commands can be translated more than once
+fn read_files(
+    input_files: &[PathBuf],
+    options: &Options,
+) -> Result<Vec<(String, Vec<Command>)>, std::io::Error> {
+    let mut files = Vec::new();
+    for input_file in input_files {
+        println!("Translating {}", input_file.to_string_lossy());
+        let statics_base = input_file.file_stem().unwrap().to_string_lossy();
+
+        if let Some(commands) = read_file(input_file, options)? {
+            let commands = if options.optimize {
+                optimizer::optimize(commands)
+            } else {
+                commands
+            };
+            files.push((statics_base.into_owned(), commands));
         }
-    };
-    Ok(())
+    }
+    Ok(files)
 }
 
-fn create_output_path(input_path: &Path) -> (Vec<PathBuf>, PathBuf) {
-    let base_name = input_path
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .into_owned();
-    let (files, mut output_path) = if input_path.is_file() {
-        (vec![PathBuf::from(input_path)], PathBuf::from(input_path))
-    } else if input_path.is_dir() {
-        let files = input_path
-            .read_dir()
-            .unwrap()
-            .filter_map(|p| {
-                let file = p.unwrap().path();
-                file.extension().and_then(|extn| {
-                    if extn.to_string_lossy().as_ref() == "vm" {
-                        Some(file.clone())
-                    } else {
-                        None
-                    }
+// a program that calls into os classes it doesn't have gets them, along
+// with a bootstrap. Then only what Sys.init can reach is kept, the os
+// being too big to fit in the rom along with most programs
+fn link_os(
+    mut files: Vec<(String, Vec<Command>)>,
+    options: &Options,
+) -> Result<Vec<(String, Vec<Command>)>, std::io::Error> {
+    let classes = files
+        .iter()
+        .map(|(class, _)| class.clone())
+        .collect::<Vec<_>>();
+    let defined = files
+        .iter()
+        .flat_map(|(_, commands)| commands)
+        .filter_map(|command| match command {
+            Command::Function(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let needs_os = files
+        .iter()
+        .flat_map(|(_, commands)| commands)
+        .any(|command| match command {
+            Command::Call(name, _) => {
+                !defined.contains(name.as_str())
+                    && name.split('.').next().is_some_and(os::is_os_class)
+            }
+            _ => false,
+        });
+    if !needs_os {
+        return Ok(files);
+    }
+
+    match &options.os {
+        Os::BuiltIn => {
+            println!("Linking the built in OS");
+            for (class, commands) in os::link(&classes) {
+                let commands = if options.optimize {
+                    optimizer::optimize(commands)
+                } else {
+                    commands
+                };
+                files.push((class, commands));
+            }
+        }
+        Os::Directory(directory) => {
+            let (os_files, _) = files::input_files(directory, ".vm");
+            let os_files = os_files
+                .into_iter()
+                .filter(|path| {
+                    let class = path.file_stem().unwrap().to_string_lossy();
+                    !classes.iter().any(|other| *other == class)
                 })
-            })
-            .collect();
+                .collect::<Vec<_>>();
+            files.append(&mut read_files(&os_files, options)?);
+        }
+        Os::Off => return Ok(files),
+    }
+    Ok(linker::remove_unreachable(files, "Sys.init"))
+}
 
-        let output_path = PathBuf::from(input_path).join(base_name);
-        (files, output_path)
-    } else {
-        panic!("Unable to find {}.", input_path.to_string_lossy());
-    };
-    output_path.set_extension("asm");
-    (files, output_path)
+fn emit_options(options: &Options) -> EmitOptions {
+    EmitOptions {
+        short_compare: options.short_compare,
+        shared_compare: options.shared_compare,
+    }
+}
+
+fn collect_stats(
+    files: &[(String, Vec<Command>)],
+    bootstrap: bool,
+    emit_options: EmitOptions,
+) -> stats::Stats {
+    let mut stats = stats::Stats::new(emit_options);
+    if bootstrap {
+        stats.add_bootstrap();
+    }
+    for (statics_base, commands) in files {
+        stats.add_commands(commands, statics_base);
+    }
+    stats
+}
+
+// optimized files go in an "opt" directory next to the originals
+// so that the result is itself a complete program that can be translated
+fn optimize_file(input_path: &Path, options: &Options) -> Result<(), std::io::Error> {
+    println!("Optimizing {}", input_path.to_string_lossy());
+    if let Some(commands) = read_file(input_path, options)? {
+        let commands = optimizer::optimize(commands);
+
+        let output_dir = input_path.parent().unwrap().join("opt");
+        create_dir_all(&output_dir)?;
+        let output_path = output_dir.join(input_path.file_name().unwrap());
+        println!("Creating {}", output_path.to_string_lossy());
+        let output_file = File::create(&output_path)?;
+        let mut writer = BufWriter::new(output_file);
+        for command in commands {
+            writeln!(writer, "{}", printer::print_command(&command))?;
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,587 @@
+// vm to vm optimizer. The Jack compiler is a straightforward
+// syntax directed translator so its output is full of patterns that
+// are easy to clean up with a few peephole passes over the commands.
+// Each pass is a simple rewrite and they are repeated until nothing changes.
+// Labels are scoped to their function so everything is done a function
+// at a time.
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+
+pub fn optimize(commands: Vec<Command>) -> Vec<Command> {
+    split_functions(strip_comments(commands))
+        .into_iter()
+        .flat_map(optimize_function)
+        .collect()
+}
+
+fn optimize_function(commands: Vec<Command>) -> Vec<Command> {
+    let mut commands = commands;
+    loop {
+        let optimized = fold_constants(commands.clone());
+        let optimized = invert_branches(optimized);
+        let optimized = thread_branches(optimized);
+        let optimized = remove_dead_code(optimized);
+        let optimized = remove_unused_labels(optimized);
+        if optimized == commands {
+            break commands;
+        }
+        commands = optimized;
+    }
+}
+
+fn strip_comments(commands: Vec<Command>) -> Vec<Command> {
+    commands
+        .into_iter()
+        .filter(|command| !matches!(command, Command::Comment(_)))
+        .collect()
+}
+
+// anything before the first function is kept as its own chunk
+fn split_functions(commands: Vec<Command>) -> Vec<Vec<Command>> {
+    let mut functions = Vec::new();
+    let mut current = Vec::new();
+    for command in commands {
+        if matches!(command, Command::Function(_, _)) && !current.is_empty() {
+            functions.push(current);
+            current = Vec::new();
+        }
+        current.push(command);
+    }
+    if !current.is_empty() {
+        functions.push(current);
+    }
+    functions
+}
+
+fn fold_constants(commands: Vec<Command>) -> Vec<Command> {
+    let mut results = Vec::new();
+    for command in commands {
+        match command {
+            Command::Add
+            | Command::Sub
+            | Command::And
+            | Command::Or
+            | Command::Eq
+            | Command::Gt
+            | Command::Lt
+            | Command::Mul
+            | Command::Div
+            | Command::Mod
+            | Command::Shl
+            | Command::Shr
+            | Command::Le
+            | Command::Ge
+            | Command::Ne => fold_binary(&mut results, command),
+            Command::Neg | Command::Not => fold_unary(&mut results, command),
+            Command::IfGoto(label) => fold_if_goto(&mut results, label),
+            _ => results.push(command),
+        }
+    }
+    results
+}
+
+fn fold_binary(results: &mut Vec<Command>, command: Command) {
+    if let Some((arg2, len2)) = trailing_constant(results) {
+        if let Some((arg1, len1)) = trailing_constant(&results[..results.len() - len2]) {
+            let value = match command {
+                Command::Add => Some(arg1.wrapping_add(arg2)),
+                Command::Sub => Some(arg1.wrapping_sub(arg2)),
+                Command::And => Some(arg1 & arg2),
+                Command::Or => Some(arg1 | arg2),
+                Command::Eq => Some(bool_value(arg1 == arg2)),
+                Command::Gt => Some(bool_value(arg1 > arg2)),
+                Command::Lt => Some(bool_value(arg1 < arg2)),
+                Command::Mul => Some(arg1.wrapping_mul(arg2)),
+                // leave division by zero for run time
+                Command::Div if arg2 != 0 => Some(arg1.wrapping_div(arg2)),
+                Command::Mod if arg2 != 0 => Some(arg1.wrapping_rem(arg2)),
+                Command::Div | Command::Mod => None,
+                Command::Shl => Some(shift_left(arg1, arg2)),
+                Command::Shr => Some(shift_right(arg1, arg2)),
+                Command::Le => Some(bool_value(arg1 <= arg2)),
+                Command::Ge => Some(bool_value(arg1 >= arg2)),
+                Command::Ne => Some(bool_value(arg1 != arg2)),
+                _ => unreachable!("not a binary command"),
+            };
+            if let Some(value) = value {
+                results.truncate(results.len() - len1 - len2);
+                results.append(&mut constant(value));
+                return;
+            }
+        }
+    }
+    results.push(command)
+}
+
+fn fold_unary(results: &mut Vec<Command>, command: Command) {
+    if let Some((arg, len)) = trailing_constant(results) {
+        let value = match command {
+            Command::Neg => arg.wrapping_neg(),
+            Command::Not => !arg,
+            _ => unreachable!("not a unary command"),
+        };
+        results.truncate(results.len() - len);
+        results.append(&mut constant(value));
+    } else if results.last() == Some(&command) {
+        // not not x = x and neg neg x = x
+        results.pop();
+    } else {
+        results.push(command)
+    }
+}
+
+// a branch on a constant is either always or never taken
+fn fold_if_goto(results: &mut Vec<Command>, label: String) {
+    if let Some((value, len)) = trailing_constant(results) {
+        results.truncate(results.len() - len);
+        if value != 0 {
+            results.push(Command::Goto(label));
+        }
+    } else {
+        results.push(Command::IfGoto(label));
+    }
+}
+
+// finds a constant at the end of the commands, either
+// a bare push or a push followed by a not or neg. Returns
+// the value and the number of commands used to produce it
+fn trailing_constant(commands: &[Command]) -> Option<(i16, usize)> {
+    match commands {
+        [.., Command::Push(Segment::Constant, n), Command::Not] => Some((!(*n as i16), 2)),
+        [.., Command::Push(Segment::Constant, n), Command::Neg] => {
+            Some(((*n as i16).wrapping_neg(), 2))
+        }
+        [.., Command::Push(Segment::Constant, n)] => Some((*n as i16, 1)),
+        _ => None,
+    }
+}
+
+// the shortest sequence that pushes the value. Negative values
+// use neg except where that's impossible (-32768) or where
+// not is the more familiar idiom (-1 is true)
+fn constant(value: i16) -> Vec<Command> {
+    if value >= 0 {
+        vec![Command::Push(Segment::Constant, value as u16)]
+    } else if value == -1 || value == i16::MIN {
+        vec![
+            Command::Push(Segment::Constant, !value as u16),
+            Command::Not,
+        ]
+    } else {
+        vec![
+            Command::Push(Segment::Constant, -value as u16),
+            Command::Neg,
+        ]
+    }
+}
+
+// same as the runtime routines, counts of 0 or less don't shift
+fn shift_left(value: i16, count: i16) -> i16 {
+    match count {
+        i16::MIN..=0 => value,
+        1..=15 => value << count,
+        _ => 0,
+    }
+}
+
+fn shift_right(value: i16, count: i16) -> i16 {
+    match count {
+        i16::MIN..=0 => value,
+        1..=15 => value >> count,
+        _ => value >> 15,
+    }
+}
+
+fn bool_value(b: bool) -> i16 {
+    if b {
+        -1
+    } else {
+        0
+    }
+}
+
+// rewrites "if-goto L1; goto L2; label L1" into "not; if-goto L2; label L1"
+// and "eq; not; if-goto L" into "sub; if-goto L". not is bitwise so
+// the first rewrite is only safe when the condition is known to be
+// a proper boolean (0 or -1)
+fn invert_branches(commands: Vec<Command>) -> Vec<Command> {
+    let mut results: Vec<Command> = Vec::new();
+    let mut iter = commands.into_iter().peekable();
+    while let Some(command) = iter.next() {
+        match command {
+            Command::IfGoto(label) => {
+                if results.ends_with(&[Command::Eq, Command::Not]) {
+                    results.truncate(results.len() - 2);
+                    results.push(Command::Sub);
+                    results.push(Command::IfGoto(label));
+                } else if produces_boolean(&results) {
+                    match iter.peek() {
+                        Some(Command::Goto(target)) => {
+                            let target = target.clone();
+                            iter.next();
+                            if iter.peek() == Some(&Command::Label(label.clone())) {
+                                results.push(Command::Not);
+                                results.push(Command::IfGoto(target));
+                            } else {
+                                results.push(Command::IfGoto(label));
+                                results.push(Command::Goto(target));
+                            }
+                        }
+                        _ => results.push(Command::IfGoto(label)),
+                    }
+                } else {
+                    results.push(Command::IfGoto(label));
+                }
+            }
+            _ => results.push(command),
+        }
+    }
+    results
+}
+
+fn produces_boolean(commands: &[Command]) -> bool {
+    match commands {
+        [.., Command::Eq | Command::Gt | Command::Lt | Command::Le | Command::Ge | Command::Ne, Command::Not] => {
+            true
+        }
+        [.., Command::Eq | Command::Gt | Command::Lt | Command::Le | Command::Ge | Command::Ne] => {
+            true
+        }
+        _ => trailing_constant(commands).is_some_and(|(value, _)| value == 0 || value == -1),
+    }
+}
+
+// a jump to a label that immediately jumps somewhere else can
+// go directly to the final destination, and a goto to the very
+// next command isn't needed at all
+fn thread_branches(commands: Vec<Command>) -> Vec<Command> {
+    let mut forwards = HashMap::new();
+    for (index, command) in commands.iter().enumerate() {
+        if let Command::Label(label) = command {
+            let next = commands[index + 1..]
+                .iter()
+                .find(|command| !matches!(command, Command::Label(_)));
+            if let Some(Command::Goto(target)) = next {
+                if target != label {
+                    forwards.insert(label.clone(), target.clone());
+                }
+            }
+        }
+    }
+
+    let resolve = |label: &String| {
+        let mut seen = HashSet::new();
+        let mut label = label;
+        while let Some(target) = forwards.get(label) {
+            if !seen.insert(label) {
+                break;
+            }
+            label = target;
+        }
+        label.clone()
+    };
+
+    let mut results = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::Goto(label) => {
+                let label = resolve(label);
+                let falls_through = commands[index + 1..]
+                    .iter()
+                    .take_while(|command| matches!(command, Command::Label(_)))
+                    .any(|command| command == &Command::Label(label.clone()));
+                if !falls_through {
+                    results.push(Command::Goto(label));
+                }
+            }
+            Command::IfGoto(label) => results.push(Command::IfGoto(resolve(label))),
+            _ => results.push(command.clone()),
+        }
+    }
+    results
+}
+
+// nothing after an unconditional jump can run until the next label
+fn remove_dead_code(commands: Vec<Command>) -> Vec<Command> {
+    let mut results = Vec::new();
+    let mut dead = false;
+    for command in commands {
+        match command {
+            Command::Label(_) | Command::Function(_, _) => dead = false,
+            _ => (),
+        }
+        if !dead {
+            dead = matches!(command, Command::Goto(_) | Command::Return);
+            results.push(command);
+        }
+    }
+    results
+}
+
+fn remove_unused_labels(commands: Vec<Command>) -> Vec<Command> {
+    let used: HashSet<_> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Goto(label) | Command::IfGoto(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+
+    commands
+        .into_iter()
+        .filter(|command| match command {
+            Command::Label(label) => used.contains(label),
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_lines;
+    use crate::printer::print_command;
+
+    fn optimize_lines(lines: &[&str]) -> Vec<String> {
+        optimize(parse_lines(lines, true).unwrap())
+            .iter()
+            .map(print_command)
+            .collect()
+    }
+
+    #[test]
+    fn test_constant() {
+        assert_eq!(constant(42), vec![Command::Push(Segment::Constant, 42)]);
+        assert_eq!(
+            constant(-1),
+            vec![Command::Push(Segment::Constant, 0), Command::Not]
+        );
+        assert_eq!(
+            constant(-42),
+            vec![Command::Push(Segment::Constant, 42), Command::Neg]
+        );
+        assert_eq!(
+            constant(i16::MIN),
+            vec![Command::Push(Segment::Constant, 32767), Command::Not]
+        );
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_eq!(
+            optimize_lines(&["push constant 8", "push constant 4", "add"]),
+            vec!["push constant 12"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 4", "push constant 8", "sub"]),
+            vec!["push constant 4", "neg"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 32767", "push constant 1", "add"]),
+            vec!["push constant 32767", "not"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 1", "neg", "push constant 2", "lt"]),
+            vec!["push constant 0", "not"]
+        );
+        assert_eq!(
+            optimize_lines(&["push local 0", "not", "not"]),
+            vec!["push local 0"]
+        );
+    }
+
+    #[test]
+    fn test_fold_extended() {
+        assert_eq!(
+            optimize_lines(&["push constant 300", "push constant 300", "mul"]),
+            vec!["push constant 24464"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 7", "neg", "push constant 2", "div"]),
+            vec!["push constant 3", "neg"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 7", "neg", "push constant 2", "mod"]),
+            vec!["push constant 0", "not"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 7", "push constant 0", "div"]),
+            vec!["push constant 7", "push constant 0", "div"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 3", "push constant 4", "shl"]),
+            vec!["push constant 48"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 48", "neg", "push constant 20", "shr"]),
+            vec!["push constant 0", "not"]
+        );
+        assert_eq!(
+            optimize_lines(&["push constant 3", "push constant 3", "le"]),
+            vec!["push constant 0", "not"]
+        );
+    }
+
+    #[test]
+    fn test_fold_if_goto() {
+        assert_eq!(
+            optimize_lines(&[
+                "function Main.main 0",
+                "label WHILE_EXP0",
+                "push constant 0",
+                "not",
+                "not",
+                "if-goto WHILE_END0",
+                "call Main.step 0",
+                "pop temp 0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+                "push constant 0",
+                "return",
+            ]),
+            vec![
+                "function Main.main 0",
+                "label WHILE_EXP0",
+                "call Main.step 0",
+                "pop temp 0",
+                "goto WHILE_EXP0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invert_branches() {
+        assert_eq!(
+            optimize_lines(&[
+                "function Main.main 0",
+                "push local 0",
+                "push local 1",
+                "lt",
+                "if-goto IF_TRUE0",
+                "goto IF_FALSE0",
+                "label IF_TRUE0",
+                "push constant 1",
+                "pop local 0",
+                "label IF_FALSE0",
+                "push constant 0",
+                "return",
+            ]),
+            vec![
+                "function Main.main 0",
+                "push local 0",
+                "push local 1",
+                "lt",
+                "not",
+                "if-goto IF_FALSE0",
+                "push constant 1",
+                "pop local 0",
+                "label IF_FALSE0",
+                "push constant 0",
+                "return",
+            ]
+        );
+        assert_eq!(
+            optimize_lines(&[
+                "function Main.main 0",
+                "label WHILE_EXP0",
+                "push local 0",
+                "push constant 3",
+                "eq",
+                "not",
+                "if-goto WHILE_END0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+                "push constant 0",
+                "return",
+            ]),
+            vec![
+                "function Main.main 0",
+                "label WHILE_EXP0",
+                "push local 0",
+                "push constant 3",
+                "sub",
+                "if-goto WHILE_END0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+                "push constant 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_boolean_branch_not_inverted() {
+        let lines = [
+            "function Main.main 0",
+            "push local 0",
+            "if-goto IF_TRUE0",
+            "goto IF_FALSE0",
+            "label IF_TRUE0",
+            "push constant 1",
+            "return",
+            "label IF_FALSE0",
+            "push constant 0",
+            "return",
+        ];
+        assert_eq!(optimize_lines(&lines), lines);
+    }
+
+    #[test]
+    fn test_thread_branches() {
+        assert_eq!(
+            optimize_lines(&[
+                "function Main.main 0",
+                "push local 0",
+                "if-goto A",
+                "push constant 1",
+                "return",
+                "label A",
+                "goto B",
+                "label B",
+                "push constant 2",
+                "return",
+            ]),
+            vec![
+                "function Main.main 0",
+                "push local 0",
+                "if-goto B",
+                "push constant 1",
+                "return",
+                "label B",
+                "push constant 2",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels_are_per_function() {
+        assert_eq!(
+            optimize_lines(&[
+                "function Main.a 0",
+                "goto L",
+                "label L",
+                "push constant 0",
+                "return",
+                "function Main.b 0",
+                "label L",
+                "push local 0",
+                "if-goto L",
+                "push constant 0",
+                "return",
+            ]),
+            vec![
+                "function Main.a 0",
+                "push constant 0",
+                "return",
+                "function Main.b 0",
+                "label L",
+                "push local 0",
+                "if-goto L",
+                "push constant 0",
+                "return",
+            ]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// the jack os from projects/12, built in so a program can be linked
+// without a copy of it. The vm code is compiled from its .jack files,
+// so after changing the os those need compiling again. The signatures
+// are what a compiler needs to check calls into it. They're written out
+// by hand, and compile's tests check them against the .jack files
+use crate::{ast::Command, parser};
+
+// each os class as vm code
+pub const CLASSES: [(&str, &str); 8] = [
+    ("Array", include_str!("../../projects/12/Array.vm")),
+    ("Keyboard", include_str!("../../projects/12/Keyboard.vm")),
+    ("Math", include_str!("../../projects/12/Math.vm")),
+    ("Memory", include_str!("../../projects/12/Memory.vm")),
+    ("Output", include_str!("../../projects/12/Output.vm")),
+    ("Screen", include_str!("../../projects/12/Screen.vm")),
+    ("String", include_str!("../../projects/12/String.vm")),
+    ("Sys", include_str!("../../projects/12/Sys.vm")),
+];
+
+pub struct Signature {
+    pub class: &'static str,
+    // function, method or constructor
+    pub kind: &'static str,
+    pub return_type: &'static str,
+    pub name: &'static str,
+    // type and name of each parameter, not counting this
+    pub params: &'static [(&'static str, &'static str)],
+}
+
+const fn signature(
+    class: &'static str,
+    kind: &'static str,
+    return_type: &'static str,
+    name: &'static str,
+    params: &'static [(&'static str, &'static str)],
+) -> Signature {
+    Signature {
+        class,
+        kind,
+        return_type,
+        name,
+        params,
+    }
+}
+
+pub const SIGNATURES: &[Signature] = &[
+    signature("Array", "function", "Array", "new", &[("int", "size")]),
+    signature("Array", "method", "void", "dispose", &[]),
+    signature("Keyboard", "function", "void", "init", &[]),
+    signature("Keyboard", "function", "char", "keyPressed", &[]),
+    signature("Keyboard", "function", "char", "readChar", &[]),
+    signature(
+        "Keyboard",
+        "function",
+        "String",
+        "readLine",
+        &[("String", "message")],
+    ),
+    signature(
+        "Keyboard",
+        "function",
+        "int",
+        "readInt",
+        &[("String", "message")],
+    ),
+    signature("Math", "function", "void", "init", &[]),
+    signature("Math", "function", "int", "abs", &[("int", "x")]),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "multiply",
+        &[("int", "x"), ("int", "y")],
+    ),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "divide",
+        &[("int", "x"), ("int", "y")],
+    ),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "mod",
+        &[("int", "x"), ("int", "y")],
+    ),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "divide_r",
+        &[("int", "x"), ("int", "y")],
+    ),
+    signature("Math", "function", "int", "sqrt", &[("int", "x")]),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "max",
+        &[("int", "a"), ("int", "b")],
+    ),
+    signature(
+        "Math",
+        "function",
+        "int",
+        "min",
+        &[("int", "a"), ("int", "b")],
+    ),
+    signature("Memory", "function", "void", "init", &[]),
+    signature("Memory", "function", "int", "peek", &[("int", "address")]),
+    signature(
+        "Memory",
+        "function",
+        "void",
+        "poke",
+        &[("int", "address"), ("int", "value")],
+    ),
+    signature("Memory", "function", "int", "alloc", &[("int", "size")]),
+    signature("Memory", "function", "void", "deAlloc", &[("Array", "o")]),
+    signature("Output", "function", "void", "init", &[]),
+    signature("Output", "function", "void", "initMap", &[]),
+    signature(
+        "Output",
+        "function",
+        "void",
+        "create",
+        &[
+            ("int", "index"),
+            ("int", "a"),
+            ("int", "b"),
+            ("int", "c"),
+            ("int", "d"),
+            ("int", "e"),
+            ("int", "f"),
+            ("int", "g"),
+            ("int", "h"),
+            ("int", "i"),
+            ("int", "j"),
+            ("int", "k"),
+        ],
+    ),
+    signature("Output", "function", "Array", "getMap", &[("char", "c")]),
+    signature(
+        "Output",
+        "function",
+        "void",
+        "moveCursor",
+        &[("int", "i"), ("int", "j")],
+    ),
+    signature("Output", "function", "void", "printChar", &[("char", "c")]),
+    signature(
+        "Output",
+        "function",
+        "void",
+        "showGlyph",
+        &[("Array", "glyph")],
+    ),
+    signature("Output", "function", "void", "showCursor", &[]),
+    signature("Output", "function", "void", "clearCursor", &[]),
+    signature(
+        "Output",
+        "function",
+        "void",
+        "printString",
+        &[("String", "s")],
+    ),
+    signature("Output", "function", "void", "printInt", &[("int", "i")]),
+    signature("Output", "function", "void", "println", &[]),
+    signature("Output", "function", "void", "backSpace", &[]),
+    signature("Screen", "function", "void", "init", &[]),
+    signature("Screen", "function", "void", "clearScreen", &[]),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "setColor",
+        &[("boolean", "b")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawPixel",
+        &[("int", "x"), ("int", "y")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawLine",
+        &[("int", "x1"), ("int", "y1"), ("int", "x2"), ("int", "y2")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawHorizontalLine",
+        &[("int", "x1"), ("int", "x2"), ("int", "y")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawVerticalLine",
+        &[("int", "x"), ("int", "y1"), ("int", "y2")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawRectangle",
+        &[("int", "x1"), ("int", "y1"), ("int", "x2"), ("int", "y2")],
+    ),
+    signature(
+        "Screen",
+        "function",
+        "void",
+        "drawCircle",
+        &[("int", "x"), ("int", "y"), ("int", "r")],
+    ),
+    signature(
+        "String",
+        "constructor",
+        "String",
+        "new",
+        &[("int", "maxLength")],
+    ),
+    signature("String", "method", "void", "dispose", &[]),
+    signature("String", "method", "int", "length", &[]),
+    signature("String", "method", "char", "charAt", &[("int", "j")]),
+    signature(
+        "String",
+        "method",
+        "void",
+        "setCharAt",
+        &[("int", "j"), ("char", "c")],
+    ),
+    signature("String", "method", "String", "appendChar", &[("char", "c")]),
+    signature("String", "method", "void", "grow", &[]),
+    signature("String", "method", "void", "eraseLastChar", &[]),
+    signature("String", "method", "int", "intValue", &[]),
+    signature("String", "method", "void", "setInt", &[("int", "val")]),
+    signature("String", "method", "void", "reverse", &[]),
+    signature("String", "function", "char", "newLine", &[]),
+    signature("String", "function", "char", "backSpace", &[]),
+    signature("String", "function", "char", "doubleQuote", &[]),
+    signature("Sys", "function", "void", "init", &[]),
+    signature("Sys", "function", "void", "halt", &[]),
+    signature("Sys", "function", "void", "wait", &[("int", "duration")]),
+    signature("Sys", "function", "void", "waitMillisecond", &[]),
+    signature("Sys", "function", "void", "error", &[("int", "errorCode")]),
+];
+
+pub fn is_os_class(class: &str) -> bool {
+    CLASSES.iter().any(|(name, _)| *name == class)
+}
+
+pub fn lookup(class: &str, name: &str) -> Option<&'static Signature> {
+    SIGNATURES
+        .iter()
+        .find(|signature| signature.class == class && signature.name == name)
+}
+
+// the os classes that aren't among the given ones, ready to translate
+pub fn link(classes: &[String]) -> Vec<(String, Vec<Command>)> {
+    CLASSES
+        .iter()
+        .filter(|(name, _)| !classes.iter().any(|class| class == name))
+        .map(|(name, vm)| {
+            let commands = parser::parse_lines(vm.lines(), false)
+                .unwrap_or_else(|_| panic!("the built in {}.vm doesn't parse", name));
+            (name.to_string(), commands)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signatures_match_classes() {
+        for (class, vm) in CLASSES {
+            let functions = vm
+                .lines()
+                .filter_map(|line| line.strip_prefix("function "))
+                .map(|line| line.split(' ').next().unwrap())
+                .collect::<Vec<_>>();
+            let signatures = SIGNATURES
+                .iter()
+                .filter(|signature| signature.class == class)
+                .map(|signature| format!("{}.{}", signature.class, signature.name))
+                .collect::<Vec<_>>();
+            assert_eq!(functions, signatures);
+        }
+    }
+
+    #[test]
+    fn test_link() {
+        let linked = link(&["Sys".to_string(), "Main".to_string()]);
+        assert_eq!(linked.len(), 7);
+        assert!(linked.iter().all(|(class, _)| class != "Sys"));
+        assert_eq!(
+            linked[0].1[0],
+            Command::Function("Array.new".to_string(), 0)
+        );
+    }
+}
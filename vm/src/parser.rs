@@ -1,13 +1,23 @@
 use crate::ast::*;
 
-pub fn parse_lines<S, T>(lines: T) -> Result<Vec<Command>, Vec<(usize, ParseError)>>
+// extended commands are only accepted when asked for so that
+// by default only standard vm code gets through
+pub fn parse_lines<S, T>(lines: T, extended: bool) -> Result<Vec<Command>, Vec<(usize, ParseError)>>
 where
     T: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
     let results = lines
         .into_iter()
-        .map(|line| parse_line(line.as_ref()))
+        .map(|line| {
+            parse_line(line.as_ref()).and_then(|command| {
+                if !extended && is_extended(&command) {
+                    Err(ParseError::ExtendedCommand(command))
+                } else {
+                    Ok(command)
+                }
+            })
+        })
         .enumerate();
     let mut commands = Vec::new();
     let mut errors = Vec::new();
@@ -25,6 +35,20 @@ where
     }
 }
 
+fn is_extended(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Mul
+            | Command::Div
+            | Command::Mod
+            | Command::Shl
+            | Command::Shr
+            | Command::Le
+            | Command::Ge
+            | Command::Ne
+    )
+}
+
 fn parse_line(line: &str) -> Result<Command, ParseError> {
     let mut trimmed = line.trim().to_string();
     if trimmed.is_empty() || trimmed.starts_with("//") {
@@ -60,6 +84,14 @@ fn parse_command1(command: &str) -> Result<Command, ParseError> {
         "and" => Ok(Command::And),
         "or" => Ok(Command::Or),
         "not" => Ok(Command::Not),
+        "mul" => Ok(Command::Mul),
+        "div" => Ok(Command::Div),
+        "mod" => Ok(Command::Mod),
+        "shl" => Ok(Command::Shl),
+        "shr" => Ok(Command::Shr),
+        "le" => Ok(Command::Le),
+        "ge" => Ok(Command::Ge),
+        "ne" => Ok(Command::Ne),
         "return" => Ok(Command::Return),
         _ => Err(ParseError::Invalid1WordCommand(command.to_string())),
     }
@@ -125,6 +157,7 @@ pub enum ParseError {
     InvalidSegment(String),
     InvalidIndex(String),
     IndexOutOfRange(u16),
+    ExtendedCommand(Command),
 }
 
 #[cfg(test)]
@@ -215,6 +248,14 @@ mod test {
         assert_eq!(parse_command1("and"), Ok(Command::And));
         assert_eq!(parse_command1("or"), Ok(Command::Or));
         assert_eq!(parse_command1("not"), Ok(Command::Not));
+        assert_eq!(parse_command1("mul"), Ok(Command::Mul));
+        assert_eq!(parse_command1("div"), Ok(Command::Div));
+        assert_eq!(parse_command1("mod"), Ok(Command::Mod));
+        assert_eq!(parse_command1("shl"), Ok(Command::Shl));
+        assert_eq!(parse_command1("shr"), Ok(Command::Shr));
+        assert_eq!(parse_command1("le"), Ok(Command::Le));
+        assert_eq!(parse_command1("ge"), Ok(Command::Ge));
+        assert_eq!(parse_command1("ne"), Ok(Command::Ne));
         assert_eq!(parse_command1("return"), Ok(Command::Return));
         assert_eq!(
             parse_command1("foo"),
@@ -256,4 +297,20 @@ mod test {
         );
         assert_eq!(parse_line(""), Ok(Command::Comment("".to_string())));
     }
+
+    #[test]
+    fn test_parse_lines() {
+        assert_eq!(
+            parse_lines(["push constant 2", "add"], false),
+            Ok(vec![Command::Push(Segment::Constant, 2), Command::Add])
+        );
+        assert_eq!(
+            parse_lines(["push constant 2", "mul"], false),
+            Err(vec![(1, ParseError::ExtendedCommand(Command::Mul))])
+        );
+        assert_eq!(
+            parse_lines(["push constant 2", "mul"], true),
+            Ok(vec![Command::Push(Segment::Constant, 2), Command::Mul])
+        );
+    }
 }
@@ -13,6 +13,14 @@ pub fn print_command(command: &Command) -> String {
         Command::And => "and".to_string(),
         Command::Or => "or".to_string(),
         Command::Not => "not".to_string(),
+        Command::Mul => "mul".to_string(),
+        Command::Div => "div".to_string(),
+        Command::Mod => "mod".to_string(),
+        Command::Shl => "shl".to_string(),
+        Command::Shr => "shr".to_string(),
+        Command::Le => "le".to_string(),
+        Command::Ge => "ge".to_string(),
+        Command::Ne => "ne".to_string(),
         Command::Goto(label) => print_command2("goto", label),
         Command::IfGoto(label) => print_command2("if-goto", label),
         Command::Label(label) => print_command2("label", label),
@@ -109,6 +117,14 @@ mod test {
         assert_eq!(print_command(&Command::And), "and");
         assert_eq!(print_command(&Command::Or), "or");
         assert_eq!(print_command(&Command::Not), "not");
+        assert_eq!(print_command(&Command::Mul), "mul");
+        assert_eq!(print_command(&Command::Div), "div");
+        assert_eq!(print_command(&Command::Mod), "mod");
+        assert_eq!(print_command(&Command::Shl), "shl");
+        assert_eq!(print_command(&Command::Shr), "shr");
+        assert_eq!(print_command(&Command::Le), "le");
+        assert_eq!(print_command(&Command::Ge), "ge");
+        assert_eq!(print_command(&Command::Ne), "ne");
         assert_eq!(
             print_command(&Command::Goto("LOOP".to_string())),
             "goto LOOP"
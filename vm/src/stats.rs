@@ -0,0 +1,267 @@
+// instruction counts for translated code, broken down by the kind of
+// vm command and the function that produced them. Each command is
+// translated on its own and only real instructions are counted, not
+// labels or comments, so the totals are what ends up in ROM.
+// Cycles are a static estimate: every instruction a command emits is
+// counted once, plus the worst case of any shared routine it calls
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::AddAssign;
+
+use crate::ast::*;
+use crate::emitter::{self, EmitOptions, Routine};
+
+pub const ROM_SIZE: usize = 32768;
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct Count {
+    pub instructions: usize,
+    pub cycles: usize,
+}
+
+impl AddAssign for Count {
+    fn add_assign(&mut self, other: Self) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+pub struct Stats {
+    pub kinds: BTreeMap<&'static str, Count>,
+    pub functions: BTreeMap<String, Count>,
+    routines: BTreeSet<Routine>,
+    options: EmitOptions,
+}
+
+impl Stats {
+    pub fn new(options: EmitOptions) -> Self {
+        Self {
+            kinds: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            routines: BTreeSet::new(),
+            options,
+        }
+    }
+
+    pub fn add_bootstrap(&mut self) {
+        let count = self.count(Vec::new(), "Sys", true);
+        *self.kinds.entry("bootstrap").or_default() += count;
+        *self.functions.entry("bootstrap".to_string()).or_default() += count;
+    }
+
+    // anything before the first function is put under the file's name
+    pub fn add_commands(&mut self, commands: &[Command], statics_base: &str) {
+        let mut function = statics_base.to_string();
+        for command in commands {
+            if let Command::Function(name, _) = command {
+                function = name.clone();
+            }
+            let count = self.count(vec![command.clone()], statics_base, false);
+            *self.kinds.entry(kind(command)).or_default() += count;
+            *self.functions.entry(function.clone()).or_default() += count;
+        }
+    }
+
+    fn count(&mut self, commands: Vec<Command>, statics_base: &str, bootstrap: bool) -> Count {
+        let mut routines = BTreeSet::new();
+        let instructions = count_instructions(emitter::emit_commands(
+            commands,
+            statics_base,
+            bootstrap,
+            self.options,
+            &mut routines,
+        ));
+        let cycles = instructions
+            + routines
+                .iter()
+                .map(|routine| routine.worst_case_cycles(self.options))
+                .sum::<usize>();
+        self.routines.append(&mut routines);
+        Count {
+            instructions,
+            cycles,
+        }
+    }
+
+    // the shared routines used by all the commands so far
+    pub fn runtime(&self) -> usize {
+        count_instructions(emitter::emit_routines(&self.routines, self.options))
+    }
+
+    pub fn total(&self) -> Count {
+        let mut total = Count {
+            instructions: self.runtime(),
+            cycles: 0,
+        };
+        for count in self.kinds.values() {
+            total += *count;
+        }
+        total
+    }
+}
+
+pub fn print_report(stats: &Stats) {
+    println!("{:<32}{:>14}{:>10}", "command", "instructions", "cycles");
+    for (kind, count) in &stats.kinds {
+        if count.instructions != 0 {
+            println!("{:<32}{:>14}{:>10}", kind, count.instructions, count.cycles);
+        }
+    }
+    println!();
+    println!("{:<32}{:>14}{:>10}", "function", "instructions", "cycles");
+    for (function, count) in &stats.functions {
+        if count.instructions == 0 {
+            continue;
+        }
+        println!(
+            "{:<32}{:>14}{:>10}",
+            function, count.instructions, count.cycles
+        );
+    }
+    println!();
+    println!("{:<32}{:>14}", "runtime", stats.runtime());
+    let total = stats.total();
+    println!(
+        "{:<32}{:>14}{:>10}",
+        "total", total.instructions, total.cycles
+    );
+}
+
+pub fn print_comparison(before_name: &str, before: &Stats, after_name: &str, after: &Stats) {
+    println!("{:<12}{:>10}{:>10}", "command", before_name, after_name);
+    let kinds: BTreeSet<_> = before.kinds.keys().chain(after.kinds.keys()).collect();
+    for kind in kinds {
+        let before_count = before.kinds.get(kind).copied().unwrap_or_default();
+        let after_count = after.kinds.get(kind).copied().unwrap_or_default();
+        if before_count.instructions != 0 || after_count.instructions != 0 {
+            println!(
+                "{:<12}{:>10}{:>10}",
+                kind, before_count.instructions, after_count.instructions
+            );
+        }
+    }
+    println!(
+        "{:<12}{:>10}{:>10}",
+        "runtime",
+        before.runtime(),
+        after.runtime()
+    );
+    println!(
+        "{:<12}{:>10}{:>10}",
+        "total",
+        before.total().instructions,
+        after.total().instructions
+    );
+}
+
+pub fn count_instructions<S: AsRef<str>>(asm: impl IntoIterator<Item = S>) -> usize {
+    asm.into_iter()
+        .filter(|line| {
+            let line = line.as_ref();
+            !line.starts_with("//") && !line.starts_with('(')
+        })
+        .count()
+}
+
+pub fn kind(command: &Command) -> &'static str {
+    match command {
+        Command::Push(_, _) => "push",
+        Command::Pop(_, _) => "pop",
+        Command::Add => "add",
+        Command::Sub => "sub",
+        Command::Neg => "neg",
+        Command::Eq => "eq",
+        Command::Gt => "gt",
+        Command::Lt => "lt",
+        Command::And => "and",
+        Command::Or => "or",
+        Command::Not => "not",
+        Command::Mul => "mul",
+        Command::Div => "div",
+        Command::Mod => "mod",
+        Command::Shl => "shl",
+        Command::Shr => "shr",
+        Command::Le => "le",
+        Command::Ge => "ge",
+        Command::Ne => "ne",
+        Command::Goto(_) => "goto",
+        Command::IfGoto(_) => "if-goto",
+        Command::Label(_) => "label",
+        Command::Function(_, _) => "function",
+        Command::Call(_, _) => "call",
+        Command::Return => "return",
+        Command::Comment(_) => "comment",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count_instructions() {
+        assert_eq!(
+            count_instructions(
+                ["// add", "(foo)", "@SP", "M=M-1"]
+                    .iter()
+                    .map(|s| s.to_string())
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn test_shared_compare_stats() {
+        let commands = vec![Command::Gt, Command::Gt, Command::Add];
+        let mut inline = Stats::new(EmitOptions::default());
+        inline.add_commands(&commands, "Foo");
+        let mut shared = Stats::new(EmitOptions {
+            shared_compare: true,
+            ..EmitOptions::default()
+        });
+        shared.add_commands(&commands, "Foo");
+
+        assert_eq!(inline.kinds["add"], shared.kinds["add"]);
+        assert_eq!(shared.kinds["gt"].instructions, 8);
+        assert!(inline.kinds["gt"].instructions > 2 * shared.kinds["gt"].instructions);
+        assert_eq!(inline.runtime(), 0);
+        assert!(shared.runtime() > 0);
+        assert_eq!(
+            shared.total().instructions,
+            shared.kinds["gt"].instructions + shared.kinds["add"].instructions + shared.runtime()
+        );
+    }
+
+    #[test]
+    fn test_function_stats() {
+        let commands = vec![
+            Command::Push(Segment::Constant, 1),
+            Command::Function("Foo.bar".to_string(), 0),
+            Command::Push(Segment::Constant, 2),
+            Command::Push(Segment::Constant, 3),
+            Command::Mul,
+            Command::Return,
+        ];
+        let mut stats = Stats::new(EmitOptions::default());
+        stats.add_commands(&commands, "Foo");
+
+        assert_eq!(stats.functions.len(), 2);
+        assert_eq!(
+            3 * stats.functions["Foo"].instructions,
+            stats.kinds["push"].instructions
+        );
+        let mul = stats.kinds["mul"];
+        assert_eq!(
+            mul.cycles,
+            mul.instructions + Routine::Mul.worst_case_cycles(EmitOptions::default())
+        );
+        assert_eq!(
+            stats.total().instructions,
+            stats
+                .functions
+                .values()
+                .map(|count| count.instructions)
+                .sum::<usize>()
+                + stats.runtime()
+        );
+    }
+}